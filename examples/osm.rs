@@ -1,5 +1,8 @@
-use egui::{Align2, Context, Painter, RichText, Shape, Ui, Window};
-use walkers::{Map, MapMemory, Position, PositionExt, Tiles};
+use egui::{Align2, Context, Painter, Shape, Window};
+use walkers::{
+    extras::{Place, Places, Style},
+    Controls, Map, MapMemory, Plugin, Popup, Position, Projector, Tiles,
+};
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init();
@@ -13,13 +16,23 @@ fn main() -> Result<(), eframe::Error> {
 struct Osm {
     tiles: Tiles,
     map_memory: MapMemory,
+    places: Places,
+
+    /// Place whose popup is open.
+    selected: Option<usize>,
 }
 
 impl Osm {
     fn new(egui_ctx: Context) -> Self {
         Self {
-            tiles: Tiles::new(walkers::openstreetmap, egui_ctx),
+            tiles: Tiles::new(walkers::sources::OpenStreetMap, egui_ctx),
             map_memory: MapMemory::default(),
+            places: Places::new(vec![Place {
+                position: wroclaw_glowny(),
+                label: "Wrocław Główny\ntrain station".to_owned(),
+                style: Style::default(),
+            }]),
+            selected: None,
         }
     }
 }
@@ -38,44 +51,36 @@ fn dworcowa_bus_stop() -> Position {
 }
 
 /// Shows how to draw various things in the map.
-fn draw_custom_shapes(ui: &Ui, painter: Painter, map_memory: &MapMemory, my_position: Position) {
-    // Geographical position of the point we want to put our shapes.
-    let position = dworcowa_bus_stop();
-
-    // Turn that into a flat, mercator projection.
-    let projected_position = position.project(*map_memory.zoom);
-
-    // We also need to know where the map center is.
-    let map_center_projected_position = map_memory
-        .center_mode
-        .position(my_position)
-        .project(*map_memory.zoom);
-
-    // From the two points above we can calculate the actual point on the screen.
-    let screen_position =
-        painter.clip_rect().center() + projected_position.to_vec2() - map_center_projected_position;
-
-    // Now we can just use Painter to draw stuff.
-    let background = |text: &Shape| {
-        Shape::rect_filled(
-            text.visual_bounding_rect().expand(5.),
-            5.,
-            ui.visuals().extreme_bg_color,
-        )
-    };
-
-    let text = ui.fonts(|fonts| {
-        Shape::text(
-            fonts,
-            screen_position.to_pos2(),
-            Align2::LEFT_CENTER,
-            "⬉ Here you can board the 106 line\nwhich goes to the airport.",
-            Default::default(),
-            ui.visuals().text_color(),
-        )
-    });
-    painter.add(background(&text));
-    painter.add(text);
+struct CustomShapes {}
+
+impl Plugin for CustomShapes {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        // Position of the point we want to put our shapes.
+        let screen_position = projector.to_screen(dworcowa_bus_stop());
+
+        // Now we can just use Painter to draw stuff.
+        let visuals = painter.ctx().style().visuals.clone();
+        let background = |text: &Shape| {
+            Shape::rect_filled(
+                text.visual_bounding_rect().expand(5.),
+                5.,
+                visuals.extreme_bg_color,
+            )
+        };
+
+        let text = painter.fonts(|fonts| {
+            Shape::text(
+                fonts,
+                screen_position,
+                Align2::LEFT_CENTER,
+                "⬉ Here you can board the 106 line\nwhich goes to the airport.",
+                Default::default(),
+                visuals.text_color(),
+            )
+        });
+        painter.add(background(&text));
+        painter.add(text);
+    }
 }
 
 impl eframe::App for Osm {
@@ -91,34 +96,40 @@ impl eframe::App for Osm {
             // Typically this would be a GPS acquired position which is tracked by the map.
             let my_position = wroclaw_glowny();
 
-            // Draw the actual map.
-            let response = ui.add(Map::new(
-                Some(&mut self.tiles),
-                &mut self.map_memory,
-                wroclaw_glowny(),
-            ));
-
-            // Draw custom shapes.
-            let painter = ui.painter().with_clip_rect(response.rect);
-            draw_custom_shapes(ui, painter, &self.map_memory, my_position);
+            // Draw the actual map, along with custom shapes.
+            let response = Map::new(Some(&mut self.tiles), &mut self.map_memory, my_position)
+                .with_plugin(&mut self.places)
+                .with_plugin(CustomShapes {})
+                .with_controls(Controls::default())
+                .show(ui);
+
+            // Clicking a place opens its popup.
+            if let Some(clicked) = self.places.clicked() {
+                self.selected = Some(clicked);
+            }
+            if let Some(place) = self
+                .selected
+                .and_then(|index| self.places.places().get(index))
+            {
+                Popup::new("place", place.position).show(ui.ctx(), &response.projector, |ui| {
+                    ui.label(&place.label);
+                    if ui.button("Close").clicked() {
+                        self.selected = None;
+                    }
+                });
+            }
 
-            // Simple GUI to zoom in and out.
+            // Current zoom and the position under the pointer.
             Window::new("Map")
                 .collapsible(false)
                 .resizable(false)
                 .title_bar(false)
                 .anchor(Align2::LEFT_BOTTOM, [10., -10.])
                 .show(ui.ctx(), |ui| {
-                    ui.label(format!("zoom: {}", *self.map_memory.zoom));
-                    ui.horizontal(|ui| {
-                        if ui.button(RichText::new("➕").heading()).clicked() {
-                            let _ = self.map_memory.zoom.zoom_in();
-                        }
-
-                        if ui.button(RichText::new("➖").heading()).clicked() {
-                            let _ = self.map_memory.zoom.zoom_out();
-                        }
-                    });
+                    ui.label(format!("zoom: {:.1}", *self.map_memory.zoom));
+                    if let Some(position) = response.hovered_at {
+                        ui.label(format!("{:.5}, {:.5}", position.y(), position.x()));
+                    }
                 });
         });
     }
@@ -0,0 +1,270 @@
+//! Texture atlas, which packs the tiles into a few big textures, so the map is drawn with
+//! a handful of meshes, instead of one per tile.
+use std::sync::Arc;
+
+use egui::{
+    mutex::Mutex, Color32, ColorImage, Context, Id, Mesh, Pos2, Rect, TextureHandle, TextureId,
+    TextureOptions, Vec2,
+};
+
+/// Width and height of a single texture of the atlas, small enough for any GPU.
+const PAGE_SIZE: usize = 2048;
+
+/// Border around each image, made of its edge pixels, so the neighbours do not bleed into it
+/// when the texture is scaled.
+const GUTTER: usize = 1;
+
+/// Texture holding the images of the same size in a grid of slots.
+struct Page {
+    texture: TextureHandle,
+    slot_size: usize,
+
+    /// Slots which are not taken, as indices in the row-major order.
+    free: Vec<usize>,
+}
+
+impl Page {
+    fn new(ctx: &Context, slot_size: usize) -> Self {
+        let texture = ctx.load_texture(
+            "walkers_atlas",
+            ColorImage::new([PAGE_SIZE, PAGE_SIZE], Color32::TRANSPARENT),
+            TextureOptions::LINEAR,
+        );
+        let mut page = Self {
+            texture,
+            slot_size,
+            free: Vec::new(),
+        };
+        page.free = (0..page.capacity()).rev().collect();
+        page
+    }
+
+    fn columns(&self) -> usize {
+        PAGE_SIZE / self.slot_size
+    }
+
+    fn capacity(&self) -> usize {
+        self.columns() * self.columns()
+    }
+}
+
+/// Pages are kept in the egui context, so all layers share them. Empty ones are dropped.
+type Pages = Arc<Mutex<Vec<Option<Page>>>>;
+
+/// Image uploaded to the GPU, either into the atlas, or as its own texture, if it does not fit.
+pub(crate) struct Texture {
+    id: TextureId,
+
+    /// Part of the texture taken by the image.
+    uv: Rect,
+
+    /// Texture is freed when this is dropped.
+    _owner: Owner,
+}
+
+enum Owner {
+    Atlas {
+        pages: Pages,
+        page: usize,
+        slot: usize,
+    },
+    #[allow(dead_code)] // Significant Drop
+    Texture(TextureHandle),
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        let Owner::Atlas { pages, page, slot } = self else {
+            return;
+        };
+
+        let mut pages = pages.lock();
+        if let Some(entry) = pages.get_mut(*page) {
+            if let Some(used) = entry {
+                used.free.push(*slot);
+                if used.free.len() == used.capacity() {
+                    *entry = None;
+                }
+            }
+        }
+    }
+}
+
+impl Texture {
+    pub(crate) fn upload(ctx: &Context, image: &ColorImage) -> Self {
+        let pages = ctx.data_mut(|data| {
+            data.get_temp_mut_or_default::<Pages>(Id::new("walkers_atlas"))
+                .clone()
+        });
+
+        insert(&pages, ctx, image).unwrap_or_else(|| {
+            let texture = ctx.load_texture("walkers_tile", image.clone(), TextureOptions::LINEAR);
+            Self {
+                id: texture.id(),
+                uv: Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.)),
+                _owner: Owner::Texture(texture),
+            }
+        })
+    }
+
+    /// Textured mesh of the `uv` part of the image (in 0..1 coordinates), drawn in `rect`.
+    pub(crate) fn mesh(&self, rect: Rect, uv: Rect, tint: Color32) -> Mesh {
+        let size = self.uv.size();
+        let uv = Rect::from_min_size(self.uv.min + uv.min.to_vec2() * size, uv.size() * size);
+
+        let mut mesh = Mesh::with_texture(self.id);
+        mesh.add_rect_with_uv(rect, uv, tint);
+        mesh
+    }
+}
+
+/// Put the image into a free slot of the atlas. Only square images fit.
+fn insert(pages: &Pages, ctx: &Context, image: &ColorImage) -> Option<Texture> {
+    let [width, height] = image.size;
+    let slot_size = width + 2 * GUTTER;
+    if width == 0 || width != height || slot_size > PAGE_SIZE {
+        return None;
+    }
+
+    let mut pages_guard = pages.lock();
+    let available = pages_guard.iter().position(|page| {
+        page.as_ref()
+            .is_some_and(|page| page.slot_size == slot_size && !page.free.is_empty())
+    });
+    let index = match available {
+        Some(index) => index,
+        None => {
+            let page = Some(Page::new(ctx, slot_size));
+            match pages_guard.iter().position(Option::is_none) {
+                Some(index) => {
+                    pages_guard[index] = page;
+                    index
+                }
+                None => {
+                    pages_guard.push(page);
+                    pages_guard.len() - 1
+                }
+            }
+        }
+    };
+
+    let page = pages_guard.get_mut(index)?.as_mut()?;
+    let slot = page.free.pop()?;
+    let columns = page.columns();
+    let min = [slot % columns * slot_size, slot / columns * slot_size];
+    page.texture
+        .set_partial(min, with_gutter(image), TextureOptions::LINEAR);
+
+    let uv = Rect::from_min_size(
+        (Vec2::new((min[0] + GUTTER) as f32, (min[1] + GUTTER) as f32) / PAGE_SIZE as f32)
+            .to_pos2(),
+        Vec2::splat(width as f32 / PAGE_SIZE as f32),
+    );
+
+    Some(Texture {
+        id: page.texture.id(),
+        uv,
+        _owner: Owner::Atlas {
+            pages: pages.clone(),
+            page: index,
+            slot,
+        },
+    })
+}
+
+/// Image surrounded by the copies of its edge pixels.
+fn with_gutter(image: &ColorImage) -> ColorImage {
+    let [width, height] = image.size;
+    let size = [width + 2 * GUTTER, height + 2 * GUTTER];
+    let pixels = (0..size[1])
+        .flat_map(|y| (0..size[0]).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let x = x.saturating_sub(GUTTER).min(width - 1);
+            let y = y.saturating_sub(GUTTER).min(height - 1);
+            image.pixels[y * width + x]
+        })
+        .collect();
+    ColorImage { size, pixels }
+}
+
+/// Meshes merged by their textures, so the ones packed into the same page of the atlas are drawn
+/// at once. Order of the meshes with different textures changes, so they must not overlap.
+pub(crate) fn batched(meshes: impl IntoIterator<Item = Mesh>) -> Vec<Mesh> {
+    let mut batches: Vec<Mesh> = Vec::new();
+    for mesh in meshes {
+        match batches
+            .iter_mut()
+            .find(|batch| batch.texture_id == mesh.texture_id)
+        {
+            Some(batch) => batch.append(mesh),
+            None => batches.push(mesh),
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: usize) -> ColorImage {
+        ColorImage::new([size, size], Color32::RED)
+    }
+
+    #[test]
+    fn images_of_the_same_size_share_a_texture() {
+        let ctx = Context::default();
+        let a = Texture::upload(&ctx, &image(256));
+        let b = Texture::upload(&ctx, &image(256));
+        let c = Texture::upload(&ctx, &image(512));
+
+        assert_eq!(a.id, b.id);
+        assert_ne!(a.id, c.id);
+        assert!(!a.uv.intersects(b.uv));
+        approx::assert_relative_eq!(256. / PAGE_SIZE as f32, a.uv.width());
+
+        // Images which do not fit get their own textures.
+        let odd = Texture::upload(&ctx, &ColorImage::new([256, 128], Color32::RED));
+        assert_ne!(a.id, odd.id);
+        assert_eq!(1., odd.uv.width());
+
+        // Freed slot is taken again.
+        let uv = b.uv;
+        drop(b);
+        assert_eq!(uv, Texture::upload(&ctx, &image(256)).uv);
+    }
+
+    #[test]
+    fn empty_page_is_dropped() {
+        let ctx = Context::default();
+        let id = Texture::upload(&ctx, &image(256)).id;
+        assert_ne!(id, Texture::upload(&ctx, &image(256)).id);
+    }
+
+    #[test]
+    fn gutter_repeats_the_edges() {
+        let mut image = ColorImage::new([2, 2], Color32::RED);
+        image.pixels[3] = Color32::BLUE;
+
+        let padded = with_gutter(&image);
+        assert_eq!([4, 4], padded.size);
+        assert_eq!(Color32::RED, padded.pixels[0]);
+        assert_eq!(Color32::BLUE, padded.pixels[15]);
+        assert_eq!(Color32::BLUE, padded.pixels[11]);
+    }
+
+    #[test]
+    fn meshes_are_batched_by_texture() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(1.));
+        let mesh = |id| {
+            let mut mesh = Mesh::with_texture(TextureId::User(id));
+            mesh.add_rect_with_uv(rect, rect, Color32::WHITE);
+            mesh
+        };
+
+        let batches = batched([mesh(1), mesh(2), mesh(1)]);
+        assert_eq!(2, batches.len());
+        assert_eq!(TextureId::User(1), batches[0].texture_id);
+        assert_eq!(8, batches[0].vertices.len());
+    }
+}
@@ -0,0 +1,192 @@
+//! Stitching the tiles into a single image, without the widget, e.g. on a server.
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use image::RgbaImage;
+
+use crate::{
+    http::{default_client, HttpClient, HttpOptions, HttpOptionsError},
+    mercator::{mercator_normalized, TileId},
+    sources::TileSource,
+    tiles, Position,
+};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CompositorError {
+    #[error("zoom {0} is outside of the source's range")]
+    Zoom(u8),
+
+    #[error("{tile_id:?} could not be loaded: {reason}")]
+    Tile { tile_id: TileId, reason: String },
+
+    #[error("tiles are of different sizes")]
+    TileSize,
+}
+
+/// Makes images of the areas of the map, stitched from the tiles of a source, in the Web
+/// Mercator projection. Unlike [`crate::Tiles`], it does not need egui, nor keeps the tiles, and
+/// its [`Compositor::render`] runs on the application's executor (which has to be Tokio's,
+/// unless the source [`TileSource::fetch`]es the tiles by itself). Same tiles give the same
+/// image, so it can also be used in the golden-image tests.
+pub struct Compositor<S> {
+    source: S,
+    http: HttpClient,
+}
+
+impl<S: TileSource> Compositor<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            http: default_client(),
+        }
+    }
+
+    /// Like [`Compositor::new`], but the HTTP requests are made with given options.
+    pub fn with_options(source: S, options: HttpOptions) -> Result<Self, HttpOptionsError> {
+        Ok(Self {
+            source,
+            http: options.client()?,
+        })
+    }
+
+    /// Image of the box spanned by two positions, from the tiles of given zoom level, at their
+    /// native resolution. Note that its size doubles with each level, so the area must be
+    /// small when the zoom is high. Tiles are loaded in parallel, up to
+    /// [`HttpOptions::max_parallel_downloads`] at the same time.
+    pub async fn render(
+        &self,
+        a: Position,
+        b: Position,
+        zoom: u8,
+    ) -> Result<RgbaImage, CompositorError> {
+        if !self.source.zoom_range().contains(&zoom) {
+            return Err(CompositorError::Zoom(zoom));
+        }
+
+        let count = 1u64 << zoom;
+        let (ax, ay) = normalized(a);
+        let (bx, by) = normalized(b);
+        let (min, max) = ((ax.min(bx), ay.min(by)), (ax.max(bx), ay.max(by)));
+
+        // Tiles of the box, with the columns beyond the antimeridian taken from the other side.
+        let columns = (min.0 * count as f64).floor() as i64..(max.0 * count as f64).ceil() as i64;
+        let rows = (min.1 * count as f64).floor() as i64
+            ..((max.1 * count as f64).ceil() as i64).clamp(1, count as i64);
+        let tiles = rows.flat_map(|y| columns.clone().map(move |x| (x, y)));
+
+        let loaded: Vec<_> = stream::iter(tiles)
+            .map(|(x, y)| async move {
+                let tile_id = TileId {
+                    x: x.rem_euclid(count as i64) as u32,
+                    y: y as u32,
+                    zoom,
+                };
+                tiles::load(&self.source, &self.http, tile_id)
+                    .await
+                    .map(|image| ((x, y), image))
+                    .map_err(|reason| CompositorError::Tile { tile_id, reason })
+            })
+            .buffer_unordered(self.http.max_parallel_downloads)
+            .try_collect()
+            .await?;
+
+        let Some((_, first)) = loaded.first() else {
+            return Ok(RgbaImage::new(0, 0));
+        };
+        let [size, _] = first.size;
+        if loaded.iter().any(|(_, image)| image.size != [size, size]) {
+            return Err(CompositorError::TileSize);
+        }
+
+        // Box in the pixels of the whole world.
+        let world = (count * size as u64) as f64;
+        let left = (min.0 * world).floor() as i64;
+        let top = (min.1 * world).floor() as i64;
+        let width = ((max.0 * world).ceil() as i64 - left).max(1) as u32;
+        let height = ((max.1 * world).ceil() as i64 - top).max(1) as u32;
+
+        let mut output = RgbaImage::new(width, height);
+        for ((x, y), image) in &loaded {
+            let origin = (x * size as i64 - left, y * size as i64 - top);
+            for (index, color) in image.pixels.iter().enumerate() {
+                let px = origin.0 + (index % size) as i64;
+                let py = origin.1 + (index / size) as i64;
+                if (0..width as i64).contains(&px) && (0..height as i64).contains(&py) {
+                    output.put_pixel(
+                        px as u32,
+                        py as u32,
+                        image::Rgba(color.to_srgba_unmultiplied()),
+                    );
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Coordinates of the position, both between 0 and 1 (or beyond, horizontally, past the
+/// antimeridian), with the latitude limited to the world's edges.
+fn normalized(position: Position) -> (f64, f64) {
+    let (x, y) = mercator_normalized(position.into());
+    (x, y.clamp(0., 1.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::Plain;
+    use egui::Color32;
+
+    fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn stitching_the_box() {
+        let compositor = Compositor::new(Plain {
+            background: Color32::RED,
+            graticule: None,
+        });
+
+        // World at zoom 1 is 512 pixels big, and this is its middle quarter.
+        let image = block_on(compositor.render(
+            Position::new(-90., 66.51326),
+            Position::new(90., -66.51326),
+            1,
+        ))
+        .unwrap();
+        assert_eq!((256, 256), image.dimensions());
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+
+        // Crossing the antimeridian.
+        let image =
+            block_on(compositor.render(Position::new(170., 10.), Position::new(190., -10.), 3))
+                .unwrap();
+        approx::assert_relative_eq!(114., image.width() as f64, epsilon = 1.);
+
+        assert_eq!(
+            Err(CompositorError::Zoom(27)),
+            block_on(compositor.render(Position::new(0., 0.), Position::new(1., 1.), 27))
+        );
+    }
+
+    #[test]
+    fn tiles_which_failed_are_reported() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/0/0/0.png").with_status(404).create();
+        let url = server.url();
+        let compositor = Compositor::new(move |tile_id: TileId| {
+            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        });
+
+        let Err(CompositorError::Tile { tile_id, .. }) =
+            block_on(compositor.render(Position::new(-10., 10.), Position::new(10., -10.), 0))
+        else {
+            panic!("expected an error");
+        };
+        assert_eq!(0, tile_id.zoom);
+    }
+}
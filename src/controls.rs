@@ -0,0 +1,122 @@
+//! Buttons drawn over the map, for the applications which do not need their own.
+use egui::{
+    emath::Rot2, Align, Align2, Area, Button, Color32, Layout, Painter, Rect, RichText, Shape,
+    Stroke, Ui, Vec2,
+};
+
+use crate::MapMemory;
+
+/// Built-in controls of the map, see [`crate::Map::with_controls`].
+#[derive(Clone, Debug)]
+pub struct Controls {
+    /// Corner of the map where the buttons are placed.
+    pub corner: Align2,
+
+    /// Show the "+" and "−" buttons.
+    pub zoom: bool,
+
+    /// Show the button which centers the map at "my position", and keeps it there.
+    pub my_position: bool,
+
+    /// Show the compass while the map is rotated. Clicking it turns the north up again.
+    pub compass: bool,
+
+    /// Show the button which toggles the fullscreen mode. Walkers does not control the window,
+    /// so it is up to the application to react to [`crate::MapResponse::fullscreen_toggled`].
+    pub fullscreen: bool,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            corner: Align2::RIGHT_TOP,
+            zoom: true,
+            my_position: true,
+            compass: true,
+            fullscreen: false,
+        }
+    }
+}
+
+/// Distance between the buttons and the edges of the map.
+const MARGIN: f32 = 10.;
+
+/// Size of a single button.
+const BUTTON_SIZE: f32 = 28.;
+
+/// Show the controls in the corner of the map's `rect`. They are placed in a separate layer, so
+/// clicking them does not interact with the map underneath. Returns whether the fullscreen
+/// button was clicked.
+pub(crate) fn show(controls: &Controls, ui: &Ui, rect: Rect, memory: &mut MapMemory) -> bool {
+    let mut fullscreen_toggled = false;
+    let layout = if controls.corner.x() == Align::Max {
+        Layout::top_down(Align::Max)
+    } else {
+        Layout::top_down(Align::Min)
+    };
+
+    Area::new(ui.id().with("walkers_controls"))
+        .fixed_pos(controls.corner.pos_in_rect(&rect.shrink(MARGIN)))
+        .pivot(controls.corner)
+        .show(ui.ctx(), |ui| {
+            ui.with_layout(layout, |ui| {
+                let button = |ui: &mut Ui, text: &str, hint: &str| {
+                    ui.add(
+                        Button::new(RichText::new(text).heading())
+                            .min_size(Vec2::splat(BUTTON_SIZE)),
+                    )
+                    .on_hover_text(hint)
+                    .clicked()
+                };
+
+                if controls.zoom {
+                    if button(ui, "➕", "Zoom in") {
+                        memory.zoom.zoom_by(1.);
+                    }
+                    if button(ui, "➖", "Zoom out") {
+                        memory.zoom.zoom_by(-1.);
+                    }
+                }
+
+                if controls.my_position && button(ui, "◎", "Center at my position") {
+                    memory.follow_my_position();
+                }
+
+                if controls.compass && memory.rotation != 0. {
+                    let response = ui
+                        .add(Button::new("").min_size(Vec2::splat(BUTTON_SIZE)))
+                        .on_hover_text("Turn the north up");
+                    draw_needle(ui.painter(), response.rect, memory.rotation);
+                    if response.clicked() {
+                        memory.rotation = 0.;
+                    }
+                }
+
+                if controls.fullscreen && button(ui, "⛶", "Toggle fullscreen") {
+                    fullscreen_toggled = true;
+                }
+            });
+        });
+
+    fullscreen_toggled
+}
+
+/// Compass needle, pointing to the north of the rotated map.
+fn draw_needle(painter: &Painter, rect: Rect, rotation: f32) {
+    let center = rect.center();
+    let length = rect.height() * 0.35;
+    let width = length * 0.35;
+    let rotation = Rot2::from_angle(rotation);
+    let point = |x: f32, y: f32| center + rotation * Vec2::new(x, y);
+
+    for (tip, color) in [
+        (-length, Color32::from_rgb(220, 50, 50)),
+        (length, Color32::from_gray(150)),
+    ] {
+        painter.add(Shape::convex_polygon(
+            vec![point(0., tip), point(width, 0.), point(-width, 0.)],
+            color,
+            Stroke::NONE,
+        ));
+    }
+}
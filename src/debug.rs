@@ -0,0 +1,119 @@
+//! Overlay showing the internals of the map, see [`crate::Map::debug_overlay`].
+use egui::{emath::Rot2, Align2, Color32, FontId, Painter, Rect, Shape, Stroke, Vec2};
+
+use crate::{
+    tiles::{TileStatus, Tiles},
+    Projector,
+};
+
+const FONT_SIZE: f32 = 11.;
+
+/// Draw the boundaries of the tiles of given level, with their ids and statuses.
+pub(crate) fn draw_tiles(painter: &Painter, projector: &Projector, tiles: &Tiles, level: u8) {
+    let font = FontId::monospace(FONT_SIZE);
+
+    for (tile_id, rect) in projector.visible_tiles(level) {
+        let status = tiles.status(tile_id);
+        let color = match status {
+            TileStatus::Loaded => Color32::from_rgb(40, 160, 40),
+            TileStatus::Pending => Color32::from_rgb(220, 160, 0),
+            TileStatus::Failed => Color32::from_rgb(220, 40, 40),
+            TileStatus::Missing => Color32::GRAY,
+        };
+
+        let corners = [
+            rect.left_top(),
+            rect.right_top(),
+            rect.right_bottom(),
+            rect.left_bottom(),
+        ]
+        .map(|corner| projector.rotate(corner));
+        painter.add(Shape::closed_line(corners.to_vec(), Stroke::new(1., color)));
+
+        let mut label = format!("{}/{}/{}\n{:?}", tile_id.zoom, tile_id.x, tile_id.y, status);
+        if let Some(reason) = tiles.error(tile_id) {
+            label.push_str(": ");
+            label.push_str(reason);
+        }
+        painter.text(
+            corners[0] + Rot2::from_angle(projector.rotation()) * Vec2::splat(4.),
+            Align2::LEFT_TOP,
+            label,
+            font.clone(),
+            color,
+        );
+    }
+}
+
+/// Summary of the zoom and the tile caches, in the top-left corner of the map.
+pub(crate) fn draw_summary(painter: &Painter, projector: &Projector, layers: &[String]) {
+    let text = format!("zoom {:.2}\n{}", projector.zoom(), layers.join("\n"));
+    let galley = painter.layout_no_wrap(text, FontId::monospace(FONT_SIZE), Color32::WHITE);
+    let min = painter.clip_rect().min + Vec2::splat(6.);
+    painter.rect_filled(
+        Rect::from_min_size(min, galley.size()).expand(4.),
+        3.,
+        Color32::BLACK.gamma_multiply(0.7),
+    );
+    painter.galley(min, galley);
+}
+
+/// Line of the summary describing a tile layer.
+pub(crate) fn layer_summary(index: usize, tiles: &Tiles, level: Option<u8>) -> String {
+    let stats = tiles.stats();
+    format!(
+        "layer {}: level {}, loaded {}, pending {}, failed {}, dropped {}, {:.1} MiB",
+        index,
+        level.map_or_else(|| "-".to_owned(), |level| level.to_string()),
+        stats.loaded,
+        stats.pending,
+        stats.failed,
+        stats.dropped,
+        stats.memory_used as f64 / (1024. * 1024.)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::tests::{mockito_server, TILE_ID};
+    use crate::TileId;
+    use egui::Context;
+
+    #[test]
+    fn statuses_and_summary_of_the_tiles() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        server.mock("GET", "/3/2/2.png").with_status(404).create();
+        let failing = TileId { x: 2, ..TILE_ID };
+        let untouched = TileId { x: 3, ..TILE_ID };
+
+        let mut tiles = Tiles::new(source, Context::default());
+        tiles.at(TILE_ID);
+        tiles.at(failing);
+        assert_eq!(TileStatus::Pending, tiles.status(TILE_ID));
+        assert_eq!(TileStatus::Missing, tiles.status(untouched));
+
+        while tiles.pending_count() > 0 {
+            tiles.at(TILE_ID);
+            tiles.at(failing);
+        }
+
+        assert_eq!(TileStatus::Loaded, tiles.status(TILE_ID));
+        assert_eq!(TileStatus::Failed, tiles.status(failing));
+        assert!(tiles.error(TILE_ID).is_none());
+        assert!(tiles.error(failing).is_some());
+        assert!(
+            layer_summary(0, &tiles, Some(3))
+                .starts_with("layer 0: level 3, loaded 1, pending 0, failed 1, dropped 0,"),
+            "{}",
+            layer_summary(0, &tiles, Some(3))
+        );
+        assert!(layer_summary(1, &tiles, None).starts_with("layer 1: level -,"));
+    }
+}
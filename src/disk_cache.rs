@@ -0,0 +1,469 @@
+//! Persistent cache of the downloaded tiles.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL, CONTENT_TYPE, ETAG};
+
+use crate::mercator::TileId;
+
+/// What the server said about caching the tile. Kept next to the image, in a file with the
+/// `.meta` extension.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct CacheMetadata {
+    /// Value of the `ETag` header, used to ask the server whether the tile changed.
+    pub etag: Option<String>,
+
+    /// When the tile should be validated with the server again, according to the `max-age`
+    /// directive of the `Cache-Control` header. `None` means never.
+    pub expires: Option<SystemTime>,
+
+    /// Value of the `Content-Type` header, given to the decoder when the tile is loaded.
+    pub content_type: Option<String>,
+}
+
+impl CacheMetadata {
+    /// Metadata of the response with given headers. `None` if the response must not be stored.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut max_age = None;
+        let mut no_cache = false;
+
+        let directives = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|directive| directive.trim().to_ascii_lowercase());
+
+        for directive in directives {
+            if directive == "no-store" {
+                return None;
+            } else if directive == "no-cache" {
+                no_cache = true;
+            } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+                max_age = seconds
+                    .trim_matches('"')
+                    .parse()
+                    .ok()
+                    .map(Duration::from_secs);
+            }
+        }
+
+        let now = SystemTime::now();
+        let expires = if no_cache {
+            Some(now)
+        } else {
+            max_age.map(|max_age| now + max_age)
+        };
+
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        };
+
+        Some(Self {
+            etag: header(ETAG),
+            expires,
+            content_type: header(CONTENT_TYPE),
+        })
+    }
+
+    /// Whether the tile can be used without asking the server.
+    pub fn is_fresh(&self) -> bool {
+        self.expires
+            .map_or(true, |expires| expires > SystemTime::now())
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut metadata = Self::default();
+        for line in text.lines() {
+            if let Some(etag) = line.strip_prefix("etag: ") {
+                metadata.etag = Some(etag.to_string());
+            } else if let Some(expires) = line.strip_prefix("expires: ") {
+                metadata.expires = expires
+                    .parse()
+                    .ok()
+                    .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+            } else if let Some(content_type) = line.strip_prefix("content-type: ") {
+                metadata.content_type = Some(content_type.to_string());
+            }
+        }
+        metadata
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(etag) = &self.etag {
+            text.push_str(&format!("etag: {}\n", etag));
+        }
+        if let Some(expires) = self.expires {
+            let seconds = expires
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            text.push_str(&format!("expires: {}\n", seconds));
+        }
+        if let Some(content_type) = &self.content_type {
+            text.push_str(&format!("content-type: {}\n", content_type));
+        }
+        text
+    }
+}
+
+/// Tile image loaded from the disk cache.
+pub(crate) struct CachedImage {
+    pub image: Vec<u8>,
+    pub metadata: CacheMetadata,
+}
+
+/// Keeps raw tile images in a directory, so they survive application restarts. When total size
+/// of the files exceeds `max_size`, the least recently used ones are removed.
+pub(crate) struct DiskCache {
+    path: PathBuf,
+    max_size: u64,
+
+    /// Files known to be in the cache, with their sizes and when they were last used.
+    entries: HashMap<PathBuf, Entry>,
+
+    /// Paths of the `entries`, least recently used first.
+    order: BTreeMap<u64, PathBuf>,
+
+    /// Incremented every time a file is used.
+    use_counter: u64,
+
+    /// Sum of the sizes of all `entries`.
+    size: u64,
+}
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+impl DiskCache {
+    /// Open (or create) a cache in given directory.
+    pub fn open(path: PathBuf, max_size: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&path)?;
+
+        let mut entries = Vec::new();
+        collect_files(&path, &mut entries)?;
+        entries.sort_by_key(|(modified, _, _)| *modified);
+
+        let mut cache = Self {
+            path,
+            max_size,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            use_counter: 0,
+            size: 0,
+        };
+        for (_, path, size) in entries {
+            cache.insert(path, size);
+        }
+        cache.evict();
+        Ok(cache)
+    }
+
+    /// Raw image of the tile, if it was stored before. Makes it the last one to be evicted.
+    pub fn load(&mut self, tile_id: TileId, url: &str) -> Option<CachedImage> {
+        let path = self.tile_path(tile_id, url);
+        let image = std::fs::read(&path).ok()?;
+        self.touch(&path);
+
+        // Tiles stored without the metadata never expire.
+        let metadata = std::fs::read_to_string(metadata_path(&path))
+            .map(|text| CacheMetadata::parse(&text))
+            .unwrap_or_default();
+
+        Some(CachedImage { image, metadata })
+    }
+
+    /// Store raw image of the tile, evicting the oldest ones if the cache grows too big.
+    pub fn store(
+        &mut self,
+        tile_id: TileId,
+        url: &str,
+        image: &[u8],
+        metadata: &CacheMetadata,
+    ) -> std::io::Result<()> {
+        let path = self.tile_path(tile_id, url);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, image)?;
+        store_metadata(&path, metadata)?;
+
+        self.insert(path, image.len() as u64);
+        self.evict();
+        Ok(())
+    }
+
+    /// Replace the metadata of a stored tile, after the server confirmed it did not change.
+    pub fn refresh(
+        &mut self,
+        tile_id: TileId,
+        url: &str,
+        metadata: &CacheMetadata,
+    ) -> std::io::Result<()> {
+        let path = self.tile_path(tile_id, url);
+
+        // Servers usually do not repeat the `Content-Type` when the tile did not change.
+        let mut metadata = metadata.clone();
+        if metadata.content_type.is_none() {
+            metadata.content_type = std::fs::read_to_string(metadata_path(&path))
+                .ok()
+                .and_then(|text| CacheMetadata::parse(&text).content_type);
+        }
+        store_metadata(&path, &metadata)?;
+
+        // Tile was used recently, so it should be the last one to be evicted.
+        self.touch(&path);
+        Ok(())
+    }
+
+    /// Add a file as the most recently used one. It might be there already, e.g. when it was
+    /// corrupted on the disk and downloaded again.
+    fn insert(&mut self, path: PathBuf, size: u64) {
+        self.use_counter += 1;
+        let entry = Entry {
+            size,
+            last_used: self.use_counter,
+        };
+        if let Some(previous) = self.entries.insert(path.clone(), entry) {
+            self.order.remove(&previous.last_used);
+            self.size -= previous.size;
+        }
+        self.order.insert(self.use_counter, path);
+        self.size += size;
+    }
+
+    /// Mark a file as the most recently used one, also on the disk, so the order is the same
+    /// after a restart.
+    fn touch(&mut self, path: &Path) {
+        let Some(entry) = self.entries.get_mut(path) else {
+            return;
+        };
+        self.use_counter += 1;
+        if let Some(path) = self.order.remove(&entry.last_used) {
+            self.order.insert(self.use_counter, path);
+        }
+        entry.last_used = self.use_counter;
+
+        let touched = std::fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(err) = touched {
+            log::debug!("Could not touch {}: {}.", path.display(), err);
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((_, path)) = self.order.pop_first() else {
+                break;
+            };
+            let size = self.entries.remove(&path).map_or(0, |entry| entry.size);
+
+            log::debug!("Evicting {} from the disk cache.", path.display());
+            if let Err(err) = std::fs::remove_file(&path) {
+                log::warn!("Could not remove {}: {}.", path.display(), err);
+            }
+            let _ = std::fs::remove_file(metadata_path(&path));
+            self.size -= size;
+        }
+    }
+
+    /// Where the tile is kept. URL is a part of the key, because different sources might be cached
+    /// in the same directory.
+    fn tile_path(&self, tile_id: TileId, url: &str) -> PathBuf {
+        self.path
+            .join(tile_id.zoom.to_string())
+            .join(tile_id.x.to_string())
+            .join(format!("{}-{:016x}", tile_id.y, fnv1a(url.as_bytes())))
+    }
+}
+
+fn metadata_path(path: &Path) -> PathBuf {
+    path.with_extension("meta")
+}
+
+fn store_metadata(path: &Path, metadata: &CacheMetadata) -> std::io::Result<()> {
+    let path = metadata_path(path);
+    if metadata == &CacheMetadata::default() {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    } else {
+        std::fs::write(path, metadata.to_text())
+    }
+}
+
+fn collect_files(
+    directory: &Path,
+    files: &mut Vec<(SystemTime, PathBuf, u64)>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if entry
+            .path()
+            .extension()
+            .map_or(true, |extension| extension != "meta")
+        {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, entry.path(), metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Hash which, unlike the `DefaultHasher`, is guaranteed not to change between Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_id(x: u32) -> TileId {
+        TileId { x, y: 2, zoom: 3 }
+    }
+
+    #[test]
+    fn stored_tile_can_be_loaded() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut cache = DiskCache::open(directory.path().to_owned(), 1024).unwrap();
+
+        assert!(cache.load(tile_id(1), "url").is_none());
+        cache
+            .store(tile_id(1), "url", b"image", &CacheMetadata::default())
+            .unwrap();
+        assert_eq!(
+            Some(b"image".to_vec()),
+            cache.load(tile_id(1), "url").map(|cached| cached.image)
+        );
+
+        // Different source.
+        assert!(cache.load(tile_id(1), "other url").is_none());
+
+        // Cache persists when reopened.
+        let mut cache = DiskCache::open(directory.path().to_owned(), 1024).unwrap();
+        assert_eq!(
+            Some(b"image".to_vec()),
+            cache.load(tile_id(1), "url").map(|cached| cached.image)
+        );
+    }
+
+    #[test]
+    fn oldest_tiles_are_evicted() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut cache = DiskCache::open(directory.path().to_owned(), 10).unwrap();
+
+        cache
+            .store(tile_id(1), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        cache
+            .store(tile_id(2), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        cache
+            .store(tile_id(3), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        assert!(cache.load(tile_id(1), "url").is_none());
+        assert!(cache.load(tile_id(2), "url").is_some());
+        assert!(cache.load(tile_id(3), "url").is_some());
+
+        // Storing the same tile again does not count twice.
+        cache
+            .store(tile_id(3), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        assert!(cache.load(tile_id(2), "url").is_some());
+    }
+
+    #[test]
+    fn loaded_tiles_are_evicted_last() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut cache = DiskCache::open(directory.path().to_owned(), 10).unwrap();
+
+        cache
+            .store(tile_id(1), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        cache
+            .store(tile_id(2), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        assert!(cache.load(tile_id(1), "url").is_some());
+
+        cache
+            .store(tile_id(3), "url", b"12345", &CacheMetadata::default())
+            .unwrap();
+        assert!(cache.load(tile_id(1), "url").is_some());
+        assert!(cache.load(tile_id(2), "url").is_none());
+        assert!(cache.load(tile_id(3), "url").is_some());
+    }
+
+    #[test]
+    fn metadata_is_stored_along_the_tile() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut cache = DiskCache::open(directory.path().to_owned(), 1024).unwrap();
+
+        let metadata = CacheMetadata {
+            etag: Some("\"abc\"".to_string()),
+            expires: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000)),
+            content_type: Some("image/webp".to_string()),
+        };
+        cache.store(tile_id(1), "url", b"image", &metadata).unwrap();
+
+        let mut cache = DiskCache::open(directory.path().to_owned(), 1024).unwrap();
+        let cached = cache.load(tile_id(1), "url").unwrap();
+        assert_eq!(metadata, cached.metadata);
+        assert!(!cached.metadata.is_fresh());
+        assert_eq!(b"image".to_vec(), cached.image);
+
+        // Content type is kept when the server does not repeat it.
+        let refreshed = CacheMetadata {
+            etag: Some("\"def\"".to_string()),
+            ..Default::default()
+        };
+        cache.refresh(tile_id(1), "url", &refreshed).unwrap();
+        let cached = cache.load(tile_id(1), "url").unwrap();
+        assert_eq!(Some("\"def\"".to_string()), cached.metadata.etag);
+        assert_eq!(metadata.content_type, cached.metadata.content_type);
+    }
+
+    #[test]
+    fn cache_control_is_parsed() {
+        let headers = |cache_control: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CACHE_CONTROL, cache_control.parse().unwrap());
+            headers.insert(ETAG, "\"abc\"".parse().unwrap());
+            headers.insert(CONTENT_TYPE, "image/png".parse().unwrap());
+            headers
+        };
+
+        let metadata = CacheMetadata::from_headers(&headers("public, max-age=3600")).unwrap();
+        assert_eq!(Some("\"abc\"".to_string()), metadata.etag);
+        assert_eq!(Some("image/png".to_string()), metadata.content_type);
+        assert!(metadata.is_fresh());
+
+        let metadata = CacheMetadata::from_headers(&headers("max-age=0")).unwrap();
+        assert!(!metadata.is_fresh());
+
+        let metadata = CacheMetadata::from_headers(&headers("no-cache, max-age=3600")).unwrap();
+        assert!(!metadata.is_fresh());
+
+        assert!(CacheMetadata::from_headers(&headers("no-store")).is_none());
+        assert!(CacheMetadata::from_headers(&HeaderMap::new())
+            .unwrap()
+            .is_fresh());
+    }
+}
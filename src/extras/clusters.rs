@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use egui::{Align2, Color32, FontId, Painter, Pos2, Stroke};
+
+use super::Place;
+use crate::{Plugin, Position, PositionExt, Projector};
+
+/// Visual style of a [`Cluster`] of more than one place.
+#[derive(Clone)]
+pub struct ClusterStyle {
+    /// Radius of the smallest cluster. Bigger ones grow with the number of places.
+    pub radius: f32,
+    pub fill: Color32,
+    pub stroke: Stroke,
+    pub font: FontId,
+    pub text_color: Color32,
+}
+
+impl Default for ClusterStyle {
+    fn default() -> Self {
+        Self {
+            radius: 14.,
+            fill: Color32::from_rgb(230, 50, 50),
+            stroke: Stroke::new(2., Color32::WHITE),
+            font: FontId::proportional(12.),
+            text_color: Color32::WHITE,
+        }
+    }
+}
+
+/// Group of places which are close to each other at given zoom level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cluster {
+    /// Average position of the places.
+    pub position: Position,
+
+    /// Indices of the places, in the order they were given to [`Clusters::new`].
+    pub places: Vec<usize>,
+
+    /// South-west and north-east corners of the area covered by the places. Pass them to
+    /// [`crate::MapMemory::fit_bounds`] to expand the cluster when it is clicked.
+    pub bounds: (Position, Position),
+}
+
+/// [`Plugin`] which draws places like [`super::Places`] does, but groups the ones which would
+/// overlap into clusters, showing how many places they contain. Clusters split up when the map
+/// is zoomed in.
+///
+/// Plugin does not handle the input, but it remembers where the clusters were drawn, so the
+/// application can find the clicked one with [`Clusters::cluster_at`], and zoom into it.
+pub struct Clusters {
+    places: Vec<Place>,
+    style: ClusterStyle,
+    radius: Box<dyn Fn(u8) -> f32>,
+
+    /// Clusters computed for each zoom level.
+    cache: HashMap<u8, Vec<Cluster>>,
+
+    /// Screen positions, radii and indices of the clusters drawn in the last frame, in all copies
+    /// of the world.
+    drawn: Vec<(Pos2, f32, usize)>,
+    drawn_level: u8,
+    drawn_frame: u64,
+}
+
+impl Clusters {
+    pub fn new(places: Vec<Place>) -> Self {
+        Self {
+            places,
+            style: ClusterStyle::default(),
+            radius: Box::new(|_| 40.),
+            cache: HashMap::new(),
+            drawn: Vec::new(),
+            drawn_level: 0,
+            drawn_frame: 0,
+        }
+    }
+
+    pub fn with_style(mut self, style: ClusterStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Use a callback to choose how close (in screen points) places must be to be clustered,
+    /// for each zoom level. Default is 40 points regardless of the zoom. Zero disables
+    /// clustering, e.g. at the highest levels.
+    pub fn with_radius(mut self, radius: impl Fn(u8) -> f32 + 'static) -> Self {
+        self.radius = Box::new(radius);
+        self.cache.clear();
+        self
+    }
+
+    pub fn places(&self) -> &[Place] {
+        &self.places
+    }
+
+    /// Cluster drawn at the screen position in the last frame. Places which are not clustered
+    /// with any other are not reported.
+    pub fn cluster_at(&self, screen_position: Pos2) -> Option<&Cluster> {
+        let clusters = self.cache.get(&self.drawn_level)?;
+        self.drawn
+            .iter()
+            .rev()
+            .find(|(center, radius, _)| center.distance(screen_position) <= *radius)
+            .and_then(|(_, _, index)| clusters.get(*index))
+    }
+}
+
+impl Plugin for Clusters {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let level = projector.zoom().floor() as u8;
+        let clusters = self
+            .cache
+            .entry(level)
+            .or_insert_with(|| cluster(&self.places, level, (self.radius)(level)));
+
+        let frame = painter.ctx().frame_nr();
+        if frame != self.drawn_frame || level != self.drawn_level {
+            self.drawn.clear();
+            self.drawn_frame = frame;
+            self.drawn_level = level;
+        }
+
+        for (index, cluster) in clusters.iter().enumerate() {
+            if let [single] = cluster.places[..] {
+                self.places[single].draw(painter, projector);
+                continue;
+            }
+
+            let center = projector.to_screen(cluster.position);
+            let count = cluster.places.len();
+            let radius = self.style.radius * (1. + (count as f32).log10() / 3.);
+
+            if !painter.clip_rect().expand(radius).contains(center) {
+                continue;
+            }
+
+            painter.circle(center, radius, self.style.fill, self.style.stroke);
+            painter.text(
+                center,
+                Align2::CENTER_CENTER,
+                count.to_string(),
+                self.style.font.clone(),
+                self.style.text_color,
+            );
+            self.drawn.push((center, radius, index));
+        }
+    }
+}
+
+/// Group the places which are closer than `radius` points to each other at the zoom level.
+/// Each place joins the first cluster, which was started within the radius, so the result is
+/// stable as long as the places do not change.
+fn cluster(places: &[Place], level: u8, radius: f32) -> Vec<Cluster> {
+    let points: Vec<_> = places
+        .iter()
+        .map(|place| place.position.project(level as f64))
+        .collect();
+
+    // Places which started the clusters, in a grid of the radius-sized cells, so only the
+    // neighbouring cells need to be searched.
+    let mut seeds: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    let mut members: Vec<(Pos2, Vec<usize>)> = Vec::new();
+    let cell_size = radius.max(f32::EPSILON);
+    let cell = |point: Pos2| {
+        (
+            (point.x / cell_size).floor() as i64,
+            (point.y / cell_size).floor() as i64,
+        )
+    };
+
+    for (index, point) in points.iter().enumerate() {
+        let (x, y) = cell(*point);
+        let existing = (x - 1..=x + 1)
+            .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+            .filter_map(|cell| seeds.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|cluster| members[*cluster].0.distance(*point) < radius)
+            .min();
+
+        match existing {
+            Some(cluster) => members[cluster].1.push(index),
+            None => {
+                seeds.entry((x, y)).or_default().push(members.len());
+                members.push((*point, vec![index]));
+            }
+        }
+    }
+
+    members
+        .into_iter()
+        .map(|(_, indices)| {
+            let positions: Vec<_> = indices.iter().map(|i| places[*i].position).collect();
+            let count = positions.len() as f64;
+            let (mut min, mut max) = (positions[0], positions[0]);
+            for position in &positions {
+                min = Position::new(min.x().min(position.x()), min.y().min(position.y()));
+                max = Position::new(max.x().max(position.x()), max.y().max(position.y()));
+            }
+
+            Cluster {
+                position: Position::new(
+                    positions.iter().map(|p| p.x()).sum::<f64>() / count,
+                    positions.iter().map(|p| p.y()).sum::<f64>() / count,
+                ),
+                places: indices,
+                bounds: (min, max),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extras::Style;
+
+    fn place(x: f64, y: f64) -> Place {
+        Place {
+            position: Position::new(x, y),
+            label: String::new(),
+            style: Style::default(),
+        }
+    }
+
+    #[test]
+    fn nearby_places_are_clustered_until_zoomed_in() {
+        let places = [place(21.0, 52.2), place(21.001, 52.2), place(17.0, 51.1)];
+
+        let clusters = cluster(&places, 10, 40.);
+        assert_eq!(2, clusters.len());
+        assert_eq!(vec![0, 1], clusters[0].places);
+        assert_eq!(vec![2], clusters[1].places);
+        approx::assert_relative_eq!(21.0005, clusters[0].position.x());
+        assert_eq!(
+            (Position::new(21.0, 52.2), Position::new(21.001, 52.2)),
+            clusters[0].bounds
+        );
+
+        assert_eq!(1, cluster(&places, 3, 40.).len());
+        assert_eq!(3, cluster(&places, 18, 40.).len());
+        assert_eq!(3, cluster(&places, 10, 0.).len());
+    }
+
+    #[test]
+    fn finding_the_drawn_cluster() {
+        let ctx = egui::Context::default();
+        let rect = egui::Rect::from_min_size(Pos2::ZERO, egui::Vec2::splat(400.));
+        let mut clusters = Clusters::new(vec![place(21.0, 52.2), place(21.001, 52.2)])
+            .with_radius(|level| if level < 16 { 40. } else { 0. });
+
+        let draw = |clusters: &mut Clusters, zoom| {
+            let _ = ctx.run(Default::default(), |ctx| {
+                let painter = Painter::new(ctx.clone(), egui::LayerId::background(), rect);
+                let projector = Projector::new(rect, Position::new(21.0005, 52.2), zoom);
+                clusters.draw(&painter, &projector);
+            });
+        };
+
+        draw(&mut clusters, 10.);
+        let cluster = clusters.cluster_at(rect.center()).unwrap();
+        assert_eq!(vec![0, 1], cluster.places);
+        assert!(clusters.cluster_at(Pos2::ZERO).is_none());
+
+        // Single places are not clusters.
+        draw(&mut clusters, 18.);
+        assert!(clusters.cluster_at(rect.center()).is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use std::str::FromStr;
+
+use ::geojson::{Feature, FeatureCollection, Value};
+use egui::{Color32, Painter, Shape, Stroke};
+
+use super::shapes::{bounds, overlaps, visible_bounds, Bounds, FilledPolygon};
+use crate::{Plugin, Position, Projector};
+
+/// How a single GeoJSON feature is drawn.
+#[derive(Clone)]
+pub struct FeatureStyle {
+    /// Lines, and outlines of polygons and points.
+    pub stroke: Stroke,
+
+    /// Interior of polygons and points.
+    pub fill: Color32,
+
+    /// Radius of points, in screen pixels.
+    pub point_radius: f32,
+}
+
+impl Default for FeatureStyle {
+    fn default() -> Self {
+        Self {
+            stroke: Stroke::new(2., Color32::from_rgb(50, 100, 230)),
+            fill: Color32::from_rgb(50, 100, 230).gamma_multiply(0.3),
+            point_radius: 5.,
+        }
+    }
+}
+
+/// [`Plugin`] which draws Points, LineStrings and Polygons (including their Multi- variants
+/// and GeometryCollections) of a GeoJSON FeatureCollection. Holes of the polygons are left out
+/// of the fill.
+pub struct GeoJsonLayer {
+    features: FeatureCollection,
+
+    /// Geometries of the features, in the same order, with the polygons already triangulated.
+    geometries: Vec<(Vec<Part>, Bounds)>,
+    style: Box<dyn Fn(&Feature) -> FeatureStyle>,
+}
+
+/// Single point, line or polygon of a feature's geometry.
+enum Part {
+    Point(Position),
+    Line(Vec<Position>),
+    Polygon {
+        rings: Vec<Vec<Position>>,
+        filled: FilledPolygon,
+    },
+}
+
+impl Part {
+    /// Positions which the part's box is made of.
+    fn outline(&self) -> &[Position] {
+        match self {
+            Part::Point(position) => std::slice::from_ref(position),
+            Part::Line(positions) => positions,
+            Part::Polygon { rings, .. } => rings.first().map_or(&[], Vec::as_slice),
+        }
+    }
+}
+
+impl GeoJsonLayer {
+    pub fn new(features: FeatureCollection) -> Self {
+        let geometries = features
+            .features
+            .iter()
+            .map(|feature| {
+                let mut parts = Vec::new();
+                if let Some(geometry) = &feature.geometry {
+                    collect_parts(&geometry.value, &mut parts);
+                }
+                let bounds = bounds(parts.iter().flat_map(Part::outline));
+                (parts, bounds)
+            })
+            .collect();
+
+        Self {
+            features,
+            geometries,
+            style: Box::new(|_| FeatureStyle::default()),
+        }
+    }
+
+    /// Use a callback to choose the style of each feature, e.g. based on its properties.
+    pub fn with_style(mut self, style: impl Fn(&Feature) -> FeatureStyle + 'static) -> Self {
+        self.style = Box::new(style);
+        self
+    }
+}
+
+impl FromStr for GeoJsonLayer {
+    type Err = ::geojson::Error;
+
+    /// Parse a FeatureCollection.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s.parse()?))
+    }
+}
+
+impl Plugin for GeoJsonLayer {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let visible = visible_bounds(projector);
+        for (feature, (parts, bounds)) in self.features.features.iter().zip(&self.geometries) {
+            if overlaps(*bounds, visible) {
+                let style = (self.style)(feature);
+                for part in parts {
+                    draw_part(part, &style, painter, projector);
+                }
+            }
+        }
+    }
+}
+
+/// GeoJSON positions are `[longitude, latitude, (altitude)]`.
+fn to_position(coordinates: &[f64]) -> Option<Position> {
+    match coordinates {
+        [lon, lat, ..] => Some(Position::new(*lon, *lat)),
+        _ => None,
+    }
+}
+
+fn to_positions(coordinates: &[Vec<f64>]) -> Vec<Position> {
+    coordinates
+        .iter()
+        .filter_map(|coordinates| to_position(coordinates))
+        .collect()
+}
+
+fn polygon(rings: &[Vec<Vec<f64>>]) -> Part {
+    let rings: Vec<_> = rings.iter().map(|ring| to_positions(ring)).collect();
+    let filled = match rings.split_first() {
+        Some((exterior, holes)) => FilledPolygon::new(exterior, holes),
+        None => FilledPolygon::new(&[], &[]),
+    };
+    Part::Polygon { rings, filled }
+}
+
+fn collect_parts(value: &Value, parts: &mut Vec<Part>) {
+    match value {
+        Value::Point(point) => parts.extend(to_position(point).map(Part::Point)),
+        Value::MultiPoint(points) => {
+            parts.extend(
+                points
+                    .iter()
+                    .filter_map(|point| to_position(point).map(Part::Point)),
+            );
+        }
+        Value::LineString(line) => parts.push(Part::Line(to_positions(line))),
+        Value::MultiLineString(lines) => {
+            parts.extend(lines.iter().map(|line| Part::Line(to_positions(line))));
+        }
+        Value::Polygon(rings) => parts.push(polygon(rings)),
+        Value::MultiPolygon(polygons) => {
+            parts.extend(polygons.iter().map(|rings| polygon(rings)));
+        }
+        Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                collect_parts(&geometry.value, parts);
+            }
+        }
+    }
+}
+
+fn draw_part(part: &Part, style: &FeatureStyle, painter: &Painter, projector: &Projector) {
+    let to_screen = |positions: &[Position]| -> Vec<_> {
+        positions
+            .iter()
+            .map(|position| projector.to_screen(*position))
+            .collect()
+    };
+
+    match part {
+        Part::Point(position) => {
+            painter.circle(
+                projector.to_screen(*position),
+                style.point_radius,
+                style.fill,
+                style.stroke,
+            );
+        }
+        Part::Line(positions) => {
+            painter.add(Shape::line(to_screen(positions), style.stroke));
+        }
+        Part::Polygon { rings, filled } => {
+            painter.add(filled.mesh(projector, style.fill));
+            for ring in rings {
+                painter.add(Shape::closed_line(to_screen(ring), style.stroke));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_feature_collection() {
+        let layer: GeoJsonLayer = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {},
+                "geometry": { "type": "Point", "coordinates": [17.03664, 51.09916] }
+            }]
+        }"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(1, layer.features.features.len());
+        assert_eq!(1, layer.geometries[0].0.len());
+    }
+
+    #[test]
+    fn polygons_are_triangulated_when_parsed() {
+        let layer: GeoJsonLayer = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {},
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                        [
+                            [[0, 0], [4, 0], [4, 4], [0, 4], [0, 0]],
+                            [[1, 1], [2, 1], [2, 2], [1, 2], [1, 1]]
+                        ],
+                        [[[10, 10], [11, 10], [11, 11], [10, 10]]]
+                    ]
+                }
+            }]
+        }"#
+        .parse()
+        .unwrap();
+
+        let (parts, bounds) = &layer.geometries[0];
+        assert_eq!(2, parts.len());
+        let Part::Polygon { rings, filled } = &parts[0] else {
+            panic!("not a polygon");
+        };
+        assert_eq!(2, rings.len());
+        let projector = Projector::new(
+            egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::splat(500.)),
+            Position::new(2., 2.),
+            5.,
+        );
+        let mesh = filled.mesh(&projector, Color32::RED);
+        assert_eq!(4 + 4 + 2, mesh.vertices.len());
+        assert_eq!(8 * 3, mesh.indices.len());
+        assert_eq!((Position::new(0., 0.), Position::new(11., 11.)), *bounds);
+    }
+
+    #[test]
+    fn parsing_something_else_fails() {
+        assert!(r#"{ "type": "Point", "coordinates": [17.0, 51.0] }"#
+            .parse::<GeoJsonLayer>()
+            .is_err());
+        assert!("garbage".parse::<GeoJsonLayer>().is_err());
+    }
+
+    #[test]
+    fn converting_coordinates() {
+        assert_eq!(
+            Some(Position::new(17., 51.)),
+            to_position(&[17., 51., 120.])
+        );
+        assert_eq!(None, to_position(&[17.]));
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Read;
+use std::path::Path;
+
+use egui::{Color32, Painter, Shape, Stroke};
+
+use super::{Place, Places, Style};
+use crate::{Plugin, Position, Projector};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GpxError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Gpx(#[from] ::gpx::errors::GpxError),
+}
+
+/// [`Plugin`] which draws tracks, routes and waypoints recorded in a GPX file.
+pub struct GpxLayer {
+    /// Track segments and routes.
+    lines: Vec<Vec<Position>>,
+    waypoints: Places,
+    stroke: Stroke,
+}
+
+impl GpxLayer {
+    /// Read GPX data, e.g. from a file or a network stream.
+    pub fn from_reader(reader: impl Read) -> Result<Self, GpxError> {
+        let gpx = ::gpx::read(reader)?;
+
+        let tracks = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| &segment.points);
+        let routes = gpx.routes.iter().map(|route| &route.points);
+
+        let lines = tracks
+            .chain(routes)
+            .map(|points| points.iter().map(|point| point.point()).collect())
+            .collect();
+
+        let waypoints = gpx
+            .waypoints
+            .iter()
+            .map(|waypoint| Place {
+                position: waypoint.point(),
+                label: waypoint.name.clone().unwrap_or_default(),
+                style: Style::default(),
+            })
+            .collect();
+
+        Ok(Self {
+            lines,
+            waypoints: Places::new(waypoints),
+            stroke: Stroke::new(3., Color32::from_rgb(230, 50, 50)),
+        })
+    }
+
+    /// Read a GPX file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, GpxError> {
+        let file = std::fs::File::open(path)?;
+        Self::from_reader(std::io::BufReader::new(file))
+    }
+
+    /// Set the stroke used to draw the tracks and routes.
+    pub fn with_stroke(mut self, stroke: Stroke) -> Self {
+        self.stroke = stroke;
+        self
+    }
+}
+
+impl Plugin for GpxLayer {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        for line in &self.lines {
+            let points = line
+                .iter()
+                .map(|position| projector.to_screen(*position))
+                .collect();
+            painter.add(Shape::line(points, self.stroke));
+        }
+
+        self.waypoints.draw(painter, projector);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_tracks_and_waypoints() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="walkers">
+                <wpt lat="51.09916" lon="17.03664"><name>Wrocław Główny</name></wpt>
+                <trk><trkseg>
+                    <trkpt lat="51.09916" lon="17.03664"></trkpt>
+                    <trkpt lat="51.10005" lon="17.03940"></trkpt>
+                </trkseg></trk>
+            </gpx>"#;
+
+        let layer = GpxLayer::from_reader(gpx.as_bytes()).unwrap();
+        assert_eq!(
+            vec![vec![
+                Position::new(17.03664, 51.09916),
+                Position::new(17.03940, 51.10005)
+            ]],
+            layer.lines
+        );
+    }
+
+    #[test]
+    fn reading_garbage_fails() {
+        assert!(GpxLayer::from_reader("garbage".as_bytes()).is_err());
+        assert!(matches!(
+            GpxLayer::from_path("/does/not/exist.gpx"),
+            Err(GpxError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Shape, Stroke, Vec2};
+
+use crate::{sources::graticule_interval, Plugin, Position, Projector};
+
+/// Plugin which draws the latitude and longitude lines with their degrees, spaced more densely
+/// as the map zooms in.
+#[derive(Clone)]
+pub struct Graticule {
+    pub stroke: Stroke,
+
+    /// Closest (in screen points) the lines can be to each other.
+    pub min_spacing: f32,
+
+    pub font: FontId,
+    pub label_color: Color32,
+    pub label_background: Color32,
+}
+
+impl Default for Graticule {
+    fn default() -> Self {
+        Self {
+            stroke: Stroke::new(1., Color32::from_black_alpha(90)),
+            min_spacing: 80.,
+            font: FontId::proportional(11.),
+            label_color: Color32::from_gray(40),
+            label_background: Color32::from_white_alpha(190),
+        }
+    }
+}
+
+/// Number of straight segments each line is made of, so they follow the projection.
+const SEGMENTS: usize = 32;
+
+/// Distance between the labels and the edges of the map.
+const MARGIN: f32 = 4.;
+
+impl Plugin for Graticule {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let clip_rect = painter.clip_rect();
+        let Some((west, east, south, north)) = visible_bounds(projector, clip_rect) else {
+            return;
+        };
+
+        let degrees_per_point = (east - west) / clip_rect.width().max(1.) as f64;
+        let interval = graticule_interval(degrees_per_point * self.min_spacing as f64);
+        let labels = clip_rect.shrink(MARGIN);
+
+        // Meridians are labeled at the top, and parallels on the left.
+        for longitude in multiples(west, east, interval) {
+            let points = (0..=SEGMENTS).map(|i| {
+                let latitude = north + (south - north) * i as f64 / SEGMENTS as f64;
+                projector.to_screen(Position::new(longitude, latitude))
+            });
+            let label = format_degrees(wrap_longitude(longitude), interval, 'E', 'W');
+            self.line(painter, points, labels, label, Align2::CENTER_TOP);
+        }
+
+        for latitude in multiples(south, north, interval) {
+            let points = (0..=SEGMENTS).map(|i| {
+                let longitude = west + (east - west) * i as f64 / SEGMENTS as f64;
+                projector.to_screen(Position::new(longitude, latitude))
+            });
+            let label = format_degrees(latitude, interval, 'N', 'S');
+            self.line(painter, points, labels, label, Align2::LEFT_CENTER);
+        }
+    }
+
+    /// Lines span all the copies of the world at once.
+    fn repeated(&self) -> bool {
+        false
+    }
+}
+
+impl Graticule {
+    /// Draw the line, with the label at its first point within `labels`.
+    fn line(
+        &self,
+        painter: &Painter,
+        points: impl Iterator<Item = Pos2>,
+        labels: Rect,
+        label: String,
+        align: Align2,
+    ) {
+        let points: Vec<_> = points.collect();
+        painter.add(Shape::line(points.clone(), self.stroke));
+
+        if let Some(anchor) = points.into_iter().find(|point| labels.contains(*point)) {
+            let galley = painter.layout_no_wrap(label, self.font.clone(), self.label_color);
+            let rect = align
+                .anchor_rect(Rect::from_min_size(anchor, galley.size()))
+                .expand(2.);
+            painter.rect_filled(rect, 2., self.label_background);
+            painter.galley(rect.min + Vec2::splat(2.), galley);
+        }
+    }
+}
+
+/// Range of the longitudes and latitudes visible in the `rect`, which longitudes continue beyond
+/// the antimeridian, when the world repeats. Latitudes do not go beyond the projected world.
+fn visible_bounds(projector: &Projector, rect: Rect) -> Option<(f64, f64, f64, f64)> {
+    let corners = [
+        rect.left_top(),
+        rect.right_top(),
+        rect.left_bottom(),
+        rect.right_bottom(),
+    ]
+    .map(|corner| projector.to_position(corner));
+
+    let fold = |value: fn(Position) -> f64, f: fn(f64, f64) -> f64, init: f64| {
+        corners.iter().map(|corner| value(*corner)).fold(init, f)
+    };
+    let west = fold(Position::x, f64::min, f64::INFINITY);
+    let east = fold(Position::x, f64::max, f64::NEG_INFINITY);
+    let south = fold(Position::y, f64::min, f64::INFINITY);
+    let north = fold(Position::y, f64::max, f64::NEG_INFINITY);
+
+    let projection = projector.projection();
+    let edges = [
+        projection.unproject((0.5, 0.)).y(),
+        projection.unproject((0.5, 1.)).y(),
+    ];
+    let south = south.max(edges[0].min(edges[1])).max(-90.);
+    let north = north.min(edges[0].max(edges[1])).min(90.);
+
+    let finite = [west, east, south, north]
+        .iter()
+        .all(|value| value.is_finite());
+    (finite && west < east && south < north).then_some((west, east, south, north))
+}
+
+/// Multiples of the `interval` between `min` and `max`.
+fn multiples(min: f64, max: f64, interval: f64) -> impl Iterator<Item = f64> {
+    let first = (min / interval).ceil() as i64;
+    let last = (max / interval).floor() as i64;
+    (first..=last).map(move |i| i as f64 * interval)
+}
+
+/// Longitude of the original world, for the lines drawn in its copies.
+fn wrap_longitude(longitude: f64) -> f64 {
+    let wrapped = (longitude + 180.).rem_euclid(360.) - 180.;
+    if wrapped == -180. && longitude > 0. {
+        180.
+    } else {
+        wrapped
+    }
+}
+
+/// Degrees with as many decimal places as the `interval` needs, and the hemisphere's letter.
+fn format_degrees(value: f64, interval: f64, positive: char, negative: char) -> String {
+    let decimals = (-interval.log10()).ceil().max(0.) as usize;
+    let magnitude = format!("{:.*}", decimals, value.abs());
+    if value.abs() < interval / 2. || value.abs() == 180. {
+        format!("{}°", magnitude)
+    } else if value > 0. {
+        format!("{}°{}", magnitude, positive)
+    } else {
+        format!("{}°{}", magnitude, negative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiples_of_the_interval() {
+        assert_eq!(
+            vec![-10., 0., 10.],
+            multiples(-15., 12., 10.).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0.5, 1.], multiples(0.3, 1., 0.5).collect::<Vec<_>>());
+        assert_eq!(0, multiples(1., 2., 5.).count());
+    }
+
+    #[test]
+    fn formatting_degrees() {
+        assert_eq!("20°E", format_degrees(20., 10., 'E', 'W'));
+        assert_eq!("45°S", format_degrees(-45., 15., 'N', 'S'));
+        assert_eq!("0°", format_degrees(0., 10., 'E', 'W'));
+        assert_eq!("180°", format_degrees(wrap_longitude(180.), 10., 'E', 'W'));
+        assert_eq!("0.25°N", format_degrees(0.25, 0.05, 'N', 'S'));
+        assert_eq!("170°W", format_degrees(wrap_longitude(190.), 10., 'E', 'W'));
+    }
+
+    #[test]
+    fn bounds_of_the_view() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(512., 256.));
+        let projector = Projector::new(rect, Position::new(180., 0.), 1.);
+
+        let (west, east, south, north) = visible_bounds(&projector, rect).unwrap();
+        approx::assert_relative_eq!(0., west, epsilon = 1e-6);
+        approx::assert_relative_eq!(360., east, epsilon = 1e-6);
+        approx::assert_relative_eq!(-66.51, south, epsilon = 1e-2);
+        approx::assert_relative_eq!(66.51, north, epsilon = 1e-2);
+    }
+}
@@ -0,0 +1,243 @@
+use egui::{pos2, Color32, ColorImage, Painter, Pos2, Rect, TextureHandle, TextureOptions, Vec2};
+
+use crate::{Plugin, Position, Projector};
+
+/// How a [`Heatmap`] looks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeatmapStyle {
+    /// How far (in screen points) from a sample its weight spreads.
+    pub radius: f32,
+
+    /// Size (in screen points) of a single cell of the density grid. Bigger cells are quicker to
+    /// compute, but look blurry.
+    pub cell_size: f32,
+
+    /// Colors for the densities from the lowest to the highest, interpolated linearly. Areas
+    /// without any samples are transparent.
+    pub gradient: Vec<Color32>,
+
+    /// Density drawn with the last color of the gradient. `None` means the highest density
+    /// currently visible, which shows the distribution well, but makes colors change when the
+    /// map moves.
+    pub max_density: Option<f32>,
+}
+
+impl Default for HeatmapStyle {
+    fn default() -> Self {
+        Self {
+            radius: 25.,
+            cell_size: 4.,
+            gradient: vec![
+                Color32::from_rgba_unmultiplied(0, 0, 255, 0),
+                Color32::from_rgba_unmultiplied(0, 255, 255, 150),
+                Color32::from_rgba_unmultiplied(0, 255, 0, 180),
+                Color32::from_rgba_unmultiplied(255, 255, 0, 200),
+                Color32::from_rgba_unmultiplied(255, 0, 0, 220),
+            ],
+            max_density: None,
+        }
+    }
+}
+
+/// View for which the texture was computed.
+#[derive(PartialEq)]
+struct View {
+    style: HeatmapStyle,
+    clip_rect: Rect,
+    center: Position,
+    zoom: f64,
+    rotation: f32,
+}
+
+/// [`Plugin`] which draws the density of weighted samples as a colored overlay. It is computed
+/// into a texture, which is reused as long as the map does not move.
+pub struct Heatmap {
+    pub style: HeatmapStyle,
+    samples: Vec<(Position, f32)>,
+    texture: Option<(View, TextureHandle)>,
+}
+
+impl Heatmap {
+    /// Heatmap of the samples given as positions with their weights.
+    pub fn new(samples: Vec<(Position, f32)>) -> Self {
+        Self {
+            style: HeatmapStyle::default(),
+            samples,
+            texture: None,
+        }
+    }
+
+    pub fn with_style(mut self, style: HeatmapStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Replace the samples, e.g. when new telemetry arrives.
+    pub fn set_samples(&mut self, samples: Vec<(Position, f32)>) {
+        self.samples = samples;
+        self.texture = None;
+    }
+}
+
+impl Plugin for Heatmap {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let clip_rect = painter.clip_rect();
+        let view = View {
+            style: self.style.clone(),
+            clip_rect,
+            center: projector.to_position(clip_rect.center()),
+            zoom: projector.zoom(),
+            rotation: projector.rotation(),
+        };
+
+        let texture = match &self.texture {
+            Some((cached, texture)) if *cached == view => texture.clone(),
+            _ => {
+                let image = render(&self.samples, projector, clip_rect, &self.style);
+                let texture =
+                    painter
+                        .ctx()
+                        .load_texture("walkers_heatmap", image, TextureOptions::LINEAR);
+                self.texture = Some((view, texture.clone()));
+                texture
+            }
+        };
+
+        painter.image(
+            texture.id(),
+            clip_rect,
+            Rect::from_min_max(pos2(0., 0.), pos2(1., 1.)),
+            Color32::WHITE,
+        );
+    }
+
+    /// Copies of the world are rendered into the same texture.
+    fn repeated(&self) -> bool {
+        false
+    }
+}
+
+/// Colored density of the samples covering the `rect`.
+fn render(
+    samples: &[(Position, f32)],
+    projector: &Projector,
+    rect: Rect,
+    style: &HeatmapStyle,
+) -> ColorImage {
+    let cell_size = style.cell_size.max(1.);
+    let size = [
+        (rect.width() / cell_size).ceil().max(1.) as usize,
+        (rect.height() / cell_size).ceil().max(1.) as usize,
+    ];
+
+    let points: Vec<_> = projector
+        .world_copies()
+        .iter()
+        .flat_map(|copy| {
+            samples.iter().map(move |(position, weight)| {
+                let offset = copy.to_screen(*position) - rect.min;
+                ((offset / cell_size).to_pos2(), *weight)
+            })
+        })
+        .collect();
+
+    let density = density(&points, size, style.radius / cell_size);
+    let max = style
+        .max_density
+        .unwrap_or_else(|| density.iter().copied().fold(0., f32::max));
+
+    let pixels = density
+        .iter()
+        .map(|value| {
+            if *value <= 0. || max <= 0. {
+                Color32::TRANSPARENT
+            } else {
+                gradient_color(&style.gradient, value / max)
+            }
+        })
+        .collect();
+
+    ColorImage { size, pixels }
+}
+
+/// Density grid of `size` cells, with each point adding its weight within the `radius` (both
+/// given in cells), falling off smoothly towards the edge.
+fn density(points: &[(Pos2, f32)], size: [usize; 2], radius: f32) -> Vec<f32> {
+    let mut grid = vec![0.; size[0] * size[1]];
+    let radius = radius.max(1.);
+    let bounds = Rect::from_min_size(Pos2::ZERO, Vec2::new(size[0] as f32, size[1] as f32));
+
+    for (point, weight) in points {
+        if !bounds.expand(radius).contains(*point) {
+            continue;
+        }
+
+        let range = |center: f32, len: usize| {
+            let min = (center - radius).floor().max(0.) as usize;
+            let max = ((center + radius).ceil().max(0.) as usize).min(len);
+            min..max
+        };
+
+        for y in range(point.y, size[1]) {
+            for x in range(point.x, size[0]) {
+                let cell_center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let distance = cell_center.distance(*point) / radius;
+                if distance < 1. {
+                    let falloff = 1. - distance * distance;
+                    grid[y * size[0] + x] += weight * falloff * falloff;
+                }
+            }
+        }
+    }
+
+    grid
+}
+
+/// Color at `t` (between 0 and 1) of the gradient.
+fn gradient_color(gradient: &[Color32], t: f32) -> Color32 {
+    let Some(last) = gradient.len().checked_sub(1) else {
+        return Color32::TRANSPARENT;
+    };
+
+    let position = t.clamp(0., 1.) * last as f32;
+    let index = (position.floor() as usize).min(last.saturating_sub(1));
+    let (a, b) = (gradient[index], gradient[(index + 1).min(last)]);
+    let t = position - index as f32;
+
+    let [ar, ag, ab, aa] = a.to_srgba_unmultiplied();
+    let [br, bg, bb, ba] = b.to_srgba_unmultiplied();
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color32::from_rgba_unmultiplied(lerp(ar, br), lerp(ag, bg), lerp(ab, bb), lerp(aa, ba))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn density_is_highest_at_the_samples() {
+        let points = [(Pos2::new(5., 5.), 1.), (Pos2::new(5.5, 5.), 2.)];
+        let density = density(&points, [20, 10], 3.);
+        let at = |x: usize, y: usize| density[y * 20 + x];
+
+        assert!(at(5, 4) > at(6, 4));
+        assert!(at(5, 4) > at(5, 2));
+        assert_eq!(0., at(15, 5));
+
+        // Points outside still spread into the grid.
+        let density = super::density(&[(Pos2::new(-1., 5.), 1.)], [20, 10], 3.);
+        assert!(density[5 * 20] > 0.);
+    }
+
+    #[test]
+    fn gradient_is_interpolated() {
+        let gradient = [Color32::BLACK, Color32::WHITE, Color32::RED];
+        assert_eq!(Color32::BLACK, gradient_color(&gradient, 0.));
+        assert_eq!(Color32::WHITE, gradient_color(&gradient, 0.5));
+        assert_eq!(Color32::RED, gradient_color(&gradient, 1.));
+        assert_eq!(Color32::RED, gradient_color(&gradient, 7.));
+        assert_eq!(Color32::from_gray(64), gradient_color(&gradient, 0.125));
+        assert_eq!(Color32::TRANSPARENT, gradient_color(&[], 0.5));
+        assert_eq!(Color32::RED, gradient_color(&[Color32::RED], 0.5));
+    }
+}
@@ -0,0 +1,148 @@
+use egui::{
+    epaint::Vertex, Color32, ColorImage, Mesh, Painter, Pos2, TextureHandle, TextureId,
+    TextureOptions,
+};
+
+use crate::{Plugin, Position, Projector};
+
+/// Each edge of the image is split into this many segments, so it follows the projection.
+const SEGMENTS: usize = 16;
+
+/// [`Plugin`] which stretches a georeferenced image over the map, e.g. a weather radar, a scan of
+/// a historical map or a drone orthophoto. Its pixels are assumed to be evenly spaced in degrees
+/// of longitude and latitude.
+pub struct ImageOverlay {
+    image: Option<ColorImage>,
+    texture: Option<TextureHandle>,
+
+    /// Positions of the image's top-left, top-right, bottom-right and bottom-left corners.
+    corners: [Position; 4],
+
+    /// Between 0 (invisible) and 1 (opaque).
+    pub opacity: f32,
+}
+
+impl ImageOverlay {
+    /// Image stretched over the quad given by the positions of its top-left, top-right,
+    /// bottom-right and bottom-left corners, which does not have to follow the meridians, e.g. for
+    /// a rotated scan.
+    pub fn new(image: ColorImage, corners: [Position; 4]) -> Self {
+        Self {
+            image: Some(image),
+            texture: None,
+            corners,
+            opacity: 1.,
+        }
+    }
+
+    /// Image covering the box between the north-west and south-east positions.
+    pub fn bounds(image: ColorImage, north_west: Position, south_east: Position) -> Self {
+        Self::new(
+            image,
+            [
+                north_west,
+                Position::new(south_east.x(), north_west.y()),
+                south_east,
+                Position::new(north_west.x(), south_east.y()),
+            ],
+        )
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0., 1.);
+        self
+    }
+
+    /// Replace the image, e.g. with the next frame of a radar animation.
+    pub fn set_image(&mut self, image: ColorImage) {
+        match &mut self.texture {
+            Some(texture) => texture.set(image, TextureOptions::LINEAR),
+            None => self.image = Some(image),
+        }
+    }
+}
+
+impl Plugin for ImageOverlay {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        if let Some(image) = self.image.take() {
+            self.texture = Some(painter.ctx().load_texture(
+                "walkers_image_overlay",
+                image,
+                TextureOptions::LINEAR,
+            ));
+        }
+
+        if let Some(texture) = &self.texture {
+            let tint = Color32::WHITE.linear_multiply(self.opacity);
+            painter.add(mesh(&self.corners, projector, texture.id(), tint));
+        }
+    }
+}
+
+/// Grid of the image's parts, with the positions of their corners interpolated between the
+/// corners of the whole image.
+fn mesh(corners: &[Position; 4], projector: &Projector, texture: TextureId, tint: Color32) -> Mesh {
+    let [top_left, top_right, bottom_right, bottom_left] = *corners;
+    let lerp = |a: Position, b: Position, t: f64| {
+        Position::new(a.x() + (b.x() - a.x()) * t, a.y() + (b.y() - a.y()) * t)
+    };
+
+    let mut mesh = Mesh::with_texture(texture);
+    for row in 0..=SEGMENTS {
+        let v = row as f64 / SEGMENTS as f64;
+        let left = lerp(top_left, bottom_left, v);
+        let right = lerp(top_right, bottom_right, v);
+        for column in 0..=SEGMENTS {
+            let u = column as f64 / SEGMENTS as f64;
+            mesh.vertices.push(Vertex {
+                pos: projector.to_screen(lerp(left, right, u)),
+                uv: Pos2::new(u as f32, v as f32),
+                color: tint,
+            });
+        }
+    }
+
+    let index = |row: usize, column: usize| (row * (SEGMENTS + 1) + column) as u32;
+    for row in 0..SEGMENTS {
+        for column in 0..SEGMENTS {
+            let (a, b) = (index(row, column), index(row, column + 1));
+            let (c, d) = (index(row + 1, column + 1), index(row + 1, column));
+            mesh.indices.extend([a, b, c, a, c, d]);
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Rect, Vec2};
+
+    #[test]
+    fn image_is_stretched_over_the_corners() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.));
+        let projector = Projector::new(rect, Position::new(21., 52.), 10.);
+        let overlay = ImageOverlay::bounds(
+            ColorImage::new([2, 2], Color32::RED),
+            Position::new(20.9, 52.05),
+            Position::new(21.1, 51.95),
+        );
+
+        let mesh = mesh(
+            &overlay.corners,
+            &projector,
+            TextureId::User(0),
+            Color32::WHITE,
+        );
+        assert!(mesh.is_valid());
+        assert_eq!((SEGMENTS + 1) * (SEGMENTS + 1), mesh.vertices.len());
+
+        let first = mesh.vertices.first().unwrap();
+        let last = mesh.vertices.last().unwrap();
+        assert_eq!(projector.to_screen(Position::new(20.9, 52.05)), first.pos);
+        assert_eq!(Pos2::ZERO, first.uv);
+        assert_eq!(Pos2::new(1., 1.), last.uv);
+        assert!(first.pos.x < 200. && first.pos.y < 200.);
+        assert!(last.pos.x > 200. && last.pos.y > 200.);
+    }
+}
@@ -0,0 +1,32 @@
+//! Ready-made plugins, covering the most common use cases.
+mod clusters;
+#[cfg(feature = "geojson")]
+mod geojson;
+#[cfg(feature = "gpx")]
+mod gpx;
+mod graticule;
+mod heatmap;
+mod image_overlay;
+mod moving_markers;
+mod my_position;
+mod places;
+mod route;
+mod scale_bar;
+pub(crate) mod shapes;
+mod trail;
+
+#[cfg(feature = "geojson")]
+pub use self::geojson::{FeatureStyle, GeoJsonLayer};
+#[cfg(feature = "gpx")]
+pub use self::gpx::{GpxError, GpxLayer};
+pub use clusters::{Cluster, ClusterStyle, Clusters};
+pub use graticule::Graticule;
+pub use heatmap::{Heatmap, HeatmapStyle};
+pub use image_overlay::ImageOverlay;
+pub use moving_markers::{Easing, MovingMarkers};
+pub use my_position::MyPositionMarker;
+pub use places::{Place, Places, Style};
+pub use route::{decode_polyline, PolylineError, Route};
+pub use scale_bar::{ScaleBar, Units};
+pub use shapes::Shapes;
+pub use trail::Trail;
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+use egui::Painter;
+use web_time::Instant;
+
+use crate::{extras::MyPositionMarker, Plugin, Position, PositionExtra, Projector};
+
+/// How the markers move between the received positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    /// At a constant speed, which suits the positions received at regular intervals.
+    #[default]
+    Linear,
+
+    /// Speeding up at the start, and slowing down at the end.
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// Longest a single move is animated, when an update comes after a long break.
+const MAX_DURATION: Duration = Duration::from_secs(5);
+
+struct Marker {
+    from: (Position, Option<f32>),
+    to: (Position, Option<f32>),
+    started: Instant,
+
+    /// How long the move takes, which is the time between the last two updates.
+    duration: Duration,
+}
+
+/// [`Plugin`] which draws markers, e.g. of live tracked vehicles, moving smoothly between the
+/// positions given by [`MovingMarkers::update`], instead of jumping once per received update.
+/// Each move takes as long as the time since the previous update, so the markers arrive when the
+/// next one is expected. Keep it between frames and pass it to the map by `&mut`.
+pub struct MovingMarkers<K> {
+    markers: HashMap<K, Marker>,
+
+    /// How the markers look. Headings are drawn as arrows.
+    pub style: MyPositionMarker,
+
+    pub easing: Easing,
+
+    /// Keep moving the markers in the same direction and speed after they arrive, for at most
+    /// this long, until the next update comes. `None` (the default) stops them.
+    pub dead_reckoning: Option<Duration>,
+}
+
+impl<K> Default for MovingMarkers<K> {
+    fn default() -> Self {
+        Self {
+            markers: HashMap::new(),
+            style: MyPositionMarker::default(),
+            easing: Easing::default(),
+            dead_reckoning: None,
+        }
+    }
+}
+
+impl<K: Eq + Hash> MovingMarkers<K> {
+    /// Move the marker to the position, with the heading in degrees clockwise from the north.
+    /// Marker which is not there yet appears at the position right away.
+    pub fn update(&mut self, key: K, position: Position, heading: Option<f32>) {
+        self.update_at(key, position, heading, Instant::now());
+    }
+
+    fn update_at(&mut self, key: K, position: Position, heading: Option<f32>, now: Instant) {
+        let marker = match self.markers.get(&key) {
+            Some(marker) => Marker {
+                from: self.state(marker, now),
+                to: (position, heading),
+                started: now,
+                duration: now.duration_since(marker.started).min(MAX_DURATION),
+            },
+            None => Marker {
+                from: (position, heading),
+                to: (position, heading),
+                started: now,
+                duration: Duration::ZERO,
+            },
+        };
+        self.markers.insert(key, marker);
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        self.markers.remove(key);
+    }
+
+    /// Where the marker is drawn now.
+    pub fn position(&self, key: &K) -> Option<Position> {
+        let marker = self.markers.get(key)?;
+        Some(self.state(marker, Instant::now()).0)
+    }
+
+    /// Position and heading of the marker at given time.
+    fn state(&self, marker: &Marker, now: Instant) -> (Position, Option<f32>) {
+        let elapsed = now.duration_since(marker.started).as_secs_f64();
+        let duration = marker.duration.as_secs_f64();
+        let ((from, from_heading), (to, to_heading)) = (marker.from, marker.to);
+
+        let t = if duration <= 0. {
+            1.
+        } else if elapsed < duration {
+            self.easing.apply(elapsed / duration)
+        } else {
+            // Ahead of the last position, as far as it would have gone at the same speed.
+            let extra = elapsed.min(duration + self.dead_reckoning.map_or(0., |d| d.as_secs_f64()));
+            extra / duration
+        };
+
+        let position = Position::new(
+            from.x() + (to.x() - from.x()) * t,
+            from.y() + (to.y() - from.y()) * t,
+        );
+        let heading = match (from_heading, to_heading) {
+            (Some(from), Some(to)) => {
+                let turn = (to - from + 540.).rem_euclid(360.) - 180.;
+                Some(from + turn * t.min(1.) as f32)
+            }
+            (_, heading) => heading,
+        };
+        (position, heading)
+    }
+
+    /// Whether any of the markers is still moving.
+    fn moving(&self, now: Instant) -> bool {
+        let reckoning = self.dead_reckoning.unwrap_or_default();
+        self.markers
+            .values()
+            .any(|marker| now.duration_since(marker.started) < marker.duration + reckoning)
+    }
+}
+
+impl<K: Eq + Hash> Plugin for MovingMarkers<K> {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let now = Instant::now();
+        for marker in self.markers.values() {
+            let (position, heading) = self.state(marker, now);
+            let fix = PositionExtra {
+                heading,
+                ..PositionExtra::new(position)
+            };
+            self.style.draw(painter, projector, &fix);
+        }
+
+        if self.moving(now) {
+            painter.ctx().request_repaint();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_position(expected: (f64, f64), actual: Position) {
+        approx::assert_relative_eq!(expected.0, actual.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(expected.1, actual.y(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn markers_move_between_the_updates() {
+        let mut markers = MovingMarkers::default();
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+
+        markers.update_at("bus", Position::new(21., 52.), Some(350.), start);
+        let state = |markers: &MovingMarkers<_>, at| markers.state(&markers.markers["bus"], at);
+        assert_position((21., 52.), state(&markers, start).0);
+
+        // Next update comes after 2 seconds, so that is how long the move takes.
+        markers.update_at(
+            "bus",
+            Position::new(21.2, 52.),
+            Some(10.),
+            start + 2 * second,
+        );
+        let (position, heading) = state(&markers, start + 3 * second);
+        assert_position((21.1, 52.), position);
+        approx::assert_relative_eq!(360., heading.unwrap());
+        assert!(markers.moving(start + 3 * second));
+
+        // Marker stops at the last position.
+        assert_position((21.2, 52.), state(&markers, start + 10 * second).0);
+        assert!(!markers.moving(start + 10 * second));
+    }
+
+    #[test]
+    fn dead_reckoning_keeps_the_marker_going() {
+        let mut markers = MovingMarkers {
+            dead_reckoning: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+
+        markers.update_at(1, Position::new(21., 52.), None, start);
+        markers.update_at(1, Position::new(21.1, 52.), None, start + second);
+
+        let state = |at| markers.state(&markers.markers[&1], at).0;
+        assert_position((21.1, 52.), state(start + 2 * second));
+        assert_position((21.2, 52.), state(start + 3 * second));
+        assert_position((21.2, 52.), state(start + 9 * second));
+    }
+
+    #[test]
+    fn easing() {
+        assert_eq!(0.5, Easing::EaseInOut.apply(0.5));
+        assert!(Easing::EaseInOut.apply(0.1) < 0.1);
+        assert_eq!(1., Easing::EaseInOut.apply(1.));
+    }
+}
@@ -0,0 +1,112 @@
+use egui::{emath::Rot2, Color32, Painter, Shape, Stroke, Vec2};
+
+use crate::{mercator::meters_per_point, Position, PositionExtra, Projector};
+
+/// Marker of "my position", which the [`crate::Map`] draws on top of the tiles and plugins. Use
+/// [`crate::Map::my_position_marker`] to change or disable it. The heading, if given with
+/// [`crate::Map::my_heading`] or [`crate::Map::my_position_extra`], is drawn as an arrow.
+/// Accuracy of the latter takes precedence over [`MyPositionMarker::accuracy`].
+#[derive(Clone, Debug)]
+pub struct MyPositionMarker {
+    /// How accurate the position is, in meters. Drawn as a translucent circle around the dot.
+    pub accuracy: Option<f32>,
+
+    pub radius: f32,
+    pub fill: Color32,
+    pub stroke: Stroke,
+    pub accuracy_fill: Color32,
+    pub accuracy_stroke: Stroke,
+}
+
+impl Default for MyPositionMarker {
+    fn default() -> Self {
+        let blue = Color32::from_rgb(30, 120, 230);
+        Self {
+            accuracy: None,
+            radius: 7.,
+            fill: blue,
+            stroke: Stroke::new(2.5, Color32::WHITE),
+            accuracy_fill: blue.gamma_multiply(0.15),
+            accuracy_stroke: Stroke::new(1., blue.gamma_multiply(0.5)),
+        }
+    }
+}
+
+impl MyPositionMarker {
+    /// Radius of the accuracy circle in screen points, unless it would be hidden by the dot.
+    fn accuracy_radius(&self, accuracy: Option<f32>, position: Position, zoom: f64) -> Option<f32> {
+        let accuracy = accuracy.or(self.accuracy)?;
+        let radius = accuracy / meters_per_point(position.y(), zoom) as f32;
+        (radius > self.radius).then_some(radius)
+    }
+
+    pub(crate) fn draw(&self, painter: &Painter, projector: &Projector, fix: &PositionExtra) {
+        let PositionExtra {
+            position,
+            accuracy,
+            heading,
+            ..
+        } = *fix;
+        let center = projector.to_screen(position);
+
+        if let Some(radius) = self.accuracy_radius(accuracy, position, projector.zoom()) {
+            painter.circle(center, radius, self.accuracy_fill, self.accuracy_stroke);
+        }
+
+        if let Some(heading) = heading {
+            let rotation = Rot2::from_angle(heading.to_radians() + projector.rotation());
+            let point = |x: f32, y: f32| center + rotation * Vec2::new(x, y);
+            let tip = self.radius * 2.2;
+            let width = self.radius * 0.9;
+            painter.add(Shape::convex_polygon(
+                vec![
+                    point(0., -tip),
+                    point(width, -self.radius * 0.6),
+                    point(-width, -self.radius * 0.6),
+                ],
+                self.fill,
+                Stroke::NONE,
+            ));
+        }
+
+        painter.circle(center, self.radius, self.fill, self.stroke);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accuracy_circle_is_scaled_with_zoom() {
+        let position = Position::new(21.00027, 52.26470);
+        let marker = MyPositionMarker {
+            accuracy: Some(100.),
+            ..Default::default()
+        };
+
+        let radius = marker.accuracy_radius(None, position, 16.).unwrap();
+        approx::assert_relative_eq!(
+            2. * radius,
+            marker.accuracy_radius(None, position, 17.).unwrap()
+        );
+
+        // Circle smaller than the dot is not drawn.
+        assert_eq!(None, marker.accuracy_radius(None, position, 10.));
+        assert_eq!(
+            None,
+            MyPositionMarker::default().accuracy_radius(None, position, 16.)
+        );
+
+        // Accuracy of the fix is used instead of the marker's one.
+        let default = MyPositionMarker::default();
+        assert_eq!(
+            Some(radius),
+            default.accuracy_radius(Some(100.), position, 16.)
+        );
+        approx::assert_relative_eq!(
+            radius / 2.,
+            marker.accuracy_radius(Some(50.), position, 16.).unwrap()
+        );
+    }
+}
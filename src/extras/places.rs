@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Response, Shape, Stroke, Vec2};
+
+use crate::{Plugin, Position, Projector};
+
+/// Visual style of a [`Place`].
+#[derive(Clone)]
+pub struct Style {
+    pub label_font: FontId,
+    pub label_color: Color32,
+    pub label_background: Color32,
+
+    /// Distance between the pin and the label.
+    pub label_offset: Vec2,
+
+    pub pin_radius: f32,
+    pub pin_color: Color32,
+    pub pin_stroke: Stroke,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            label_font: FontId::proportional(12.),
+            label_color: Color32::from_gray(200),
+            label_background: Color32::BLACK.gamma_multiply(0.8),
+            label_offset: Vec2::new(10., 0.),
+            pin_radius: 6.,
+            pin_color: Color32::from_rgb(230, 50, 50),
+            pin_stroke: Stroke::new(2., Color32::WHITE),
+        }
+    }
+}
+
+/// A single place, drawn as a pin with a label next to it.
+#[derive(Clone)]
+pub struct Place {
+    pub position: Position,
+    pub label: String,
+    pub style: Style,
+}
+
+impl Place {
+    pub(crate) fn draw(&self, painter: &Painter, projector: &Projector) {
+        let screen_position = projector.to_screen(self.position);
+        let label = self.label(painter, screen_position);
+        self.draw_at(painter, screen_position, Some(label));
+    }
+
+    /// Text of the label, next to the pin at the screen position.
+    fn label(&self, painter: &Painter, screen_position: Pos2) -> Shape {
+        painter.fonts(|fonts| {
+            Shape::text(
+                fonts,
+                screen_position + self.style.label_offset,
+                Align2::LEFT_CENTER,
+                &self.label,
+                self.style.label_font.clone(),
+                self.style.label_color,
+            )
+        })
+    }
+
+    /// Screen area taken by the label, along with its background.
+    fn label_rect(label: &Shape) -> Rect {
+        label.visual_bounding_rect().expand(4.)
+    }
+
+    fn draw_at(&self, painter: &Painter, screen_position: Pos2, label: Option<Shape>) {
+        let pin = Rect::from_center_size(
+            screen_position,
+            Vec2::splat(2. * (self.style.pin_radius + self.style.pin_stroke.width)),
+        );
+
+        // Painter clips anyway, but this saves tessellating places which are far away.
+        let bounding_rect = label
+            .as_ref()
+            .map_or(pin, |label| Self::label_rect(label).expand(1.).union(pin));
+        if !painter.clip_rect().intersects(bounding_rect) {
+            return;
+        }
+
+        if let Some(label) = label {
+            painter.rect_filled(Self::label_rect(&label), 4., self.style.label_background);
+            painter.add(label);
+        }
+
+        painter.circle(
+            screen_position,
+            self.style.pin_radius,
+            self.style.pin_color,
+            self.style.pin_stroke,
+        );
+    }
+}
+
+/// Importance of a place, see [`Places::hide_overlapping_labels`].
+type Ranking = Arc<dyn Fn(&Place) -> f64 + Send + Sync>;
+
+/// [`Plugin`] which draws a list of places. It also reports which one of them was clicked or is
+/// hovered, and can let the user drag them around. For that, keep it between frames and pass
+/// it to the map by `&mut`.
+#[derive(Clone, Default)]
+pub struct Places {
+    places: Vec<Place>,
+    draggable: bool,
+    ranking: Option<Ranking>,
+
+    /// Place being dragged.
+    dragging: Option<usize>,
+
+    hovered: Option<usize>,
+    clicked: Option<usize>,
+    dragged: Option<usize>,
+}
+
+impl Places {
+    pub fn new(places: Vec<Place>) -> Self {
+        Self {
+            places,
+            ..Default::default()
+        }
+    }
+
+    /// Let the user drag the places to new positions.
+    pub fn draggable(mut self, draggable: bool) -> Self {
+        self.draggable = draggable;
+        self
+    }
+
+    /// Do not draw the labels which would overlap others, so dense places stay readable when the
+    /// map is zoomed out. Labels of the places ranked higher win, and the ties go to the ones
+    /// earlier in the list. Pins are always drawn. E.g. rank cities by their population:
+    /// `places.hide_overlapping_labels(|place| population(&place.label))`.
+    pub fn hide_overlapping_labels(
+        mut self,
+        ranking: impl Fn(&Place) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        self.ranking = Some(Arc::new(ranking));
+        self
+    }
+
+    pub fn places(&self) -> &[Place] {
+        &self.places
+    }
+
+    /// Index of the place under the mouse pointer, in the last frame.
+    pub fn hovered(&self) -> Option<usize> {
+        self.hovered
+    }
+
+    /// Index of the place clicked in the last frame.
+    pub fn clicked(&self) -> Option<usize> {
+        self.clicked
+    }
+
+    /// Index and new position of the place which was dragged in the last frame.
+    pub fn dragged(&self) -> Option<(usize, Position)> {
+        self.dragged
+            .map(|index| (index, self.places[index].position))
+    }
+
+    /// Place whose pin is at the screen point, in any copy of the world. Ones drawn later are on
+    /// top.
+    fn place_at(&self, point: Pos2, projector: &Projector) -> Option<usize> {
+        let copies = projector.world_copies();
+        self.places.iter().rposition(|place| {
+            let radius = place.style.pin_radius + place.style.pin_stroke.width;
+            copies
+                .iter()
+                .any(|copy| copy.to_screen(place.position).distance(point) <= radius)
+        })
+    }
+}
+
+impl Plugin for Places {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let Some(ranking) = &self.ranking else {
+            for place in &self.places {
+                place.draw(painter, projector);
+            }
+            return;
+        };
+
+        let labeled: Vec<_> = self
+            .places
+            .iter()
+            .map(|place| {
+                let screen_position = projector.to_screen(place.position);
+                (screen_position, place.label(painter, screen_position))
+            })
+            .collect();
+
+        // Labels which are not visible cannot hide the others.
+        let rects: Vec<_> = labeled
+            .iter()
+            .map(|(_, label)| Place::label_rect(label))
+            .map(|rect| rect.intersects(painter.clip_rect()).then_some(rect))
+            .collect();
+        let ranks: Vec<_> = self.places.iter().map(|place| ranking(place)).collect();
+        let shown = non_overlapping(&rects, &ranks);
+
+        for ((place, (screen_position, label)), shown) in self.places.iter().zip(labeled).zip(shown)
+        {
+            place.draw_at(painter, screen_position, Some(label).filter(|_| shown));
+        }
+    }
+
+    fn input(&mut self, response: &Response, projector: &Projector) -> bool {
+        self.hovered = response
+            .hover_pos()
+            .and_then(|pointer| self.place_at(pointer, projector));
+        self.clicked = response
+            .interact_pointer_pos()
+            .filter(|_| response.clicked())
+            .and_then(|pointer| self.place_at(pointer, projector));
+        self.dragged = None;
+
+        if !self.draggable || !response.dragged() {
+            self.dragging = None;
+            return false;
+        }
+
+        let Some(pointer) = response.interact_pointer_pos() else {
+            return false;
+        };
+
+        if response.drag_started() {
+            // Drag is recognized only after the pointer moved a bit.
+            self.dragging = self.place_at(pointer - response.drag_delta(), projector);
+        }
+
+        let Some(index) = self.dragging.filter(|index| *index < self.places.len()) else {
+            return false;
+        };
+
+        // Place is dragged in the copy of the world, which is the closest to the pointer.
+        let place = &mut self.places[index];
+        let screen_position = projector
+            .world_copies()
+            .iter()
+            .map(|copy| copy.to_screen(place.position))
+            .min_by(|a, b| a.distance(pointer).total_cmp(&b.distance(pointer)))
+            .unwrap_or(pointer)
+            + response.drag_delta();
+        place.position = projector.wrap(projector.to_position(screen_position));
+        self.dragged = Some(index);
+        true
+    }
+}
+
+/// Which of the rectangles (`None` for the ones which do not count) to keep, so none of them
+/// overlap, preferring the higher ranks.
+fn non_overlapping(rects: &[Option<Rect>], ranks: &[f64]) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by(|a, b| ranks[*b].total_cmp(&ranks[*a]));
+
+    let mut kept: Vec<Rect> = Vec::new();
+    let mut shown = vec![false; rects.len()];
+    for index in order {
+        let Some(rect) = rects[index] else {
+            continue;
+        };
+        if !kept.iter().any(|other| other.intersects(rect)) {
+            kept.push(rect);
+            shown[index] = true;
+        }
+    }
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finding_the_place_at_screen_position() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.);
+        let place = |position| Place {
+            position,
+            label: String::new(),
+            style: Style::default(),
+        };
+        let places = Places::new(vec![
+            place(center),
+            place(projector.to_position(rect.center() + Vec2::new(4., 0.))),
+            place(projector.to_position(Pos2::new(10., 10.))),
+        ]);
+
+        // Overlapping pins, the top one wins.
+        assert_eq!(Some(1), places.place_at(rect.center(), &projector));
+        assert_eq!(
+            Some(0),
+            places.place_at(rect.center() - Vec2::new(6., 0.), &projector)
+        );
+        assert_eq!(Some(2), places.place_at(Pos2::new(12., 8.), &projector));
+        assert_eq!(None, places.place_at(Pos2::new(200., 100.), &projector));
+    }
+
+    #[test]
+    fn overlapping_labels_are_hidden_by_rank() {
+        let rect = |x: f32| Some(Rect::from_min_size(Pos2::new(x, 0.), Vec2::new(50., 10.)));
+        let rects = [rect(0.), rect(40.), rect(80.), rect(200.), None];
+
+        // Middle one is the most important, so both its neighbours go.
+        assert_eq!(
+            vec![false, true, false, true, false],
+            non_overlapping(&rects, &[1., 2., 1., 0., 5.])
+        );
+
+        // Ties go to the earlier ones.
+        assert_eq!(
+            vec![true, false, true, true, false],
+            non_overlapping(&rects, &[0.; 5])
+        );
+    }
+}
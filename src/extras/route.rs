@@ -0,0 +1,250 @@
+use egui::{Color32, Painter, Pos2, Shape, Stroke, Vec2};
+
+use crate::{Plugin, Position, Projector};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum PolylineError {
+    #[error("invalid character {0:?} in the polyline")]
+    InvalidCharacter(char),
+
+    #[error("polyline ends in the middle of a number")]
+    Truncated,
+
+    #[error("number in the polyline is too long")]
+    Overflow,
+}
+
+/// Positions of the [encoded polyline], as given by the Google's Directions API (with the
+/// `precision` of 5 decimal digits) or by OSRM and Valhalla (with 5 or 6, depending on their
+/// `geometries` option).
+///
+/// [encoded polyline]: https://developers.google.com/maps/documentation/utilities/polylinealgorithm
+pub fn decode_polyline(encoded: &str, precision: u32) -> Result<Vec<Position>, PolylineError> {
+    let factor = 10f64.powi(precision as i32);
+    let mut chars = encoded.chars();
+    let mut next_number = || -> Result<Option<i64>, PolylineError> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let Some(c) = chars.next() else {
+                return if shift == 0 {
+                    Ok(None)
+                } else {
+                    Err(PolylineError::Truncated)
+                };
+            };
+            if !('?'..='~').contains(&c) {
+                return Err(PolylineError::InvalidCharacter(c));
+            }
+            // Values of the format are 32-bit.
+            if shift > 30 {
+                return Err(PolylineError::Overflow);
+            }
+            let chunk = (c as i64) - 63;
+            result |= (chunk & 0x1f) << shift;
+            shift += 5;
+            if chunk < 0x20 {
+                break;
+            }
+        }
+        Ok(Some(if result & 1 == 1 {
+            !(result >> 1)
+        } else {
+            result >> 1
+        }))
+    };
+
+    // Each point is the difference from the previous one, latitude first.
+    let (mut latitude, mut longitude) = (0i64, 0i64);
+    let mut positions = Vec::new();
+    while let Some(delta) = next_number()? {
+        let delta_longitude = next_number()?.ok_or(PolylineError::Truncated)?;
+        latitude = latitude.checked_add(delta).ok_or(PolylineError::Overflow)?;
+        longitude = longitude
+            .checked_add(delta_longitude)
+            .ok_or(PolylineError::Overflow)?;
+        positions.push(Position::new(
+            longitude as f64 / factor,
+            latitude as f64 / factor,
+        ));
+    }
+    Ok(positions)
+}
+
+/// [`Plugin`] which draws a route, e.g. the one from a routing service, as a line with a darker
+/// casing, markers at its start and end, and optionally arrows showing the direction.
+#[derive(Clone)]
+pub struct Route {
+    positions: Vec<Position>,
+
+    /// Width (in screen points) and color of the line.
+    pub fill: Stroke,
+
+    /// Outline of the line, which makes it stand out from the roads below. Its width is added on
+    /// each side of the line.
+    pub casing: Stroke,
+
+    /// Colors of the start and the end markers. `None` leaves the ends unmarked.
+    pub markers: Option<(Color32, Color32)>,
+
+    /// Distance (in screen points) between the arrows drawn along the line. `None` (the default)
+    /// draws no arrows.
+    pub arrow_spacing: Option<f32>,
+}
+
+impl Route {
+    /// Route going through the positions, in order.
+    pub fn new(positions: impl IntoIterator<Item = Position>) -> Self {
+        Self {
+            positions: positions.into_iter().collect(),
+            fill: Stroke::new(5., Color32::from_rgb(66, 133, 244)),
+            casing: Stroke::new(1.5, Color32::from_rgb(25, 80, 170)),
+            markers: Some((
+                Color32::from_rgb(52, 168, 83),
+                Color32::from_rgb(234, 67, 53),
+            )),
+            arrow_spacing: None,
+        }
+    }
+
+    /// Route given as an [encoded polyline](decode_polyline).
+    pub fn from_polyline(encoded: &str, precision: u32) -> Result<Self, PolylineError> {
+        Ok(Self::new(decode_polyline(encoded, precision)?))
+    }
+
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+}
+
+impl Plugin for Route {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let points: Vec<Pos2> = self
+            .positions
+            .iter()
+            .map(|position| projector.to_screen(*position))
+            .collect();
+        let (Some(start), Some(end)) = (points.first(), points.last()) else {
+            return;
+        };
+
+        let casing_width = self.fill.width + 2. * self.casing.width;
+        painter.add(Shape::line(
+            points.clone(),
+            Stroke::new(casing_width, self.casing.color),
+        ));
+        painter.add(Shape::line(points.clone(), self.fill));
+
+        if let Some(spacing) = self.arrow_spacing {
+            let size = self.fill.width * 0.8;
+            let stroke = Stroke::new(self.fill.width * 0.3, Color32::WHITE);
+            for (tip, direction) in arrows(&points, spacing) {
+                let back = tip - direction * size;
+                let side = direction.rot90() * size;
+                painter.add(Shape::line(vec![back + side, tip, back - side], stroke));
+            }
+        }
+
+        if let Some((start_color, end_color)) = self.markers {
+            let radius = casing_width;
+            let outline = Stroke::new(2., Color32::WHITE);
+            painter.circle(*start, radius, start_color, outline);
+            painter.circle(*end, radius, end_color, outline);
+        }
+    }
+}
+
+/// Points every `spacing` along the line (starting half of it from the beginning), with the
+/// directions of the line there.
+fn arrows(points: &[Pos2], spacing: f32) -> Vec<(Pos2, Vec2)> {
+    let mut arrows = Vec::new();
+    if spacing <= 0. {
+        return arrows;
+    }
+
+    let mut next = spacing / 2.;
+    let mut travelled = 0.;
+    for segment in points.windows(2) {
+        let delta = segment[1] - segment[0];
+        let length = delta.length();
+        if length == 0. {
+            continue;
+        }
+        while next <= travelled + length {
+            let t = (next - travelled) / length;
+            arrows.push((segment[0] + delta * t, delta / length));
+            next += spacing;
+        }
+        travelled += length;
+    }
+    arrows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding_polylines() {
+        // Example from the Google's documentation.
+        let positions = decode_polyline("_p~iF~ps|U_ulLnnqC_mqNvxq`@", 5).unwrap();
+        let expected = [(-120.2, 38.5), (-120.95, 40.7), (-126.453, 43.252)];
+        assert_eq!(3, positions.len());
+        for (position, (x, y)) in positions.iter().zip(expected) {
+            approx::assert_relative_eq!(x, position.x(), epsilon = 1e-9);
+            approx::assert_relative_eq!(y, position.y(), epsilon = 1e-9);
+        }
+
+        // Same digits read with the OSRM's `polyline6` precision.
+        let scaled = decode_polyline("_p~iF~ps|U", 6).unwrap();
+        approx::assert_relative_eq!(3.85, scaled[0].y(), epsilon = 1e-9);
+
+        assert_eq!(Ok(Vec::new()), decode_polyline("", 5));
+        assert_eq!(
+            Err(PolylineError::Truncated),
+            decode_polyline("_p~iF", 5).map(|_| ())
+        );
+        assert_eq!(
+            Err(PolylineError::Truncated),
+            decode_polyline("_p~iF~ps|", 5).map(|_| ())
+        );
+        assert_eq!(
+            Err(PolylineError::InvalidCharacter(' ')),
+            decode_polyline("_p~ iF", 5).map(|_| ())
+        );
+        assert_eq!(
+            Err(PolylineError::Overflow),
+            decode_polyline(&"~".repeat(20), 5).map(|_| ())
+        );
+
+        // Longest number of the format, 7 chunks, is fine, but 8 are too many.
+        let longest = format!("{}^", "~".repeat(6));
+        assert_eq!(1, decode_polyline(&longest.repeat(2), 5).unwrap().len());
+        assert_eq!(
+            Err(PolylineError::Overflow),
+            decode_polyline(&format!("~{longest}"), 5).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn arrows_are_spaced_along_the_line() {
+        let points = [
+            Pos2::new(0., 0.),
+            Pos2::new(100., 0.),
+            Pos2::new(100., 0.),
+            Pos2::new(100., 50.),
+        ];
+        let arrows = arrows(&points, 40.);
+
+        let tips = [(20., 0.), (60., 0.), (100., 0.), (100., 40.)];
+        assert_eq!(tips.len(), arrows.len());
+        for ((tip, _), (x, y)) in arrows.iter().zip(tips) {
+            assert!(tip.distance(Pos2::new(x, y)) < 1e-3, "{tip:?}");
+        }
+        assert_eq!(Vec2::X, arrows[0].1);
+        assert_eq!(Vec2::Y, arrows[3].1);
+
+        assert!(super::arrows(&points, 0.).is_empty());
+        assert!(super::arrows(&points[..1], 10.).is_empty());
+    }
+}
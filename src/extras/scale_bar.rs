@@ -0,0 +1,141 @@
+use egui::{Align2, Color32, FontId, Painter, Pos2, Stroke, Vec2};
+
+use crate::{mercator::meters_per_point, Plugin, Projector};
+
+/// Units of the distance shown by the [`ScaleBar`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Units {
+    /// Meters and kilometers.
+    Metric,
+
+    /// Feet and miles.
+    Imperial,
+}
+
+/// Plugin which draws a bar of a round real-world length, e.g. 200 m, in the corner of the map.
+/// It is measured at the latitude of the map's center.
+#[derive(Clone)]
+pub struct ScaleBar {
+    pub corner: Align2,
+    pub units: Units,
+
+    /// Longest the bar can be, in screen points. It is usually shorter, so that the distance
+    /// is a round number.
+    pub max_width: f32,
+
+    pub font: FontId,
+    pub color: Color32,
+    pub background: Color32,
+}
+
+impl Default for ScaleBar {
+    fn default() -> Self {
+        Self {
+            corner: Align2::LEFT_BOTTOM,
+            units: Units::Metric,
+            max_width: 100.,
+            font: FontId::proportional(11.),
+            color: Color32::from_gray(40),
+            background: Color32::from_white_alpha(190),
+        }
+    }
+}
+
+const FEET_PER_METER: f64 = 3.28084;
+const FEET_PER_MILE: f64 = 5280.;
+
+/// Distance between the bar and the edges of the map.
+const MARGIN: f32 = 10.;
+
+impl Plugin for ScaleBar {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let latitude = projector.to_position(painter.clip_rect().center()).y();
+        let meters_per_point = meters_per_point(latitude, projector.zoom());
+        let (label, width) = scale(meters_per_point, self.max_width, self.units);
+
+        let galley = painter.layout_no_wrap(label, self.font.clone(), self.color);
+        let padding = Vec2::new(4., 2.);
+        let tick = 4.;
+        let size = Vec2::new(width.max(galley.size().x), galley.size().y + tick) + 2. * padding;
+
+        let background = self
+            .corner
+            .align_size_within_rect(size, painter.clip_rect().shrink(MARGIN));
+        painter.rect_filled(background, 2., self.background);
+
+        let left = background.left() + padding.x;
+        let bottom = background.bottom() - padding.y;
+        let stroke = Stroke::new(1.5, self.color);
+        painter.line_segment(
+            [Pos2::new(left, bottom), Pos2::new(left + width, bottom)],
+            stroke,
+        );
+        for x in [left, left + width] {
+            painter.line_segment([Pos2::new(x, bottom), Pos2::new(x, bottom - tick)], stroke);
+        }
+
+        painter.galley(background.min + padding, galley);
+    }
+
+    fn repeated(&self) -> bool {
+        false
+    }
+}
+
+/// Label of the bar, and its width in screen points.
+fn scale(meters_per_point: f64, max_width: f32, units: Units) -> (String, f32) {
+    let max_meters = meters_per_point * max_width as f64;
+
+    let (unit, unit_meters) = match units {
+        Units::Metric if max_meters >= 1000. => ("km", 1000.),
+        Units::Metric => ("m", 1.),
+        Units::Imperial if max_meters * FEET_PER_METER >= FEET_PER_MILE => {
+            ("mi", FEET_PER_MILE / FEET_PER_METER)
+        }
+        Units::Imperial => ("ft", 1. / FEET_PER_METER),
+    };
+
+    let distance = round_down(max_meters / unit_meters);
+    let width = (distance * unit_meters / meters_per_point) as f32;
+    (format!("{} {}", distance, unit), width)
+}
+
+/// Largest number of the 1, 2 or 5 times a power of ten form, which is not greater than `value`.
+fn round_down(value: f64) -> f64 {
+    let magnitude = 10f64.powf(value.log10().floor());
+    [5., 2., 1.]
+        .into_iter()
+        .map(|multiple| multiple * magnitude)
+        .find(|round| *round <= value)
+        .unwrap_or(magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_is_rounded_down() {
+        assert_eq!(5., round_down(7.3));
+        assert_eq!(200., round_down(499.));
+        assert_eq!(1., round_down(1.));
+        assert_eq!(0.1, round_down(0.15));
+    }
+
+    #[test]
+    fn scale_in_metric_and_imperial_units() {
+        let (label, width) = scale(3., 100., Units::Metric);
+        assert_eq!("200 m", label);
+        approx::assert_relative_eq!(200. / 3., width);
+
+        let (label, width) = scale(30., 100., Units::Metric);
+        assert_eq!("2 km", label);
+        approx::assert_relative_eq!(2000. / 30., width);
+
+        let (label, _) = scale(3., 100., Units::Imperial);
+        assert_eq!("500 ft", label);
+
+        let (label, _) = scale(30., 100., Units::Imperial);
+        assert_eq!("1 mi", label);
+    }
+}
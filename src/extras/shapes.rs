@@ -0,0 +1,660 @@
+use egui::{Color32, Mesh, Painter, Pos2, Shape, Stroke};
+use geo_types::{Geometry, Polygon};
+
+use crate::{
+    geodesy::great_circle,
+    projection::{Projection, WebMercator},
+    Plugin, Position, Projector,
+};
+
+enum Item {
+    Polyline {
+        points: Vec<Position>,
+        bounds: Bounds,
+        stroke: Stroke,
+    },
+    Polygon {
+        points: Vec<Position>,
+        holes: Vec<Vec<Position>>,
+        filled: FilledPolygon,
+        fill: Color32,
+        stroke: Stroke,
+    },
+}
+
+/// [`Plugin`] which draws polylines (e.g. routes or tracks) and filled polygons (e.g. areas).
+/// Points can be given as anything convertible into [`Position`], like `geo_types::Coord`, and
+/// whole `geo_types` geometries can be added with [`Shapes::geometry`].
+#[derive(Default)]
+pub struct Shapes {
+    items: Vec<Item>,
+}
+
+impl Shapes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a line going through all the `points`.
+    pub fn polyline<P>(mut self, points: impl IntoIterator<Item = P>, stroke: Stroke) -> Self
+    where
+        P: Into<Position>,
+    {
+        let points = collect(points);
+        self.items.push(Item::Polyline {
+            bounds: bounds(&points),
+            points,
+            stroke,
+        });
+        self
+    }
+
+    /// Add a line going through all the `points` along the great circles, which are the shortest
+    /// paths on the Earth, but curves on the map. Each leg is split into `segments` straight
+    /// segments, see [`crate::geodesy::great_circle`].
+    pub fn geodesic_polyline<P>(
+        self,
+        points: impl IntoIterator<Item = P>,
+        segments: usize,
+        stroke: Stroke,
+    ) -> Self
+    where
+        P: Into<Position>,
+    {
+        self.polyline(great_circle(&collect(points), segments), stroke)
+    }
+
+    /// Add a polygon with given outline. It does not have to be convex, but its edges must not
+    /// cross each other.
+    pub fn polygon<P>(
+        mut self,
+        points: impl IntoIterator<Item = P>,
+        fill: Color32,
+        stroke: Stroke,
+    ) -> Self
+    where
+        P: Into<Position>,
+    {
+        let points = collect(points);
+        self.items.push(Item::Polygon {
+            filled: FilledPolygon::new(&points, &[]),
+            points,
+            holes: Vec::new(),
+            fill,
+            stroke,
+        });
+        self
+    }
+
+    /// Add a `geo_types` geometry. Lines are drawn with the `stroke`, polygons are also filled,
+    /// with their holes left out. Points are skipped, use
+    /// [`crate::extras::Places`] for them.
+    pub fn geometry(self, geometry: impl Into<Geometry>, fill: Color32, stroke: Stroke) -> Self {
+        match geometry.into() {
+            Geometry::Point(_) | Geometry::MultiPoint(_) => self,
+            Geometry::Line(line) => self.polyline([line.start, line.end], stroke),
+            Geometry::LineString(line) => self.polyline(line, stroke),
+            Geometry::MultiLineString(lines) => lines
+                .into_iter()
+                .fold(self, |shapes, line| shapes.polyline(line, stroke)),
+            Geometry::Polygon(polygon) => self.geo_polygon(polygon, fill, stroke),
+            Geometry::MultiPolygon(polygons) => {
+                polygons.into_iter().fold(self, |shapes, polygon| {
+                    shapes.geo_polygon(polygon, fill, stroke)
+                })
+            }
+            Geometry::Rect(rect) => self.geo_polygon(rect.to_polygon(), fill, stroke),
+            Geometry::Triangle(triangle) => self.geo_polygon(triangle.to_polygon(), fill, stroke),
+            Geometry::GeometryCollection(geometries) => {
+                geometries.into_iter().fold(self, |shapes, geometry| {
+                    shapes.geometry(geometry, fill, stroke)
+                })
+            }
+        }
+    }
+
+    fn geo_polygon(mut self, polygon: Polygon, fill: Color32, stroke: Stroke) -> Self {
+        let (exterior, interiors) = polygon.into_inner();
+        let points = collect(exterior);
+        let holes: Vec<_> = interiors.into_iter().map(collect).collect();
+        self.items.push(Item::Polygon {
+            filled: FilledPolygon::new(&points, &holes),
+            points,
+            holes,
+            fill,
+            stroke,
+        });
+        self
+    }
+}
+
+fn collect<P: Into<Position>>(points: impl IntoIterator<Item = P>) -> Vec<Position> {
+    points.into_iter().map(Into::into).collect()
+}
+
+impl Plugin for Shapes {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let to_screen = |points: &[Position]| -> Vec<Pos2> {
+            points
+                .iter()
+                .map(|position| projector.to_screen(*position))
+                .collect()
+        };
+
+        let visible = visible_bounds(projector);
+        for item in &self.items {
+            match item {
+                Item::Polyline {
+                    points,
+                    bounds,
+                    stroke,
+                } => {
+                    if overlaps(*bounds, visible) {
+                        painter.add(Shape::line(to_screen(points), *stroke));
+                    }
+                }
+                Item::Polygon {
+                    points,
+                    holes,
+                    filled,
+                    fill,
+                    stroke,
+                } => {
+                    if overlaps(filled.bounds, visible) {
+                        painter.add(filled.mesh(projector, *fill));
+                        painter.add(Shape::closed_line(to_screen(points), *stroke));
+                        for hole in holes {
+                            painter.add(Shape::closed_line(to_screen(hole), *stroke));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// South-west and north-east corners of a box of longitudes and latitudes.
+pub(crate) type Bounds = (Position, Position);
+
+/// Box around the positions. It is empty (and overlaps nothing) if there are none.
+pub(crate) fn bounds<'a>(positions: impl IntoIterator<Item = &'a Position>) -> Bounds {
+    positions.into_iter().fold(
+        (
+            Position::new(f64::INFINITY, f64::INFINITY),
+            Position::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |(min, max), position| {
+            (
+                Position::new(min.x().min(position.x()), min.y().min(position.y())),
+                Position::new(max.x().max(position.x()), max.y().max(position.y())),
+            )
+        },
+    )
+}
+
+pub(crate) fn overlaps((min, max): Bounds, (other_min, other_max): Bounds) -> bool {
+    min.x() <= other_max.x()
+        && other_min.x() <= max.x()
+        && min.y() <= other_max.y()
+        && other_min.y() <= max.y()
+}
+
+/// Distance (in screen points) from the map's edges within which the shapes are still drawn,
+/// so that their strokes and point markers are not cut off.
+const VISIBLE_MARGIN: f32 = 50.;
+
+/// [`Projector::visible_bounds`] with the [`VISIBLE_MARGIN`]. Shapes whose [`bounds`] do not
+/// overlap it can be skipped.
+pub(crate) fn visible_bounds(projector: &Projector) -> Bounds {
+    projector
+        .with_clip_rect(projector.clip_rect().expand(VISIBLE_MARGIN))
+        .visible_bounds()
+}
+
+/// Polygon with its holes left out, triangulated once, so that drawing it only projects its
+/// vertices.
+#[derive(Clone)]
+pub(crate) struct FilledPolygon {
+    /// Exterior ring joined with the holes by [`bridge_holes`].
+    vertices: Vec<Position>,
+    triangles: Vec<[usize; 3]>,
+    pub(crate) bounds: Bounds,
+}
+
+impl FilledPolygon {
+    /// Triangles are computed in the Web Mercator, relative to the polygon's box, so that `f32`
+    /// is precise enough even for the smallest polygons. They fit the other projections too,
+    /// unless the polygon is big enough for its edges to bend visibly.
+    pub(crate) fn new(exterior: &[Position], holes: &[Vec<Position>]) -> Self {
+        let rings: Vec<Vec<(f64, f64)>> = std::iter::once(exterior)
+            .chain(holes.iter().map(Vec::as_slice))
+            .map(|ring| {
+                // Closed rings, like the ones of `geo_types`, end with their first position,
+                // which the triangles do not need.
+                let ring = match ring.split_last() {
+                    Some((last, rest)) if rest.first() == Some(last) => rest,
+                    _ => ring,
+                };
+                ring.iter()
+                    .map(|position| WebMercator.project(*position))
+                    .collect()
+            })
+            .collect();
+
+        let (min_x, min_y, max_x, max_y) = rings[0].iter().fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(min_x, min_y, max_x, max_y), (x, y)| {
+                (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
+            },
+        );
+        let size = (max_x - min_x).max(max_y - min_y).max(f64::MIN_POSITIVE);
+
+        let rings: Vec<Vec<Pos2>> = rings
+            .iter()
+            .map(|ring| {
+                ring.iter()
+                    .map(|(x, y)| {
+                        Pos2::new(((x - min_x) / size) as f32, ((y - min_y) / size) as f32)
+                    })
+                    .collect()
+            })
+            .collect();
+        let points = bridge_holes(&rings[0], &rings[1..]);
+
+        Self {
+            triangles: triangulate(&points),
+            vertices: points
+                .iter()
+                .map(|point| {
+                    WebMercator
+                        .unproject((min_x + point.x as f64 * size, min_y + point.y as f64 * size))
+                })
+                .collect(),
+            bounds: bounds(exterior),
+        }
+    }
+
+    pub(crate) fn mesh(&self, projector: &Projector, fill: Color32) -> Mesh {
+        let mut mesh = Mesh::default();
+        for position in &self.vertices {
+            mesh.colored_vertex(projector.to_screen(*position), fill);
+        }
+        for [a, b, c] in &self.triangles {
+            mesh.add_triangle(*a as u32, *b as u32, *c as u32);
+        }
+        mesh
+    }
+}
+
+/// Mesh filling the polygon, except for its holes. Unlike `Shape::convex_polygon`, it handles
+/// concave polygons too.
+pub(crate) fn filled_polygon(exterior: &[Pos2], holes: &[Vec<Pos2>], fill: Color32) -> Mesh {
+    let points = bridge_holes(exterior, holes);
+    let mut mesh = Mesh::default();
+    for point in &points {
+        mesh.colored_vertex(*point, fill);
+    }
+    for [a, b, c] in triangulate(&points) {
+        mesh.add_triangle(a as u32, b as u32, c as u32);
+    }
+    mesh
+}
+
+/// Twice the signed area of the triangle, positive if the points turn counter-clockwise
+/// (in the usual, y-up coordinates).
+fn cross(a: Pos2, b: Pos2, c: Pos2) -> f32 {
+    (b - a).x * (c - b).y - (b - a).y * (c - b).x
+}
+
+fn inside_triangle(point: Pos2, [a, b, c]: [Pos2; 3]) -> bool {
+    cross(a, b, point) >= 0. && cross(b, c, point) >= 0. && cross(c, a, point) >= 0.
+}
+
+/// Whether the point lies on the segment, between its ends.
+fn on_segment(point: Pos2, (a, b): (Pos2, Pos2)) -> bool {
+    point != a && point != b && cross(a, b, point) == 0. && (point - a).dot(point - b) < 0.
+}
+
+/// Twice the signed area of the ring, positive if it goes counter-clockwise.
+fn signed_area(ring: &[Pos2]) -> f32 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum()
+}
+
+/// Whether the segments cross each other, not counting touching at the ends.
+fn segments_cross((a, b): (Pos2, Pos2), (c, d): (Pos2, Pos2)) -> bool {
+    cross(a, b, c) * cross(a, b, d) < 0. && cross(c, d, a) * cross(c, d, b) < 0.
+}
+
+/// Whether the `point` is on the inner side of the `ring`'s corner at `index`, which must go
+/// counter-clockwise.
+fn locally_inside(ring: &[Pos2], index: usize, point: Pos2) -> bool {
+    let n = ring.len();
+    let (previous, corner, next) = (
+        ring[(index + n - 1) % n],
+        ring[index],
+        ring[(index + 1) % n],
+    );
+    let after = cross(corner, next, point) > 0.;
+    let before = cross(previous, corner, point) > 0.;
+    if cross(previous, corner, next) > 0. {
+        after && before
+    } else {
+        after || before
+    }
+}
+
+fn edges(ring: &[Pos2]) -> impl Iterator<Item = (Pos2, Pos2)> + '_ {
+    (0..ring.len()).map(|i| (ring[i], ring[(i + 1) % ring.len()]))
+}
+
+/// Single ring going around the `exterior` and, along the "bridges" from its vertices, around
+/// each of the `holes` in the opposite direction. It can be [`triangulate`]d like a simple
+/// polygon, which gives the triangles of the polygon with the holes left out.
+pub(crate) fn bridge_holes(exterior: &[Pos2], holes: &[Vec<Pos2>]) -> Vec<Pos2> {
+    let mut ring = exterior.to_vec();
+    if signed_area(&ring) < 0. {
+        ring.reverse();
+    }
+
+    let mut holes: Vec<Vec<Pos2>> = holes
+        .iter()
+        .filter(|hole| hole.len() >= 3)
+        .map(|hole| {
+            let mut hole = hole.clone();
+            if signed_area(&hole) > 0. {
+                hole.reverse();
+            }
+            hole
+        })
+        .collect();
+
+    // Rightmost holes go first, so the ones left of them can be bridged to their vertices.
+    let max_x = |ring: &[Pos2]| ring.iter().map(|point| point.x).fold(f32::MIN, f32::max);
+    holes.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+
+    for (index, hole) in holes.iter().enumerate() {
+        let Some((start, from)) = hole
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.x.total_cmp(&b.x))
+        else {
+            continue;
+        };
+
+        // Nearest vertex on the right which can be reached without crossing any edge. Of the
+        // ones at the same place, e.g. the ends of the other bridges, it must be the one whose
+        // corner the bridge goes into.
+        let mut candidates: Vec<usize> = (0..ring.len()).filter(|&i| ring[i].x >= from.x).collect();
+        candidates.sort_by(|a, b| {
+            ring[*a]
+                .distance_sq(from)
+                .total_cmp(&ring[*b].distance_sq(from))
+        });
+        let visible = candidates.into_iter().find(|&i| {
+            let bridge = (from, ring[i]);
+            let mut others = edges(&ring).chain(holes[index..].iter().flat_map(|hole| edges(hole)));
+            // Bridges going along an edge, or through a vertex, would not be bridges.
+            locally_inside(&ring, i, from)
+                && !others.any(|edge| segments_cross(bridge, edge) || on_segment(edge.0, bridge))
+        });
+
+        let Some(to) = visible else {
+            log::debug!("Could not bridge a hole of the polygon.");
+            continue;
+        };
+
+        let mut bridged = Vec::with_capacity(ring.len() + hole.len() + 2);
+        bridged.extend_from_slice(&ring[..=to]);
+        bridged.extend_from_slice(&hole[start..]);
+        bridged.extend_from_slice(&hole[..=start]);
+        bridged.extend_from_slice(&ring[to..]);
+        ring = bridged;
+    }
+
+    ring
+}
+
+/// Split a simple polygon into triangles using the ear clipping method.
+pub(crate) fn triangulate(points: &[Pos2]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+
+    if points.len() < 3 {
+        return triangles;
+    }
+
+    // Make the vertices go counter-clockwise, so the convex corners are the ones with positive
+    // cross product.
+    let area: f32 = (0..points.len())
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+    if area < 0. {
+        remaining.reverse();
+    }
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let triangle = [
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            ];
+            let corners = triangle.map(|index| points[index]);
+
+            // Vertices at the same place as the corners are the ends of the bridges to the holes.
+            // Edges starting there might cut through the triangle without a vertex inside.
+            cross(corners[0], corners[1], corners[2]) > 0.
+                && remaining
+                    .iter()
+                    .map(|index| points[*index])
+                    .filter(|point| !corners.contains(point))
+                    .all(|point| !inside_triangle(point, corners))
+                && (0..n).all(|j| {
+                    let edge = (points[remaining[j]], points[remaining[(j + 1) % n]]);
+                    !segments_cross((corners[0], corners[2]), edge)
+                })
+        });
+
+        let Some(i) = ear else {
+            // Degenerate (e.g. self-intersecting) polygon, draw what we have.
+            log::debug!("Could not triangulate the polygon.");
+            return triangles;
+        };
+
+        triangles.push([
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ]);
+        remaining.remove(i);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(points: &[Pos2], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| cross(points[*a], points[*b], points[*c]).abs() / 2.)
+            .sum()
+    }
+
+    #[test]
+    fn triangulating_convex_polygon() {
+        let square = [
+            Pos2::new(0., 0.),
+            Pos2::new(1., 0.),
+            Pos2::new(1., 1.),
+            Pos2::new(0., 1.),
+        ];
+        let triangles = triangulate(&square);
+        assert_eq!(2, triangles.len());
+        assert_eq!(1., area(&square, &triangles));
+    }
+
+    #[test]
+    fn triangulating_concave_polygon() {
+        // L-shaped, going clockwise.
+        let l_shape = [
+            Pos2::new(0., 0.),
+            Pos2::new(0., 2.),
+            Pos2::new(1., 2.),
+            Pos2::new(1., 1.),
+            Pos2::new(2., 1.),
+            Pos2::new(2., 0.),
+        ];
+        let triangles = triangulate(&l_shape);
+        assert_eq!(4, triangles.len());
+        assert_eq!(3., area(&l_shape, &triangles));
+    }
+
+    #[test]
+    fn triangulating_polygon_with_holes() {
+        let square = |x: f32, y: f32, size: f32| {
+            vec![
+                Pos2::new(x, y),
+                Pos2::new(x + size, y),
+                Pos2::new(x + size, y + size),
+                Pos2::new(x, y + size),
+            ]
+        };
+        let exterior = square(0., 0., 5.);
+        let holes = [square(1., 1., 1.), square(3., 3., 1.), square(1., 3., 1.)];
+
+        let ring = bridge_holes(&exterior, &holes);
+        assert_eq!(4 + 3 * 6, ring.len());
+        let triangles = triangulate(&ring);
+        assert_eq!(25. - 3., area(&ring, &triangles));
+
+        // Same, whichever way the rings go.
+        let reversed: Vec<_> = holes
+            .iter()
+            .map(|hole| hole.iter().rev().copied().collect())
+            .collect();
+        let ring = bridge_holes(&exterior, &reversed);
+        assert_eq!(25. - 3., area(&ring, &triangulate(&ring)));
+    }
+
+    #[test]
+    fn adding_geo_types_geometries() {
+        let square = Polygon::new(
+            vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)].into(),
+            vec![vec![(0.4, 0.4), (0.6, 0.4), (0.6, 0.6)].into()],
+        );
+        let line = geo_types::LineString::from(vec![(0., 0.), (2., 1.)]);
+        let collection = Geometry::GeometryCollection(geo_types::GeometryCollection(vec![
+            square.into(),
+            line.into(),
+            Position::new(5., 5.).into(),
+        ]));
+
+        let shapes = Shapes::new().geometry(collection, Color32::RED, Stroke::NONE);
+        assert_eq!(2, shapes.items.len());
+        assert!(matches!(
+            &shapes.items[0],
+            Item::Polygon { points, holes, .. } if points.len() == 5 && holes.len() == 1
+        ));
+        assert!(matches!(
+            &shapes.items[1],
+            Item::Polyline { points, .. } if points[1] == Position::new(2., 1.)
+        ));
+    }
+
+    #[test]
+    fn polygons_are_triangulated_once() {
+        // Building-sized square with a hole, where `f32` Web Mercator coordinates would not
+        // tell its corners apart.
+        let square = |x: f64, y: f64, size: f64| {
+            vec![
+                Position::new(x, y),
+                Position::new(x + size, y),
+                Position::new(x + size, y + size),
+                Position::new(x, y + size),
+            ]
+        };
+        let exterior = square(21.0003, 52.2647, 1e-4);
+        let hole = square(21.00033, 52.26473, 4e-5);
+        let filled = FilledPolygon::new(&exterior, std::slice::from_ref(&hole));
+
+        assert_eq!(4 + 4 + 2, filled.vertices.len());
+        assert_eq!(8, filled.triangles.len());
+
+        let mut closed = exterior.clone();
+        closed.push(exterior[0]);
+        assert_eq!(2, FilledPolygon::new(&closed, &[]).triangles.len());
+        for vertex in &filled.vertices {
+            let original = exterior
+                .iter()
+                .chain(&hole)
+                .any(|p| (p.x() - vertex.x()).abs() < 1e-9 && (p.y() - vertex.y()).abs() < 1e-9);
+            assert!(original, "{vertex:?}");
+        }
+        assert_eq!((exterior[0], exterior[2]), filled.bounds);
+
+        let projector = Projector::new(
+            egui::Rect::from_min_size(Pos2::ZERO, egui::Vec2::splat(500.)),
+            Position::new(21.00035, 52.26475),
+            22.,
+        );
+        let mesh = filled.mesh(&projector, Color32::RED);
+        assert_eq!(
+            projector.to_screen(filled.vertices[3]),
+            mesh.vertices[3].pos
+        );
+        let area: f32 = mesh
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[t[i] as usize].pos);
+                cross(a, b, c).abs() / 2.
+            })
+            .sum();
+        let corner = |index: usize| projector.to_screen(exterior[index]);
+        let (width, height) = (corner(1).x - corner(0).x, corner(0).y - corner(3).y);
+        approx::assert_relative_eq!(width * height * (1. - 0.4 * 0.4), area, max_relative = 1e-3);
+    }
+
+    #[test]
+    fn shapes_out_of_view_are_skipped() {
+        let projector = Projector::new(
+            egui::Rect::from_min_size(Pos2::ZERO, egui::Vec2::splat(500.)),
+            Position::new(21., 52.),
+            10.,
+        );
+        let visible = visible_bounds(&projector);
+        let near = bounds(&[Position::new(21.1, 52.1), Position::new(21.2, 52.2)]);
+        let far = bounds(&[Position::new(25., 52.), Position::new(26., 53.)]);
+        // Across the whole view.
+        let wide = bounds(&[Position::new(20., 51.9), Position::new(22., 52.1)]);
+
+        assert!(overlaps(near, visible));
+        assert!(!overlaps(far, visible));
+        assert!(overlaps(wide, visible));
+        assert!(!overlaps(bounds(&[]), visible));
+    }
+
+    #[test]
+    fn triangulating_degenerate_polygon() {
+        assert!(triangulate(&[Pos2::new(0., 0.), Pos2::new(1., 1.)]).is_empty());
+    }
+}
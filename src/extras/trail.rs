@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use egui::{Color32, Painter, Stroke};
+use web_time::Instant;
+
+use crate::{geodesy::distance, Plugin, Position, PositionExtra, Projector};
+
+/// [`Plugin`] which records the successive positions, like the "my position" given to the map,
+/// and draws the path behind them as a line fading towards its oldest end. Keep it between
+/// frames and pass it to the map by `&mut`.
+#[derive(Clone)]
+pub struct Trail {
+    pub stroke: Stroke,
+
+    /// Most positions kept, the oldest ones are dropped first.
+    pub max_len: usize,
+
+    /// Positions older than this are dropped. `None` (the default) keeps them.
+    pub max_age: Option<Duration>,
+
+    /// Smallest distance (in meters) from the last recorded position, for the next one to be
+    /// recorded, so the GPS noise of a standing user does not fill the trail.
+    pub min_distance: f64,
+
+    /// Positions less accurate than this (in meters) are not recorded. `None` (the default)
+    /// records all of them. Only the ones given with [`Trail::push_extra`] have the accuracy.
+    pub max_accuracy: Option<f32>,
+
+    /// Recorded positions, each with its timestamp.
+    positions: VecDeque<(PositionExtra, Instant)>,
+}
+
+impl Default for Trail {
+    fn default() -> Self {
+        Self {
+            stroke: Stroke::new(3., Color32::from_rgb(0, 122, 255)),
+            max_len: 1000,
+            max_age: None,
+            min_distance: 2.,
+            max_accuracy: None,
+            positions: VecDeque::new(),
+        }
+    }
+}
+
+impl Trail {
+    /// Record the position, e.g. in every frame, with the same one as given to the map.
+    pub fn push(&mut self, position: Position) {
+        self.push_at(position, Instant::now());
+    }
+
+    /// Record the position along with what else is known about it. Its timestamp, if given,
+    /// is used instead of the current time.
+    pub fn push_extra(&mut self, fix: PositionExtra) {
+        let now = Instant::now();
+        self.push_fix(fix, fix.timestamp.unwrap_or(now), now);
+    }
+
+    fn push_at(&mut self, position: Position, now: Instant) {
+        self.push_fix(PositionExtra::new(position), now, now);
+    }
+
+    fn push_fix(&mut self, fix: PositionExtra, recorded: Instant, now: Instant) {
+        let inaccurate = self
+            .max_accuracy
+            .is_some_and(|max| fix.accuracy.is_some_and(|accuracy| accuracy > max));
+        let standing = self
+            .positions
+            .back()
+            .is_some_and(|(last, _)| distance(last.position, fix.position) < self.min_distance);
+        if !inaccurate && !standing {
+            self.positions.push_back((fix, recorded));
+        }
+
+        while self.positions.len() > self.max_len {
+            self.positions.pop_front();
+        }
+        self.drop_old(now);
+    }
+
+    fn drop_old(&mut self, now: Instant) {
+        if let Some(max_age) = self.max_age {
+            while self
+                .positions
+                .front()
+                .is_some_and(|(_, recorded)| now.duration_since(*recorded) > max_age)
+            {
+                self.positions.pop_front();
+            }
+        }
+    }
+
+    /// Recorded positions, from the oldest.
+    pub fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.positions.iter().map(|(fix, _)| fix.position)
+    }
+
+    /// Recorded positions with everything known about them, e.g. to plot the altitude. The
+    /// timestamps are always there, set to the time of recording if not given.
+    pub fn fixes(&self) -> impl Iterator<Item = PositionExtra> + '_ {
+        self.positions.iter().map(|(fix, recorded)| PositionExtra {
+            timestamp: Some(*recorded),
+            ..*fix
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+
+    /// Opacity of the segment ending at the `index`th position, which falls with its age, or with
+    /// its place in the trail, if the age is not limited.
+    fn opacity(&self, index: usize, now: Instant) -> f32 {
+        match self.max_age {
+            Some(max_age) if !max_age.is_zero() => {
+                let age = now.duration_since(self.positions[index].1);
+                1. - (age.as_secs_f32() / max_age.as_secs_f32()).min(1.)
+            }
+            _ => index as f32 / self.positions.len().saturating_sub(1).max(1) as f32,
+        }
+    }
+}
+
+impl Plugin for Trail {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        let now = Instant::now();
+        self.drop_old(now);
+
+        let points: Vec<_> = self
+            .positions()
+            .map(|position| projector.to_screen(position))
+            .collect();
+
+        for (index, segment) in points.windows(2).enumerate() {
+            let opacity = self.opacity(index + 1, now);
+            let stroke = Stroke::new(self.stroke.width, self.stroke.color.gamma_multiply(opacity));
+            painter.line_segment([segment[0], segment[1]], stroke);
+        }
+
+        if self.max_age.is_some() && !self.positions.is_empty() {
+            painter
+                .ctx()
+                .request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trail_is_limited() {
+        let mut trail = Trail {
+            max_len: 3,
+            ..Default::default()
+        };
+        let now = Instant::now();
+
+        for i in 0..5 {
+            trail.push_at(Position::new(21. + i as f64 * 0.001, 52.), now);
+        }
+        assert_eq!(3, trail.positions().count());
+        assert_eq!(Some(Position::new(21.002, 52.)), trail.positions().next());
+
+        // Standing in place does not add anything.
+        trail.push_at(Position::new(21.004, 52.000001), now);
+        assert_eq!(3, trail.positions().count());
+
+        // Oldest position is the most transparent.
+        assert_eq!(0., trail.opacity(0, now));
+        assert_eq!(1., trail.opacity(2, now));
+    }
+
+    #[test]
+    fn old_positions_are_dropped() {
+        let mut trail = Trail {
+            max_age: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+
+        trail.push_at(Position::new(21., 52.), start);
+        trail.push_at(Position::new(21.01, 52.), start + Duration::from_secs(5));
+        approx::assert_relative_eq!(0.5, trail.opacity(0, start + Duration::from_secs(5)));
+
+        trail.push_at(Position::new(21.02, 52.), start + Duration::from_secs(12));
+        assert_eq!(2, trail.positions().count());
+    }
+
+    #[test]
+    fn fixes_keep_what_is_known_about_them() {
+        let mut trail = Trail {
+            max_accuracy: Some(50.),
+            ..Default::default()
+        };
+        let measured = Instant::now() - Duration::from_secs(3);
+
+        trail.push_extra(PositionExtra {
+            altitude: Some(120.),
+            accuracy: Some(10.),
+            timestamp: Some(measured),
+            ..PositionExtra::new(Position::new(21., 52.))
+        });
+        trail.push(Position::new(21.01, 52.));
+
+        // Inaccurate fix is skipped.
+        trail.push_extra(PositionExtra {
+            accuracy: Some(200.),
+            ..PositionExtra::new(Position::new(21.02, 52.))
+        });
+
+        let fixes: Vec<_> = trail.fixes().collect();
+        assert_eq!(2, fixes.len());
+        assert_eq!(Some(120.), fixes[0].altitude);
+        assert_eq!(Some(measured), fixes[0].timestamp);
+        assert_eq!(None, fixes[1].altitude);
+        assert!(fixes[1].timestamp.is_some());
+    }
+}
@@ -0,0 +1,137 @@
+//! Changing the colors of the tiles before they are drawn, see [`crate::Tiles::with_filter`].
+use egui::{Color32, ColorImage};
+
+/// Transformation of the tile's pixels, e.g. to make the map fit a dark theme.
+pub enum TileFilter {
+    /// Shades of gray, so the colorful data drawn on top stand out.
+    Grayscale,
+
+    /// Light areas become dark and the other way around, but the hues stay, so the water is
+    /// still blue and the parks are green.
+    DarkMode,
+
+    /// Colors multiplied by the given one.
+    Tint(Color32),
+
+    /// 4×5 matrix, in the row-major order, which turns the red, green, blue and alpha
+    /// components (between 0 and 1) into the new ones. The last column is added, like in the
+    /// `feColorMatrix` of SVG.
+    ColorMatrix([f32; 20]),
+
+    /// Application's own transformation.
+    Custom(Box<dyn Fn(&mut ColorImage) + Send + Sync>),
+}
+
+/// Luminance of the red, green and blue components.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Colors inverted, and then their hues rotated by 180°.
+const DARK_MODE: [f32; 20] = [
+    0.574, -1.43, -0.144, 0., 1., //
+    -0.426, -0.43, -0.144, 0., 1., //
+    -0.426, -1.43, 0.856, 0., 1., //
+    0., 0., 0., 1., 0.,
+];
+
+impl TileFilter {
+    pub(crate) fn apply(&self, image: &mut ColorImage) {
+        let matrix = match self {
+            TileFilter::Grayscale => {
+                let [r, g, b] = LUMA;
+                [
+                    r, g, b, 0., 0., //
+                    r, g, b, 0., 0., //
+                    r, g, b, 0., 0., //
+                    0., 0., 0., 1., 0.,
+                ]
+            }
+            TileFilter::DarkMode => DARK_MODE,
+            TileFilter::Tint(color) => {
+                let [r, g, b, a] = color.to_srgba_unmultiplied().map(|c| c as f32 / 255.);
+                [
+                    r, 0., 0., 0., 0., //
+                    0., g, 0., 0., 0., //
+                    0., 0., b, 0., 0., //
+                    0., 0., 0., a, 0.,
+                ]
+            }
+            TileFilter::ColorMatrix(matrix) => *matrix,
+            TileFilter::Custom(filter) => {
+                filter(image);
+                return;
+            }
+        };
+
+        for pixel in &mut image.pixels {
+            *pixel = transformed(*pixel, &matrix);
+        }
+    }
+}
+
+fn transformed(color: Color32, matrix: &[f32; 20]) -> Color32 {
+    let input = color.to_srgba_unmultiplied().map(|c| c as f32 / 255.);
+    let component = |row: &[f32]| {
+        let value = row[..4]
+            .iter()
+            .zip(input)
+            .map(|(weight, c)| weight * c)
+            .sum::<f32>()
+            + row[4];
+        (value.clamp(0., 1.) * 255.).round() as u8
+    };
+    let mut rows = matrix.chunks_exact(5).map(component);
+    let mut next = || rows.next().unwrap_or_default();
+    Color32::from_rgba_unmultiplied(next(), next(), next(), next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(filter: TileFilter, color: Color32) -> Color32 {
+        let mut image = ColorImage::new([2, 2], color);
+        filter.apply(&mut image);
+        image.pixels[3]
+    }
+
+    #[test]
+    fn filters_change_the_colors() {
+        assert_eq!(
+            Color32::from_gray(54),
+            filtered(TileFilter::Grayscale, Color32::RED)
+        );
+        assert_eq!(
+            Color32::from_gray(255),
+            filtered(TileFilter::Grayscale, Color32::WHITE)
+        );
+
+        // White becomes black, but blue stays blue.
+        assert_eq!(
+            Color32::BLACK,
+            filtered(TileFilter::DarkMode, Color32::WHITE)
+        );
+        let water = filtered(TileFilter::DarkMode, Color32::from_rgb(170, 211, 223));
+        let [r, g, b, _] = water.to_array();
+        assert!(b > r && b > g && b < 170);
+
+        assert_eq!(
+            Color32::from_rgb(128, 0, 0),
+            filtered(
+                TileFilter::Tint(Color32::from_rgb(255, 0, 0)),
+                Color32::from_gray(128)
+            )
+        );
+
+        let identity = [
+            1., 0., 0., 0., 0., //
+            0., 1., 0., 0., 0., //
+            0., 0., 1., 0., 0., //
+            0., 0., 0., 1., 0.,
+        ];
+        let color = Color32::from_rgb(10, 20, 30);
+        assert_eq!(color, filtered(TileFilter::ColorMatrix(identity), color));
+
+        let custom = TileFilter::Custom(Box::new(|image| image.pixels[3] = Color32::GOLD));
+        assert_eq!(Color32::GOLD, filtered(custom, color));
+    }
+}
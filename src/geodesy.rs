@@ -0,0 +1,250 @@
+//! Calculations on the Earth's surface, treating it as a sphere. That is accurate to about 0.5%,
+//! which is plenty for showing distances on a map.
+//! <https://www.movable-type.co.uk/scripts/latlong.html>
+
+use crate::Position;
+
+/// Mean radius of the Earth, in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Distance (in meters) between two positions, along the great circle.
+pub fn distance(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from.y().to_radians(), to.y().to_radians());
+    let delta_lat = lat2 - lat1;
+    let delta_lon = (to.x() - from.x()).to_radians();
+
+    let a =
+        (delta_lat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (delta_lon / 2.).sin().powi(2);
+    2. * EARTH_RADIUS * a.sqrt().atan2((1. - a).sqrt())
+}
+
+/// Initial bearing (in degrees clockwise from the north, between 0 and 360) of the shortest path
+/// going from one position to another. It changes along the way, unless going along a meridian
+/// or the equator.
+pub fn bearing(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from.y().to_radians(), to.y().to_radians());
+    let delta_lon = (to.x() - from.x()).to_radians();
+
+    let y = delta_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.)
+}
+
+/// Position reached by going `distance` meters from a position, starting with the `bearing`
+/// (in degrees clockwise from the north).
+pub fn destination(from: Position, bearing: f64, distance: f64) -> Position {
+    let lat1 = from.y().to_radians();
+    let lon1 = from.x().to_radians();
+    let bearing = bearing.to_radians();
+    let angle = distance / EARTH_RADIUS;
+
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+    let lon2 = lon1
+        + (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+
+    Position::new(
+        (lon2.to_degrees() + 540.).rem_euclid(360.) - 180.,
+        lat2.to_degrees(),
+    )
+}
+
+/// Length (in meters) of the path going through all the positions.
+pub fn length(points: &[Position]) -> f64 {
+    points.windows(2).map(|leg| distance(leg[0], leg[1])).sum()
+}
+
+/// Area (in square meters) of the polygon with given outline. Whether it goes clockwise or not
+/// does not matter, but its edges must not cross each other.
+pub fn area(points: &[Position]) -> f64 {
+    let sum: f64 = (0..points.len())
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            (b.x() - a.x()).to_radians()
+                * (2. + a.y().to_radians().sin() + b.y().to_radians().sin())
+        })
+        .sum();
+    (sum * EARTH_RADIUS * EARTH_RADIUS / 2.).abs()
+}
+
+/// Position at the `fraction` (between 0 and 1) of the great circle path from one position to
+/// another. Antipodal positions are joined by every meridian, so the path goes along the one of
+/// `from`, over the north pole (or from it, if `from` is the pole).
+pub fn intermediate(from: Position, to: Position, fraction: f64) -> Position {
+    let (lat1, lon1) = (from.y().to_radians(), from.x().to_radians());
+    let (lat2, lon2) = (to.y().to_radians(), to.x().to_radians());
+    let angle = distance(from, to) / EARTH_RADIUS;
+
+    // Below, the positions are weighted by dividing by the sine, which is zero when they are at
+    // the same place, or on the opposite sides of the Earth.
+    if angle.sin() < 1e-6 {
+        return if angle < std::f64::consts::FRAC_PI_2 {
+            from
+        } else {
+            destination(from, 0., fraction * angle * EARTH_RADIUS)
+        };
+    }
+
+    let a = ((1. - fraction) * angle).sin() / angle.sin();
+    let b = (fraction * angle).sin() / angle.sin();
+    let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+    let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+    let z = a * lat1.sin() + b * lat2.sin();
+
+    Position::new(
+        y.atan2(x).to_degrees(),
+        z.atan2((x * x + y * y).sqrt()).to_degrees(),
+    )
+}
+
+/// Points of the great circle path going through all the `points`, with each leg split into
+/// `segments` straight segments. Straight lines on a Mercator map are not the shortest paths,
+/// which is especially visible for long ones, like flights.
+///
+/// Longitudes stay continuous, so a path crossing the antimeridian goes beyond 180 (or -180)
+/// degrees, instead of jumping to the other side of the map.
+pub fn great_circle(points: &[Position], segments: usize) -> Vec<Position> {
+    let segments = segments.max(1);
+    let mut path: Vec<Position> = Vec::new();
+
+    for leg in points.windows(2) {
+        let first = usize::from(!path.is_empty());
+        for i in first..=segments {
+            let mut point = intermediate(leg[0], leg[1], i as f64 / segments as f64);
+            if let Some(previous) = path.last() {
+                let offset = ((previous.x() - point.x()) / 360.).round() * 360.;
+                point = Position::new(point.x() + offset, point.y());
+            }
+            path.push(point);
+        }
+    }
+
+    if path.is_empty() {
+        path.extend(points.first());
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WARSAW: Position = geo_types::Point(geo_types::Coord {
+        x: 21.01178,
+        y: 52.22977,
+    });
+    const WROCLAW: Position = geo_types::Point(geo_types::Coord {
+        x: 17.03333,
+        y: 51.10000,
+    });
+
+    #[test]
+    fn distance_between_cities() {
+        approx::assert_relative_eq!(301_000., distance(WARSAW, WROCLAW), max_relative = 0.01);
+        assert_eq!(0., distance(WARSAW, WARSAW));
+
+        // Quarter of the equator.
+        approx::assert_relative_eq!(
+            EARTH_RADIUS * std::f64::consts::FRAC_PI_2,
+            distance(Position::new(0., 0.), Position::new(90., 0.))
+        );
+    }
+
+    #[test]
+    fn bearing_to_the_cardinal_directions() {
+        let origin = Position::new(0., 0.);
+        approx::assert_relative_eq!(0., bearing(origin, Position::new(0., 10.)));
+        approx::assert_relative_eq!(90., bearing(origin, Position::new(10., 0.)));
+        approx::assert_relative_eq!(180., bearing(origin, Position::new(0., -10.)));
+        approx::assert_relative_eq!(270., bearing(origin, Position::new(-10., 0.)));
+
+        // Wroclaw is south-west of Warsaw.
+        let bearing = bearing(WARSAW, WROCLAW);
+        assert!((180. ..270.).contains(&bearing), "{bearing}");
+    }
+
+    #[test]
+    fn destination_is_where_bearing_and_distance_lead() {
+        let destination = destination(WARSAW, bearing(WARSAW, WROCLAW), distance(WARSAW, WROCLAW));
+        approx::assert_relative_eq!(WROCLAW.x(), destination.x(), epsilon = 1e-6);
+        approx::assert_relative_eq!(WROCLAW.y(), destination.y(), epsilon = 1e-6);
+
+        // Crossing the antimeridian.
+        let destination = super::destination(Position::new(179.5, 0.), 90., 111_195.);
+        approx::assert_relative_eq!(-179.5, destination.x(), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn length_and_area() {
+        let square = [
+            Position::new(0., 0.),
+            Position::new(1., 0.),
+            Position::new(1., 1.),
+            Position::new(0., 1.),
+        ];
+        let side = distance(square[0], square[1]);
+
+        approx::assert_relative_eq!(3. * side, length(&square), max_relative = 1e-3);
+        approx::assert_relative_eq!(side * side, area(&square), max_relative = 1e-3);
+
+        let mut reversed = square;
+        reversed.reverse();
+        approx::assert_relative_eq!(area(&square), area(&reversed));
+        assert_eq!(0., area(&square[..2]));
+    }
+
+    #[test]
+    fn great_circle_goes_north_of_the_straight_line() {
+        let new_york = Position::new(-74.006, 40.7128);
+        let madrid = Position::new(-3.7038, 40.4168);
+
+        let path = great_circle(&[new_york, madrid], 10);
+        assert_eq!(11, path.len());
+        assert_eq!(new_york, path[0]);
+        approx::assert_relative_eq!(madrid.x(), path[10].x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(madrid.y(), path[10].y(), epsilon = 1e-9);
+
+        // Shortest path bulges towards the pole.
+        assert!(path[5].y() > 45.);
+
+        // Legs share their points.
+        assert_eq!(21, great_circle(&[new_york, madrid, new_york], 10).len());
+        assert_eq!(vec![madrid], great_circle(&[madrid], 10));
+    }
+
+    #[test]
+    fn great_circle_longitudes_are_continuous() {
+        let tokyo = Position::new(139.6917, 35.6895);
+        let san_francisco = Position::new(-122.4194, 37.7749);
+
+        let path = great_circle(&[tokyo, san_francisco], 20);
+        assert!(path
+            .windows(2)
+            .all(|leg| (leg[1].x() - leg[0].x()).abs() < 30.));
+        approx::assert_relative_eq!(san_francisco.x() + 360., path[20].x(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn great_circle_between_antipodes() {
+        let (from, to) = (Position::new(20., 10.), Position::new(-160., -10.));
+
+        let path = great_circle(&[from, to], 4);
+        assert_eq!(5, path.len());
+        assert!(path.iter().all(|p| p.x().is_finite() && p.y().is_finite()));
+
+        // Along the meridian, over the north pole.
+        approx::assert_relative_eq!(20., path[1].x(), epsilon = 1e-5);
+        approx::assert_relative_eq!(55., path[1].y(), epsilon = 1e-5);
+        approx::assert_relative_eq!(80., path[2].y(), epsilon = 1e-5);
+        approx::assert_relative_eq!(to.y(), path[4].y(), epsilon = 1e-5);
+        approx::assert_relative_eq!(0., (path[4].x() - to.x()).rem_euclid(360.), epsilon = 1e-5);
+
+        let middle = intermediate(Position::new(0., 0.), Position::new(180., 0.), 0.5);
+        approx::assert_relative_eq!(90., middle.y(), epsilon = 1e-5);
+
+        // From the pole, it goes along the meridian too.
+        let pole = Position::new(0., 90.);
+        let middle = intermediate(pole, Position::new(0., -90.), 0.5);
+        approx::assert_relative_eq!(0., middle.y(), epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,372 @@
+//! Configuration of the HTTP client used to download the tiles.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use web_time::Instant;
+
+/// Options of the HTTP requests sent to the tile server. Many servers require an identifying
+/// `User-Agent` (OpenStreetMap does), or API keys passed in the headers.
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    /// Value of the `User-Agent` header. Default is "Walkers".
+    pub user_agent: String,
+
+    /// Additional headers sent with each request.
+    pub headers: Vec<(String, String)>,
+
+    /// URL of the proxy for all requests, e.g. `http://proxy.example.com:8080`. Not supported on
+    /// the web, where the browser's settings apply.
+    pub proxy: Option<String>,
+
+    /// Time after which a request is abandoned. Not supported on the web.
+    pub timeout: Option<Duration>,
+
+    /// How many tiles are downloaded at the same time. Default is 6, which is what the browsers
+    /// allow per host.
+    pub max_parallel_downloads: usize,
+
+    /// How many tiles can wait for a download slot. Each tile waits only once, no matter how
+    /// many times it is asked for. When there are more, the ones which were asked for least
+    /// recently are dropped, and counted in [`crate::TilesStats::dropped`]. They are asked for
+    /// again if they are still needed. Default is 512.
+    pub max_queued_tiles: usize,
+
+    /// How many times a tile is requested before giving up, when the server is not available
+    /// or responds with a transient error, like 503. Default is 3.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled after each failed attempt. Default is 500 ms.
+    pub retry_delay: Duration,
+
+    /// When all attempts failed, the tile is requested again when needed, but not sooner than
+    /// after this time. Default is one minute.
+    pub failed_tile_cooldown: Duration,
+
+    /// Limit of the rate at which the downloads are started, e.g. to comply with the tile
+    /// server's usage policy. Tiles found in the disk cache, and the retries, do not count.
+    /// Default is no limit.
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// Rate of the requests, enforced with a token bucket: it holds up to `burst` tokens, refilled at
+/// `requests_per_second`, and each request takes one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+
+    /// How many requests can be sent at once, after a period of inactivity.
+    pub burst: u32,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: "Walkers".to_string(),
+            headers: Vec::new(),
+            proxy: None,
+            timeout: None,
+            max_parallel_downloads: 6,
+            max_queued_tiles: 512,
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(500),
+            failed_tile_cooldown: Duration::from_secs(60),
+            rate_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid HTTP options: {0}")]
+pub struct HttpOptionsError(String);
+
+/// HTTP client, along with the options which are not a part of the `reqwest::Client`.
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    pub client: reqwest::Client,
+    pub max_parallel_downloads: usize,
+    pub max_queued_tiles: usize,
+    pub max_attempts: u32,
+    pub retry_delay: Duration,
+    pub failed_tile_cooldown: Duration,
+    pub rate_limit: Option<RateLimit>,
+
+    /// Whether the server cannot be reached, see [`Connectivity`].
+    pub offline: Arc<AtomicBool>,
+}
+
+impl HttpClient {
+    /// Delay before given (counting from 1) retry.
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        self.retry_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+impl HttpOptions {
+    pub(crate) fn client(&self) -> Result<HttpClient, HttpOptionsError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|_| HttpOptionsError(format!("invalid header name '{}'", name)))?;
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|_| HttpOptionsError(format!("invalid value of the '{}' header", name)))?;
+            headers.append(name, value);
+        }
+
+        let builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .default_headers(headers);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let mut builder = builder;
+            if let Some(proxy) = &self.proxy {
+                let proxy = reqwest::Proxy::all(proxy)
+                    .map_err(|err| HttpOptionsError(format!("invalid proxy: {}", err)))?;
+                builder = builder.proxy(proxy);
+            }
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            builder
+        };
+
+        let client = builder
+            .build()
+            .map_err(|err| HttpOptionsError(err.to_string()))?;
+
+        Ok(HttpClient {
+            client,
+            max_parallel_downloads: self.max_parallel_downloads.max(1),
+            max_queued_tiles: self.max_queued_tiles.max(1),
+            max_attempts: self.max_attempts.max(1),
+            retry_delay: self.retry_delay,
+            failed_tile_cooldown: self.failed_tile_cooldown,
+            rate_limit: self.rate_limit,
+            offline: Default::default(),
+        })
+    }
+}
+
+/// Client with the default options.
+pub(crate) fn default_client() -> HttpClient {
+    let options = HttpOptions::default();
+    options.client().unwrap_or_else(|err| {
+        log::error!("Could not create the HTTP client: {}.", err);
+        HttpClient {
+            client: reqwest::Client::new(),
+            max_parallel_downloads: options.max_parallel_downloads,
+            max_queued_tiles: options.max_queued_tiles,
+            max_attempts: options.max_attempts,
+            retry_delay: options.retry_delay,
+            failed_tile_cooldown: options.failed_tile_cooldown,
+            rate_limit: options.rate_limit,
+            offline: Default::default(),
+        }
+    })
+}
+
+/// Token bucket enforcing the [`RateLimit`].
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst.max(1) as f64,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token if there is one. Otherwise, return how long to wait for it.
+    pub fn acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let capacity = self.limit.burst.max(1) as f64;
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.requests_per_second).min(capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            Ok(())
+        } else if self.limit.requests_per_second > 0. {
+            let wait = (1. - self.tokens) / self.limit.requests_per_second;
+            Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+        } else {
+            log::warn!("Rate limit does not allow any requests.");
+            Err(Duration::MAX)
+        }
+    }
+}
+
+/// After how many consecutive connection errors the network is considered unavailable.
+const OFFLINE_AFTER_ERRORS: u32 = 3;
+
+/// Time between the attempts to reach the server while offline. Doubled after each failed one,
+/// up to [`MAX_PROBE_INTERVAL`].
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether the tile server can be reached. When too many requests in a row fail to connect, the
+/// downloads stop, except for an occasional probe, which tells when the network is back.
+pub(crate) struct Connectivity {
+    /// Shared with the [`crate::Tiles`], so the application can tell the user.
+    offline: Arc<AtomicBool>,
+    errors: u32,
+    next_probe: Instant,
+    probe_interval: Duration,
+}
+
+impl Connectivity {
+    pub fn new(offline: Arc<AtomicBool>) -> Self {
+        Self {
+            offline,
+            errors: 0,
+            next_probe: Instant::now(),
+            probe_interval: PROBE_INTERVAL,
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Whether a request can go out, i.e. the network is available, or it is time to check if it
+    /// came back.
+    pub fn can_download(&self, now: Instant) -> bool {
+        !self.is_offline() || now >= self.next_probe
+    }
+
+    /// Call when a request is sent, to schedule the next probe.
+    pub fn downloading(&mut self, now: Instant) {
+        if self.is_offline() {
+            self.next_probe = now + self.probe_interval;
+            self.probe_interval = (self.probe_interval * 2).min(MAX_PROBE_INTERVAL);
+        }
+    }
+
+    /// Record whether the server was reached, even if it responded with an error.
+    pub fn record(&mut self, connected: bool) {
+        if connected {
+            self.errors = 0;
+            if self.is_offline() {
+                log::info!("Tile server is reachable again.");
+                self.offline.store(false, Ordering::Relaxed);
+            }
+        } else {
+            self.errors += 1;
+            if self.errors >= OFFLINE_AFTER_ERRORS && !self.is_offline() {
+                log::warn!(
+                    "Could not connect to the tile server {} times in a row, going offline.",
+                    self.errors
+                );
+                self.offline.store(true, Ordering::Relaxed);
+                self.next_probe = Instant::now() + PROBE_INTERVAL;
+                self.probe_interval = PROBE_INTERVAL * 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_options_are_reported() {
+        assert!(HttpOptions::default().client().is_ok());
+
+        let options = HttpOptions {
+            headers: vec![("Bad Header".to_string(), "value".to_string())],
+            ..Default::default()
+        };
+        assert!(options.client().is_err());
+
+        let options = HttpOptions {
+            headers: vec![("X-Api-Key".to_string(), "new\nline".to_string())],
+            ..Default::default()
+        };
+        assert!(options.client().is_err());
+
+        let options = HttpOptions {
+            proxy: Some("not a proxy".to_string()),
+            ..Default::default()
+        };
+        assert!(options.client().is_err());
+    }
+
+    #[test]
+    fn rate_limiter_allows_bursts_and_refills() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(
+            RateLimit {
+                requests_per_second: 2.,
+                burst: 3,
+            },
+            start,
+        );
+
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire(start));
+        }
+        assert_eq!(Err(Duration::from_millis(500)), limiter.acquire(start));
+
+        let later = start + Duration::from_millis(250);
+        assert_eq!(Err(Duration::from_millis(250)), limiter.acquire(later));
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(Ok(()), limiter.acquire(later));
+        assert!(limiter.acquire(later).is_err());
+
+        // Bucket does not grow beyond the burst.
+        let later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire(later));
+        }
+        assert!(limiter.acquire(later).is_err());
+    }
+
+    #[test]
+    fn going_offline_and_back() {
+        let mut connectivity = Connectivity::new(Default::default());
+        let now = Instant::now();
+
+        connectivity.record(false);
+        connectivity.record(false);
+        connectivity.record(true);
+        connectivity.record(false);
+        connectivity.record(false);
+        assert!(!connectivity.is_offline());
+        assert!(connectivity.can_download(now));
+
+        connectivity.record(false);
+        assert!(connectivity.is_offline());
+        assert!(!connectivity.can_download(now));
+
+        // Probes are sent less and less often.
+        let probe = connectivity.next_probe;
+        assert!(connectivity.can_download(probe));
+        connectivity.downloading(probe);
+        assert!(!connectivity.can_download(probe + PROBE_INTERVAL));
+        assert!(connectivity.can_download(probe + 2 * PROBE_INTERVAL));
+
+        connectivity.record(true);
+        assert!(!connectivity.is_offline());
+        assert!(connectivity.can_download(now));
+    }
+
+    #[test]
+    fn retry_delay_is_doubled() {
+        let client = default_client();
+        assert_eq!(Duration::from_millis(500), client.retry_delay(1));
+        assert_eq!(Duration::from_millis(1000), client.retry_delay(2));
+        assert_eq!(Duration::from_millis(2000), client.retry_delay(3));
+    }
+}
@@ -0,0 +1,115 @@
+//! Runtime driving the downloads. Natively, it is a dedicated thread running Tokio, unless the
+//! application provides its own executor. On the web, where there are no threads, the futures
+//! are driven by the browser's event loop instead.
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// `Send`, but only where there are threads. Futures on the web are not `Send`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait MaybeSend: Send {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) trait MaybeSend {}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// Download task, to be run by a [`Spawner`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Download task, to be run by a [`Spawner`].
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Executor running the download tasks, for applications which do not want walkers to start its
+/// own thread. It is implemented for [`tokio::runtime::Handle`], so an existing Tokio runtime
+/// can be used. Note that downloads use `reqwest` and wait between retries, so the runtime needs
+/// Tokio's IO and time drivers enabled.
+pub trait Spawner {
+    fn spawn(&self, future: BoxFuture);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, future: BoxFuture) {
+        tokio::runtime::Handle::spawn(self, future);
+    }
+}
+
+/// Walkers' own runtime, used when the application does not provide a [`Spawner`].
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Runtime(crate::tokio::TokioRuntimeThread);
+
+/// Walkers' own runtime, used when the application does not provide a [`Spawner`].
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct Runtime;
+
+impl Runtime {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn new() -> std::io::Result<Self> {
+        crate::tokio::TokioRuntimeThread::new().map(Self)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn new() -> std::io::Result<Self> {
+        Ok(Self)
+    }
+}
+
+impl Spawner for Runtime {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn(&self, future: BoxFuture) {
+        self.0.runtime.spawn(future);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn spawn(&self, future: BoxFuture) {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+}
+
+/// Spawn the `future` using the `spawner`, or walkers' own runtime if there is none. In the
+/// latter case, returned runtime must be kept as long as the future is needed. If the runtime
+/// cannot be started, the future is dropped, which its channels will tell.
+pub(crate) fn spawn<F>(spawner: Option<&dyn Spawner>, future: F) -> Option<Runtime>
+where
+    F: Future + MaybeSend + 'static,
+{
+    let future = Box::pin(async move {
+        let _ = future.await;
+    });
+
+    if let Some(spawner) = spawner {
+        spawner.spawn(future);
+        None
+    } else {
+        match Runtime::new() {
+            Ok(runtime) => {
+                runtime.spawn(future);
+                Some(runtime)
+            }
+            Err(err) => {
+                log::error!(
+                    "Could not start the IO thread, nothing will be downloaded: {}.",
+                    err
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    gloo_timers::future::sleep(duration).await;
+}
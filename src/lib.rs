@@ -1,13 +1,51 @@
 #![doc = include_str!("../README.md")]
-#![deny(clippy::unwrap_used)]
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
 
+mod atlas;
+mod compositor;
+mod controls;
+mod debug;
+mod disk_cache;
+pub mod extras;
+mod filter;
+pub mod geodesy;
+mod http;
+mod io;
 mod map;
 mod mercator;
+mod minimap;
+#[cfg(feature = "mvt")]
+pub mod mvt;
+mod popup;
+mod prefetch;
+pub mod projection;
+mod snapshot;
+pub mod sources;
+pub mod terrain;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
 mod tiles;
+#[cfg(not(target_arch = "wasm32"))]
 mod tokio;
+mod tools;
 mod zoom;
 
-pub use map::{Map, MapCenterMode, MapMemory};
-pub use mercator::{Position, PositionExt, screen_to_position};
+pub use compositor::{Compositor, CompositorError};
+pub use controls::Controls;
+pub use filter::TileFilter;
+pub use http::{HttpOptions, HttpOptionsError, RateLimit};
+pub use io::{BoxFuture, Spawner};
+pub use map::{
+    AttributionStyle, Gesture, Gestures, Map, MapCenterMode, MapMemory, MapResponse, Plugin,
+    Projector,
+};
+pub use mercator::{
+    meters_per_point, screen_to_position, Position, PositionExt, PositionExtra, TileId,
+};
+pub use minimap::MiniMap;
+pub use popup::Popup;
+pub use prefetch::Progress;
+pub use sources::openstreetmap;
+pub use tiles::{Placeholder, Tiles, TilesStats};
+pub use tools::Tool;
 pub use zoom::Zoom;
-pub use {tiles::openstreetmap, tiles::Tiles};
@@ -1,98 +1,1014 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::HashSet;
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+use std::time::Duration;
 
-use egui::{Mesh, Painter, Pos2, Response, Sense, Ui, Widget};
+use egui::{
+    emath::Rot2, output::OpenUrl, Align2, Color32, CursorIcon, FontId, Key, Mesh, Modifiers,
+    Painter, PointerButton, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2, Widget,
+};
 
 use crate::{
-    mercator::{screen_to_position, PositionExt, TileId},
-    Position, Tiles, Zoom,
+    atlas,
+    controls::{self, Controls},
+    debug,
+    extras::MyPositionMarker,
+    mercator::{TileId, TILE_SIZE},
+    minimap::{self, MiniMap},
+    projection::{self, from_pixels, moved, to_pixels, Projection, WebMercator},
+    snapshot,
+    sources::Attribution,
+    tiles::TileStatus,
+    tools::{self, Tool, ToolState},
+    Position, PositionExtra, Tiles, Zoom,
 };
+use geo_types::Geometry;
+
+/// Plugins allow drawing custom shapes on the map. After the tiles are drawn, [`Plugin::draw`] is
+/// called for each plugin, ordered by their [`Plugin::z_index`], and then in the order they were
+/// added to the [`Map`].
+///
+/// Everything is drawn in this order:
+///
+/// 1. The first layer of tiles.
+/// 2. Plugins with a negative [`Plugin::z_index`].
+/// 3. The other layers, added with [`Map::with_layer`].
+/// 4. The remaining plugins.
+/// 5. "My position" marker, the debug overlay, the [`Map::with_tool`] drawing, the loading
+///    indicator and the attribution.
+/// 6. The [`Controls`] and the [`MiniMap`], which are egui areas, so they are above the whole
+///    map.
+pub trait Plugin {
+    /// Draw the plugin's content. `painter` is clipped to the map's area, and `projector` can
+    /// be used to get screen coordinates of geographical positions.
+    fn draw(&mut self, painter: &Painter, projector: &Projector);
+
+    /// Handle the input before the map does, e.g. to find the clicked marker. `response` is the
+    /// map's, and `projector` is the one from before the map moved in this frame. Plugins are
+    /// asked in the reverse order, so the one drawn on top goes first. Returning `true` means
+    /// the drag was consumed, so the map and the remaining plugins do not get it.
+    fn input(&mut self, _response: &Response, _projector: &Projector) -> bool {
+        false
+    }
+
+    /// Whether [`Plugin::draw`] is called once for every copy of the world visible on the map,
+    /// when it repeats horizontally. Plugins which draw in the screen coordinates, like a scale
+    /// bar, or draw the copies themselves with [`Projector::world_copies`], return `false`.
+    fn repeated(&self) -> bool {
+        true
+    }
+
+    /// Plugins with the higher values are drawn on top of the ones with the lower. Negative ones
+    /// go under the tile layers added with [`Map::with_layer`], e.g. a route under the layer of
+    /// labels. Default is 0.
+    fn z_index(&self) -> i32 {
+        0
+    }
+}
+
+/// Allows passing plugins which need to outlive the [`Map`], e.g. because they keep a cache.
+impl<T: Plugin + ?Sized> Plugin for &mut T {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        (**self).draw(painter, projector);
+    }
+
+    fn input(&mut self, response: &Response, projector: &Projector) -> bool {
+        (**self).input(response, projector)
+    }
+
+    fn repeated(&self) -> bool {
+        (**self).repeated()
+    }
+
+    fn z_index(&self) -> i32 {
+        (**self).z_index()
+    }
+}
 
 /// Slippy map widget.
-pub struct Map<'a, 'b> {
-    tiles: Option<&'b mut Tiles>,
+pub struct Map<'a, 'b, 'c> {
+    /// Tile layers with their opacity, bottom first.
+    layers: Vec<(&'b mut Tiles, f32)>,
     memory: &'a mut MapMemory,
     my_position: Position,
+    plugins: Vec<Box<dyn Plugin + 'c>>,
+    gestures: Gestures,
+    prefetch: u32,
+    attribution_style: AttributionStyle,
+    controls: Option<Controls>,
+    minimap: Option<MiniMap>,
+    my_position_marker: Option<MyPositionMarker>,
+    my_heading: Option<f32>,
+    my_accuracy: Option<f32>,
+    tool: Option<Tool>,
+    debug_overlay: bool,
+    loading_indicator: bool,
 }
 
-impl<'a, 'b> Map<'a, 'b> {
+impl<'a, 'b, 'c> Map<'a, 'b, 'c> {
     pub fn new(
         tiles: Option<&'b mut Tiles>,
         memory: &'a mut MapMemory,
         my_position: Position,
     ) -> Self {
         Self {
-            tiles,
+            layers: tiles.into_iter().map(|tiles| (tiles, 1.)).collect(),
             memory,
             my_position,
+            plugins: Vec::default(),
+            gestures: Gestures::default(),
+            prefetch: 0,
+            attribution_style: AttributionStyle::default(),
+            controls: None,
+            minimap: None,
+            my_position_marker: Some(MyPositionMarker::default()),
+            my_heading: None,
+            my_accuracy: None,
+            tool: None,
+            debug_overlay: false,
+            loading_indicator: false,
+        }
+    }
+
+    /// Which gestures move the map, and how.
+    pub fn gestures(mut self, gestures: Gestures) -> Self {
+        self.gestures = gestures;
+        self
+    }
+
+    /// Request `rings` rows of tiles around the visible area, and the tiles of the next zoom level
+    /// around the center, so they are already loaded when the map is panned or zoomed in. They are
+    /// requested after the visible ones. Disabled (0) by default.
+    pub fn prefetch(mut self, rings: u32) -> Self {
+        self.prefetch = rings;
+        self
+    }
+
+    /// Where and how the attributions of the tile layers are shown.
+    pub fn attribution_style(mut self, style: AttributionStyle) -> Self {
+        self.attribution_style = style;
+        self
+    }
+
+    /// Direction the user is heading to, in degrees clockwise from the north. It is shown by the
+    /// marker of "my position", and kept up in [`MapCenterMode::MyPositionHeadingUp`].
+    pub fn my_heading(mut self, heading: f32) -> Self {
+        self.my_heading = Some(heading);
+        self
+    }
+
+    /// "My position" along with its heading and accuracy, when they are known, which replaces
+    /// the position given to [`Map::new`].
+    pub fn my_position_extra(mut self, fix: PositionExtra) -> Self {
+        self.my_position = fix.position;
+        self.my_heading = fix.heading.or(self.my_heading);
+        self.my_accuracy = fix.accuracy;
+        self
+    }
+
+    /// How "my position" is drawn, e.g. with the heading and accuracy of the GPS fix. `None`
+    /// hides it. By default, it is a blue dot.
+    pub fn my_position_marker(mut self, marker: Option<MyPositionMarker>) -> Self {
+        self.my_position_marker = marker;
+        self
+    }
+
+    /// Let the user draw on the map with the tool. The finished geometry is returned in
+    /// [`MapResponse::drawn`]. Double-click finishes drawing, so it does not zoom in.
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.tool = Some(tool);
+        self
+    }
+
+    /// Draw the boundaries of the tiles, along with their ids and whether they are loaded, and
+    /// a summary of the zoom and the tile caches. Useful for diagnosing projection or caching
+    /// issues.
+    pub fn debug_overlay(mut self, enabled: bool) -> Self {
+        self.debug_overlay = enabled;
+        self
+    }
+
+    /// Draw a spinner at the top of the map while some of the visible tiles are still being
+    /// downloaded. See also [`MapResponse::loading`].
+    pub fn loading_indicator(mut self, enabled: bool) -> Self {
+        self.loading_indicator = enabled;
+        self
+    }
+
+    /// Show built-in buttons over the map: zoom in and out, center at my position and toggle the
+    /// fullscreen mode.
+    pub fn with_controls(mut self, controls: Controls) -> Self {
+        self.controls = Some(controls);
+        self
+    }
+
+    /// Show a zoomed-out overview in the corner of the map, with the visible area outlined.
+    pub fn with_minimap(mut self, minimap: MiniMap) -> Self {
+        self.minimap = Some(minimap);
+        self
+    }
+
+    /// Add another layer of tiles, drawn on top of the previous ones with given `opacity`
+    /// (between 0 and 1). For example, a semi-transparent weather radar over the OpenStreetMap.
+    pub fn with_layer(mut self, tiles: &'b mut Tiles, opacity: f32) -> Self {
+        self.layers.push((tiles, opacity.clamp(0., 1.)));
+        self
+    }
+
+    /// Add a plugin which will be drawn on top of the tiles.
+    pub fn with_plugin(mut self, plugin: impl Plugin + 'c) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+}
+
+/// Look of the label with the attributions of the tile layers, which most providers require to be
+/// visible on the map.
+#[derive(Clone, Debug)]
+pub struct AttributionStyle {
+    /// Corner of the map where the label is shown. `None` hides it, e.g. when the attribution
+    /// is displayed elsewhere in the application.
+    pub corner: Option<Align2>,
+    pub font: FontId,
+    pub text_color: Color32,
+    pub background: Color32,
+}
+
+impl Default for AttributionStyle {
+    fn default() -> Self {
+        Self {
+            corner: Some(Align2::RIGHT_BOTTOM),
+            font: FontId::proportional(11.),
+            text_color: Color32::from_gray(40),
+            background: Color32::from_white_alpha(190),
+        }
+    }
+}
+
+/// Gestures which move the [`Map`], see [`Map::gestures`]. Plugins get the input regardless.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gestures {
+    /// Button which drags the map. `None` disables dragging, e.g. when the map is only a preview.
+    pub drag: Option<PointerButton>,
+
+    /// Modifiers which need to be held for the mouse wheel to zoom. `None` disables it, so the
+    /// map does not steal scrolling of the page it is on. Note that egui reports `Ctrl` + wheel
+    /// as a pinch, so requiring `Ctrl` is done by disabling the wheel and keeping
+    /// [`Gestures::pinch_zoom`].
+    pub scroll_zoom: Option<Modifiers>,
+
+    /// Whether pinching (or `Ctrl` + wheel) zooms.
+    pub pinch_zoom: bool,
+
+    /// Whether the map can be rotated with two fingers, see [`MapMemory::rotation`].
+    pub rotation: bool,
+
+    /// Whether double-click (or double-tap) zooms in, centering the map at the clicked position.
+    pub double_click_zoom: bool,
+
+    /// Whether the map can be moved with the keyboard, once it is focused by clicking it (or with
+    /// Tab): arrows pan, `+` and `-` zoom, and `Home` centers the map at "my position".
+    pub keyboard: bool,
+}
+
+impl Default for Gestures {
+    /// Everything, but the rotation and the keyboard.
+    fn default() -> Self {
+        Self {
+            drag: Some(PointerButton::Primary),
+            scroll_zoom: Some(Modifiers::NONE),
+            pinch_zoom: true,
+            rotation: false,
+            double_click_zoom: true,
+            keyboard: false,
+        }
+    }
+}
+
+impl Gestures {
+    /// None of the gestures, which makes the map static, unless moved by the application.
+    pub fn none() -> Self {
+        Self {
+            drag: None,
+            scroll_zoom: None,
+            pinch_zoom: false,
+            rotation: false,
+            double_click_zoom: false,
+            keyboard: false,
+        }
+    }
+
+    /// Zoom levels to change by, given the input of this frame.
+    fn zoom_delta(&self, scroll: f32, zoom_delta: f32, modifiers: Modifiers) -> f64 {
+        let mut delta = 0.;
+        if self
+            .scroll_zoom
+            .is_some_and(|required| modifiers.contains(required))
+        {
+            delta += scroll as f64 / SCROLL_POINTS_PER_ZOOM_LEVEL;
+        }
+        if self.pinch_zoom {
+            delta += (zoom_delta as f64).log2();
+        }
+        delta
+    }
+}
+
+/// Transforms geographical positions into screen coordinates of a particular map widget. The one
+/// used to draw the map is given to the [`Plugin`]s and returned in the [`MapResponse`], but it
+/// can also be created directly.
+#[derive(Clone, Debug)]
+pub struct Projector {
+    clip_rect: Rect,
+    projection: Arc<dyn Projection>,
+    center: Position,
+
+    /// Center, projected at the zoom, see [`to_pixels`].
+    map_center: (f64, f64),
+    zoom: f64,
+
+    /// Clockwise, in radians.
+    rotation: f32,
+}
+
+impl Projector {
+    /// Projector of the map drawn in the `clip_rect`, centered at `map_center`, in the Web
+    /// Mercator projection.
+    pub fn new(clip_rect: Rect, map_center: Position, zoom: f64) -> Self {
+        Self {
+            clip_rect,
+            projection: Arc::new(WebMercator),
+            center: map_center,
+            map_center: to_pixels(&WebMercator, map_center, zoom),
+            zoom,
+            rotation: 0.,
+        }
+    }
+
+    /// Same projector, but for the tiles made in another projection.
+    pub fn with_projection(self, projection: Arc<dyn Projection>) -> Self {
+        Self {
+            map_center: to_pixels(&*projection, self.center, self.zoom),
+            projection,
+            ..self
+        }
+    }
+
+    /// Same projector, but for the map drawn in another area.
+    pub(crate) fn with_clip_rect(&self, clip_rect: Rect) -> Self {
+        Self {
+            clip_rect,
+            ..self.clone()
+        }
+    }
+
+    /// Same projector, but for the map rotated clockwise by `rotation` radians.
+    pub fn rotated(self, rotation: f32) -> Self {
+        Self { rotation, ..self }
+    }
+
+    /// Screen coordinates of the geographical position.
+    pub fn to_screen(&self, position: Position) -> Pos2 {
+        let (x, y) = to_pixels(&*self.projection, position, self.zoom);
+        let offset = Vec2::new(
+            (x - self.map_center.0) as f32,
+            (y - self.map_center.1) as f32,
+        );
+        self.rotate(self.clip_rect.center() + offset)
+    }
+
+    /// Geographical position at the screen coordinates.
+    pub fn to_position(&self, screen_position: Pos2) -> Position {
+        let offset = Rot2::from_angle(-self.rotation) * (screen_position - self.clip_rect.center());
+        let (x, y) = self.map_center;
+        from_pixels(
+            &*self.projection,
+            (x + offset.x as f64, y + offset.y as f64),
+            self.zoom,
+        )
+    }
+
+    /// Same position, but in the original world, if it is in one of its copies.
+    pub(crate) fn wrap(&self, position: Position) -> Position {
+        projection::wrap(&*self.projection, position)
+    }
+
+    /// South-west and north-east corners of the smallest box (of latitudes and longitudes) which
+    /// contains the whole visible area, e.g. for querying the features to draw. Longitudes are
+    /// not wrapped, so they go beyond ±180° when the antimeridian is in view.
+    pub fn visible_bounds(&self) -> (Position, Position) {
+        // Projections other than Web Mercator bend the edges, so not only the corners count.
+        const STEPS: usize = 8;
+        let rect = self.clip_rect;
+        let (min, max) = (0..STEPS)
+            .flat_map(|step| {
+                let t = step as f32 / STEPS as f32;
+                [
+                    rect.lerp_inside(Vec2::new(t, 0.)),
+                    rect.lerp_inside(Vec2::new(1., t)),
+                    rect.lerp_inside(Vec2::new(1. - t, 1.)),
+                    rect.lerp_inside(Vec2::new(0., 1. - t)),
+                ]
+            })
+            .map(|point| self.to_position(point))
+            .fold(
+                (
+                    (f64::INFINITY, f64::INFINITY),
+                    (f64::NEG_INFINITY, f64::NEG_INFINITY),
+                ),
+                |(min, max), position| {
+                    (
+                        (min.0.min(position.x()), min.1.min(position.y())),
+                        (max.0.max(position.x()), max.1.max(position.y())),
+                    )
+                },
+            );
+        (Position::new(min.0, min.1), Position::new(max.0, max.1))
+    }
+
+    pub(crate) fn projection(&self) -> &dyn Projection {
+        &*self.projection
+    }
+
+    pub(crate) fn projection_arc(&self) -> Arc<dyn Projection> {
+        self.projection.clone()
+    }
+
+    /// Angle (in radians) by which the map is rotated clockwise.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Rotate the point around the center of the widget, like the whole map is. Used with the
+    /// screen areas given by [`Projector::visible_tiles`].
+    pub(crate) fn rotate(&self, point: Pos2) -> Pos2 {
+        let center = self.clip_rect.center();
+        center + Rot2::from_angle(self.rotation) * (point - center)
+    }
+
+    /// Part of the map which covers the widget, before it is rotated. It is bigger than the
+    /// widget itself, unless the rotation is a multiple of the right angle.
+    pub(crate) fn unrotated_clip_rect(&self) -> Rect {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (sin, cos) = (sin.abs(), cos.abs());
+        let size = self.clip_rect.size();
+        Rect::from_center_size(
+            self.clip_rect.center(),
+            Vec2::new(size.x * cos + size.y * sin, size.x * sin + size.y * cos),
+        )
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Projectors of the copies of the world visible in the widget, starting with this one.
+    /// There is more than one only if the projection wraps (see [`Projection::wraps`]), and the
+    /// map is zoomed out or shows the antimeridian.
+    pub fn world_copies(&self) -> Vec<Projector> {
+        if !self.projection.wraps() {
+            return vec![self.clone()];
         }
+
+        let width = projection::world_size(self.zoom);
+        let clip_rect = self.unrotated_clip_rect();
+
+        // Screen position of the world's left edge.
+        let left = self.clip_rect.center().x as f64 - self.map_center.0;
+        let first = ((clip_rect.min.x as f64 - left) / width).floor() as i32;
+        let last = ((clip_rect.max.x as f64 - left) / width).ceil() as i32;
+
+        std::iter::once(0)
+            .chain((first..last).filter(|copy| *copy != 0))
+            .map(|copy| {
+                let map_center = (self.map_center.0 - copy as f64 * width, self.map_center.1);
+                Self {
+                    center: from_pixels(&*self.projection, map_center, self.zoom),
+                    map_center,
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Screen area covered by the map.
+    pub fn clip_rect(&self) -> Rect {
+        self.clip_rect
+    }
+
+    /// Tiles of given zoom level which cover the widget, along with their screen areas (before
+    /// the rotation). If the world repeats, tiles of all its visible copies are included.
+    pub(crate) fn visible_tiles(&self, tile_zoom: u8) -> Vec<(TileId, Rect)> {
+        let tile_size = TILE_SIZE as f64 * 2f64.powf(self.zoom - tile_zoom as f64);
+        let tiles_count = 1i64 << tile_zoom;
+        let wraps = self.projection.wraps();
+
+        // Offsets from the map center are small, unlike the positions in the whole world.
+        let center = self.clip_rect.center();
+        let (center_x, center_y) = self.map_center;
+
+        let range = |min: f64, max: f64, wraps: bool| {
+            let first = (min / tile_size).floor() as i64;
+            let last = (max / tile_size).ceil() as i64;
+            if wraps {
+                first..last
+            } else {
+                first.max(0)..last.min(tiles_count)
+            }
+        };
+
+        let clip_rect = self.unrotated_clip_rect().translate(-center.to_vec2());
+        let xs = range(
+            center_x + clip_rect.min.x as f64,
+            center_x + clip_rect.max.x as f64,
+            wraps,
+        );
+        let ys = range(
+            center_y + clip_rect.min.y as f64,
+            center_y + clip_rect.max.y as f64,
+            false,
+        );
+
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let tile_id = TileId {
+                    x: x.rem_euclid(tiles_count) as u32,
+                    y: y as u32,
+                    zoom: tile_zoom,
+                };
+                let min = center
+                    + Vec2::new(
+                        (x as f64 * tile_size - center_x) as f32,
+                        (y as f64 * tile_size - center_y) as f32,
+                    );
+                (
+                    tile_id,
+                    Rect::from_min_size(min, Vec2::splat(tile_size as f32)),
+                )
+            })
+            .collect()
+    }
+}
+
+/// How long the pointer needs to be pressed, without moving, for a long-press.
+const LONG_PRESS_DURATION: f64 = 0.5;
+
+/// Result of showing the [`Map`]. Besides the usual egui [`Response`], it tells what happened
+/// to the map in geographical terms.
+pub struct MapResponse {
+    pub response: Response,
+
+    /// Position clicked (or tapped) during this frame.
+    pub clicked_at: Option<Position>,
+
+    /// Position long-pressed (or clicked with the secondary button) during this frame. Typically
+    /// used to open a context menu.
+    pub long_pressed_at: Option<Position>,
+
+    /// Position under the mouse pointer, if it hovers over the map.
+    pub hovered_at: Option<Position>,
+
+    /// Fullscreen button of the [`Controls`] was clicked during this frame.
+    pub fullscreen_toggled: bool,
+
+    /// Projector used to draw the map in this frame. Useful for placing egui windows or other
+    /// widgets next to geographical positions.
+    pub projector: Projector,
+
+    /// Geometry finished with the [`Map::with_tool`] in this frame.
+    pub drawn: Option<Geometry>,
+
+    /// Some of the visible tiles are still being downloaded.
+    pub loading: bool,
+
+    /// Center, zoom, rotation or size of the map is different than in the previous frame (or
+    /// this is the first one), so the data shown for the visible area might need to be loaded.
+    pub changed: bool,
+
+    /// Geographical extent of the map, see [`Projector::visible_bounds`].
+    pub visible_bounds: (Position, Position),
+
+    /// Gesture which moved the map during this frame.
+    pub gesture: Option<Gesture>,
+}
+
+/// How the user moved the [`Map`], see [`MapResponse::gesture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    Drag,
+
+    /// Mouse wheel or pinch.
+    Zoom,
+
+    /// Twisting two fingers.
+    Rotation,
+    DoubleClick,
+    Keyboard,
+
+    /// Clicking or dragging the [`MiniMap`].
+    MiniMap,
+}
+
+impl Map<'_, '_, '_> {
+    /// Image `size` pixels big, of the tiles of all the layers, as they are currently viewed,
+    /// e.g. for exporting the map or making thumbnails. Plugins are not included. It is `None`
+    /// until all the tiles are loaded. They get requested, so call it again in one of the
+    /// following frames.
+    pub fn snapshot(&mut self, size: [u32; 2]) -> Option<image::RgbaImage> {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(size[0] as f32, size[1] as f32));
+        let projector = Projector::new(
+            rect,
+            self.memory.center(self.my_position),
+            *self.memory.zoom,
+        )
+        .with_projection(self.memory.projection.clone())
+        .rotated(self.memory.rotation);
+        snapshot::render(&projector, self.memory.zoom, &mut self.layers)
     }
 }
 
-impl Widget for Map<'_, '_> {
+impl Widget for Map<'_, '_, '_> {
     fn ui(self, ui: &mut Ui) -> Response {
-        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
+        self.show(ui).response
+    }
+}
+
+impl Map<'_, '_, '_> {
+    /// Show the map, like `ui.add(map)` does, but return the [`MapResponse`].
+    pub fn show(mut self, ui: &mut Ui) -> MapResponse {
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+
+        let fullscreen_toggled = self
+            .controls
+            .as_ref()
+            .is_some_and(|controls| controls::show(controls, ui, rect, self.memory));
+
+        self.memory.size = Some(rect.size());
+        self.memory.apply_pending_fit();
+
+        let projector = Projector::new(
+            rect,
+            self.memory.center(self.my_position),
+            *self.memory.zoom,
+        )
+        .with_projection(self.memory.projection.clone())
+        .rotated(self.memory.rotation);
+        self.plugins.sort_by_key(|plugin| plugin.z_index());
+        let drag_consumed = self
+            .plugins
+            .iter_mut()
+            .rev()
+            .any(|plugin| plugin.input(&response, &projector));
+
+        let following = self.memory.center_mode.clone();
+        let drag_button = self.gestures.drag.filter(|_| !drag_consumed);
+        let mut gesture = drag_button
+            .filter(|button| response.dragged_by(*button))
+            .map(|_| Gesture::Drag);
 
         self.memory
-            .center_mode
-            .screen_drag(&response, self.my_position, *self.memory.zoom);
+            .screen_drag(ui, &response, self.my_position, drag_button);
+        self.memory.animate(ui, self.my_position);
+
+        if response.hovered() {
+            gesture = self
+                .memory
+                .scroll_and_pinch(ui, &response, self.my_position, &self.gestures)
+                .or(gesture);
+        }
+
+        if self.gestures.keyboard && self.memory.keyboard(ui, &response, self.my_position) {
+            gesture = Some(Gesture::Keyboard);
+        }
+
+        if self.gestures.double_click_zoom && self.tool.is_none() && response.double_clicked() {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                self.memory
+                    .zoom_in_at(pointer - rect.center(), self.my_position);
+                gesture = Some(Gesture::DoubleClick);
+            }
+        }
+
+        if let Some(minimap) = &self.minimap {
+            let projector = Projector::new(
+                rect,
+                self.memory.center(self.my_position),
+                *self.memory.zoom,
+            )
+            .with_projection(self.memory.projection.clone())
+            .rotated(self.memory.rotation);
+            let tiles = self.layers.first_mut().map(|(tiles, _)| &mut **tiles);
+            if minimap::show(minimap, ui, &projector, tiles, self.memory) {
+                gesture = Some(Gesture::MiniMap);
+            }
+        }
+
+        self.memory.clamp();
+        let interacted = gesture.is_some();
 
-        let map_center = self.memory.center_mode.position(self.my_position);
+        let now = ui.input(|input| input.time);
+        if let Some(remaining) = self.memory.update_detached(following, interacted, now) {
+            ui.ctx().request_repaint_after(remaining);
+        }
+
+        if self.memory.center_mode == MapCenterMode::MyPositionHeadingUp {
+            if let Some(heading) = self.my_heading {
+                self.memory.rotation = -heading.to_radians();
+            }
+        }
+
+        let map_center = self.memory.center(self.my_position);
+        let projector = Projector::new(rect, map_center, *self.memory.zoom)
+            .with_projection(self.memory.projection.clone())
+            .rotated(self.memory.rotation);
+        let copies = projector.world_copies();
         let painter = ui.painter().with_clip_rect(rect);
+        let mut attributions = Vec::new();
+        let mut loading = false;
+        for (tiles, _) in &self.layers {
+            if let Some(attribution) = tiles.attribution() {
+                if !attributions.contains(attribution) {
+                    attributions.push(attribution.clone());
+                }
+            }
+        }
+        let (mut below_layers, above_layers): (Vec<_>, Vec<_>) = std::mem::take(&mut self.plugins)
+            .into_iter()
+            .partition(|plugin| plugin.z_index() < 0);
+        for (index, (tiles, opacity)) in self.layers.iter_mut().enumerate() {
+            if index == 1 {
+                draw_plugins(std::mem::take(&mut below_layers), &painter, &projector);
+            }
 
-        if let Some(tiles) = self.tiles {
-            let mut meshes = Default::default();
-            draw_tiles(
-                &painter,
-                map_center.tile_id(*self.memory.zoom),
-                map_center.project(*self.memory.zoom),
+            let Some(tile_level) = tiles.tile_level(self.memory.zoom) else {
+                continue;
+            };
+
+            let (tile_ids, meshes) = draw_tiles(
+                &projector,
+                tile_level,
+                Color32::WHITE.linear_multiply(*opacity),
                 tiles,
                 ui,
-                &mut meshes,
             );
 
-            for (_, shape) in meshes {
+            loading |= tile_ids
+                .iter()
+                .any(|tile_id| tiles.status(*tile_id) == TileStatus::Pending);
+
+            for shape in meshes {
                 painter.add(shape);
             }
+
+            let prefetched = prefetched_tiles(&projector, tile_level, self.prefetch);
+            for tile_id in &prefetched {
+                if tiles.zoom_range().contains(&tile_id.zoom) {
+                    tiles.at(*tile_id);
+                }
+            }
+        }
+
+        draw_plugins(below_layers, &painter, &projector);
+        draw_plugins(above_layers, &painter, &projector);
+
+        if let Some(marker) = &self.my_position_marker {
+            for copy in &copies {
+                let fix = PositionExtra {
+                    accuracy: self.my_accuracy,
+                    heading: self.my_heading,
+                    ..PositionExtra::new(self.my_position)
+                };
+                marker.draw(&painter, copy, &fix);
+            }
+        }
+
+        if self.debug_overlay {
+            let mut summary = Vec::new();
+            for (index, (tiles, _)) in self.layers.iter().enumerate() {
+                let level = tiles.tile_level(self.memory.zoom);
+                if let Some(level) = level {
+                    debug::draw_tiles(&painter, &projector, tiles, level);
+                }
+                summary.push(debug::layer_summary(index, tiles, level));
+            }
+            debug::draw_summary(&painter, &projector, &summary);
+        }
+
+        let drawn = self.tool.and_then(|tool| {
+            tools::show(
+                tool,
+                &mut self.memory.tool,
+                ui,
+                &response,
+                &painter,
+                &projector,
+            )
+        });
+
+        if self.loading_indicator && loading {
+            draw_loading_indicator(ui, &painter);
+        }
+
+        draw_attributions(ui, &painter, &attributions, &self.attribution_style);
+
+        // Positions in the copies of the world are reported as the ones in the original.
+        let to_position = |pointer| projector.wrap(projector.to_position(pointer));
+
+        let pointer = response.interact_pointer_pos();
+        let long_pressed = response.secondary_clicked() || self.memory.long_press(ui, &response);
+        let long_pressed_at = pointer.filter(|_| long_pressed).map(to_position);
+
+        // Releasing a long-press is not a click, even if egui counts it as one.
+        let clicked = response.clicked() && !self.memory.long_pressed;
+        let clicked_at = pointer.filter(|_| clicked).map(to_position);
+
+        let hovered_at = response.hover_pos().map(to_position);
+
+        let view = (map_center, *self.memory.zoom, self.memory.rotation, rect);
+        let changed = self.memory.last_view != Some(view);
+        self.memory.last_view = Some(view);
+
+        let visible_bounds = projector.visible_bounds();
+
+        MapResponse {
+            response,
+            clicked_at,
+            long_pressed_at,
+            hovered_at,
+            fullscreen_toggled,
+            projector,
+            drawn,
+            loading,
+            changed,
+            visible_bounds,
+            gesture,
         }
+    }
+}
 
-        response
+fn draw_plugins(plugins: Vec<Box<dyn Plugin + '_>>, painter: &Painter, projector: &Projector) {
+    let copies = projector.world_copies();
+    for mut plugin in plugins {
+        if plugin.repeated() {
+            for copy in &copies {
+                plugin.draw(painter, copy);
+            }
+        } else {
+            plugin.draw(painter, projector);
+        }
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// What the map is centered at.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MapCenterMode {
+    /// Follow "my position", until the map is dragged.
     MyPosition,
+
+    /// Follow "my position", rotating the map so that the heading given by [`Map::my_heading`]
+    /// points up.
+    MyPositionHeadingUp,
+
     Exact(Position),
 }
 
 impl MapCenterMode {
-    fn screen_drag(&mut self, response: &Response, my_position: Position, zoom: u8) {
-        if response.dragged_by(egui::PointerButton::Primary) {
-            *self = match *self {
-                // This makes it "detach" from the "my position".
-                MapCenterMode::MyPosition => MapCenterMode::Exact(my_position),
-
-                // Just shift already "detached" position.
-                MapCenterMode::Exact(position) => MapCenterMode::Exact(screen_to_position(
-                    position.project(zoom) - response.drag_delta(),
-                    zoom,
-                )),
-            };
-        }
+    /// Move the map by `delta` screen points, detaching it from "my position".
+    fn shift(
+        &mut self,
+        delta: Vec2,
+        my_position: Position,
+        zoom: f64,
+        projection: &dyn Projection,
+    ) {
+        *self = MapCenterMode::Exact(moved(projection, self.position(my_position), -delta, zoom));
+    }
+
+    /// Whether the map follows "my position".
+    pub fn is_following(&self) -> bool {
+        !matches!(self, MapCenterMode::Exact(_))
     }
 
     pub fn position(&self, my_position: Position) -> Position {
         match self {
-            MapCenterMode::MyPosition => my_position,
+            MapCenterMode::MyPosition | MapCenterMode::MyPositionHeadingUp => my_position,
             MapCenterMode::Exact(position) => *position,
         }
     }
 }
 
-/// State of the map widget which must persist between frames.
+/// How many screen points of scrolling zoom the map by one level. One "notch" of a typical mouse
+/// wheel is 50 points.
+const SCROLL_POINTS_PER_ZOOM_LEVEL: f64 = 50.;
+
+/// Below this velocity (in screen points per second), gliding map stops.
+const MIN_GLIDING_VELOCITY: f32 = 10.;
+
+/// How fast (in screen points per second) the arrow keys pan the map.
+const KEYBOARD_PAN_SPEED: f32 = 500.;
+
+/// Keys pressed (or held) during a frame.
+#[derive(Debug, Default, PartialEq)]
+struct Keys {
+    /// Screen points by which the map is panned.
+    pan: Vec2,
+
+    /// Zoom levels by which the map is zoomed.
+    zoom: i32,
+
+    /// Center the map at "my position".
+    home: bool,
+}
+
+/// State of the map widget which must persist between frames. With the `serde` feature, it can
+/// also be persisted between sessions. Only the viewed location and settings are serialized, not
+/// ongoing animations.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct MapMemory {
     pub center_mode: MapCenterMode,
     pub zoom: Zoom,
+
+    /// After being dragged, the map keeps gliding, slowing down with this friction. Its velocity
+    /// is multiplied by `exp(-inertia)` every second, so bigger values stop the map quicker.
+    /// `None` disables gliding.
+    pub inertia: Option<f32>,
+
+    /// Angle (in radians) by which the map is rotated clockwise. Zero means north is up. To keep
+    /// a vehicle's heading up, set it to minus the heading, or use
+    /// [`MapCenterMode::MyPositionHeadingUp`].
+    pub rotation: f32,
+
+    /// Range of zoom levels the map can be zoomed to, be it by the user or programmatically.
+    /// Useful when tiles are available (or licensed) only for some levels.
+    pub min_zoom: Zoom,
+    pub max_zoom: Zoom,
+
+    /// Region (given by its two opposite corners) which the center of the map cannot leave.
+    /// If "my position" is outside of it, the map is still centered as close as possible.
+    pub bounds: Option<(Position, Position)>,
+
+    /// Keep the center of the map between the top and bottom edges of the world, so it cannot be
+    /// dragged past the poles into the blank space. In Web Mercator, these edges are at the
+    /// latitude of about ±85.05°. Enabled by default.
+    pub clamp_to_world: bool,
+
+    /// When the map was dragged away from "my position", it goes back to following it after
+    /// this time without any interaction. `None` (the default) means never.
+    pub snap_back: Option<Duration>,
+
+    /// Mode the map was following before it got dragged away, and when the user interacted with
+    /// the map for the last time (in egui's time).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    detached: Option<(MapCenterMode, f64)>,
+
+    /// Current velocity of the gliding map, in screen points per second.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    velocity: Vec2,
+
+    /// Ongoing [`MapMemory::fly_to`] animation.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    flight: Option<Flight>,
+
+    /// Size of the widget when it was drawn last time.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    size: Option<Vec2>,
+
+    /// [`MapMemory::fit_bounds`] which waits for the widget size to be known.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pending_fit: Option<(Position, Position, f32)>,
+
+    /// Long-press of the current (or the just released) pointer press was already reported.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    long_pressed: bool,
+
+    /// What was drawn so far with the [`Map::with_tool`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tool: ToolState,
+
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_projection"))]
+    projection: Arc<dyn Projection>,
+
+    /// Center, zoom, rotation and rectangle of the map in the previous frame.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_view: Option<(Position, f64, f32, Rect)>,
+}
+
+#[cfg(feature = "serde")]
+fn default_projection() -> Arc<dyn Projection> {
+    Arc::new(WebMercator)
+}
+
+/// Animation of the map center and zoom.
+struct Flight {
+    /// Where the animation started. It is known only when the first frame is drawn, because
+    /// the map might be following "my position".
+    from: Option<(Position, f64)>,
+    to: Position,
+    to_zoom: Zoom,
+    duration: f32,
+    elapsed: f32,
 }
 
 impl Default for MapMemory {
@@ -100,50 +1016,1588 @@ impl Default for MapMemory {
         Self {
             center_mode: MapCenterMode::MyPosition,
             zoom: Default::default(),
+            inertia: Some(5.),
+            rotation: 0.,
+            min_zoom: Zoom::MIN,
+            max_zoom: Zoom::DEFAULT_MAX,
+            bounds: None,
+            clamp_to_world: true,
+            snap_back: None,
+            detached: None,
+            velocity: Vec2::ZERO,
+            flight: None,
+            size: None,
+            pending_fit: None,
+            long_pressed: false,
+            tool: ToolState::default(),
+            projection: Arc::new(WebMercator),
+            last_view: None,
         }
     }
 }
 
-fn draw_tiles(
-    painter: &Painter,
-    tile_id: TileId,
-    map_center_projected_position: Pos2,
-    tiles: &mut Tiles,
-    ui: &mut Ui,
-    meshes: &mut HashMap<TileId, Mesh>,
-) {
-    let tile_projected = tile_id.project();
-    let tile_screen_position = painter.clip_rect().center().to_vec2() + tile_projected.to_vec2()
-        - map_center_projected_position.to_vec2();
+impl MapMemory {
+    /// Projection of the tiles shown in the map, Web Mercator by default. All layers must use
+    /// the same one, and so do the [`Plugin`]s, given the [`Projector`].
+    pub fn set_projection(&mut self, projection: impl Projection + 'static) {
+        self.projection = Arc::new(projection);
+    }
 
-    let image = if let Some(image) = tiles.at(tile_id) {
-        image
-    } else {
-        return;
-    };
+    /// Center the map between the two positions and choose the highest zoom at which both of
+    /// them are visible, leaving `padding` screen points on each side. If the map was not drawn
+    /// yet, this happens when it is drawn for the first time.
+    pub fn fit_bounds(&mut self, min_position: Position, max_position: Position, padding: f32) {
+        self.pending_fit = Some((min_position, max_position, padding));
+        self.apply_pending_fit();
+    }
 
-    if painter
-        .clip_rect()
-        .intersects(image.rect(tile_screen_position))
-    {
-        if let Entry::Vacant(vacant) = meshes.entry(tile_id) {
-            vacant.insert(image.mesh(tile_screen_position, ui.ctx()));
-
-            for coordinates in [
-                tile_id.north(),
-                tile_id.east(),
-                tile_id.south(),
-                tile_id.west(),
-            ] {
-                draw_tiles(
-                    painter,
-                    coordinates,
-                    map_center_projected_position,
-                    tiles,
-                    ui,
-                    meshes,
-                );
-            }
-        }
+    fn apply_pending_fit(&mut self) {
+        let (Some(size), Some((a, b, padding))) = (self.size, self.pending_fit) else {
+            return;
+        };
+
+        let size = (size - Vec2::splat(2. * padding)).max(Vec2::splat(1.));
+        let (center, zoom) =
+            projection::fit_bounds(&*self.projection, a, b, (size.x as f64, size.y as f64));
+
+        self.center_mode = MapCenterMode::Exact(center);
+        self.zoom.zoom_by(zoom - *self.zoom);
+        self.flight = None;
+        self.velocity = Vec2::ZERO;
+        self.pending_fit = None;
+    }
+
+    /// Smoothly move the map to the `position` and `zoom`, over the `duration`. Dragging the map
+    /// interrupts the animation.
+    pub fn fly_to(&mut self, position: Position, zoom: Zoom, duration: Duration) {
+        self.velocity = Vec2::ZERO;
+        self.flight = Some(Flight {
+            from: None,
+            to: position,
+            to_zoom: zoom,
+            duration: duration.as_secs_f32(),
+            elapsed: 0.,
+        });
+    }
+
+    /// Center the map at "my position" and keep following it.
+    pub fn follow_my_position(&mut self) {
+        self.follow(MapCenterMode::MyPosition);
+    }
+
+    /// Center the map at "my position" and keep following it, with the heading pointing up.
+    pub fn follow_my_position_heading_up(&mut self) {
+        self.follow(MapCenterMode::MyPositionHeadingUp);
+    }
+
+    fn follow(&mut self, mode: MapCenterMode) {
+        self.center_mode = mode;
+        self.velocity = Vec2::ZERO;
+        self.flight = None;
+        self.detached = None;
+    }
+
+    /// Show the same place as the `other` map, at the same zoom and rotation, e.g. to compare two
+    /// tile sources side by side. Call it with the memory of the map which the user moved, when
+    /// its [`MapResponse::changed`], and the other way around for the second map. Widgets can
+    /// have different sizes, as their centers are the same, and so is the scale. When the `other`
+    /// follows "my position", so does this one, so both should be given the same.
+    pub fn sync_with(&mut self, other: &MapMemory) {
+        self.center_mode = match (&other.center_mode, &other.last_view) {
+            (mode, _) if mode.is_following() => mode.clone(),
+            (MapCenterMode::Exact(position), _) => MapCenterMode::Exact(*position),
+            (_, Some((center, ..))) => MapCenterMode::Exact(*center),
+            (mode, None) => mode.clone(),
+        };
+        self.zoom = other.zoom;
+        self.rotation = other.rotation;
+        self.velocity = Vec2::ZERO;
+        self.flight = None;
+        self.pending_fit = None;
+    }
+
+    /// Whether the map was dragged (or zoomed) away from "my position", which it was following.
+    /// Typically used to show a "recenter" button.
+    pub fn is_detached(&self) -> bool {
+        self.detached.is_some()
+    }
+
+    /// South-west and north-east corners of the area viewed in a map of `rect_size`, see
+    /// [`Projector::visible_bounds`]. When the map follows "my position", which the memory does
+    /// not know, it is centered where it was drawn last time (or at 0, 0 before that).
+    pub fn visible_bounds(&self, rect_size: Vec2) -> (Position, Position) {
+        let center = match (&self.center_mode, &self.last_view) {
+            (MapCenterMode::Exact(position), _) => self.clamp_position(*position),
+            (_, Some((center, ..))) => *center,
+            (_, None) => self.center(Position::new(0., 0.)),
+        };
+        Projector::new(
+            Rect::from_center_size(Pos2::ZERO, rect_size),
+            center,
+            *self.zoom,
+        )
+        .with_projection(self.projection.clone())
+        .rotated(self.rotation)
+        .visible_bounds()
+    }
+
+    /// Keep track of the user detaching the map from "my position", and bring it back after
+    /// [`MapMemory::snap_back`]. Returns how long until that happens.
+    fn update_detached(
+        &mut self,
+        following: MapCenterMode,
+        interacted: bool,
+        now: f64,
+    ) -> Option<Duration> {
+        if self.center_mode.is_following() {
+            self.detached = None;
+        } else if interacted {
+            let mode = match self.detached.take() {
+                Some((mode, _)) => Some(mode),
+                None => Some(following).filter(MapCenterMode::is_following),
+            };
+            self.detached = mode.map(|mode| (mode, now));
+        }
+
+        let (Some(snap_back), Some((mode, last_interaction))) = (self.snap_back, &self.detached)
+        else {
+            return None;
+        };
+
+        let remaining = snap_back.as_secs_f64() - (now - last_interaction);
+        if remaining <= 0. {
+            let mode = mode.clone();
+            self.follow(mode);
+            None
+        } else {
+            Some(Duration::from_secs_f64(remaining))
+        }
+    }
+
+    /// Center the map at the position, stopping any animation.
+    pub(crate) fn center_at(&mut self, position: Position) {
+        self.center_mode = MapCenterMode::Exact(position);
+        self.velocity = Vec2::ZERO;
+        self.flight = None;
+    }
+
+    /// Keep the zoom and the center within [`MapMemory::min_zoom`], [`MapMemory::max_zoom`],
+    /// [`MapMemory::bounds`] and the world (see [`MapMemory::clamp_to_world`]). Center which went
+    /// past the antimeridian is moved to the other side of the world, which looks the same.
+    fn clamp(&mut self) {
+        self.zoom = self.zoom.clamp(self.min_zoom, self.max_zoom);
+        if let MapCenterMode::Exact(position) = self.center_mode {
+            let position = projection::wrap(&*self.projection, position);
+            self.center_mode = MapCenterMode::Exact(self.clamp_position(position));
+        }
+    }
+
+    fn clamp_position(&self, position: Position) -> Position {
+        let position = if self.clamp_to_world {
+            projection::clamp_vertically(&*self.projection, position)
+        } else {
+            position
+        };
+
+        let Some((a, b)) = self.bounds else {
+            return position;
+        };
+        Position::new(
+            position.x().clamp(a.x().min(b.x()), a.x().max(b.x())),
+            position.y().clamp(a.y().min(b.y()), a.y().max(b.y())),
+        )
+    }
+
+    /// Position the map is centered at, taking [`MapMemory::bounds`] into account.
+    fn center(&self, my_position: Position) -> Position {
+        self.clamp_position(self.center_mode.position(my_position))
+    }
+
+    /// Whether the pointer has been pressed long enough, without dragging. Reported only once
+    /// per press.
+    fn long_press(&mut self, ui: &Ui, response: &Response) -> bool {
+        let (now, press_start_time, dragging, pressed) = ui.input(|input| {
+            (
+                input.time,
+                input.pointer.press_start_time(),
+                input.pointer.is_decidedly_dragging(),
+                input.pointer.any_pressed(),
+            )
+        });
+
+        // Kept until the next press, so the release of this one is not taken for a click.
+        if pressed {
+            self.long_pressed = false;
+        }
+
+        if !response.is_pointer_button_down_on() {
+            return false;
+        }
+
+        let Some(press_start_time) = press_start_time else {
+            return false;
+        };
+
+        if self.long_pressed || dragging {
+            return false;
+        }
+
+        let remaining = LONG_PRESS_DURATION - (now - press_start_time);
+        if remaining > 0. {
+            // Make sure we are called again, even if the pointer does not move.
+            ui.ctx()
+                .request_repaint_after(Duration::from_secs_f64(remaining));
+            false
+        } else {
+            self.long_pressed = true;
+            true
+        }
+    }
+
+    /// Advance the [`MapMemory::fly_to`] animation, if there is one.
+    fn animate(&mut self, ui: &Ui, my_position: Position) {
+        if self.flight.is_some() {
+            let dt = ui.input(|input| input.stable_dt.min(0.1));
+            self.advance_flight(dt, my_position);
+            ui.ctx().request_repaint();
+        }
+    }
+
+    fn advance_flight(&mut self, dt: f32, my_position: Position) {
+        let Some(flight) = &mut self.flight else {
+            return;
+        };
+
+        let (from, from_zoom) = *flight
+            .from
+            .get_or_insert((self.center_mode.position(my_position), *self.zoom));
+
+        flight.elapsed += dt;
+        if flight.elapsed >= flight.duration {
+            self.center_mode = MapCenterMode::Exact(flight.to);
+            self.zoom = flight.to_zoom;
+            self.flight = None;
+            return;
+        }
+
+        // Ease in and out.
+        let t = (flight.elapsed / flight.duration) as f64;
+        let t = t * t * (3. - 2. * t);
+
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+
+        // When the world repeats, the shortest way might be across the antimeridian.
+        let mut delta_x = flight.to.x() - from.x();
+        if self.projection.wraps() && delta_x.abs() > 180. {
+            delta_x -= 360f64.copysign(delta_x);
+        }
+        self.center_mode = MapCenterMode::Exact(projection::wrap(
+            &*self.projection,
+            Position::new(from.x() + delta_x * t, lerp(from.y(), flight.to.y())),
+        ));
+        if let Ok(zoom) = Zoom::try_from(lerp(from_zoom, *flight.to_zoom)) {
+            self.zoom = zoom;
+        }
+    }
+
+    /// Handle dragging the map with the `button`, and gliding after the drag is released.
+    fn screen_drag(
+        &mut self,
+        ui: &Ui,
+        response: &Response,
+        my_position: Position,
+        button: Option<PointerButton>,
+    ) {
+        let (dt, pointer_velocity) =
+            ui.input(|input| (input.stable_dt.min(0.1), input.pointer.velocity()));
+
+        if button.is_some_and(|button| response.dragged_by(button)) {
+            self.center_mode.shift(
+                self.unrotate(response.drag_delta()),
+                my_position,
+                *self.zoom,
+                &*self.projection,
+            );
+            self.velocity = Vec2::ZERO;
+            self.flight = None;
+        } else if button.is_some() && response.drag_released() {
+            self.velocity = pointer_velocity;
+        } else if let Some(inertia) = self.inertia {
+            self.glide(dt, inertia, my_position);
+            if self.velocity != Vec2::ZERO {
+                ui.ctx().request_repaint();
+            }
+        } else {
+            self.velocity = Vec2::ZERO;
+        }
+    }
+
+    /// Move the map according to its current velocity and slow it down.
+    fn glide(&mut self, dt: f32, inertia: f32, my_position: Position) {
+        if self.velocity.length() < MIN_GLIDING_VELOCITY {
+            self.velocity = Vec2::ZERO;
+            return;
+        }
+
+        self.center_mode.shift(
+            self.unrotate(self.velocity * dt),
+            my_position,
+            *self.zoom,
+            &*self.projection,
+        );
+        self.velocity *= (-inertia * dt).exp();
+    }
+
+    /// Handle the keyboard, if the map is focused. Returns whether the map was moved.
+    fn keyboard(&mut self, ui: &Ui, response: &Response, my_position: Position) -> bool {
+        ui.memory_mut(|memory| memory.interested_in_focus(response.id));
+        if response.clicked() || response.drag_started() {
+            response.request_focus();
+        }
+        if !response.has_focus() {
+            return false;
+        }
+
+        let keys = ui.input(|input| {
+            let axis = |negative, positive| {
+                input.key_down(positive) as i32 as f32 - input.key_down(negative) as i32 as f32
+            };
+            Keys {
+                pan: -Vec2::new(
+                    axis(Key::ArrowLeft, Key::ArrowRight),
+                    axis(Key::ArrowUp, Key::ArrowDown),
+                ) * KEYBOARD_PAN_SPEED
+                    * input.stable_dt.min(0.1),
+                zoom: input.num_presses(Key::PlusEquals) as i32
+                    - input.num_presses(Key::Minus) as i32,
+                home: input.key_pressed(Key::Home),
+            }
+        });
+
+        if keys.pan != Vec2::ZERO {
+            // Keys being held do not produce events, which would repaint the map.
+            ui.ctx().request_repaint();
+        }
+        self.apply_keys(&keys, my_position)
+    }
+
+    fn apply_keys(&mut self, keys: &Keys, my_position: Position) -> bool {
+        if keys.home {
+            self.follow_my_position();
+        }
+        if keys.pan != Vec2::ZERO {
+            self.center_mode.shift(
+                self.unrotate(keys.pan),
+                my_position,
+                *self.zoom,
+                &*self.projection,
+            );
+            self.velocity = Vec2::ZERO;
+            self.flight = None;
+        }
+        if keys.zoom != 0 {
+            self.zoom.zoom_by(keys.zoom as f64);
+        }
+        keys.home || keys.pan != Vec2::ZERO || keys.zoom != 0
+    }
+
+    /// Handle mouse wheel and pinch gestures, zooming around the pointer (or the pinch center).
+    /// Returns whether the map was zoomed or rotated.
+    fn scroll_and_pinch(
+        &mut self,
+        ui: &Ui,
+        response: &Response,
+        my_position: Position,
+        gestures: &Gestures,
+    ) -> Option<Gesture> {
+        let (delta, multi_touch) = ui.input(|input| {
+            (
+                gestures.zoom_delta(input.scroll_delta.y, input.zoom_delta(), input.modifiers),
+                input.multi_touch(),
+            )
+        });
+
+        let anchor = multi_touch
+            .map(|touch| touch.start_pos)
+            .or_else(|| response.hover_pos())?;
+        let offset = anchor - response.rect.center();
+        let mut gesture = None;
+
+        if delta != 0. {
+            self.zoom_around(offset, my_position, delta);
+            gesture = Some(Gesture::Zoom);
+        }
+
+        if let Some(touch) = multi_touch.filter(|_| gestures.rotation) {
+            if touch.rotation_delta != 0. {
+                self.rotate_around(offset, my_position, touch.rotation_delta);
+                // Pinches usually twist the fingers a bit too, but it is the zoom which is meant.
+                gesture = gesture.or(Some(Gesture::Rotation));
+            }
+        }
+
+        gesture
+    }
+
+    /// Rotate the map clockwise by `angle` (in radians), keeping the point at `offset` from
+    /// the center of the widget in place.
+    fn rotate_around(&mut self, offset: Vec2, my_position: Position, angle: f32) {
+        let before = self.unrotate(offset);
+        self.rotation = (self.rotation + angle + PI).rem_euclid(TAU) - PI;
+        let after = self.unrotate(offset);
+
+        // Anchor is at `before` from the old center, and at `after` from the new one.
+        self.center_mode = MapCenterMode::Exact(moved(
+            &*self.projection,
+            self.center_mode.position(my_position),
+            before - after,
+            *self.zoom,
+        ));
+    }
+
+    /// Turn a screen vector into the one in the (not rotated) map's coordinates.
+    fn unrotate(&self, vector: Vec2) -> Vec2 {
+        Rot2::from_angle(-self.rotation) * vector
+    }
+
+    /// Center the map at the position `offset` away from the current center and zoom in.
+    fn zoom_in_at(&mut self, offset: Vec2, my_position: Position) {
+        let offset = self.unrotate(offset);
+        self.center_mode = MapCenterMode::Exact(moved(
+            &*self.projection,
+            self.center_mode.position(my_position),
+            offset,
+            *self.zoom,
+        ));
+        self.zoom.zoom_by(1.);
+    }
+
+    /// Change the zoom by `delta` levels, keeping the geographical position `offset` away from
+    /// the map center at the same place on the screen.
+    fn zoom_around(&mut self, offset: Vec2, my_position: Position, delta: f64) {
+        let offset = self.unrotate(offset);
+        let projection = &*self.projection;
+        let center = self.center_mode.position(my_position);
+        let anchor = moved(projection, center, offset, *self.zoom);
+
+        self.zoom.zoom_by(delta);
+        self.center_mode = MapCenterMode::Exact(moved(projection, anchor, -offset, *self.zoom));
+    }
+}
+
+/// Tiles which are not visible yet, but will likely be after a pan or zoom: `rings` rows around
+/// the `rect`, and the next zoom level in its central part.
+fn prefetched_tiles(projector: &Projector, tile_level: u8, rings: u32) -> Vec<TileId> {
+    if rings == 0 {
+        return Vec::new();
+    }
+
+    let rect = projector.clip_rect;
+    let tile_size = TILE_SIZE as f32 * 2f64.powf(projector.zoom - tile_level as f64) as f32;
+
+    let visible: HashSet<_> = projector
+        .visible_tiles(tile_level)
+        .into_iter()
+        .map(|(tile_id, _)| tile_id)
+        .collect();
+
+    let around = projector.with_clip_rect(rect.expand(rings as f32 * tile_size));
+    let mut tile_ids: Vec<_> = around
+        .visible_tiles(tile_level)
+        .into_iter()
+        .map(|(tile_id, _)| tile_id)
+        .filter(|tile_id| !visible.contains(tile_id))
+        .collect();
+
+    // Central half of the widget is what stays visible after zooming in by one level.
+    if Zoom::try_from(tile_level + 1).is_ok() {
+        let center = projector.with_clip_rect(rect.shrink2(rect.size() / 4.));
+        tile_ids.extend(
+            center
+                .visible_tiles(tile_level + 1)
+                .into_iter()
+                .map(|(tile_id, _)| tile_id),
+        );
+    }
+
+    tile_ids
+}
+
+/// Spinner at the top of the map, like [`egui::Spinner`], which needs its own space in the `Ui`.
+fn draw_loading_indicator(ui: &Ui, painter: &Painter) {
+    let radius = 8.;
+    let center = painter.clip_rect().center_top() + Vec2::new(0., 10. + radius);
+    painter.circle_filled(center, radius + 4., Color32::from_white_alpha(200));
+
+    let time = ui.input(|input| input.time);
+    let start_angle = time * TAU as f64;
+    let end_angle = start_angle + 240f64.to_radians() * time.sin();
+    let points: Vec<Pos2> = (0..=20)
+        .map(|i| {
+            let angle = egui::lerp(start_angle..=end_angle, i as f64 / 20.) as f32;
+            center + radius * Vec2::angled(angle)
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        Stroke::new(2.5, Color32::from_rgb(30, 120, 230)),
+    ));
+
+    ui.ctx().request_repaint();
+}
+
+/// Draw the attributions in a row, in the corner of the map. The ones with URLs can be clicked.
+fn draw_attributions(
+    ui: &Ui,
+    painter: &Painter,
+    attributions: &[Attribution],
+    style: &AttributionStyle,
+) {
+    let Some(corner) = style.corner else {
+        return;
+    };
+    if attributions.is_empty() {
+        return;
+    }
+
+    let padding = Vec2::new(4., 2.);
+    let spacing = 8.;
+
+    let galleys: Vec<_> = attributions
+        .iter()
+        .map(|attribution| {
+            painter.layout_no_wrap(
+                attribution.text.clone(),
+                style.font.clone(),
+                style.text_color,
+            )
+        })
+        .collect();
+
+    let width = galleys.iter().map(|galley| galley.size().x).sum::<f32>()
+        + spacing * (galleys.len() - 1) as f32;
+    let height = galleys
+        .iter()
+        .map(|galley| galley.size().y)
+        .fold(0., f32::max);
+    let size = Vec2::new(width, height) + 2. * padding;
+
+    let background = corner.align_size_within_rect(size, painter.clip_rect());
+    painter.rect_filled(background, 0., style.background);
+
+    let mut position = background.min + padding;
+    for (index, (attribution, galley)) in attributions.iter().zip(galleys).enumerate() {
+        let text_rect = Rect::from_min_size(position, galley.size());
+        position.x += galley.size().x + spacing;
+
+        if let Some(url) = &attribution.url {
+            let response = ui
+                .interact(
+                    text_rect,
+                    ui.id().with(("attribution", index)),
+                    Sense::click(),
+                )
+                .on_hover_cursor(CursorIcon::PointingHand);
+
+            if response.hovered() {
+                painter.hline(
+                    text_rect.x_range(),
+                    text_rect.bottom(),
+                    Stroke::new(1., style.text_color),
+                );
+            }
+            if response.clicked() {
+                ui.ctx()
+                    .output_mut(|output| output.open_url = Some(OpenUrl::new_tab(url)));
+            }
+        }
+
+        painter.galley(text_rect.min, galley);
+    }
+}
+
+/// Visible tiles of given level, and the meshes they are drawn with. Tiles which are not loaded
+/// yet are requested, the ones closest to the center first, and filled in with the cached tiles
+/// of other levels in the meantime.
+pub(crate) fn draw_tiles(
+    projector: &Projector,
+    tile_level: u8,
+    tint: Color32,
+    tiles: &mut Tiles,
+    ui: &mut Ui,
+) -> (Vec<TileId>, Vec<Mesh>) {
+    let center = projector.clip_rect.center();
+    let mut visible = projector.visible_tiles(tile_level);
+    visible.sort_by(|(_, a), (_, b)| {
+        a.center()
+            .distance_sq(center)
+            .total_cmp(&b.center().distance_sq(center))
+    });
+
+    let mut fallbacks = Vec::new();
+    let mut images = Vec::new();
+    for (tile_id, tile_rect) in &visible {
+        let (fallback, image) = tile_meshes(*tile_id, *tile_rect, tint, tiles, ui);
+        fallbacks.extend(fallback);
+        images.extend(image);
+    }
+
+    // Tiles do not overlap each other, and neither do the fallbacks, so they can be batched
+    // separately, as long as the fallbacks stay below the tiles which are fading in.
+    let mut meshes = atlas::batched(fallbacks);
+    meshes.extend(atlas::batched(images));
+
+    if projector.rotation != 0. {
+        let rotation = Rot2::from_angle(projector.rotation);
+        for mesh in &mut meshes {
+            mesh.rotate(rotation, center);
+        }
+    }
+
+    (
+        visible.into_iter().map(|(tile_id, _)| tile_id).collect(),
+        meshes,
+    )
+}
+
+/// Meshes of whatever is shown in the place of a single tile before it is fully loaded, and of
+/// the tile itself (or the placeholder, if it failed), before the rotation.
+fn tile_meshes(
+    tile_id: TileId,
+    tile_rect: Rect,
+    tint: Color32,
+    tiles: &mut Tiles,
+    ui: &Ui,
+) -> (Vec<Mesh>, Option<Mesh>) {
+    match tiles.at(tile_id) {
+        Some(image) => {
+            // Tile which is fading in is drawn over whatever was shown while it was loading.
+            let opacity = tiles.opacity(tile_id);
+            let fallback = if opacity < 1. {
+                ui.ctx().request_repaint();
+                fallback_meshes(tile_id, tile_rect, tint, tiles, ui.ctx())
+            } else {
+                Vec::new()
+            };
+            let mesh = image.mesh(
+                tile_rect.min.to_vec2(),
+                tile_rect.width(),
+                tint.linear_multiply(opacity),
+                ui.ctx(),
+            );
+            (fallback, Some(mesh))
+        }
+        None => {
+            let fallback = fallback_meshes(tile_id, tile_rect, tint, tiles, ui.ctx());
+            let placeholder = tiles
+                .placeholder()
+                .filter(|_| fallback.is_empty() && tiles.status(tile_id) == TileStatus::Failed)
+                .map(|placeholder| {
+                    placeholder.mesh(tile_rect.min.to_vec2(), tile_rect.width(), tint, ui.ctx())
+                });
+            (fallback, placeholder)
+        }
+    }
+}
+
+/// Meshes which fill in the place of a tile which is not loaded yet: its four children, if they
+/// are all cached, or the part of the closest cached ancestor, scaled up.
+fn fallback_meshes(
+    tile_id: TileId,
+    rect: Rect,
+    tint: Color32,
+    tiles: &mut Tiles,
+    ctx: &egui::Context,
+) -> Vec<Mesh> {
+    let full_uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+
+    let children: Option<Vec<_>> = tile_id
+        .children()
+        .into_iter()
+        .map(|child| tiles.cached(child))
+        .collect();
+
+    if let Some(children) = children {
+        let half = rect.size() / 2.;
+        let offsets = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)];
+        return children
+            .iter()
+            .zip(offsets)
+            .map(|(child, (dx, dy))| {
+                let min = rect.min + Vec2::new(dx * half.x, dy * half.y);
+                child.mesh_with_uv(Rect::from_min_size(min, half), full_uv, tint, ctx)
+            })
+            .collect();
+    }
+
+    let mut ancestor = tile_id;
+    let mut depth = 0;
+    while let Some(parent) = ancestor.parent() {
+        ancestor = parent;
+        depth += 1;
+
+        if let Some(tile) = tiles.cached(ancestor) {
+            // Part of the ancestor covered by the tile.
+            let size = 1. / (1u32 << depth) as f32;
+            let min = Pos2::new(
+                (tile_id.x - (ancestor.x << depth)) as f32 * size,
+                (tile_id.y - (ancestor.y << depth)) as f32 * size,
+            );
+            let uv = Rect::from_min_size(min, Vec2::splat(size));
+            return vec![tile.mesh_with_uv(rect, uv, tint, ctx)];
+        }
+    }
+
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mercator::PositionExt;
+    use crate::testing::Harness;
+
+    #[test]
+    fn zooming_around_a_point_keeps_it_in_place() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory::default();
+        let offset = Vec2::new(100., -50.);
+
+        let zoom = *memory.zoom;
+        let anchor = moved(&WebMercator, my_position, offset, zoom);
+
+        memory.zoom_around(offset, my_position, 1.5);
+        assert_eq!(zoom + 1.5, *memory.zoom);
+
+        let center = memory.center_mode.position(my_position);
+        let zoom = *memory.zoom;
+        let anchor_after = moved(&WebMercator, center, offset, zoom);
+
+        approx::assert_relative_eq!(anchor.x(), anchor_after.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(anchor.y(), anchor_after.y(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn dragging_by_a_point_moves_the_map_at_high_zoom() {
+        let position = Position::new(21.00027, 52.26470);
+        let zoom = 24.;
+        let mut center = MapCenterMode::Exact(position);
+        center.shift(Vec2::new(1., 0.), position, zoom, &WebMercator);
+
+        let moved = center.position(position);
+        assert_ne!(position, moved);
+
+        // Previous center is now one point to the right.
+        let projector = Projector::new(
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.)),
+            moved,
+            zoom,
+        );
+        let screen = projector.to_screen(position);
+        assert!((screen.x - 51.).abs() < 0.01, "{screen:?}");
+        assert!((screen.y - 50.).abs() < 0.01, "{screen:?}");
+
+        let back = projector.to_position(screen);
+        approx::assert_relative_eq!(position.x(), back.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(position.y(), back.y(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn zooming_in_at_a_point_centers_the_map_there() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory::default();
+        let offset = Vec2::new(100., -50.);
+
+        let zoom = *memory.zoom;
+        let clicked = moved(&WebMercator, my_position, offset, zoom);
+
+        memory.zoom_in_at(offset, my_position);
+        assert_eq!(zoom + 1., *memory.zoom);
+        assert!(memory.center_mode == MapCenterMode::Exact(clicked));
+    }
+
+    #[test]
+    fn map_is_moved_in_its_projection() {
+        let mut memory = MapMemory::default();
+        memory.set_projection(projection::Grid::new(
+            |position: Position| (position.x(), position.y()),
+            |(x, y)| Position::new(x, y),
+            (-180., 180.),
+            360.,
+        ));
+        memory.zoom = Zoom::try_from(1.).unwrap();
+        memory.center_mode = MapCenterMode::Exact(Position::new(0., 0.));
+
+        // Whole world is 512 points wide and tall at this zoom.
+        memory.zoom_in_at(Vec2::new(128., 64.), Position::new(0., 0.));
+        assert!(memory.center_mode == MapCenterMode::Exact(Position::new(90., -45.)));
+
+        let projector = Projector::new(
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.)),
+            Position::new(90., -45.),
+            2.,
+        )
+        .with_projection(memory.projection.clone());
+        assert_eq!(
+            Pos2::new(50., 50.),
+            projector.to_screen(Position::new(90., -45.))
+        );
+        assert_eq!(
+            Pos2::new(50. + 256., 50.),
+            projector.to_screen(Position::new(180., -45.))
+        );
+    }
+
+    #[test]
+    fn moving_the_map_with_keys() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory::default();
+        let zoom = *memory.zoom;
+
+        assert!(!memory.apply_keys(&Keys::default(), my_position));
+        assert!(memory.center_mode == MapCenterMode::MyPosition);
+
+        // Arrow right moves to the east.
+        let keys = Keys {
+            pan: Vec2::new(-10., 0.),
+            zoom: 1,
+            home: false,
+        };
+        assert!(memory.apply_keys(&keys, my_position));
+        assert_eq!(zoom + 1., *memory.zoom);
+        let center = memory.center(my_position);
+        assert!(center.x() > my_position.x());
+        approx::assert_relative_eq!(center.y(), my_position.y(), epsilon = 1e-4);
+
+        let keys = Keys {
+            home: true,
+            ..Default::default()
+        };
+        assert!(memory.apply_keys(&keys, my_position));
+        assert!(memory.center_mode == MapCenterMode::MyPosition);
+    }
+
+    #[test]
+    fn gestures_choose_what_zooms() {
+        let gestures = Gestures::default();
+        assert_eq!(1., gestures.zoom_delta(50., 1., Modifiers::NONE));
+        assert_eq!(2., gestures.zoom_delta(50., 2., Modifiers::SHIFT));
+
+        let gestures = Gestures {
+            scroll_zoom: Some(Modifiers::SHIFT),
+            pinch_zoom: false,
+            ..Default::default()
+        };
+        assert_eq!(0., gestures.zoom_delta(50., 2., Modifiers::NONE));
+        assert_eq!(1., gestures.zoom_delta(50., 2., Modifiers::SHIFT));
+
+        assert_eq!(0., Gestures::none().zoom_delta(50., 2., Modifiers::SHIFT));
+    }
+
+    #[test]
+    fn map_glides_and_slows_down() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory {
+            velocity: Vec2::new(100., 0.),
+            ..Default::default()
+        };
+
+        memory.glide(0.1, 5., my_position);
+        let after_first_frame = memory.center_mode.position(my_position);
+
+        // Map moves against the direction of the drag, i.e. to the west.
+        assert!(after_first_frame.x() < my_position.x());
+        assert!(memory.velocity.x < 100.);
+
+        // Eventually it stops.
+        for _ in 0..100 {
+            memory.glide(0.1, 5., my_position);
+        }
+        assert_eq!(Vec2::ZERO, memory.velocity);
+    }
+
+    #[test]
+    fn flying_to_a_position() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let destination = Position::new(17.03664, 51.09916);
+        let mut memory = MapMemory::default();
+
+        memory.fly_to(
+            destination,
+            Zoom::try_from(10).unwrap(),
+            Duration::from_secs(1),
+        );
+
+        // Half way there.
+        memory.advance_flight(0.5, my_position);
+        let center = memory.center_mode.position(my_position);
+        approx::assert_relative_eq!(center.x(), (21.00027 + 17.03664) / 2.);
+        approx::assert_relative_eq!(*memory.zoom, 13.);
+
+        memory.advance_flight(0.6, my_position);
+        assert!(memory.center_mode == MapCenterMode::Exact(destination));
+        assert_eq!(10., *memory.zoom);
+        assert!(memory.flight.is_none());
+    }
+
+    #[test]
+    fn flying_across_the_antimeridian() {
+        let mut memory = MapMemory::default();
+        memory.center_at(Position::new(170., 10.));
+        memory.fly_to(
+            Position::new(-170., 10.),
+            Zoom::default(),
+            Duration::from_secs(1),
+        );
+
+        memory.advance_flight(0.25, Position::new(0., 0.));
+        let center = memory.center_mode.position(Position::new(0., 0.));
+        assert!(center.x() > 170., "{center:?}");
+
+        // Half way, it is at the antimeridian, from either side.
+        memory.advance_flight(0.25, Position::new(0., 0.));
+        let center = memory.center_mode.position(Position::new(0., 0.));
+        approx::assert_relative_eq!(180., center.x().abs(), epsilon = 1e-9);
+
+        memory.advance_flight(0.25, Position::new(0., 0.));
+        let center = memory.center_mode.position(Position::new(0., 0.));
+        assert!(center.x() < -170., "{center:?}");
+    }
+
+    #[test]
+    fn following_my_position_stops_the_flight() {
+        let mut memory = MapMemory::default();
+        memory.fly_to(
+            Position::new(17.03664, 51.09916),
+            Zoom::try_from(10).unwrap(),
+            Duration::from_secs(1),
+        );
+        memory.center_mode = MapCenterMode::Exact(Position::new(21.00027, 52.26470));
+
+        memory.follow_my_position();
+        assert!(memory.center_mode == MapCenterMode::MyPosition);
+        assert!(memory.flight.is_none());
+    }
+
+    #[test]
+    fn detached_map_snaps_back_to_my_position() {
+        let mut memory = MapMemory {
+            snap_back: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        memory.follow_my_position_heading_up();
+
+        // Programmatic moves do not count.
+        memory.center_mode = MapCenterMode::Exact(Position::new(21.00027, 52.26470));
+        assert_eq!(
+            None,
+            memory.update_detached(MapCenterMode::MyPositionHeadingUp, false, 0.)
+        );
+        assert!(!memory.is_detached());
+
+        memory.follow_my_position_heading_up();
+        memory.center_mode = MapCenterMode::Exact(Position::new(21.00027, 52.26470));
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            memory.update_detached(MapCenterMode::MyPositionHeadingUp, true, 10.)
+        );
+        assert!(memory.is_detached());
+
+        // Another drag postpones snapping back.
+        let following = memory.center_mode.clone();
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            memory.update_detached(following.clone(), true, 12.)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            memory.update_detached(following.clone(), false, 16.)
+        );
+        assert!(memory.is_detached());
+
+        assert_eq!(None, memory.update_detached(following, false, 17.));
+        assert!(!memory.is_detached());
+        assert_eq!(MapCenterMode::MyPositionHeadingUp, memory.center_mode);
+    }
+
+    #[test]
+    fn detached_map_stays_without_snap_back() {
+        let mut memory = MapMemory {
+            center_mode: MapCenterMode::Exact(Position::new(21.00027, 52.26470)),
+            ..Default::default()
+        };
+        assert_eq!(
+            None,
+            memory.update_detached(MapCenterMode::MyPosition, true, 0.)
+        );
+        assert!(memory.is_detached());
+
+        memory.follow_my_position();
+        assert!(!memory.is_detached());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn memory_is_serialized_without_animations() {
+        let mut memory = MapMemory {
+            center_mode: MapCenterMode::Exact(Position::new(21.00027, 52.26470)),
+            zoom: Zoom::try_from(12.5).unwrap(),
+            rotation: 1.,
+            ..Default::default()
+        };
+        memory.fly_to(
+            Position::new(17.03664, 51.09916),
+            Zoom::try_from(10).unwrap(),
+            Duration::from_secs(1),
+        );
+
+        let json = serde_json::to_string(&memory).unwrap();
+        let restored: MapMemory = serde_json::from_str(&json).unwrap();
+        assert_eq!(memory.center_mode, restored.center_mode);
+        assert_eq!(memory.zoom, restored.zoom);
+        assert_eq!(memory.rotation, restored.rotation);
+        assert!(restored.flight.is_none());
+
+        // Fields missing in older data are defaulted, invalid zoom is rejected.
+        let restored: MapMemory = serde_json::from_str(r#"{"zoom": 3.0}"#).unwrap();
+        assert_eq!(MapCenterMode::MyPosition, restored.center_mode);
+        assert!(serde_json::from_str::<MapMemory>(r#"{"zoom": 30.0}"#).is_err());
+    }
+
+    #[test]
+    fn zoom_and_center_are_clamped() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory {
+            min_zoom: Zoom::try_from(5).unwrap(),
+            max_zoom: Zoom::try_from(12).unwrap(),
+            bounds: Some((Position::new(14., 49.), Position::new(24., 55.))),
+            ..Default::default()
+        };
+
+        memory.zoom_around(Vec2::ZERO, my_position, -20.);
+        memory.clamp();
+        assert_eq!(5., *memory.zoom);
+
+        memory.zoom = Zoom::try_from(18).unwrap();
+        memory.clamp();
+        assert_eq!(12., *memory.zoom);
+
+        // Dragged away.
+        memory.center_mode = MapCenterMode::Exact(Position::new(30., 40.));
+        memory.clamp();
+        assert_eq!(
+            MapCenterMode::Exact(Position::new(24., 49.)),
+            memory.center_mode
+        );
+
+        // Dragged past the pole.
+        memory.bounds = None;
+        memory.center_mode = MapCenterMode::Exact(Position::new(20., 89.));
+        memory.clamp();
+        let center = memory.center(my_position);
+        assert!(center.y() < 85.06, "{center:?}");
+
+        memory.clamp_to_world = false;
+        memory.center_mode = MapCenterMode::Exact(Position::new(20., 89.));
+        memory.clamp();
+        assert_eq!(Position::new(20., 89.), memory.center(my_position));
+
+        // Following "my position", which is outside of the bounds.
+        memory.clamp_to_world = true;
+        memory.bounds = Some((Position::new(14., 49.), Position::new(24., 55.)));
+        memory.follow_my_position();
+        memory.clamp();
+        assert_eq!(MapCenterMode::MyPosition, memory.center_mode);
+        assert_eq!(
+            Position::new(14., 55.),
+            memory.center(Position::new(10., 60.))
+        );
+        assert_eq!(my_position, memory.center(my_position));
+    }
+
+    #[test]
+    fn fitting_bounds_waits_for_the_widget_size() {
+        let mut memory = MapMemory::default();
+        let a = Position::new(-10., -10.);
+        let b = Position::new(10., 10.);
+
+        memory.fit_bounds(a, b, 10.);
+        assert!(memory.center_mode == MapCenterMode::MyPosition);
+
+        memory.size = Some(Vec2::new(276., 1000.));
+        memory.apply_pending_fit();
+        approx::assert_relative_eq!(*memory.zoom, 18f64.log2());
+        assert!(memory.pending_fit.is_none());
+    }
+
+    #[test]
+    fn projecting_map_center_gives_center_of_the_widget() {
+        let rect = Rect::from_min_max(Pos2::new(10., 20.), Pos2::new(110., 220.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.5);
+
+        assert_eq!(rect.center(), projector.to_screen(center));
+        let position = projector.to_position(rect.center());
+        approx::assert_relative_eq!(center.x(), position.x(), epsilon = 1e-5);
+        approx::assert_relative_eq!(center.y(), position.y(), epsilon = 1e-5);
+
+        // Position more to the north-east is drawn up and to the right.
+        let north_east = projector.to_screen(Position::new(21.001, 52.265));
+        assert!(north_east.x > rect.center().x);
+        assert!(north_east.y < rect.center().y);
+    }
+
+    #[test]
+    fn rotated_projector() {
+        let rect = Rect::from_min_max(Pos2::new(10., 20.), Pos2::new(110., 220.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.).rotated(PI / 2.);
+
+        // Map is rotated clockwise, so the north is on the right.
+        let north = projector.to_screen(Position::new(21.00027, 52.265));
+        assert!(north.x > rect.center().x);
+        approx::assert_relative_eq!(north.y, rect.center().y, epsilon = 1e-3);
+
+        let position = projector.to_position(north);
+        approx::assert_relative_eq!(52.265, position.y(), epsilon = 1e-5);
+
+        // Widget is taller than wider, so after the rotation more of the map has to be covered.
+        let covered = projector.unrotated_clip_rect();
+        approx::assert_relative_eq!(200., covered.width(), epsilon = 1e-3);
+        approx::assert_relative_eq!(100., covered.height(), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn visible_bounds_cover_the_view() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(256.));
+        let (min, max) = Projector::new(rect, Position::new(0., 0.), 0.).visible_bounds();
+        approx::assert_relative_eq!(-180., min.x(), epsilon = 1e-4);
+        approx::assert_relative_eq!(-85.0511, min.y(), epsilon = 1e-4);
+        approx::assert_relative_eq!(180., max.x(), epsilon = 1e-4);
+        approx::assert_relative_eq!(85.0511, max.y(), epsilon = 1e-4);
+
+        // Corners of the rotated view stick out.
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.));
+        let center = Position::new(21.00027, 52.26470);
+        let (min, max) = Projector::new(rect, center, 16.).visible_bounds();
+        let (rotated_min, rotated_max) = Projector::new(rect, center, 16.)
+            .rotated(PI / 4.)
+            .visible_bounds();
+        approx::assert_relative_eq!(
+            (max.x() - min.x()) * 2f64.sqrt(),
+            rotated_max.x() - rotated_min.x(),
+            max_relative = 0.01
+        );
+
+        // Longitudes are not wrapped.
+        let (_, max) = Projector::new(rect, Position::new(179.9999, 0.), 16.).visible_bounds();
+        assert!(max.x() > 180.);
+    }
+
+    #[test]
+    fn visible_bounds_of_the_memory() {
+        let center = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory {
+            center_mode: MapCenterMode::Exact(center),
+            zoom: Zoom::try_from(16.).unwrap(),
+            ..Default::default()
+        };
+        let size = Vec2::new(300., 200.);
+        let rect = Rect::from_min_size(Pos2::new(10., 10.), size);
+        assert_eq!(
+            Projector::new(rect, center, 16.).visible_bounds(),
+            memory.visible_bounds(size)
+        );
+
+        // Position followed is the one from the last frame.
+        memory.center_mode = MapCenterMode::MyPosition;
+        let (min, max) = memory.visible_bounds(size);
+        assert!(min.x() < 0. && 0. < max.x());
+
+        memory.last_view = Some((center, 16., 0., rect));
+        let (min, max) = memory.visible_bounds(size);
+        assert!(min.x() < center.x() && center.x() < max.x());
+    }
+
+    /// Plugin recording when it is drawn and asked for input.
+    struct Recorder {
+        name: &'static str,
+        z_index: i32,
+        log: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+    }
+
+    impl Plugin for Recorder {
+        fn draw(&mut self, _: &Painter, _: &Projector) {
+            self.log.borrow_mut().push(format!("draw {}", self.name));
+        }
+
+        fn input(&mut self, _: &Response, _: &Projector) -> bool {
+            self.log.borrow_mut().push(format!("input {}", self.name));
+            false
+        }
+
+        fn repeated(&self) -> bool {
+            false
+        }
+
+        fn z_index(&self) -> i32 {
+            self.z_index
+        }
+    }
+
+    #[test]
+    fn plugins_are_drawn_by_their_z_index() {
+        let ctx = egui::Context::default();
+        let mut memory = MapMemory::default();
+        let log = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorder = |name, z_index| Recorder {
+            name,
+            z_index,
+            log: log.clone(),
+        };
+
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                Map::new(None, &mut memory, Position::new(21., 52.))
+                    .with_plugin(recorder("top", 1))
+                    .with_plugin(recorder("first", 0))
+                    .with_plugin(recorder("bottom", -1))
+                    .with_plugin(recorder("second", 0))
+                    .show(ui);
+            });
+        });
+
+        assert_eq!(
+            vec![
+                "input top",
+                "input second",
+                "input first",
+                "input bottom",
+                "draw bottom",
+                "draw first",
+                "draw second",
+                "draw top"
+            ],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn response_tells_whether_the_view_changed() {
+        let ctx = egui::Context::default();
+        let mut memory = MapMemory::default();
+        let my_position = Position::new(21.00027, 52.26470);
+        let show = |memory: &mut MapMemory| {
+            let mut response = None;
+            let _ = ctx.run(Default::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    response = Some(Map::new(None, memory, my_position).show(ui));
+                });
+            });
+            response.unwrap()
+        };
+
+        // First frame always counts as a change.
+        let response = show(&mut memory);
+        assert!(response.changed);
+        assert_eq!(None, response.gesture);
+        let (min, max) = response.visible_bounds;
+        assert!(min.x() < my_position.x() && my_position.x() < max.x());
+        assert!(min.y() < my_position.y() && my_position.y() < max.y());
+
+        assert!(!show(&mut memory).changed);
+
+        memory.zoom = Zoom::try_from(10.).unwrap();
+        assert!(show(&mut memory).changed);
+        assert!(!show(&mut memory).changed);
+    }
+
+    #[test]
+    fn maps_of_different_sizes_are_synced() {
+        let ctx = egui::Context::default();
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut left = MapMemory::default();
+        let mut right = MapMemory::default();
+        let show = |left: &mut MapMemory, right: &mut MapMemory| {
+            let mut responses = None;
+            let _ = ctx.run(Default::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        let show = |ui: &mut Ui, memory: &mut MapMemory, width: f32| {
+                            ui.allocate_ui(Vec2::new(width, 300.), |ui| {
+                                Map::new(None, memory, my_position).show(ui)
+                            })
+                            .inner
+                        };
+                        responses = Some((show(ui, left, 200.), show(ui, right, 400.)));
+                    });
+                });
+            });
+            responses.unwrap()
+        };
+        let center = |response: &MapResponse| {
+            let (min, max) = response.visible_bounds;
+            ((min.x() + max.x()) / 2., (min.y() + max.y()) / 2.)
+        };
+
+        // User moved the left map.
+        left.center_mode = MapCenterMode::Exact(Position::new(2.35, 48.86));
+        left.zoom = Zoom::try_from(12.).unwrap();
+        let (response, _) = show(&mut left, &mut right);
+        assert!(response.changed);
+        right.sync_with(&left);
+        assert!(right.detached.is_none());
+
+        let (left_response, right_response) = show(&mut left, &mut right);
+        let (lx, ly) = center(&left_response);
+        let (rx, ry) = center(&right_response);
+        approx::assert_relative_eq!(lx, rx, epsilon = 1e-6);
+        approx::assert_relative_eq!(ly, ry, epsilon = 1e-6);
+        approx::assert_relative_eq!(2.35, rx, epsilon = 1e-4);
+        assert_eq!(
+            left_response.projector.zoom(),
+            right_response.projector.zoom()
+        );
+
+        // Syncing back does not change anything.
+        left.sync_with(&right);
+        let (left_response, _) = show(&mut left, &mut right);
+        assert!(!left_response.changed);
+
+        // Following "my position" is synced as such.
+        left.follow_my_position();
+        right.sync_with(&left);
+        assert!(matches!(right.center_mode, MapCenterMode::MyPosition));
+    }
+
+    #[test]
+    fn rotating_around_a_point_keeps_it_in_place() {
+        let my_position = Position::new(21.00027, 52.26470);
+        let mut memory = MapMemory::default();
+        let offset = Vec2::new(100., -50.);
+        let rect = Rect::from_center_size(Pos2::ZERO, Vec2::splat(500.));
+
+        let projector = |memory: &MapMemory| {
+            let center = memory.center_mode.position(my_position);
+            Projector::new(rect, center, *memory.zoom).rotated(memory.rotation)
+        };
+        let anchor = projector(&memory).to_position(offset.to_pos2());
+
+        memory.rotate_around(offset, my_position, 0.5);
+        memory.rotate_around(offset, my_position, 3.);
+        approx::assert_relative_eq!(3.5 - TAU, memory.rotation);
+
+        let anchor_after = projector(&memory).to_position(offset.to_pos2());
+        approx::assert_relative_eq!(anchor.x(), anchor_after.x(), epsilon = 1e-5);
+        approx::assert_relative_eq!(anchor.y(), anchor_after.y(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn visible_tiles_cover_the_widget() {
+        let rect = Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(300., 200.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.);
+
+        let tiles = projector.visible_tiles(16);
+        assert!((2..=6).contains(&tiles.len()));
+        assert!(tiles
+            .iter()
+            .any(|(tile_id, _)| *tile_id == center.tile_id(16)));
+
+        let covered = tiles.iter().fold(Rect::NOTHING, |covered, (_, tile_rect)| {
+            covered.union(*tile_rect)
+        });
+        assert!(covered.contains_rect(rect));
+
+        // Lower zoom level, so the tiles are scaled up.
+        let tiles = projector.visible_tiles(15);
+        assert!(tiles.len() <= 4);
+        assert_eq!(512., tiles[0].1.width());
+
+        // Whole world is smaller than the widget, so it repeats.
+        let projector = Projector::new(rect, center, 0.);
+        let tiles = projector.visible_tiles(0);
+        assert_eq!(3, tiles.len());
+        assert!(tiles.iter().all(|(tile_id, _)| *tile_id
+            == TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }));
+        assert_eq!(256., tiles[1].1.min.x - tiles[0].1.min.x);
+    }
+
+    #[test]
+    fn world_repeats_across_the_antimeridian() {
+        let rect = Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(300., 200.));
+        let projector = Projector::new(rect, Position::new(179.99, 0.), 10.);
+
+        // Copy to the east shows the western hemisphere.
+        let copies = projector.world_copies();
+        assert_eq!(2, copies.len());
+        let west = Position::new(-179.99, 0.);
+        assert!(!rect.contains(copies[0].to_screen(west)));
+        assert!(rect.contains(copies[1].to_screen(west)));
+        assert!(projector
+            .visible_tiles(10)
+            .iter()
+            .any(|(tile_id, _)| tile_id.x == 0));
+
+        // Projections which do not wrap have only one copy.
+        let grid = projection::Grid::new(
+            |p| (p.x(), p.y()),
+            |(x, y)| Position::new(x, y),
+            (0., 0.),
+            1.,
+        );
+        assert_eq!(
+            1,
+            projector
+                .with_projection(Arc::new(grid))
+                .world_copies()
+                .len()
+        );
+
+        // Panning past the antimeridian moves the center to the other side.
+        let mut memory = MapMemory {
+            center_mode: MapCenterMode::Exact(Position::new(179.99, 0.)),
+            ..Default::default()
+        };
+        memory.center_mode.shift(
+            Vec2::new(-100., 0.),
+            Position::new(0., 0.),
+            10.,
+            &WebMercator,
+        );
+        memory.clamp();
+        let center = memory.center(Position::new(0., 0.));
+        assert!((-180. ..-179.8).contains(&center.x()), "{center:?}");
+    }
+
+    #[test]
+    fn prefetching_tiles_around_the_viewport() {
+        let rect = Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(300., 200.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.);
+
+        assert!(prefetched_tiles(&projector, 16, 0).is_empty());
+
+        let visible = projector.visible_tiles(16);
+        let prefetched = prefetched_tiles(&projector, 16, 1);
+
+        // Visible tiles are not prefetched, but their neighbours are.
+        assert!(visible
+            .iter()
+            .all(|(tile_id, _)| !prefetched.contains(tile_id)));
+        assert!(
+            prefetched
+                .iter()
+                .filter(|tile_id| tile_id.zoom == 16)
+                .count()
+                >= 12
+        );
+        assert!(prefetched.contains(&center.tile_id(17)));
+
+        // There is no next level at the maximum zoom.
+        let projector = Projector::new(rect, center, *Zoom::MAX);
+        assert!(prefetched_tiles(&projector, 26, 1)
+            .iter()
+            .all(|tile_id| tile_id.zoom == 26));
+    }
+
+    #[test]
+    fn tiles_closest_to_the_center_are_drawn_first() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png");
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(1000., 600.));
+        let center = Position::new(21.00027, 52.26470);
+        let projector = Projector::new(rect, center, 16.);
+
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let (tile_ids, meshes) = draw_tiles(&projector, 16, Color32::WHITE, &mut tiles, ui);
+                assert_eq!(projector.visible_tiles(16).len(), tile_ids.len());
+                assert_eq!(center.tile_id(16), tile_ids[0]);
+
+                // Nothing is loaded.
+                assert!(meshes.is_empty());
+            });
+        });
+    }
+
+    #[test]
+    fn missing_tile_is_filled_in_with_cached_parent_or_children() {
+        let directory = crate::tiles::tests::directory_with_tile("1/0/0.png");
+
+        let ctx = egui::Context::default();
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png");
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(256.));
+        let tile_id = |x, y, zoom| TileId { x, y, zoom };
+
+        // Nothing is cached yet.
+        assert!(
+            fallback_meshes(tile_id(0, 0, 2), rect, Color32::WHITE, &mut tiles, &ctx).is_empty()
+        );
+
+        // Parent covers the south-east quarter of the tile.
+        let parent = tiles.at(tile_id(0, 0, 1)).unwrap();
+        let meshes = fallback_meshes(tile_id(1, 1, 2), rect, Color32::WHITE, &mut tiles, &ctx);
+        assert_eq!(1, meshes.len());
+        let uv_bounds = |mesh: &Mesh| {
+            mesh.vertices.iter().fold(Rect::NOTHING, |uv, vertex| {
+                uv.union(Rect::from_min_max(vertex.uv, vertex.uv))
+            })
+        };
+
+        // Whole parent, wherever the atlas put it.
+        let whole = uv_bounds(&parent.mesh(Vec2::ZERO, 256., Color32::WHITE, &ctx));
+        assert_eq!(
+            Rect::from_min_max(whole.center(), whole.max),
+            uv_bounds(&meshes[0])
+        );
+
+        // Only one of the children is cached, and there is no parent.
+        assert!(
+            fallback_meshes(tile_id(0, 0, 0), rect, Color32::WHITE, &mut tiles, &ctx).is_empty()
+        );
+    }
+
+    /// Clicks and long-presses reported while the pointer is pressed for `frames`, and then
+    /// released.
+    fn press(frames: usize) -> (usize, usize) {
+        let mut harness = Harness::new([100, 100]);
+        let mut memory = MapMemory::default();
+        let (mut clicks, mut long_presses) = (0, 0);
+        let mut ui = |ui: &mut Ui| {
+            let response = Map::new(None, &mut memory, Position::new(21., 52.)).show(ui);
+            clicks += response.clicked_at.is_some() as usize;
+            long_presses += response.long_pressed_at.is_some() as usize;
+        };
+
+        let button = |pressed| egui::Event::PointerButton {
+            pos: Pos2::new(50., 50.),
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: egui::Modifiers::NONE,
+        };
+        harness.push_event(egui::Event::PointerMoved(Pos2::new(50., 50.)));
+        harness.push_event(button(true));
+        for _ in 0..frames {
+            harness.run(&mut ui);
+        }
+        harness.push_event(button(false));
+        harness.run(&mut ui);
+        harness.run(&mut ui);
+
+        (clicks, long_presses)
+    }
+
+    #[test]
+    fn long_press_is_not_a_click() {
+        // Short press, and a long one which egui would still count as a click (under 0.6 s).
+        assert_eq!((1, 0), press(5));
+        assert_eq!((0, 1), press(33));
+        assert_eq!((0, 1), press(120));
     }
 }
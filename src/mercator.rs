@@ -11,6 +11,45 @@
 /// Geographical position with latitude and longitude.
 pub type Position = geo_types::Point;
 
+/// Position with what else the GPS (or whatever gave it) knows about it. Built-in plugins use
+/// the fields which are given, e.g. the [`crate::extras::MyPositionMarker`] draws the heading and
+/// the accuracy, and the [`crate::extras::Trail`] keeps the timestamps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionExtra {
+    pub position: Position,
+
+    /// Height above the sea level, in meters.
+    pub altitude: Option<f64>,
+
+    /// Radius (in meters) of the area where the real position is likely to be.
+    pub accuracy: Option<f32>,
+
+    /// Direction of the movement, in degrees clockwise from the north.
+    pub heading: Option<f32>,
+
+    /// When the position was measured.
+    pub timestamp: Option<web_time::Instant>,
+}
+
+impl PositionExtra {
+    /// Position with nothing else known about it.
+    pub fn new(position: Position) -> Self {
+        Self {
+            position,
+            altitude: None,
+            accuracy: None,
+            heading: None,
+            timestamp: None,
+        }
+    }
+}
+
+impl From<Position> for PositionExtra {
+    fn from(position: Position) -> Self {
+        Self::new(position)
+    }
+}
+
 /// Location projected on the screen or an abstract bitmap.
 pub type Pixels = Pos2;
 
@@ -18,17 +57,26 @@ use egui::Pos2;
 use std::f64::consts::PI;
 
 pub trait PositionExt {
-    /// Project geographical position into a 2D plane using Mercator.
-    fn project(&self, zoom: u8) -> Pixels;
+    /// Project geographical position into a 2D plane using Mercator. Zoom can be fractional.
+    fn project(&self, zoom: f64) -> Pixels;
 
     /// Tile this position is on.
     fn tile_id(&self, zoom: u8) -> TileId;
 }
 
 /// Size of the tiles used by the services like the OSM.
-const TILE_SIZE: u32 = 256;
+pub(crate) const TILE_SIZE: u32 = 256;
+
+/// Length of the equator, in meters of the Web Mercator (EPSG:3857).
+const EARTH_CIRCUMFERENCE: f64 = 40075016.68557849;
+
+/// Real-world length (in meters) of a screen point at given latitude (in degrees) and zoom.
+/// Mercator stretches the map more the further from the equator, so it gets shorter there.
+pub fn meters_per_point(latitude: f64, zoom: f64) -> f64 {
+    EARTH_CIRCUMFERENCE * latitude.to_radians().cos() / (2f64.powf(zoom) * TILE_SIZE as f64)
+}
 
-fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
+pub(crate) fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
     // Project into Mercator (cylindrical map projection).
     let x = x.to_radians();
     let y = y.to_radians().tan().asinh();
@@ -41,13 +89,13 @@ fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
 }
 
 impl PositionExt for Position {
-    fn project(&self, zoom: u8) -> Pixels {
+    fn project(&self, zoom: f64) -> Pixels {
         let (x, y) = mercator_normalized((*self).into());
 
         // Map that into a big bitmap made out of web tiles.
-        let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-        let x = x * number_of_pixels as f64;
-        let y = y * number_of_pixels as f64;
+        let number_of_pixels = 2f64.powf(zoom) * TILE_SIZE as f64;
+        let x = x * number_of_pixels;
+        let y = y * number_of_pixels;
 
         Pixels::new(x as f32, y as f32)
     }
@@ -84,6 +132,31 @@ impl TileId {
         Pixels::new((self.x * TILE_SIZE) as f32, (self.y * TILE_SIZE) as f32)
     }
 
+    /// Quadkey, which is how Bing Maps address the tiles.
+    /// <https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system>
+    pub fn quadkey(&self) -> String {
+        (1..=self.zoom)
+            .rev()
+            .map(|level| {
+                let mask = 1 << (level - 1);
+                let mut digit = b'0';
+                if self.x & mask != 0 {
+                    digit += 1;
+                }
+                if self.y & mask != 0 {
+                    digit += 2;
+                }
+                digit as char
+            })
+            .collect()
+    }
+
+    /// Y coordinate in the TMS scheme, where it grows northward, like in the MBTiles files and
+    /// some tile servers.
+    pub fn tms_y(&self) -> u32 {
+        (1u32 << self.zoom).wrapping_sub(1).wrapping_sub(self.y)
+    }
+
     pub fn east(&self) -> TileId {
         TileId {
             x: self.x + 1,
@@ -115,21 +188,49 @@ impl TileId {
             zoom: self.zoom,
         }
     }
+
+    /// Tile of the lower zoom level which contains this one.
+    pub fn parent(&self) -> Option<TileId> {
+        Some(TileId {
+            x: self.x / 2,
+            y: self.y / 2,
+            zoom: self.zoom.checked_sub(1)?,
+        })
+    }
+
+    /// Four tiles of the higher zoom level which this one consists of: north-west, north-east,
+    /// south-west and south-east.
+    pub fn children(&self) -> [TileId; 4] {
+        let child = |dx, dy| TileId {
+            x: self.x * 2 + dx,
+            y: self.y * 2 + dy,
+            zoom: self.zoom + 1,
+        };
+        [child(0, 0), child(1, 0), child(0, 1), child(1, 1)]
+    }
+
+    /// Whether the tile exists, i.e. its coordinates are within the world at its zoom level.
+    pub fn is_valid(&self) -> bool {
+        let tiles_count = 1u64.checked_shl(self.zoom.into()).unwrap_or(u64::MAX);
+        (self.x as u64) < tiles_count && (self.y as u64) < tiles_count
+    }
 }
 
 /// Transforms screen pixels into a geographical position.
-pub fn screen_to_position(pixels: Pixels, zoom: u8) -> Position {
-    let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-    let number_of_pixels: f64 = number_of_pixels.into();
+pub fn screen_to_position(pixels: Pixels, zoom: f64) -> Position {
+    let number_of_pixels = 2f64.powf(zoom) * TILE_SIZE as f64;
+    position_from_normalized((
+        pixels.x as f64 / number_of_pixels,
+        pixels.y as f64 / number_of_pixels,
+    ))
+}
 
-    let lon = pixels.x as f64;
-    let lon = lon / number_of_pixels;
-    let lon = (lon * 2. - 1.) * PI;
+/// Inverse of the `mercator_normalized`.
+pub(crate) fn position_from_normalized((x, y): (f64, f64)) -> Position {
+    let lon = (x * 2. - 1.) * PI;
     let lon = lon.to_degrees();
 
-    let lat = pixels.y as f64;
-    let lat = lat / number_of_pixels;
-    let lat = (-lat * 2. + 1.) * PI;
+    let lat = (-y * 2. + 1.) * PI;
     let lat = lat.sinh().atan().to_degrees();
 
     Position::new(lon, lat)
@@ -164,17 +265,114 @@ mod tests {
         // position on the tile.
         assert_eq!(
             Pixels::new(36590. * 256. + 252., 21569. * 256. + 7.5),
-            citadel.project(zoom)
+            citadel.project(zoom as f64)
+        );
+
+        // Half way to the next level.
+        assert_eq!(
+            Pixels::new(
+                (36590. * 256. + 252.) * 2f32.sqrt(),
+                (21569. * 256. + 7.5) * 2f32.sqrt()
+            ),
+            citadel.project(zoom as f64 + 0.5)
+        );
+    }
+
+    #[test]
+    fn quadkey() {
+        assert_eq!(
+            "",
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }
+            .quadkey()
+        );
+        assert_eq!(
+            "213",
+            TileId {
+                x: 3,
+                y: 5,
+                zoom: 3
+            }
+            .quadkey()
+        );
+        assert_eq!(
+            "1202102332221212",
+            TileId {
+                x: 35210,
+                y: 21493,
+                zoom: 16
+            }
+            .quadkey()
         );
     }
 
     #[test]
     fn project_there_and_back() {
         let citadel = Position::new(21.00027, 52.26470);
-        let zoom = 16;
+        let zoom = 16.5;
         let calculated = screen_to_position(citadel.project(zoom), zoom);
 
         approx::assert_relative_eq!(calculated.x(), citadel.x(), max_relative = 1.0);
         approx::assert_relative_eq!(calculated.y(), citadel.y(), max_relative = 1.0);
     }
+
+    #[test]
+    fn meters_per_point_depend_on_latitude() {
+        approx::assert_relative_eq!(156543.03392804097, meters_per_point(0., 0.));
+        approx::assert_relative_eq!(
+            meters_per_point(0., 16.) / 2.,
+            meters_per_point(60., 16.),
+            epsilon = 1e-9
+        );
+        approx::assert_relative_eq!(meters_per_point(0., 1.), meters_per_point(0., 0.) / 2.);
+    }
+
+    #[test]
+    fn tms_y_grows_northward() {
+        let tile_id = |y, zoom| TileId { x: 0, y, zoom };
+        assert_eq!(0, tile_id(0, 0).tms_y());
+        assert_eq!(7, tile_id(0, 3).tms_y());
+        assert_eq!(0, tile_id(7, 3).tms_y());
+        assert_eq!(5, tile_id(2, 3).tms_y());
+    }
+
+    #[test]
+    fn parent_and_children_of_a_tile() {
+        let tile_id = TileId {
+            x: 35210,
+            y: 21493,
+            zoom: 16,
+        };
+
+        let parent = tile_id.parent().unwrap();
+        assert_eq!(
+            TileId {
+                x: 17605,
+                y: 10746,
+                zoom: 15
+            },
+            parent
+        );
+        assert!(parent.children().contains(&tile_id));
+        assert_eq!(
+            None,
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }
+            .parent()
+        );
+
+        assert!(tile_id.is_valid());
+        assert!(!TileId {
+            x: 0,
+            y: 2,
+            zoom: 1
+        }
+        .is_valid());
+    }
 }
@@ -0,0 +1,139 @@
+//! Zoomed-out overview of the map, shown in its corner.
+use egui::{Align2, Area, Color32, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+
+use crate::{map::draw_tiles, MapMemory, Projector, Tiles, Zoom};
+
+/// Mini-map, see [`crate::Map::with_minimap`]. It shows the tiles of the map's bottom layer,
+/// taken from the same cache, with the area visible on the map outlined. Clicking (or dragging)
+/// it moves the map there.
+#[derive(Clone, Debug)]
+pub struct MiniMap {
+    /// Corner of the map where the mini-map is placed.
+    pub corner: Align2,
+
+    /// Size of the mini-map, in screen points.
+    pub size: Vec2,
+
+    /// By how many levels the mini-map is zoomed out from the map.
+    pub zoom_out: f64,
+
+    /// Color of the outline of the area visible on the map.
+    pub color: Color32,
+}
+
+impl Default for MiniMap {
+    fn default() -> Self {
+        Self {
+            corner: Align2::LEFT_TOP,
+            size: Vec2::new(160., 120.),
+            zoom_out: 4.,
+            color: Color32::from_rgb(220, 50, 50),
+        }
+    }
+}
+
+/// Distance between the mini-map and the edges of the map.
+const MARGIN: f32 = 10.;
+
+/// Show the mini-map in the corner of the map seen through the `projector`. It is placed in
+/// a separate layer, so it does not interact with the map underneath. Returns whether it moved
+/// the map.
+pub(crate) fn show(
+    minimap: &MiniMap,
+    ui: &Ui,
+    projector: &Projector,
+    tiles: Option<&mut Tiles>,
+    memory: &mut MapMemory,
+) -> bool {
+    let rect = minimap
+        .corner
+        .align_size_within_rect(minimap.size, projector.clip_rect().shrink(MARGIN));
+
+    Area::new(ui.id().with("walkers_minimap"))
+        .fixed_pos(rect.min)
+        .show(ui.ctx(), |ui| {
+            let (rect, response) = ui.allocate_exact_size(rect.size(), Sense::click_and_drag());
+            let overview = overview(projector, rect, minimap.zoom_out);
+            let painter = ui.painter_at(rect);
+            let visuals = ui.visuals().clone();
+            painter.rect_filled(rect, 0., visuals.extreme_bg_color);
+
+            if let Some(tiles) = tiles {
+                let level = Zoom::try_from(overview.zoom())
+                    .ok()
+                    .and_then(|zoom| tiles.tile_level(zoom));
+                if let Some(level) = level {
+                    let (_, meshes) = draw_tiles(&overview, level, Color32::WHITE, tiles, ui);
+                    for mesh in meshes {
+                        painter.add(mesh);
+                    }
+                }
+            }
+
+            let outline = outline(projector, &overview);
+            painter.add(Shape::convex_polygon(
+                outline,
+                minimap.color.gamma_multiply(0.15),
+                Stroke::new(1.5, minimap.color),
+            ));
+            painter.rect_stroke(rect, 0., visuals.window_stroke());
+
+            let pointer = response
+                .interact_pointer_pos()
+                .filter(|_| response.clicked() || response.dragged());
+            match pointer {
+                Some(pointer) => {
+                    memory.center_at(overview.wrap(overview.to_position(pointer)));
+                    true
+                }
+                None => false,
+            }
+        })
+        .inner
+}
+
+/// Projector of the mini-map, which is centered like the map, but north-up and zoomed out.
+fn overview(projector: &Projector, rect: Rect, zoom_out: f64) -> Projector {
+    let center = projector.to_position(projector.clip_rect().center());
+    Projector::new(rect, center, (projector.zoom() - zoom_out).max(0.))
+        .with_projection(projector.projection_arc())
+}
+
+/// Corners of the area visible on the map, in the mini-map.
+fn outline(projector: &Projector, overview: &Projector) -> Vec<Pos2> {
+    let clip_rect = projector.clip_rect();
+    [
+        clip_rect.left_top(),
+        clip_rect.right_top(),
+        clip_rect.right_bottom(),
+        clip_rect.left_bottom(),
+    ]
+    .into_iter()
+    .map(|corner| overview.to_screen(projector.to_position(corner)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Position;
+
+    #[test]
+    fn outline_is_smaller_by_the_zoom_out() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(800., 400.));
+        let projector = Projector::new(rect, Position::new(21., 52.), 12.);
+        let mini = Rect::from_min_size(Pos2::new(10., 10.), Vec2::new(160., 120.));
+
+        let overview = overview(&projector, mini, 4.);
+        assert_eq!(8., overview.zoom());
+
+        let outline = outline(&projector, &overview);
+        approx::assert_relative_eq!(50., outline[1].x - outline[0].x, epsilon = 1e-3);
+        approx::assert_relative_eq!(25., outline[3].y - outline[0].y, epsilon = 1e-3);
+        approx::assert_relative_eq!(
+            mini.center().x,
+            (outline[0].x + outline[1].x) / 2.,
+            epsilon = 1e-3
+        );
+    }
+}
@@ -0,0 +1,961 @@
+//! Vector tiles in the [Mapbox Vector Tile](https://github.com/mapbox/vector-tile-spec) format.
+//! Unlike raster tiles, they stay sharp at any zoom and can be styled by the application.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::{Align2, Color32, Context, FontId, Mesh, Painter, Pos2, Rect, Shape, Stroke};
+use prost::Message;
+use tokio::sync::mpsc::error::TryRecvError;
+use web_time::Instant;
+
+use crate::extras::shapes::{bridge_holes, triangulate};
+use crate::io::{self, Runtime};
+use crate::mercator::TileId;
+use crate::sources::TileSource;
+use crate::tiles::Request;
+use crate::{Plugin, Projector};
+
+/// Raw protobuf messages, as defined in `vector_tile.proto`.
+mod raw {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Tile {
+        #[prost(message, repeated, tag = "3")]
+        pub layers: Vec<Layer>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Layer {
+        #[prost(uint32, tag = "15")]
+        pub version: u32,
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub features: Vec<Feature>,
+        #[prost(string, repeated, tag = "3")]
+        pub keys: Vec<String>,
+        #[prost(message, repeated, tag = "4")]
+        pub values: Vec<Value>,
+        #[prost(uint32, optional, tag = "5")]
+        pub extent: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Feature {
+        #[prost(uint64, optional, tag = "1")]
+        pub id: Option<u64>,
+        #[prost(uint32, repeated, packed = "true", tag = "2")]
+        pub tags: Vec<u32>,
+        #[prost(int32, optional, tag = "3")]
+        pub r#type: Option<i32>,
+        #[prost(uint32, repeated, packed = "true", tag = "4")]
+        pub geometry: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Value {
+        #[prost(string, optional, tag = "1")]
+        pub string_value: Option<String>,
+        #[prost(float, optional, tag = "2")]
+        pub float_value: Option<f32>,
+        #[prost(double, optional, tag = "3")]
+        pub double_value: Option<f64>,
+        #[prost(int64, optional, tag = "4")]
+        pub int_value: Option<i64>,
+        #[prost(uint64, optional, tag = "5")]
+        pub uint_value: Option<u64>,
+        #[prost(sint64, optional, tag = "6")]
+        pub sint_value: Option<i64>,
+        #[prost(bool, optional, tag = "7")]
+        pub bool_value: Option<bool>,
+    }
+
+    impl Value {
+        pub fn as_string(&self) -> Option<String> {
+            self.string_value
+                .clone()
+                .or_else(|| self.float_value.map(|value| value.to_string()))
+                .or_else(|| self.double_value.map(|value| value.to_string()))
+                .or_else(|| self.int_value.map(|value| value.to_string()))
+                .or_else(|| self.uint_value.map(|value| value.to_string()))
+                .or_else(|| self.sint_value.map(|value| value.to_string()))
+                .or_else(|| self.bool_value.map(|value| value.to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeometryType {
+    Point,
+    LineString,
+    Polygon,
+}
+
+/// Geometry in the tile's coordinates, normalized to 0..1. Features reach into the buffer
+/// around the tile, where the neighbouring tiles draw them too, so they are clipped to the tile.
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Points(Vec<Pos2>),
+    Lines(Vec<Vec<Pos2>>),
+    Polygons(Vec<Polygon>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Polygon {
+    /// Parts of the rings (the exterior and the holes) inside the tile. They are not closed,
+    /// because the edges of the tile are not the polygon's.
+    outlines: Vec<Vec<Pos2>>,
+
+    /// Clipped exterior ring joined with the holes by [`bridge_holes`], and its triangulation,
+    /// computed once when the tile is decoded.
+    vertices: Vec<Pos2>,
+    triangles: Vec<[usize; 3]>,
+}
+
+/// Single feature of a vector tile, e.g. a road or a building.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorFeature {
+    pub geometry_type: GeometryType,
+
+    /// Feature's attributes. Numbers and booleans are converted to strings.
+    pub properties: HashMap<String, String>,
+
+    geometry: Geometry,
+}
+
+/// Decoded vector tile, features grouped by the layer name.
+#[derive(Debug, Default, PartialEq)]
+struct VectorTile {
+    layers: Vec<(String, Vec<VectorFeature>)>,
+}
+
+impl VectorTile {
+    fn decode(data: &[u8]) -> Result<Self, prost::DecodeError> {
+        let tile = raw::Tile::decode(data)?;
+
+        let layers = tile
+            .layers
+            .into_iter()
+            .map(|layer| {
+                let extent = layer.extent.unwrap_or(4096).max(1) as f32;
+                let features = layer
+                    .features
+                    .iter()
+                    .filter_map(|feature| decode_feature(&layer, feature, extent))
+                    .collect();
+                (layer.name, features)
+            })
+            .collect();
+
+        Ok(Self { layers })
+    }
+}
+
+fn decode_feature(
+    layer: &raw::Layer,
+    feature: &raw::Feature,
+    extent: f32,
+) -> Option<VectorFeature> {
+    let parts = decode_geometry(&feature.geometry, extent);
+
+    let (geometry_type, geometry) = match feature.r#type? {
+        1 => (
+            GeometryType::Point,
+            Geometry::Points(parts.into_iter().flatten().filter(in_tile).collect()),
+        ),
+        2 => (
+            GeometryType::LineString,
+            Geometry::Lines(parts.iter().flat_map(|line| clip_line(line)).collect()),
+        ),
+        3 => (
+            GeometryType::Polygon,
+            Geometry::Polygons(group_rings(parts)),
+        ),
+        _ => return None,
+    };
+
+    let properties = feature
+        .tags
+        .chunks_exact(2)
+        .filter_map(|tag| {
+            let key = layer.keys.get(tag[0] as usize)?;
+            let value = layer.values.get(tag[1] as usize)?.as_string()?;
+            Some((key.clone(), value))
+        })
+        .collect();
+
+    Some(VectorFeature {
+        geometry_type,
+        properties,
+        geometry,
+    })
+}
+
+fn zigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Decode geometry commands into the parts starting with each `MoveTo`, i.e. single points,
+/// lines or rings.
+fn decode_geometry(commands: &[u32], extent: f32) -> Vec<Vec<Pos2>> {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+
+    let mut parts: Vec<Vec<Pos2>> = Vec::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut commands = commands.iter();
+
+    while let Some(command) = commands.next() {
+        let (id, count) = (command & 0x7, command >> 3);
+        match id {
+            MOVE_TO | LINE_TO => {
+                for _ in 0..count {
+                    let (Some(dx), Some(dy)) = (commands.next(), commands.next()) else {
+                        log::warn!("Truncated vector tile geometry.");
+                        return parts;
+                    };
+                    x = x.wrapping_add(zigzag(*dx));
+                    y = y.wrapping_add(zigzag(*dy));
+                    let point = Pos2::new(x as f32 / extent, y as f32 / extent);
+
+                    match parts.last_mut() {
+                        Some(part) if id == LINE_TO => part.push(point),
+                        _ => parts.push(vec![point]),
+                    }
+                }
+            }
+            // Rings are always drawn closed.
+            CLOSE_PATH => {}
+            _ => {
+                log::warn!("Unknown vector tile geometry command: {}.", id);
+                return parts;
+            }
+        }
+    }
+
+    parts
+}
+
+/// Twice the signed area of the ring. Positive for exterior rings, which go clockwise in the
+/// tile's (y-down) coordinates.
+fn signed_area(ring: &[Pos2]) -> f32 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum()
+}
+
+/// Each exterior ring starts a new polygon, interior rings which follow are its holes.
+fn group_rings(rings: Vec<Vec<Pos2>>) -> Vec<Polygon> {
+    let mut grouped: Vec<Vec<Vec<Pos2>>> = Vec::new();
+
+    for ring in rings {
+        let area = signed_area(&ring);
+        match grouped.last_mut() {
+            Some(polygon) if area < 0. => polygon.push(ring),
+            _ if area == 0. => {}
+            _ => grouped.push(vec![ring]),
+        }
+    }
+
+    grouped
+        .into_iter()
+        .filter_map(|rings| {
+            let clipped: Vec<_> = rings.iter().map(|ring| clip_ring(ring)).collect();
+            let (exterior, holes) = clipped.split_first()?;
+            if signed_area(exterior) == 0. {
+                return None;
+            }
+            let holes: Vec<_> = holes
+                .iter()
+                .filter(|hole| signed_area(hole) != 0.)
+                .cloned()
+                .collect();
+
+            let vertices = bridge_holes(exterior, &holes);
+            let outlines = rings
+                .iter()
+                .flat_map(|ring| {
+                    let closed: Vec<_> = ring.iter().chain(ring.first()).copied().collect();
+                    clip_line(&closed)
+                })
+                .collect();
+            Some(Polygon {
+                triangles: triangulate(&vertices),
+                vertices,
+                outlines,
+            })
+        })
+        .collect()
+}
+
+/// Whether the point is in the tile. Its right and bottom edges belong to the neighbours.
+fn in_tile(point: &Pos2) -> bool {
+    (0. ..1.).contains(&point.x) && (0. ..1.).contains(&point.y)
+}
+
+/// Part of the segment inside the tile, found with the Liang-Barsky algorithm.
+fn clip_segment(a: Pos2, b: Pos2) -> Option<(Pos2, Pos2)> {
+    let delta = b - a;
+    let (mut enter, mut exit) = (0f32, 1f32);
+    // Distances from each edge of the tile, and how fast they shrink along the segment.
+    for (speed, distance) in [
+        (-delta.x, a.x),
+        (delta.x, 1. - a.x),
+        (-delta.y, a.y),
+        (delta.y, 1. - a.y),
+    ] {
+        if speed == 0. {
+            if distance < 0. {
+                return None;
+            }
+        } else if speed < 0. {
+            enter = enter.max(distance / speed);
+        } else {
+            exit = exit.min(distance / speed);
+        }
+    }
+
+    // Ends inside are kept as they are, so the clipped segments still join.
+    let at = |t: f32| match t {
+        0. => a,
+        1. => b,
+        _ => a + delta * t,
+    };
+    (enter <= exit).then(|| (at(enter), at(exit)))
+}
+
+/// Parts of the line inside the tile.
+fn clip_line(line: &[Pos2]) -> Vec<Vec<Pos2>> {
+    let mut lines = Vec::new();
+    let mut current: Vec<Pos2> = Vec::new();
+    for segment in line.windows(2) {
+        let Some((start, end)) = clip_segment(segment[0], segment[1]) else {
+            continue;
+        };
+        if current.last() != Some(&start) {
+            if current.len() >= 2 {
+                lines.push(std::mem::take(&mut current));
+            }
+            current = vec![start];
+        }
+        current.push(end);
+    }
+    if current.len() >= 2 {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Part of the ring inside the tile, found with the Sutherland-Hodgman algorithm. Where the ring
+/// goes out and back in, it follows the tile's edge.
+fn clip_ring(ring: &[Pos2]) -> Vec<Pos2> {
+    let edges: [fn(Pos2) -> f32; 4] = [|p| p.x, |p| 1. - p.x, |p| p.y, |p| 1. - p.y];
+
+    let mut ring = ring.to_vec();
+    for distance in edges {
+        let input = std::mem::take(&mut ring);
+        for (index, &current) in input.iter().enumerate() {
+            let previous = input[(index + input.len() - 1) % input.len()];
+            let (from, to) = (distance(previous), distance(current));
+            if (from >= 0.) != (to >= 0.) {
+                ring.push(previous + (current - previous) * (from / (from - to)));
+            }
+            if to >= 0. {
+                ring.push(current);
+            }
+        }
+    }
+    ring
+}
+
+/// How a feature is drawn.
+#[derive(Clone)]
+pub struct VectorStyle {
+    /// Stroke of lines and polygon outlines.
+    pub stroke: Stroke,
+
+    /// Fill of polygons and points.
+    pub fill: Color32,
+
+    pub point_radius: f32,
+
+    /// Text drawn next to the point features.
+    pub label: Option<String>,
+    pub label_font: FontId,
+    pub label_color: Color32,
+}
+
+impl Default for VectorStyle {
+    fn default() -> Self {
+        Self {
+            stroke: Stroke::new(1., Color32::from_gray(120)),
+            fill: Color32::from_gray(220),
+            point_radius: 3.,
+            label: None,
+            label_font: FontId::proportional(12.),
+            label_color: Color32::BLACK,
+        }
+    }
+}
+
+type StyleFn = Box<dyn Fn(&str, &VectorFeature) -> Option<VectorStyle>>;
+
+/// Default style, drawing all features in gray and labeling points with their `name`.
+fn default_style(_layer: &str, feature: &VectorFeature) -> Option<VectorStyle> {
+    Some(VectorStyle {
+        label: feature.properties.get("name").cloned(),
+        ..Default::default()
+    })
+}
+
+/// Entry of the cache.
+struct CachedTile {
+    /// `None` means that the tile was requested, but not yet downloaded, or it failed.
+    tile: Option<Arc<VectorTile>>,
+
+    /// When the tile failed to download or decode.
+    failed_at: Option<Instant>,
+
+    /// Value of `VectorTiles::use_counter` when the tile was last used.
+    last_used: u64,
+}
+
+/// Default number of the tiles kept in the cache.
+const DEFAULT_CACHE_SIZE: usize = 256;
+
+/// [`Plugin`] which downloads vector tiles and draws them with a style supplied by the
+/// application. It keeps a cache of the tiles, so it must persist between frames, and should be
+/// passed to the [`crate::Map`] by a mutable reference.
+pub struct VectorTiles {
+    cache: HashMap<TileId, CachedTile>,
+    use_counter: u64,
+    cache_size: usize,
+
+    /// Number of the tiles drawn in the last frame. Cache always fits them, even if it is bigger
+    /// than the `cache_size`.
+    visible_tiles: usize,
+    max_zoom: u8,
+    failed_tile_cooldown: Duration,
+    style: StyleFn,
+
+    /// Tiles to be downloaded by the IO thread.
+    request_tx: tokio::sync::mpsc::UnboundedSender<Request>,
+
+    /// Tiles that got downloaded (or failed to) and should be put in the cache.
+    tile_rx: tokio::sync::mpsc::Receiver<(TileId, Result<VectorTile, String>)>,
+
+    #[allow(dead_code)] // Significant Drop
+    runtime: Option<Runtime>,
+}
+
+impl VectorTiles {
+    /// Vector tiles downloaded from the `source`, which should return URLs of `.pbf` (or `.mvt`)
+    /// files. Gzip-compressed responses are supported.
+    pub fn new<S>(source: S, egui_ctx: Context) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        // Same as for the raster tiles.
+        let channel_size = 20;
+
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
+        let http = crate::http::default_client();
+        let failed_tile_cooldown = http.failed_tile_cooldown;
+        let runtime = io::spawn(
+            None,
+            crate::tiles::download(
+                source,
+                |_: &S, _: TileId, data: &[u8], _: Option<&str>| {
+                    VectorTile::decode(data)
+                        .map_err(|err| log::warn!("Could not decode a vector tile: {}.", err))
+                        .ok()
+                },
+                http,
+                None,
+                request_rx,
+                tile_tx,
+                egui_ctx,
+            ),
+        );
+
+        Self {
+            cache: Default::default(),
+            use_counter: 0,
+            cache_size: DEFAULT_CACHE_SIZE,
+            visible_tiles: 0,
+            max_zoom: 14,
+            failed_tile_cooldown,
+            style: Box::new(default_style),
+            request_tx,
+            tile_rx,
+            runtime,
+        }
+    }
+
+    /// Decide how the features are drawn. `style` gets the name of the layer and the feature,
+    /// and returns `None` if the feature should not be drawn at all.
+    pub fn with_style(
+        mut self,
+        style: impl Fn(&str, &VectorFeature) -> Option<VectorStyle> + 'static,
+    ) -> Self {
+        self.style = Box::new(style);
+        self
+    }
+
+    /// Highest zoom level provided by the source. When the map is zoomed in further, tiles of
+    /// this level are scaled up, which does not lose any detail. Default is 14.
+    pub fn max_zoom(mut self, zoom: u8) -> Self {
+        self.max_zoom = zoom;
+        self
+    }
+
+    /// How long to wait before requesting a failed tile again. Default is 60 seconds, as for the
+    /// raster tiles.
+    pub fn failed_tile_cooldown(mut self, cooldown: Duration) -> Self {
+        self.failed_tile_cooldown = cooldown;
+        self
+    }
+
+    /// Maximum number of the tiles kept in the memory. Default is 256. Tiles which are visible,
+    /// or still being downloaded, are kept even above it.
+    pub fn cache_size(mut self, tiles: usize) -> Self {
+        self.cache_size = tiles.max(1);
+        self
+    }
+
+    /// Return a tile if already in cache, schedule a download otherwise.
+    fn at(&mut self, tile_id: TileId) -> Option<Arc<VectorTile>> {
+        self.use_counter += 1;
+
+        if let Some(cached) = self.cache.get_mut(&tile_id) {
+            let cooled_down = cached
+                .failed_at
+                .is_some_and(|failed_at| failed_at.elapsed() >= self.failed_tile_cooldown);
+            if !cooled_down {
+                cached.last_used = self.use_counter;
+                return cached.tile.clone();
+            }
+            log::debug!("Requesting vector tile {:?} again.", tile_id);
+        }
+
+        if let Ok(()) = self.request_tx.send(Request::Download(tile_id)) {
+            log::debug!("Requested vector tile: {:?}", tile_id);
+            self.insert(tile_id, None, None);
+        }
+        None
+    }
+
+    /// Put the downloaded tiles into the cache.
+    fn receive(&mut self) {
+        loop {
+            match self.tile_rx.try_recv() {
+                Ok((tile_id, Ok(tile))) => self.insert(tile_id, Some(Arc::new(tile)), None),
+                // Failed tiles stay empty, until the cooldown passes.
+                Ok((tile_id, Err(_))) => self.insert(tile_id, None, Some(Instant::now())),
+                Err(TryRecvError::Empty) => break,
+                // Requests will not get through either, so there is nothing more to do.
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        tile_id: TileId,
+        tile: Option<Arc<VectorTile>>,
+        failed_at: Option<Instant>,
+    ) {
+        self.cache.insert(
+            tile_id,
+            CachedTile {
+                tile,
+                failed_at,
+                last_used: self.use_counter,
+            },
+        );
+
+        // Tiles which are still being downloaded stay, otherwise they would be requested again.
+        while self.cache.len() > self.cache_size.max(self.visible_tiles) {
+            let least_recently_used = self
+                .cache
+                .iter()
+                .filter(|(_, cached)| cached.tile.is_some() || cached.failed_at.is_some())
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(tile_id, _)| *tile_id);
+
+            let Some(tile_id) = least_recently_used else {
+                break;
+            };
+            self.cache.remove(&tile_id);
+        }
+    }
+}
+
+impl Plugin for VectorTiles {
+    fn draw(&mut self, painter: &Painter, projector: &Projector) {
+        self.receive();
+
+        let tile_zoom = (projector.zoom().floor().max(0.) as u8).min(self.max_zoom);
+        let mut labels = Vec::new();
+
+        let visible_tiles = projector.visible_tiles(tile_zoom);
+        self.visible_tiles = visible_tiles.len();
+        for (tile_id, rect) in visible_tiles {
+            if let Some(tile) = self.at(tile_id) {
+                draw_tile(&tile, rect, &self.style, painter, projector, &mut labels);
+            }
+        }
+
+        // Labels go on top of everything, so they are not covered by the neighbouring tiles.
+        for (position, text, style) in labels {
+            painter.text(
+                position,
+                Align2::LEFT_CENTER,
+                text,
+                style.label_font,
+                style.label_color,
+            );
+        }
+    }
+
+    /// Visible tiles already include the copies of the world.
+    fn repeated(&self) -> bool {
+        false
+    }
+}
+
+fn draw_tile(
+    tile: &VectorTile,
+    rect: Rect,
+    style: &StyleFn,
+    painter: &Painter,
+    projector: &Projector,
+    labels: &mut Vec<(Pos2, String, VectorStyle)>,
+) {
+    let to_screen = |point: &Pos2| projector.rotate(rect.min + point.to_vec2() * rect.width());
+    let ring_to_screen = |ring: &[Pos2]| ring.iter().map(to_screen).collect::<Vec<_>>();
+
+    for (layer, features) in &tile.layers {
+        for feature in features {
+            let Some(style) = style(layer, feature) else {
+                continue;
+            };
+
+            match &feature.geometry {
+                Geometry::Points(points) => {
+                    for point in points {
+                        let point = to_screen(point);
+                        painter.circle(point, style.point_radius, style.fill, style.stroke);
+
+                        if let Some(label) = &style.label {
+                            let offset = egui::vec2(style.point_radius + 2., 0.);
+                            labels.push((point + offset, label.clone(), style.clone()));
+                        }
+                    }
+                }
+                Geometry::Lines(lines) => {
+                    for line in lines {
+                        painter.add(Shape::line(ring_to_screen(line), style.stroke));
+                    }
+                }
+                Geometry::Polygons(polygons) => {
+                    for polygon in polygons {
+                        let mut mesh = Mesh::default();
+                        for point in &polygon.vertices {
+                            mesh.colored_vertex(to_screen(point), style.fill);
+                        }
+                        for [a, b, c] in &polygon.triangles {
+                            mesh.add_triangle(*a as u32, *b as u32, *c as u32);
+                        }
+                        painter.add(mesh);
+
+                        if style.stroke.width > 0. {
+                            for outline in &polygon.outlines {
+                                painter.add(Shape::line(ring_to_screen(outline), style.stroke));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(id: u32, count: u32) -> u32 {
+        (id & 0x7) | (count << 3)
+    }
+
+    fn parameter(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    fn layer(features: Vec<raw::Feature>) -> raw::Layer {
+        raw::Layer {
+            version: 2,
+            name: "places".to_string(),
+            features,
+            keys: vec!["name".to_string(), "population".to_string()],
+            values: vec![
+                raw::Value {
+                    string_value: Some("Wrocław".to_string()),
+                    ..Default::default()
+                },
+                raw::Value {
+                    uint_value: Some(672929),
+                    ..Default::default()
+                },
+            ],
+            extent: Some(100),
+        }
+    }
+
+    #[test]
+    fn geometry_commands() {
+        // Example from the specification: two points.
+        let commands = [17, 10, 14, 3, 9];
+        assert_eq!(
+            vec![vec![Pos2::new(5., 7.)], vec![Pos2::new(3., 2.)]],
+            decode_geometry(&commands, 1.)
+        );
+
+        // Line with three points, normalized by the extent.
+        let commands = [
+            command(1, 1),
+            parameter(2),
+            parameter(2),
+            command(2, 2),
+            parameter(0),
+            parameter(8),
+            parameter(8),
+            parameter(0),
+        ];
+        assert_eq!(
+            vec![vec![
+                Pos2::new(0.1, 0.1),
+                Pos2::new(0.1, 0.5),
+                Pos2::new(0.5, 0.5)
+            ]],
+            decode_geometry(&commands, 20.)
+        );
+
+        // Truncated input is not a panic.
+        assert!(decode_geometry(&[command(1, 1), 2], 1.).is_empty());
+    }
+
+    #[test]
+    fn decoding_a_tile() {
+        let square = |x: i32, size: i32| -> Vec<u32> {
+            vec![
+                command(1, 1),
+                parameter(x),
+                parameter(x),
+                command(2, 3),
+                parameter(size),
+                parameter(0),
+                parameter(0),
+                parameter(size),
+                parameter(-size),
+                parameter(0),
+                command(7, 1),
+            ]
+        };
+
+        // Exterior ring goes clockwise, the hole counter-clockwise.
+        let mut geometry = square(10, 80);
+        geometry.extend([
+            command(1, 1),
+            parameter(20),
+            parameter(-60),
+            command(2, 3),
+            parameter(0),
+            parameter(20),
+            parameter(20),
+            parameter(0),
+            parameter(0),
+            parameter(-20),
+            command(7, 1),
+        ]);
+
+        let tile = raw::Tile {
+            layers: vec![layer(vec![
+                raw::Feature {
+                    id: Some(1),
+                    tags: vec![0, 0, 1, 1],
+                    r#type: Some(1),
+                    geometry: vec![command(1, 1), parameter(50), parameter(50)],
+                },
+                raw::Feature {
+                    id: Some(2),
+                    tags: vec![],
+                    r#type: Some(3),
+                    geometry,
+                },
+            ])],
+        };
+
+        let tile = VectorTile::decode(&tile.encode_to_vec()).unwrap();
+        assert_eq!(1, tile.layers.len());
+
+        let (name, features) = &tile.layers[0];
+        assert_eq!("places", name);
+        assert_eq!(2, features.len());
+
+        assert_eq!(GeometryType::Point, features[0].geometry_type);
+        assert_eq!("Wrocław", features[0].properties["name"]);
+        assert_eq!("672929", features[0].properties["population"]);
+        assert_eq!(
+            Geometry::Points(vec![Pos2::new(0.5, 0.5)]),
+            features[0].geometry
+        );
+
+        let Geometry::Polygons(polygons) = &features[1].geometry else {
+            panic!("expected polygons");
+        };
+        assert_eq!(1, polygons.len());
+        assert_eq!(2, polygons[0].outlines.len());
+        assert!(polygons[0]
+            .outlines
+            .iter()
+            .all(|outline| outline.len() == 5));
+
+        // Hole is left out of the fill.
+        let filled = filled_area(&polygons[0]);
+        assert!((filled - (0.8 * 0.8 - 0.2 * 0.2)).abs() < 1e-6, "{filled}");
+    }
+
+    fn filled_area(polygon: &Polygon) -> f32 {
+        polygon
+            .triangles
+            .iter()
+            .map(|triangle| signed_area(&triangle.map(|index| polygon.vertices[index])).abs() / 2.)
+            .sum()
+    }
+
+    #[test]
+    fn features_are_clipped_to_the_tile() {
+        let points = [(-0.1, 0.5), (0.5, 0.5), (1., 0.5)].map(|(x, y)| Pos2::new(x, y));
+        let inside: Vec<_> = points.into_iter().filter(in_tile).collect();
+        assert_eq!(vec![Pos2::new(0.5, 0.5)], inside);
+
+        let line = [(-0.5, 0.5), (0.5, 0.5), (0.5, 1.5)].map(|(x, y)| Pos2::new(x, y));
+        assert_eq!(
+            vec![vec![
+                Pos2::new(0., 0.5),
+                Pos2::new(0.5, 0.5),
+                Pos2::new(0.5, 1.)
+            ]],
+            clip_line(&line)
+        );
+
+        // Going out of the tile and back in.
+        let line = [(0.2, 0.2), (0.2, -0.2), (0.4, -0.2), (0.4, 0.2)].map(|(x, y)| Pos2::new(x, y));
+        assert_eq!(2, clip_line(&line).len());
+        assert!(clip_line(&[Pos2::new(-1., -1.), Pos2::new(-1., 2.)]).is_empty());
+
+        // Quarter of the square is in the tile, with a part of the hole on its edge.
+        let square = [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
+        let hole = [(-0.1, 0.1), (-0.1, 0.3), (0.1, 0.3), (0.1, 0.1)];
+        let polygons = group_rings(
+            [&square[..], &hole[..]]
+                .map(|ring| ring.iter().map(|(x, y)| Pos2::new(*x, *y)).collect())
+                .to_vec(),
+        );
+        assert_eq!(1, polygons.len());
+        let filled = filled_area(&polygons[0]);
+        assert!((filled - (0.25 - 0.1 * 0.2)).abs() < 1e-6, "{filled}");
+        assert_eq!(
+            vec![3, 4],
+            polygons[0]
+                .outlines
+                .iter()
+                .map(Vec::len)
+                .collect::<Vec<_>>()
+        );
+
+        // Two parts in the tile, joined outside of it.
+        let arch = [
+            (0.1, -0.5),
+            (0.9, -0.5),
+            (0.9, 0.5),
+            (0.6, 0.5),
+            (0.6, -0.2),
+            (0.4, -0.2),
+            (0.4, 0.5),
+            (0.1, 0.5),
+        ];
+        let polygons = group_rings(vec![arch.iter().map(|(x, y)| Pos2::new(*x, *y)).collect()]);
+        let filled = filled_area(&polygons[0]);
+        assert!((filled - 2. * 0.3 * 0.5).abs() < 1e-6, "{filled}");
+        assert_eq!(2, polygons[0].outlines.len());
+
+        // Polygons out of the tile are dropped.
+        let outside = square.map(|(x, y)| Pos2::new(x + 2., y));
+        assert!(group_rings(vec![outside.to_vec()]).is_empty());
+    }
+
+    #[test]
+    fn garbage_is_not_a_tile() {
+        assert!(VectorTile::decode(b"definitely not a vector tile").is_err());
+    }
+
+    #[test]
+    fn pending_and_visible_tiles_are_not_evicted() {
+        let (_server, source) = crate::tiles::tests::mockito_server();
+        let mut tiles = VectorTiles::new(source, Context::default()).cache_size(2);
+        let tile_id = |x| TileId { x, y: 0, zoom: 5 };
+        let insert = |tiles: &mut VectorTiles, x, tile: Option<VectorTile>| {
+            tiles.use_counter += 1;
+            tiles.insert(tile_id(x), tile.map(Arc::new), None);
+        };
+
+        for x in 0..4 {
+            insert(&mut tiles, x, None);
+        }
+        assert_eq!(4, tiles.cache.len());
+
+        // Downloaded ones are evicted, oldest first.
+        for x in 0..4 {
+            insert(&mut tiles, x, Some(VectorTile::default()));
+        }
+        assert_eq!(2, tiles.cache.len());
+        assert!(tiles.cache.contains_key(&tile_id(2)));
+        assert!(tiles.cache.contains_key(&tile_id(3)));
+
+        // As long as they are not visible.
+        tiles.visible_tiles = 3;
+        for x in 4..7 {
+            insert(&mut tiles, x, Some(VectorTile::default()));
+        }
+        assert_eq!(3, tiles.cache.len());
+        assert!(!tiles.cache.contains_key(&tile_id(3)));
+    }
+
+    #[test]
+    fn failed_tile_is_requested_again_after_cooldown() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = crate::tiles::tests::mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(404)
+            .expect_at_least(2)
+            .create();
+
+        let mut tiles = VectorTiles::new(source, Context::default())
+            .failed_tile_cooldown(Duration::from_millis(100));
+        while !tile_mock.matched() {
+            tiles.receive();
+            assert!(tiles.at(crate::tiles::tests::TILE_ID).is_none());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Windows anchored to geographical positions, like the details of a clicked marker.
+use std::hash::Hash;
+
+use egui::{Align2, Area, Context, Frame, Id, InnerResponse, Order, Pos2, Ui, Vec2};
+
+use crate::{Position, Projector};
+
+/// Popup which follows a geographical position as the map pans and zooms. Show it after the
+/// map, in every frame for as long as it should be open, with the [`crate::MapResponse`]'s
+/// projector.
+#[derive(Clone, Debug)]
+pub struct Popup {
+    id: Id,
+    position: Position,
+    pivot: Align2,
+    offset: Vec2,
+}
+
+impl Popup {
+    pub fn new(id_source: impl Hash, position: Position) -> Self {
+        Self {
+            id: Id::new(id_source),
+            position,
+            pivot: Align2::CENTER_BOTTOM,
+            offset: Vec2::new(0., -12.),
+        }
+    }
+
+    /// Corner (or middle of the edge) of the popup touching the position. Default is the middle
+    /// of the bottom edge, so the popup is above the position.
+    pub fn pivot(mut self, pivot: Align2) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    /// Shift (in screen points) of the pivot from the position, e.g. to clear the marker.
+    pub fn offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Show the popup, unless the position is outside of the map.
+    pub fn show<R>(
+        self,
+        ctx: &Context,
+        projector: &Projector,
+        add_contents: impl FnOnce(&mut Ui) -> R,
+    ) -> Option<InnerResponse<R>> {
+        let anchor = anchor(projector, self.position)?;
+        Some(
+            Area::new(self.id)
+                .order(Order::Foreground)
+                .fixed_pos(anchor + self.offset)
+                .pivot(self.pivot)
+                .show(ctx, |ui| {
+                    Frame::popup(ui.style()).show(ui, add_contents).inner
+                }),
+        )
+    }
+}
+
+/// Screen position of the geographical one, in whichever copy of the world it is visible.
+fn anchor(projector: &Projector, position: Position) -> Option<Pos2> {
+    projector
+        .world_copies()
+        .iter()
+        .map(|copy| copy.to_screen(position))
+        .find(|point| projector.clip_rect().contains(*point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Rect;
+
+    #[test]
+    fn popup_is_anchored_in_the_visible_copy() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.));
+        let projector = Projector::new(rect, Position::new(179.99, 0.), 10.);
+
+        // Just across the antimeridian, it is to the right of the center.
+        let anchor = anchor(&projector, Position::new(-179.99, 0.)).unwrap();
+        assert!(anchor.x > 200., "{anchor:?}");
+
+        assert_eq!(None, super::anchor(&projector, Position::new(170., 0.)));
+    }
+}
@@ -0,0 +1,176 @@
+//! Downloading the tiles of a region ahead of time, so they are in the disk cache when there is
+//! no network, see [`crate::Tiles::prefetch_region`].
+use std::ops::RangeInclusive;
+
+use futures_util::Stream;
+
+use crate::mercator::{PositionExt, TileId};
+use crate::Position;
+
+/// How far [`crate::Tiles::prefetch_region`] got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Tiles which are now in the disk cache, including the ones which already were.
+    pub done: usize,
+
+    /// Tiles which could not be downloaded (or were not valid images).
+    pub failed: usize,
+
+    /// All tiles of the region.
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn is_finished(&self) -> bool {
+        self.done + self.failed >= self.total
+    }
+}
+
+/// Tiles of a region, downloaded by the IO thread when there are no tiles to show waiting.
+pub(crate) struct Job {
+    tiles: Box<dyn Iterator<Item = TileId> + Send>,
+
+    /// Tiles taken, but not downloaded, e.g. because of the rate limit.
+    put_back: Vec<TileId>,
+    progress: Progress,
+
+    /// Closed when the application drops the stream, which cancels the job.
+    progress_tx: tokio::sync::mpsc::UnboundedSender<Progress>,
+}
+
+impl Job {
+    /// Job for the tiles covering the box spanned by two positions, at each of the zoom levels,
+    /// and the stream reporting its progress.
+    pub(crate) fn new(
+        a: Position,
+        b: Position,
+        zoom_range: RangeInclusive<u8>,
+    ) -> (Self, impl Stream<Item = Progress>) {
+        let total = zoom_range
+            .clone()
+            .map(|zoom| {
+                let (x, y) = tile_range(a, b, zoom);
+                x.count() * y.count()
+            })
+            .sum();
+
+        let tiles = zoom_range.flat_map(move |zoom| {
+            let (x, y) = tile_range(a, b, zoom);
+            y.flat_map(move |y| x.clone().map(move |x| TileId { x, y, zoom }))
+        });
+
+        let (progress_tx, progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let stream = futures_util::stream::unfold(progress_rx, |mut progress_rx| async move {
+            let progress = progress_rx.recv().await?;
+            Some((progress, progress_rx))
+        });
+
+        let job = Self {
+            tiles: Box::new(tiles),
+            put_back: Vec::new(),
+            progress: Progress {
+                total,
+                ..Default::default()
+            },
+            progress_tx,
+        };
+        (job, stream)
+    }
+
+    /// Next tile to download, if there are any left.
+    pub(crate) fn next_tile(&mut self) -> Option<TileId> {
+        self.put_back.pop().or_else(|| self.tiles.next())
+    }
+
+    /// Tile to be returned by the [`Job::next_tile`] again.
+    pub(crate) fn put_back(&mut self, tile_id: TileId) {
+        self.put_back.push(tile_id);
+    }
+
+    /// Count the tile as done or failed and report that.
+    pub(crate) fn record(&mut self, done: bool) {
+        if done {
+            self.progress.done += 1;
+        } else {
+            self.progress.failed += 1;
+        }
+        // Stream might have been dropped in the meantime. Then, this job ends anyway.
+        let _ = self.progress_tx.send(self.progress);
+    }
+
+    /// Whether there is nothing more to do, either because all tiles were counted, or because
+    /// the application is no longer interested.
+    pub(crate) fn is_over(&self) -> bool {
+        self.progress.is_finished() || self.progress_tx.is_closed()
+    }
+}
+
+/// Columns and rows of the tiles at given zoom, which cover the box spanned by two positions.
+fn tile_range(a: Position, b: Position, zoom: u8) -> (RangeInclusive<u32>, RangeInclusive<u32>) {
+    let last = (1u32 << zoom.min(31)) - 1;
+    let (a, b) = (a.tile_id(zoom), b.tile_id(zoom));
+    let range = |a: u32, b: u32| a.min(b).min(last)..=a.max(b).min(last);
+    (range(a.x, b.x), range(a.y, b.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn region_is_covered_at_each_zoom() {
+        let a = Position::new(20.9, 52.3);
+        let b = Position::new(21.3, 52.1);
+
+        let (x, y) = tile_range(a, b, 10);
+        assert_eq!(571..=572, x);
+        assert_eq!(336..=337, y);
+
+        // Order of the corners does not matter, and the world's edges are not crossed.
+        assert_eq!((x, y), tile_range(b, a, 10));
+        let (x, y) = tile_range(Position::new(-180., 90.), Position::new(180., -90.), 1);
+        assert_eq!((0..=1, 0..=1), (x, y));
+
+        let (mut job, _) = Job::new(a, b, 9..=10);
+        assert_eq!(6, job.progress.total);
+        assert_eq!(6, std::iter::from_fn(|| job.next_tile()).count());
+    }
+
+    #[test]
+    fn progress_is_reported_until_the_stream_is_dropped() {
+        let a = Position::new(21., 52.);
+        let (mut job, stream) = Job::new(a, a, 0..=1);
+        let mut stream = Box::pin(stream);
+
+        job.record(true);
+        job.record(false);
+        assert!(job.is_over());
+        drop(job);
+
+        let reported: Vec<_> = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mut reported = Vec::new();
+                while let Some(progress) = stream.next().await {
+                    reported.push(progress);
+                }
+                reported
+            });
+        assert_eq!(2, reported.len());
+        assert_eq!(
+            Progress {
+                done: 1,
+                failed: 1,
+                total: 2
+            },
+            reported[1]
+        );
+
+        let (job, stream) = Job::new(a, a, 0..=1);
+        assert!(!job.is_over());
+        drop(stream);
+        assert!(job.is_over());
+    }
+}
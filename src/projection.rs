@@ -0,0 +1,259 @@
+//! Projections of the geographical positions onto the plane of the tiles. Most tile servers use
+//! the Web Mercator, but some tile sets, like the polar or national ones, are made in other
+//! projections. See [`crate::MapMemory::set_projection`].
+use std::fmt::{self, Debug};
+
+use egui::Vec2;
+
+use crate::mercator::{mercator_normalized, position_from_normalized, TILE_SIZE};
+use crate::Position;
+
+/// Projection of the tile set. Tiles form the usual quadtree: the level 0 tile covers the square
+/// which the projection maps into the 0 to 1 range on both axes (with `y` growing downwards),
+/// and each level splits every tile into four.
+pub trait Projection: Send + Sync {
+    /// Normalized coordinates of the position.
+    fn project(&self, position: Position) -> (f64, f64);
+
+    /// Position at the normalized coordinates.
+    fn unproject(&self, point: (f64, f64)) -> Position;
+
+    /// Whether the left and right edges of the level 0 tile meet, like they do at the
+    /// antimeridian, so the map can repeat horizontally. Default is `false`.
+    fn wraps(&self) -> bool {
+        false
+    }
+}
+
+impl Debug for dyn Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Projection")
+    }
+}
+
+/// Web Mercator (EPSG:3857), which is used by OpenStreetMap and most other tile servers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebMercator;
+
+impl Projection for WebMercator {
+    fn project(&self, position: Position) -> (f64, f64) {
+        mercator_normalized(position.into())
+    }
+
+    fn unproject(&self, point: (f64, f64)) -> Position {
+        position_from_normalized(point)
+    }
+
+    fn wraps(&self) -> bool {
+        true
+    }
+}
+
+type Conversion<From, To> = Box<dyn Fn(From) -> To + Send + Sync>;
+
+/// Tile set made in a projected coordinate system, like a polar stereographic (EPSG:3413) or
+/// a national grid (EPSG:27700, EPSG:2056). Conversions between the positions and the system's
+/// coordinates come from the application, e.g. from the `proj` crate, which walkers does not
+/// depend on.
+///
+/// Note that the tiles still have to form a quadtree, i.e. the resolution must double with
+/// each zoom level.
+pub struct Grid {
+    forward: Conversion<Position, (f64, f64)>,
+    inverse: Conversion<(f64, f64), Position>,
+    origin: (f64, f64),
+    size: f64,
+}
+
+impl Grid {
+    /// `forward` turns a position into the system's coordinates (with `y` growing upwards, like
+    /// northings do), and `inverse` turns them back. The level 0 tile is a square of `size`
+    /// (in the system's units), whose top-left corner is at `origin`.
+    pub fn new(
+        forward: impl Fn(Position) -> (f64, f64) + Send + Sync + 'static,
+        inverse: impl Fn((f64, f64)) -> Position + Send + Sync + 'static,
+        origin: (f64, f64),
+        size: f64,
+    ) -> Self {
+        Self {
+            forward: Box::new(forward),
+            inverse: Box::new(inverse),
+            origin,
+            size,
+        }
+    }
+}
+
+impl Projection for Grid {
+    fn project(&self, position: Position) -> (f64, f64) {
+        let (x, y) = (self.forward)(position);
+        (
+            (x - self.origin.0) / self.size,
+            (self.origin.1 - y) / self.size,
+        )
+    }
+
+    fn unproject(&self, (x, y): (f64, f64)) -> Position {
+        (self.inverse)((self.origin.0 + x * self.size, self.origin.1 - y * self.size))
+    }
+}
+
+/// Size (in screen points) of the whole projected world at given zoom.
+pub(crate) fn world_size(zoom: f64) -> f64 {
+    2f64.powf(zoom) * TILE_SIZE as f64
+}
+
+/// Position projected into a bitmap made of the tiles at given (possibly fractional) zoom. It is
+/// kept in `f64`, because at the highest zoom levels `f32` cannot tell its neighbouring pixels
+/// apart. Only the differences, like the offsets from the map center, fit in the screen points.
+pub(crate) fn to_pixels(projection: &dyn Projection, position: Position, zoom: f64) -> (f64, f64) {
+    let (x, y) = projection.project(position);
+    let size = world_size(zoom);
+    (x * size, y * size)
+}
+
+/// Inverse of the [`to_pixels`].
+pub(crate) fn from_pixels(projection: &dyn Projection, (x, y): (f64, f64), zoom: f64) -> Position {
+    let size = world_size(zoom);
+    projection.unproject((x / size, y / size))
+}
+
+/// Position `offset` screen points away from the given one, on the map at the zoom.
+pub(crate) fn moved(
+    projection: &dyn Projection,
+    position: Position,
+    offset: Vec2,
+    zoom: f64,
+) -> Position {
+    let (x, y) = to_pixels(projection, position, zoom);
+    from_pixels(projection, (x + offset.x as f64, y + offset.y as f64), zoom)
+}
+
+/// Same position, but within the level 0 tile, if the projection wraps. Otherwise, e.g. after
+/// panning west past the antimeridian, longitude would grow indefinitely.
+pub(crate) fn wrap(projection: &dyn Projection, position: Position) -> Position {
+    if !projection.wraps() {
+        return position;
+    }
+
+    let (x, y) = projection.project(position);
+    if (0. ..1.).contains(&x) {
+        position
+    } else {
+        projection.unproject((x.rem_euclid(1.), y))
+    }
+}
+
+/// Same position, but moved vertically to the edge of the level 0 tile, if it is beyond it. In
+/// Web Mercator, that is the latitude of about ±85.05°.
+pub(crate) fn clamp_vertically(projection: &dyn Projection, position: Position) -> Position {
+    let (x, y) = projection.project(position);
+    if (0. ..=1.).contains(&y) {
+        position
+    } else {
+        projection.unproject((x, y.clamp(0., 1.)))
+    }
+}
+
+/// Center and the highest zoom at which the box spanned by two positions fits in `size` screen
+/// points.
+pub(crate) fn fit_bounds(
+    projection: &dyn Projection,
+    a: Position,
+    b: Position,
+    size: (f64, f64),
+) -> (Position, f64) {
+    let (ax, ay) = projection.project(a);
+    let (bx, by) = projection.project(b);
+
+    let center = projection.unproject(((ax + bx) / 2., (ay + by) / 2.));
+
+    // Size of the whole world is `2^zoom * TILE_SIZE`.
+    let zoom_to_fit = |screen: f64, span: f64| (screen / (span * TILE_SIZE as f64)).log2();
+    let zoom = zoom_to_fit(size.0, (ax - bx).abs()).min(zoom_to_fit(size.1, (ay - by).abs()));
+
+    (center, zoom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PositionExt;
+
+    /// Plate carrée, with the level 0 tile covering the whole world and the poles.
+    fn equirectangular() -> Grid {
+        Grid::new(
+            |position: Position| (position.x(), position.y()),
+            |(x, y)| Position::new(x, y),
+            (-180., 180.),
+            360.,
+        )
+    }
+
+    #[test]
+    fn web_mercator_is_the_same_as_before() {
+        let citadel = Position::new(21.00027, 52.26470);
+        let pixels = citadel.project(16.);
+        let (x, y) = to_pixels(&WebMercator, citadel, 16.);
+        assert_eq!(pixels, egui::Pos2::new(x as f32, y as f32));
+
+        let back = from_pixels(&WebMercator, (pixels.x as f64, pixels.y as f64), 16.);
+        approx::assert_relative_eq!(citadel.x(), back.x(), epsilon = 1e-4);
+        approx::assert_relative_eq!(citadel.y(), back.y(), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn grid_projection() {
+        let grid = equirectangular();
+        assert_eq!((0.5, 0.5), grid.project(Position::new(0., 0.)));
+        assert_eq!((0., 0.25), grid.project(Position::new(-180., 90.)));
+        assert_eq!(Position::new(90., -45.), grid.unproject((0.75, 0.625)));
+    }
+
+    #[test]
+    fn wrapping_the_longitude() {
+        let position = Position::new(-190., 52.);
+        let wrapped = wrap(&WebMercator, position);
+        approx::assert_relative_eq!(170., wrapped.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(52., wrapped.y(), epsilon = 1e-9);
+
+        let inside = Position::new(21., 52.);
+        assert_eq!(inside, wrap(&WebMercator, inside));
+
+        // Grids do not wrap.
+        assert_eq!(position, wrap(&equirectangular(), position));
+    }
+
+    #[test]
+    fn clamping_beyond_the_poles() {
+        let north = clamp_vertically(&WebMercator, Position::new(21., 89.));
+        approx::assert_relative_eq!(21., north.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(85.0511, north.y(), epsilon = 1e-4);
+
+        let inside = Position::new(21., -85.);
+        assert_eq!(inside, clamp_vertically(&WebMercator, inside));
+    }
+
+    #[test]
+    fn fitting_bounds() {
+        let a = Position::new(-10., -10.);
+        let b = Position::new(10., 10.);
+
+        // 20 degrees is 1/18 of the world.
+        let (center, zoom) = fit_bounds(&WebMercator, a, b, (256., 1000.));
+        approx::assert_relative_eq!(center.x(), 0.);
+        approx::assert_relative_eq!(center.y(), 0., epsilon = 1e-10);
+        approx::assert_relative_eq!(zoom, 18f64.log2());
+
+        // Order of the corners does not matter.
+        assert_eq!(
+            (center, zoom),
+            fit_bounds(&WebMercator, b, a, (256., 1000.))
+        );
+
+        // Height is what limits it in this projection.
+        let (center, zoom) = fit_bounds(&equirectangular(), a, b, (1000., 256.));
+        assert_eq!(Position::new(0., 0.), center);
+        approx::assert_relative_eq!(zoom, 18f64.log2());
+    }
+}
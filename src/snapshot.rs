@@ -0,0 +1,160 @@
+//! Rendering the tiles into an image, without the GPU.
+use std::collections::HashMap;
+
+use egui::{emath::Rot2, vec2, Color32, ColorImage, Pos2, Rect};
+use image::RgbaImage;
+
+use crate::{tiles::TileStatus, Projector, Tiles, Zoom};
+
+/// Tiles of all the `layers` (bottom first, with their opacity), as seen by the `projector`,
+/// which clip rect's size is the size of the image in pixels. `None` if some of the tiles are
+/// not loaded yet, in which case they are requested. Tiles which failed are drawn with the
+/// [`Tiles::error_placeholder`], if there is one.
+pub(crate) fn render(
+    projector: &Projector,
+    zoom: Zoom,
+    layers: &mut [(&mut Tiles, f32)],
+) -> Option<RgbaImage> {
+    let clip_rect = projector.clip_rect();
+    let mut canvas =
+        vec![Color32::TRANSPARENT; clip_rect.width() as usize * clip_rect.height() as usize];
+    let mut complete = true;
+
+    for (tiles, opacity) in layers.iter_mut() {
+        let Some(tile_level) = tiles.tile_level(zoom) else {
+            continue;
+        };
+
+        let visible: Vec<_> = projector
+            .visible_tiles(tile_level)
+            .into_iter()
+            .map(|(tile_id, rect)| (tile_id, rect, tiles.at(tile_id)))
+            .collect();
+
+        let mut loaded = Vec::new();
+        for (tile_id, rect, tile) in visible {
+            match tile {
+                Some(tile) => loaded.push((rect, tile)),
+                // Ones which failed are left out, unless there is a placeholder.
+                None if tiles.status(tile_id) == TileStatus::Failed => {
+                    loaded.extend(tiles.placeholder().map(|placeholder| (rect, placeholder)));
+                }
+                None => complete = false,
+            }
+        }
+
+        if complete {
+            let images: Vec<_> = loaded
+                .iter()
+                .map(|(rect, tile)| (*rect, tile.image()))
+                .collect();
+            draw_layer(&mut canvas, projector, &images, *opacity);
+        }
+    }
+
+    let pixels = canvas
+        .iter()
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect();
+    complete
+        .then(|| RgbaImage::from_raw(clip_rect.width() as u32, clip_rect.height() as u32, pixels))
+        .flatten()
+}
+
+/// Blend the tiles, given with their screen areas (before the rotation), over the canvas.
+fn draw_layer(
+    canvas: &mut [Color32],
+    projector: &Projector,
+    tiles: &[(Rect, &ColorImage)],
+    opacity: f32,
+) {
+    let Some((first, _)) = tiles.first() else {
+        return;
+    };
+
+    // Tiles form a grid, so the one under a pixel can be found without searching.
+    let size = first.width();
+    let cell = |point: Pos2| {
+        let offset = (point - first.min) / size;
+        (offset.x.floor() as i64, offset.y.floor() as i64)
+    };
+    let grid: HashMap<_, _> = tiles
+        .iter()
+        .map(|(rect, image)| (cell(rect.center()), (*rect, *image)))
+        .collect();
+
+    let clip_rect = projector.clip_rect();
+    let width = clip_rect.width() as usize;
+    let unrotation = Rot2::from_angle(-projector.rotation());
+
+    for (index, pixel) in canvas.iter_mut().enumerate() {
+        let point = clip_rect.min + vec2((index % width) as f32, (index / width) as f32);
+        let point = clip_rect.center() + unrotation * (point + vec2(0.5, 0.5) - clip_rect.center());
+
+        let Some((rect, image)) = grid.get(&cell(point)) else {
+            continue;
+        };
+
+        let uv = (point - rect.min) / rect.size();
+        let x = ((uv.x * image.size[0] as f32) as usize).min(image.size[0] - 1);
+        let y = ((uv.y * image.size[1] as f32) as usize).min(image.size[1] - 1);
+        *pixel = over(image.pixels[y * image.size[0] + x], *pixel, opacity);
+    }
+}
+
+/// Premultiplied `top` color made `opacity` transparent, blended over the `bottom` one.
+fn over(top: Color32, bottom: Color32, opacity: f32) -> Color32 {
+    let top = top.to_array().map(|channel| channel as f32 * opacity);
+    let transparency = 1. - top[3] / 255.;
+    let bottom = bottom.to_array();
+    let [r, g, b, a] =
+        std::array::from_fn(|i| (top[i] + bottom[i] as f32 * transparency).round() as u8);
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sources::Plain, Position};
+    use egui::{Context, Vec2};
+
+    #[test]
+    fn layers_are_blended() {
+        let ctx = Context::default();
+        let plain = |background| Plain {
+            background,
+            graticule: None,
+        };
+        let mut bottom = Tiles::new(plain(Color32::WHITE), ctx.clone());
+        let mut top = Tiles::new(plain(Color32::BLACK), ctx);
+
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(300., 200.));
+        let projector = Projector::new(rect, Position::new(21., 52.), 10.5).rotated(0.3);
+        let zoom = Zoom::try_from(10.5).unwrap();
+
+        // Tiles are made by the IO thread, so they take a moment.
+        let image = (0..500)
+            .find_map(|_| {
+                let mut layers = [(&mut bottom, 1.), (&mut top, 0.5)];
+                let image = render(&projector, zoom, &mut layers);
+                if image.is_none() {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                image
+            })
+            .expect("tiles were not loaded in time");
+
+        assert_eq!((300, 200), image.dimensions());
+        assert!(image.pixels().all(|pixel| pixel.0 == [128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn blending_colors() {
+        assert_eq!(Color32::RED, over(Color32::RED, Color32::BLUE, 1.));
+        assert_eq!(Color32::BLUE, over(Color32::RED, Color32::BLUE, 0.));
+        assert_eq!(
+            Color32::from_rgba_premultiplied(128, 0, 0, 128),
+            over(Color32::RED, Color32::TRANSPARENT, 0.5)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Offline tiles stored in a MBTiles file.
+//! <https://github.com/mapbox/mbtiles-spec>
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::mercator::TileId;
+
+/// Raster tiles stored in a MBTiles (SQLite) file. Use [`crate::Tiles::from_mbtiles`] to show
+/// them on the map.
+pub struct MbTiles {
+    connection: Connection,
+}
+
+impl MbTiles {
+    /// Open the file read-only.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, rusqlite::Error> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(Self { connection })
+    }
+
+    /// Image of the tile, or `None` if there is no such tile in the file.
+    pub fn tile(&self, tile_id: TileId) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+        self.connection
+            .prepare_cached(
+                "SELECT tile_data FROM tiles \
+                 WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            )?
+            .query_row((tile_id.zoom, tile_id.x, tile_id.tms_y()), |row| row.get(0))
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tiles;
+
+    #[test]
+    fn reading_tiles_from_mbtiles() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("map.mbtiles");
+
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, \
+                 tile_row INTEGER, tile_data BLOB);",
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (3, 1, 5, ?1)",
+                [include_bytes!("../valid.png").as_slice()],
+            )
+            .unwrap();
+        drop(connection);
+
+        let tile_id = TileId {
+            x: 1,
+            y: 2,
+            zoom: 3,
+        };
+        let mbtiles = MbTiles::open(&path).unwrap();
+        assert!(mbtiles.tile(tile_id).unwrap().is_some());
+        assert!(mbtiles
+            .tile(TileId {
+                x: 1,
+                y: 5,
+                zoom: 3
+            })
+            .unwrap()
+            .is_none());
+
+        let mut tiles = Tiles::from_mbtiles(mbtiles);
+        assert!(tiles.at(tile_id).is_some());
+        assert!(tiles
+            .at(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            })
+            .is_none());
+    }
+
+    #[test]
+    fn opening_nonexistent_file_fails() {
+        assert!(MbTiles::open("/does/not/exist.mbtiles").is_err());
+    }
+}
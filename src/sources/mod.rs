@@ -0,0 +1,581 @@
+//! Tile providers. Besides the ones defined here, any `Fn(TileId) -> String` returning an URL
+//! can be used as a source.
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+
+use egui::ColorImage;
+use image::ImageFormat;
+
+use crate::mercator::{TileId, TILE_SIZE};
+
+#[cfg(feature = "mbtiles")]
+mod mbtiles;
+mod plain;
+pub mod wms;
+#[cfg(feature = "wmts")]
+pub mod wmts;
+
+#[cfg(feature = "mbtiles")]
+pub use mbtiles::MbTiles;
+pub(crate) use plain::graticule_interval;
+pub use plain::Plain;
+
+/// Text which has to be shown on the map, as required by the terms of use of most providers,
+/// e.g. the [OSM's tile usage policy](https://operations.osmfoundation.org/policies/tiles/).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribution {
+    pub text: String,
+
+    /// Opened when the text is clicked.
+    pub url: Option<String>,
+}
+
+impl Attribution {
+    pub fn new(text: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            url: Some(url.into()),
+        }
+    }
+}
+
+/// Data of a tile, obtained by [`TileSource::fetch`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send>>;
+
+/// Data of a tile, obtained by [`TileSource::fetch`].
+#[cfg(target_arch = "wasm32")]
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>>>>;
+
+/// Zoom levels provided by most tile servers.
+pub(crate) const DEFAULT_ZOOM_RANGE: RangeInclusive<u8> = 0..=19;
+
+/// Provider of the tiles.
+pub trait TileSource {
+    /// URL of the tile's image. Sources which [`TileSource::fetch`] the tiles by themselves
+    /// still need to give something unique, as it identifies the tile in the logs.
+    fn tile_url(&self, tile_id: TileId) -> String;
+
+    /// Get the tile's data some other way than downloading the [`TileSource::tile_url`], e.g.
+    /// with signed requests, from a gRPC service or a database, or by generating it. The future
+    /// runs on the IO thread (or the application's [`crate::Spawner`]), and the data is then
+    /// given to [`TileSource::decode`]. Fetched tiles are not retried nor stored in the disk
+    /// cache. `None`, which is the default, means that the tile is downloaded from its URL.
+    fn fetch(&self, _tile_id: TileId) -> Option<FetchFuture> {
+        None
+    }
+
+    /// Attribution shown in the corner of the map. None by default, which is what functions
+    /// returning URLs give.
+    fn attribution(&self) -> Option<Attribution> {
+        None
+    }
+
+    /// Zoom levels for which the tiles exist. When the map is zoomed in further, tiles of the
+    /// highest level are scaled up. Default is 0 to 19.
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        DEFAULT_ZOOM_RANGE
+    }
+
+    /// Size (in screen points) at which the tiles are meant to be shown. Most providers use 256,
+    /// but some have 512 tiles, which cover the same area as the 256 ones of the next level, so
+    /// the map uses the tiles of one level lower. Note that `@2x` tiles are 512 pixels big, but
+    /// still meant to be shown at 256 points, which is what makes them crisp on HiDPI screens.
+    fn tile_size(&self) -> u32 {
+        TILE_SIZE
+    }
+
+    /// Turn the downloaded data into an image. `content_type` is the value of the server's
+    /// `Content-Type` header, which is not known for the tiles taken from the disk cache. Default
+    /// is [`decode_image`], override it for formats it does not support.
+    fn decode(&self, data: &[u8], content_type: Option<&str>) -> Result<ColorImage, String> {
+        decode_image(data, content_type)
+    }
+
+    /// Like [`TileSource::decode`], but also given the tile, for the formats whose meaning
+    /// depends on where the tile is, e.g. when the elevations are shaded, see
+    /// [`crate::terrain::Hillshade`]. Default calls [`TileSource::decode`].
+    fn decode_tile(
+        &self,
+        _tile_id: TileId,
+        data: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<ColorImage, String> {
+        self.decode(data, content_type)
+    }
+}
+
+/// Decode a PNG, JPEG or WebP image, in the format given by the `Content-Type`, or guessed from
+/// the data if it is not known (or wrong, which happens).
+pub fn decode_image(data: &[u8], content_type: Option<&str>) -> Result<ColorImage, String> {
+    let format = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .and_then(|mime| match mime.trim() {
+            "image/png" => Some(ImageFormat::Png),
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/webp" => Some(ImageFormat::WebP),
+            _ => None,
+        });
+
+    let image = match format {
+        Some(format) => image::load_from_memory_with_format(data, format)
+            .or_else(|_| image::load_from_memory(data)),
+        None => image::load_from_memory(data),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let image = image.to_rgba8();
+    Ok(ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_flat_samples().as_slice(),
+    ))
+}
+
+impl<F> TileSource for F
+where
+    F: Fn(TileId) -> String,
+{
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self(tile_id)
+    }
+}
+
+/// Source of the tiles in the TMS scheme, where Y grows northward. `Tms(source)` asks the
+/// `source` for the tiles with the Y coordinate flipped.
+pub struct Tms<S>(pub S);
+
+impl<S: TileSource> TileSource for Tms<S> {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self.0.tile_url(TileId {
+            y: tile_id.tms_y(),
+            ..tile_id
+        })
+    }
+
+    fn fetch(&self, tile_id: TileId) -> Option<FetchFuture> {
+        self.0.fetch(TileId {
+            y: tile_id.tms_y(),
+            ..tile_id
+        })
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.0.attribution()
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.0.zoom_range()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.0.tile_size()
+    }
+
+    fn decode(&self, data: &[u8], content_type: Option<&str>) -> Result<ColorImage, String> {
+        self.0.decode(data, content_type)
+    }
+
+    fn decode_tile(
+        &self,
+        tile_id: TileId,
+        data: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<ColorImage, String> {
+        self.0.decode_tile(
+            TileId {
+                y: tile_id.tms_y(),
+                ..tile_id
+            },
+            data,
+            content_type,
+        )
+    }
+}
+
+/// <https://www.openstreetmap.org>. Prefer [`OpenStreetMap`], which also gives the attribution.
+pub fn openstreetmap(tile_id: TileId) -> String {
+    format!(
+        "https://tile.openstreetmap.org/{}/{}/{}.png",
+        tile_id.zoom, tile_id.x, tile_id.y
+    )
+}
+
+/// <https://www.openstreetmap.org>
+pub struct OpenStreetMap;
+
+impl TileSource for OpenStreetMap {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        openstreetmap(tile_id)
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© OpenStreetMap contributors",
+            "https://www.openstreetmap.org/copyright",
+        ))
+    }
+}
+
+/// Size of the tiles of the providers which serve both 256 and 512 ones.
+fn large_tiles_size(large_tiles: bool) -> u32 {
+    if large_tiles {
+        2 * TILE_SIZE
+    } else {
+        TILE_SIZE
+    }
+}
+
+/// Suffix used by the providers which serve tiles twice as big for HiDPI screens.
+fn retina(high_resolution: bool) -> &'static str {
+    if high_resolution {
+        "@2x"
+    } else {
+        ""
+    }
+}
+
+/// <https://opentopomap.org>
+pub struct OpenTopoMap;
+
+impl TileSource for OpenTopoMap {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://tile.opentopomap.org/{}/{}/{}.png",
+            tile_id.zoom, tile_id.x, tile_id.y
+        )
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        0..=17
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© OpenStreetMap contributors, SRTM, © OpenTopoMap (CC-BY-SA)",
+            "https://opentopomap.org/about",
+        ))
+    }
+}
+
+/// <https://www.mapbox.com>
+pub struct Mapbox {
+    /// Style in the `username/style_id` form, e.g. `mapbox/streets-v12`.
+    pub style: String,
+    pub access_token: String,
+
+    /// Use `@2x` tiles, which are crisp on HiDPI screens.
+    pub high_resolution: bool,
+
+    /// Use 512 tiles instead of 256 ones, which makes for less requests and bigger labels.
+    pub large_tiles: bool,
+}
+
+impl TileSource for Mapbox {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://api.mapbox.com/styles/v1/{}/tiles/{}/{}/{}/{}{}?access_token={}",
+            self.style,
+            large_tiles_size(self.large_tiles),
+            tile_id.zoom,
+            tile_id.x,
+            tile_id.y,
+            retina(self.high_resolution),
+            self.access_token
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© Mapbox © OpenStreetMap contributors",
+            "https://www.mapbox.com/about/maps/",
+        ))
+    }
+
+    fn tile_size(&self) -> u32 {
+        large_tiles_size(self.large_tiles)
+    }
+}
+
+/// <https://www.maptiler.com>
+pub struct MapTiler {
+    /// Map id, e.g. `streets-v2`.
+    pub map: String,
+    pub api_key: String,
+
+    /// Use `@2x` tiles, which are crisp on HiDPI screens.
+    pub high_resolution: bool,
+
+    /// Use 512 tiles instead of 256 ones, which makes for less requests and bigger labels.
+    pub large_tiles: bool,
+}
+
+impl TileSource for MapTiler {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://api.maptiler.com/maps/{}/{}/{}/{}/{}{}.png?key={}",
+            self.map,
+            large_tiles_size(self.large_tiles),
+            tile_id.zoom,
+            tile_id.x,
+            tile_id.y,
+            retina(self.high_resolution),
+            self.api_key
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© MapTiler © OpenStreetMap contributors",
+            "https://www.maptiler.com/copyright/",
+        ))
+    }
+
+    fn tile_size(&self) -> u32 {
+        large_tiles_size(self.large_tiles)
+    }
+}
+
+/// <https://www.thunderforest.com>
+pub struct Thunderforest {
+    /// Style, e.g. `cycle`, `transport` or `outdoors`.
+    pub style: String,
+    pub api_key: String,
+
+    /// Use `@2x` tiles, which are crisp on HiDPI screens.
+    pub high_resolution: bool,
+}
+
+impl TileSource for Thunderforest {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://tile.thunderforest.com/{}/{}/{}/{}{}.png?apikey={}",
+            self.style,
+            tile_id.zoom,
+            tile_id.x,
+            tile_id.y,
+            retina(self.high_resolution),
+            self.api_key
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "Maps © Thunderforest, Data © OpenStreetMap contributors",
+            "https://www.thunderforest.com/terms/",
+        ))
+    }
+}
+
+/// <https://stadiamaps.com>
+pub struct Stadia {
+    /// Style, e.g. `alidade_smooth` or `osm_bright`.
+    pub style: String,
+    pub api_key: String,
+
+    /// Use `@2x` tiles, which are crisp on HiDPI screens.
+    pub high_resolution: bool,
+}
+
+impl TileSource for Stadia {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://tiles.stadiamaps.com/tiles/{}/{}/{}/{}{}.png?api_key={}",
+            self.style,
+            tile_id.zoom,
+            tile_id.x,
+            tile_id.y,
+            retina(self.high_resolution),
+            self.api_key
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© Stadia Maps © OpenMapTiles © OpenStreetMap contributors",
+            "https://stadiamaps.com/attribution",
+        ))
+    }
+}
+
+/// Imagery served by the [`Bing`] Maps.
+#[derive(Clone, Copy)]
+pub enum BingImagery {
+    Aerial,
+    AerialWithLabels,
+    Road,
+}
+
+/// <https://www.bingmaps.com>. These tiles are JPEGs, addressed with [`TileId::quadkey`].
+///
+/// They come from the `ecn.t0-3.tiles.virtualearth.net` servers, which do not check the key, so
+/// it is not sent with the requests. Tile URLs given by the
+/// [Imagery Metadata](https://learn.microsoft.com/en-us/bingmaps/rest-services/imagery/get-imagery-metadata)
+/// API for the key can be used with a custom [`TileSource`] instead.
+pub struct Bing {
+    pub api_key: String,
+    pub imagery: BingImagery,
+}
+
+impl Bing {
+    pub fn new(api_key: impl Into<String>, imagery: BingImagery) -> Self {
+        Self {
+            api_key: api_key.into(),
+            imagery,
+        }
+    }
+}
+
+impl TileSource for Bing {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let quadkey = tile_id.quadkey();
+        let imagery = match self.imagery {
+            BingImagery::Aerial => 'a',
+            BingImagery::AerialWithLabels => 'h',
+            BingImagery::Road => 'r',
+        };
+
+        // Spread the requests over the servers, but make sure the same tile always comes from the
+        // same one, so it can be cached.
+        let server = quadkey.bytes().map(u32::from).sum::<u32>() % 4;
+
+        format!(
+            "https://ecn.t{}.tiles.virtualearth.net/tiles/{}{}.jpeg?g=1",
+            server, imagery, quadkey
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "© Microsoft",
+            "https://www.microsoft.com/en-us/maps/product/terms",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 3,
+    };
+
+    #[test]
+    fn decoding_images_by_content_type() {
+        let png = include_bytes!("../valid.png");
+        assert_eq!(
+            [256, 256],
+            decode_image(png, Some("image/png")).unwrap().size
+        );
+        assert_eq!([256, 256], decode_image(png, None).unwrap().size);
+
+        // Servers are sometimes wrong about the type.
+        assert!(decode_image(png, Some("image/jpeg")).is_ok());
+
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg)
+            .encode(&[255; 2 * 2 * 3], 2, 2, image::ColorType::Rgb8)
+            .unwrap();
+        assert_eq!(
+            [2, 2],
+            decode_image(&jpeg, Some("image/jpeg; charset=binary"))
+                .unwrap()
+                .size
+        );
+
+        let webp = include_bytes!("../valid.webp");
+        assert_eq!([1, 1], decode_image(webp, Some("image/webp")).unwrap().size);
+
+        assert!(decode_image(b"not an image", Some("image/png")).is_err());
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            "https://tile.openstreetmap.org/3/1/2.png",
+            openstreetmap.tile_url(TILE_ID)
+        );
+        assert_eq!(
+            "https://tile.openstreetmap.org/3/1/5.png",
+            Tms(openstreetmap).tile_url(TILE_ID)
+        );
+        assert_eq!(OpenTopoMap.zoom_range(), Tms(OpenTopoMap).zoom_range());
+        assert_eq!(
+            "https://tile.openstreetmap.org/3/1/2.png",
+            OpenStreetMap.tile_url(TILE_ID)
+        );
+
+        assert_eq!(
+            "https://api.mapbox.com/styles/v1/mapbox/streets-v12/tiles/256/3/1/2@2x?access_token=t",
+            Mapbox {
+                style: "mapbox/streets-v12".to_owned(),
+                access_token: "t".to_owned(),
+                high_resolution: true,
+                large_tiles: false,
+            }
+            .tile_url(TILE_ID)
+        );
+
+        let mapbox = Mapbox {
+            style: "mapbox/streets-v12".to_owned(),
+            access_token: "t".to_owned(),
+            high_resolution: false,
+            large_tiles: true,
+        };
+        assert_eq!(
+            "https://api.mapbox.com/styles/v1/mapbox/streets-v12/tiles/512/3/1/2?access_token=t",
+            mapbox.tile_url(TILE_ID)
+        );
+        assert_eq!(512, mapbox.tile_size());
+
+        assert_eq!(
+            "https://api.maptiler.com/maps/streets-v2/256/3/1/2.png?key=k",
+            MapTiler {
+                map: "streets-v2".to_owned(),
+                api_key: "k".to_owned(),
+                high_resolution: false,
+                large_tiles: false,
+            }
+            .tile_url(TILE_ID)
+        );
+
+        assert_eq!(
+            "https://tile.thunderforest.com/cycle/3/1/2@2x.png?apikey=k",
+            Thunderforest {
+                style: "cycle".to_owned(),
+                api_key: "k".to_owned(),
+                high_resolution: true,
+            }
+            .tile_url(TILE_ID)
+        );
+
+        assert_eq!(
+            "https://tiles.stadiamaps.com/tiles/osm_bright/3/1/2.png?api_key=k",
+            Stadia {
+                style: "osm_bright".to_owned(),
+                api_key: "k".to_owned(),
+                high_resolution: false,
+            }
+            .tile_url(TILE_ID)
+        );
+
+        assert_eq!(
+            "https://ecn.t3.tiles.virtualearth.net/tiles/a021.jpeg?g=1",
+            Bing::new("k", BingImagery::Aerial).tile_url(TILE_ID)
+        );
+    }
+
+    #[test]
+    fn attributions() {
+        assert_eq!(None, openstreetmap.attribution());
+        assert_eq!(
+            Some("https://www.openstreetmap.org/copyright".to_owned()),
+            OpenStreetMap
+                .attribution()
+                .and_then(|attribution| attribution.url)
+        );
+    }
+}
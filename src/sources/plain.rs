@@ -0,0 +1,156 @@
+//! Tiles generated locally, without any network access.
+use std::ops::RangeInclusive;
+
+use egui::{Color32, ColorImage};
+
+use super::{FetchFuture, TileSource};
+use crate::mercator::{position_from_normalized, TileId, TILE_SIZE};
+
+/// Spacings (in degrees) of the graticule lines, from the widest.
+const INTERVALS: [f64; 20] = [
+    90., 45., 30., 15., 10., 5., 2., 1., 0.5, 0.2, 0.1, 0.05, 0.02, 0.01, 0.005, 0.002, 0.001,
+    0.0005, 0.0002, 0.0001,
+];
+
+/// Background of a solid color, optionally with the latitude and longitude lines, which is always
+/// available, e.g. when no provider is configured, or as the bottom layer under the one which
+/// might not be reachable.
+#[derive(Clone, Debug)]
+pub struct Plain {
+    pub background: Color32,
+
+    /// Color of the graticule lines, spaced more densely as the map zooms in. `None` leaves the
+    /// tiles blank.
+    pub graticule: Option<Color32>,
+}
+
+impl Default for Plain {
+    fn default() -> Self {
+        Self {
+            background: Color32::from_rgb(242, 239, 233),
+            graticule: Some(Color32::from_rgb(200, 196, 188)),
+        }
+    }
+}
+
+impl TileSource for Plain {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!("plain://{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y)
+    }
+
+    fn fetch(&self, tile_id: TileId) -> Option<FetchFuture> {
+        let pixels = render(tile_id, self.background, self.graticule);
+        Some(Box::pin(async move { Ok(pixels) }))
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        0..=26
+    }
+
+    /// Data are the raw RGBA pixels made by [`Plain::fetch`].
+    fn decode(&self, data: &[u8], _content_type: Option<&str>) -> Result<ColorImage, String> {
+        let size = TILE_SIZE as usize;
+        if data.len() != size * size * 4 {
+            return Err(format!(
+                "expected {} bytes, got {}",
+                size * size * 4,
+                data.len()
+            ));
+        }
+        Ok(ColorImage::from_rgba_unmultiplied([size, size], data))
+    }
+}
+
+/// Densest spacing (in degrees) of the graticule lines, which is at least `min`.
+pub(crate) fn graticule_interval(min: f64) -> f64 {
+    INTERVALS
+        .iter()
+        .rev()
+        .copied()
+        .find(|interval| *interval >= min)
+        .unwrap_or(INTERVALS[0])
+}
+
+/// RGBA pixels of the tile.
+fn render(tile_id: TileId, background: Color32, graticule: Option<Color32>) -> Vec<u8> {
+    let size = TILE_SIZE as u64;
+    let world = size << tile_id.zoom;
+
+    // Few lines on each tile.
+    let interval = graticule_interval(90. / (1u64 << tile_id.zoom) as f64);
+
+    // Whether there is a line between the given pixel and the next one, counting from the world's
+    // edge, so lines on the edges of neighbouring tiles are drawn only once.
+    let crosses = |pixel: u64, degrees: fn(f64) -> f64| {
+        let from = degrees(pixel as f64 / world as f64) / interval;
+        let to = degrees((pixel + 1) as f64 / world as f64) / interval;
+        from.floor() != to.floor()
+    };
+    let longitude = |x: f64| position_from_normalized((x, 0.5)).x();
+    let latitude = |y: f64| position_from_normalized((0.5, y)).y();
+
+    let columns: Vec<bool> = (0..size)
+        .map(|x| crosses(tile_id.x as u64 * size + x, longitude))
+        .collect();
+    let rows: Vec<bool> = (0..size)
+        .map(|y| crosses(tile_id.y as u64 * size + y, latitude))
+        .collect();
+
+    rows.iter()
+        .flat_map(|row| columns.iter().map(move |column| *row || *column))
+        .flat_map(|line| {
+            let color = graticule.filter(|_| line).unwrap_or(background);
+            color.to_srgba_unmultiplied()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_are_round() {
+        assert_eq!(90., graticule_interval(90.));
+        assert_eq!(45., graticule_interval(40.));
+        assert_eq!(0.2, graticule_interval(0.17));
+        assert_eq!(90., graticule_interval(1000.));
+        assert_eq!(0.0001, graticule_interval(0.));
+    }
+
+    #[test]
+    fn tiles_have_the_graticule() {
+        let line = Color32::RED;
+        let source = Plain {
+            background: Color32::WHITE,
+            graticule: Some(line),
+        };
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+
+        let image = source
+            .decode(&render(tile_id, source.background, source.graticule), None)
+            .unwrap();
+        assert_eq!([256, 256], image.size);
+        let at = |x: usize, y: usize| image.pixels[y * 256 + x];
+
+        // Meridians every 90 degrees, including the prime one in the middle, and the equator.
+        assert_eq!(line, at(127, 10));
+        assert_eq!(line, at(63, 10));
+        assert_eq!(line, at(10, 128));
+        assert_eq!(Color32::WHITE, at(100, 10));
+
+        let blank = Plain {
+            graticule: None,
+            ..source
+        };
+        assert!(render(tile_id, blank.background, blank.graticule)
+            .iter()
+            .all(|channel| *channel == 255));
+
+        assert!(source.decode(b"not pixels", None).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Adapter for the Web Map Service, which, instead of tiles, serves images of arbitrary areas.
+//! <https://www.ogc.org/standard/wms/>
+use crate::mercator::{position_from_normalized, TileId, TILE_SIZE};
+
+use super::{Attribution, TileSource};
+
+/// Half of the Earth's circumference, in meters of the Web Mercator (EPSG:3857).
+const HALF_CIRCUMFERENCE: f64 = 20037508.342789244;
+
+/// Coordinate reference system of the requested images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crs {
+    /// Web Mercator, in meters. Tiles are rectangular in it, so this is the preferred one.
+    Epsg3857,
+
+    /// Latitude and longitude in degrees. Images get slightly distorted.
+    Epsg4326,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WmsVersion {
+    V1_1_1,
+    V1_3_0,
+}
+
+/// Turns [`TileId`]s into `GetMap` requests.
+#[derive(Clone, Debug)]
+pub struct WmsSource {
+    url: String,
+    layers: String,
+    styles: String,
+    crs: Crs,
+    format: String,
+    version: WmsVersion,
+    transparent: bool,
+    attribution: Option<Attribution>,
+}
+
+impl WmsSource {
+    /// Source requesting comma-separated `layers` from the service at `url`. Defaults are:
+    /// version 1.3.0, EPSG:3857, `image/png` and transparent background.
+    pub fn new(url: impl Into<String>, layers: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            layers: layers.into(),
+            styles: String::new(),
+            crs: Crs::Epsg3857,
+            format: "image/png".to_owned(),
+            version: WmsVersion::V1_3_0,
+            transparent: true,
+            attribution: None,
+        }
+    }
+
+    pub fn styles(mut self, styles: impl Into<String>) -> Self {
+        self.styles = styles.into();
+        self
+    }
+
+    pub fn crs(mut self, crs: Crs) -> Self {
+        self.crs = crs;
+        self
+    }
+
+    /// MIME type of the images, e.g. `image/jpeg`.
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into();
+        self
+    }
+
+    pub fn version(mut self, version: WmsVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Attribution required by the service's provider.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attribution = Some(attribution);
+        self
+    }
+
+    /// Bounding box of the tile, as expected by the `BBOX` parameter.
+    fn bbox(&self, tile_id: TileId) -> [f64; 4] {
+        let number_of_tiles = 2f64.powi(tile_id.zoom as i32);
+        let west = tile_id.x as f64 / number_of_tiles;
+        let east = (tile_id.x + 1) as f64 / number_of_tiles;
+        let north = tile_id.y as f64 / number_of_tiles;
+        let south = (tile_id.y + 1) as f64 / number_of_tiles;
+
+        match self.crs {
+            Crs::Epsg3857 => {
+                let x = |x: f64| (x * 2. - 1.) * HALF_CIRCUMFERENCE;
+                let y = |y: f64| (1. - y * 2.) * HALF_CIRCUMFERENCE;
+                [x(west), y(south), x(east), y(north)]
+            }
+            Crs::Epsg4326 => {
+                let south_west = position_from_normalized((west, south));
+                let north_east = position_from_normalized((east, north));
+
+                // WMS 1.3.0 follows the axis order of the EPSG database, which is lat/lon.
+                match self.version {
+                    WmsVersion::V1_1_1 => [
+                        south_west.x(),
+                        south_west.y(),
+                        north_east.x(),
+                        north_east.y(),
+                    ],
+                    WmsVersion::V1_3_0 => [
+                        south_west.y(),
+                        south_west.x(),
+                        north_east.y(),
+                        north_east.x(),
+                    ],
+                }
+            }
+        }
+    }
+}
+
+impl TileSource for WmsSource {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let (version, crs_parameter) = match self.version {
+            WmsVersion::V1_1_1 => ("1.1.1", "SRS"),
+            WmsVersion::V1_3_0 => ("1.3.0", "CRS"),
+        };
+        let crs = match self.crs {
+            Crs::Epsg3857 => "EPSG:3857",
+            Crs::Epsg4326 => "EPSG:4326",
+        };
+        let [min_x, min_y, max_x, max_y] = self.bbox(tile_id);
+        let separator = if self.url.contains('?') { '&' } else { '?' };
+
+        format!(
+            "{}{}SERVICE=WMS&REQUEST=GetMap&VERSION={}&LAYERS={}&STYLES={}&{}={}\
+             &BBOX={},{},{},{}&WIDTH={}&HEIGHT={}&FORMAT={}&TRANSPARENT={}",
+            self.url,
+            separator,
+            version,
+            self.layers,
+            self.styles,
+            crs_parameter,
+            crs,
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+            TILE_SIZE,
+            TILE_SIZE,
+            self.format,
+            if self.transparent { "TRUE" } else { "FALSE" }
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.attribution.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_world_in_web_mercator() {
+        let source = WmsSource::new("https://example.com/wms", "a,b");
+        assert_eq!(
+            "https://example.com/wms?SERVICE=WMS&REQUEST=GetMap&VERSION=1.3.0&LAYERS=a,b&STYLES=\
+             &CRS=EPSG:3857&BBOX=-20037508.342789244,-20037508.342789244,20037508.342789244,\
+             20037508.342789244&WIDTH=256&HEIGHT=256&FORMAT=image/png&TRANSPARENT=TRUE",
+            source.tile_url(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            })
+        );
+    }
+
+    #[test]
+    fn bounding_box_in_degrees() {
+        let tile_id = TileId {
+            x: 1,
+            y: 0,
+            zoom: 1,
+        };
+        let source = WmsSource::new("https://example.com/wms?map=x", "a")
+            .crs(Crs::Epsg4326)
+            .version(WmsVersion::V1_1_1);
+
+        let [west, south, east, north] = source.bbox(tile_id);
+        assert_eq!([0., 0., 180.], [west, south, east]);
+        approx::assert_relative_eq!(north, 85.0511287798066);
+        assert!(source
+            .tile_url(tile_id)
+            .starts_with("https://example.com/wms?map=x&SERVICE=WMS"));
+        assert!(source.tile_url(tile_id).contains("&SRS=EPSG:4326&"));
+
+        // Axes are swapped in 1.3.0.
+        let source = source.version(WmsVersion::V1_3_0);
+        assert_eq!([0., 0.], source.bbox(tile_id)[..2]);
+        assert_eq!(180., source.bbox(tile_id)[3]);
+    }
+}
@@ -0,0 +1,534 @@
+//! Client of the Web Map Tile Service, configured from its `GetCapabilities` document.
+//! <https://www.ogc.org/standard/wmts/>
+//!
+//! Only tile matrix sets compatible with the Web Mercator are supported, i.e. those in its
+//! `EPSG:3857` CRS which on each level cover the whole world with a square grid of `2^level`
+//! tiles.
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use roxmltree::{Document, Node};
+
+use super::{Attribution, TileSource};
+use crate::mercator::TileId;
+use crate::{HttpOptions, HttpOptionsError};
+
+/// Codes of the Web Mercator CRS, the official one and the ones used before it was assigned.
+const WEB_MERCATOR_CODES: [&str; 4] = ["3857", "3785", "900913", "102100"];
+
+/// Top left corner of the world in the Web Mercator, in meters.
+const WEB_MERCATOR_CORNER: (f64, f64) = (-20037508.3427892, 20037508.3427892);
+
+#[derive(Debug, thiserror::Error)]
+pub enum WmtsError {
+    #[error("could not download the capabilities: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    HttpOptions(#[from] HttpOptionsError),
+
+    #[error("could not parse the capabilities: {0}")]
+    Xml(#[from] roxmltree::Error),
+
+    #[error("no such layer: {0}")]
+    NoSuchLayer(String),
+
+    #[error("layer does not use tile matrix set: {0}")]
+    NoSuchTileMatrixSet(String),
+
+    #[error("tile matrix set is not compatible with the Web Mercator")]
+    NotWebMercator,
+
+    #[error("layer has no tile URL")]
+    NoTileUrl,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+/// Parsed `GetCapabilities` document.
+pub struct WmtsCapabilities {
+    layers: Vec<Layer>,
+    tile_matrix_sets: Vec<TileMatrixSet>,
+
+    /// URL of the KVP `GetTile` operation.
+    get_tile: Option<String>,
+}
+
+struct Layer {
+    identifier: String,
+
+    /// Default style, or the first one.
+    style: String,
+    format: String,
+    tile_matrix_sets: Vec<String>,
+
+    /// Template of the RESTful `ResourceURL`.
+    resource_url: Option<String>,
+}
+
+struct TileMatrixSet {
+    identifier: String,
+    supported_crs: Option<String>,
+    matrices: Vec<TileMatrix>,
+}
+
+impl TileMatrixSet {
+    /// Whether the CRS is the Web Mercator, given as e.g. `EPSG:3857` or
+    /// `urn:ogc:def:crs:EPSG::3857`.
+    fn is_web_mercator(&self) -> bool {
+        self.supported_crs.as_deref().is_some_and(|crs| {
+            crs.rsplit(':')
+                .next()
+                .is_some_and(|code| WEB_MERCATOR_CODES.contains(&code))
+        })
+    }
+}
+
+struct TileMatrix {
+    identifier: String,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+
+    /// In the units of the CRS, or `None` if not given.
+    top_left_corner: Option<(f64, f64)>,
+}
+
+impl TileMatrix {
+    /// Whether the matrix covers the whole world with `2^level` tiles on each side.
+    fn covers_the_world(&self) -> bool {
+        let corner_matches = self.top_left_corner.map_or(true, |(x, y)| {
+            (x - WEB_MERCATOR_CORNER.0).abs() < 1. && (y - WEB_MERCATOR_CORNER.1).abs() < 1.
+        });
+        self.width == self.height && self.width.is_power_of_two() && corner_matches
+    }
+}
+
+impl WmtsCapabilities {
+    /// Download the capabilities, e.g. from
+    /// `https://example.com/wmts?SERVICE=WMTS&REQUEST=GetCapabilities`.
+    pub async fn fetch(url: &str) -> Result<Self, WmtsError> {
+        Self::fetch_with_options(url, &HttpOptions::default()).await
+    }
+
+    /// Download the capabilities with the same `User-Agent`, headers and proxy as the tiles, which
+    /// is needed by the services requiring API keys.
+    pub async fn fetch_with_options(url: &str, options: &HttpOptions) -> Result<Self, WmtsError> {
+        let xml = options
+            .client()?
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Self::parse(xml)
+    }
+
+    pub fn parse(xml: impl AsRef<str>) -> Result<Self, WmtsError> {
+        let document = Document::parse(xml.as_ref())?;
+
+        let layers = contents_children(&document, "Layer")
+            .filter_map(|layer| {
+                Some(Layer {
+                    identifier: child_text(layer, "Identifier")?.to_owned(),
+                    style: children(layer, "Style")
+                        .find(|style| style.attribute("isDefault") == Some("true"))
+                        .or_else(|| child(layer, "Style"))
+                        .and_then(|style| child_text(style, "Identifier"))
+                        .unwrap_or("default")
+                        .to_owned(),
+                    format: child_text(layer, "Format")
+                        .unwrap_or("image/png")
+                        .to_owned(),
+                    tile_matrix_sets: children(layer, "TileMatrixSetLink")
+                        .filter_map(|link| child_text(link, "TileMatrixSet"))
+                        .map(str::to_owned)
+                        .collect(),
+                    resource_url: children(layer, "ResourceURL")
+                        .find(|url| url.attribute("resourceType") == Some("tile"))
+                        .and_then(|url| url.attribute("template"))
+                        .map(str::to_owned),
+                })
+            })
+            .collect();
+
+        let tile_matrix_sets = contents_children(&document, "TileMatrixSet")
+            .filter_map(|set| {
+                Some(TileMatrixSet {
+                    identifier: child_text(set, "Identifier")?.to_owned(),
+                    supported_crs: child_text(set, "SupportedCRS").map(str::to_owned),
+                    matrices: children(set, "TileMatrix")
+                        .filter_map(|matrix| {
+                            let number =
+                                |name| child_text(matrix, name).and_then(|text| text.parse().ok());
+                            Some(TileMatrix {
+                                identifier: child_text(matrix, "Identifier")?.to_owned(),
+                                width: number("MatrixWidth")?,
+                                height: number("MatrixHeight")?,
+                                tile_width: number("TileWidth")?,
+                                top_left_corner: child_text(matrix, "TopLeftCorner")
+                                    .and_then(parse_corner),
+                            })
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+
+        let get_tile = document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "Operation")
+            .find(|node| node.attribute("name") == Some("GetTile"))
+            .and_then(|operation| {
+                operation
+                    .descendants()
+                    .find(|node| node.tag_name().name() == "Get")
+            })
+            .and_then(|get| {
+                get.attributes()
+                    .find(|attribute| attribute.name() == "href")
+                    .map(|attribute| attribute.value().to_owned())
+            });
+
+        Ok(Self {
+            layers,
+            tile_matrix_sets,
+            get_tile,
+        })
+    }
+
+    /// Identifiers of the available layers.
+    pub fn layers(&self) -> Vec<String> {
+        self.layers
+            .iter()
+            .map(|layer| layer.identifier.clone())
+            .collect()
+    }
+
+    /// Identifiers of the tile matrix sets which can be used with the layer.
+    pub fn tile_matrix_sets(&self, layer: &str) -> Result<Vec<String>, WmtsError> {
+        Ok(self.find_layer(layer)?.tile_matrix_sets.clone())
+    }
+
+    /// Source of the tiles of given layer, using the tile matrix set.
+    pub fn source(&self, layer: &str, tile_matrix_set: &str) -> Result<WmtsSource, WmtsError> {
+        let layer = self.find_layer(layer)?;
+        if !layer.tile_matrix_sets.iter().any(|s| s == tile_matrix_set) {
+            return Err(WmtsError::NoSuchTileMatrixSet(tile_matrix_set.to_owned()));
+        }
+        let set = self
+            .tile_matrix_sets
+            .iter()
+            .find(|set| set.identifier == tile_matrix_set)
+            .ok_or_else(|| WmtsError::NoSuchTileMatrixSet(tile_matrix_set.to_owned()))?;
+
+        let template = self
+            .tile_url_template(layer, tile_matrix_set)?
+            .replace("{Style}", &layer.style)
+            .replace("{TileMatrixSet}", tile_matrix_set);
+
+        let mut matrices = BTreeMap::new();
+        let mut tile_size = None;
+
+        if !set.is_web_mercator() {
+            return Err(WmtsError::NotWebMercator);
+        }
+
+        for matrix in &set.matrices {
+            if !matrix.covers_the_world() {
+                return Err(WmtsError::NotWebMercator);
+            }
+
+            matrices.insert(
+                matrix.width.trailing_zeros() as u8,
+                matrix.identifier.clone(),
+            );
+            tile_size = Some(matrix.tile_width);
+        }
+
+        Ok(WmtsSource {
+            template,
+            matrices,
+            tile_size: tile_size.ok_or(WmtsError::NotWebMercator)?,
+            attribution: None,
+        })
+    }
+
+    fn find_layer(&self, layer: &str) -> Result<&Layer, WmtsError> {
+        self.layers
+            .iter()
+            .find(|candidate| candidate.identifier == layer)
+            .ok_or_else(|| WmtsError::NoSuchLayer(layer.to_owned()))
+    }
+
+    /// URL with `{TileMatrix}`, `{TileRow}` and `{TileCol}` placeholders. It comes either from
+    /// the layer's RESTful `ResourceURL` or the KVP `GetTile` operation.
+    fn tile_url_template(&self, layer: &Layer, tile_matrix_set: &str) -> Result<String, WmtsError> {
+        if let Some(template) = &layer.resource_url {
+            return Ok(template.clone());
+        }
+
+        let get_tile = self.get_tile.as_ref().ok_or(WmtsError::NoTileUrl)?;
+        let separator = if get_tile.contains('?') { "" } else { "?" };
+        Ok(format!(
+            "{}{}SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER={}&STYLE={{Style}}&FORMAT={}\
+             &TILEMATRIXSET={}&TILEMATRIX={{TileMatrix}}&TILEROW={{TileRow}}&TILECOL={{TileCol}}",
+            get_tile, separator, layer.identifier, layer.format, tile_matrix_set
+        ))
+    }
+}
+
+/// Coordinates separated by a space, e.g. `-20037508.3428 20037508.3428`.
+fn parse_corner(text: &str) -> Option<(f64, f64)> {
+    let mut coordinates = text.split_whitespace().map(str::parse);
+    match (coordinates.next(), coordinates.next(), coordinates.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Some((x, y)),
+        _ => None,
+    }
+}
+
+fn contents_children<'a, 'input: 'a>(
+    document: &'a Document<'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    child(document.root_element(), "Contents")
+        .into_iter()
+        .flat_map(move |contents| children(contents, name))
+}
+
+/// Source created by [`WmtsCapabilities::source`].
+#[derive(Clone, Debug)]
+pub struct WmtsSource {
+    template: String,
+
+    /// Identifiers of the tile matrices for each zoom level.
+    matrices: BTreeMap<u8, String>,
+
+    tile_size: u32,
+    attribution: Option<Attribution>,
+}
+
+impl WmtsSource {
+    /// Zoom levels provided by the service.
+    pub fn zoom_range(&self) -> Option<RangeInclusive<u8>> {
+        let min = *self.matrices.keys().next()?;
+        let max = *self.matrices.keys().next_back()?;
+        Some(min..=max)
+    }
+
+    /// Size of the tiles in pixels, which does not have to be 256.
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Attribution required by the service's provider.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attribution = Some(attribution);
+        self
+    }
+}
+
+impl TileSource for WmtsSource {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let matrix = self
+            .matrices
+            .get(&tile_id.zoom)
+            .cloned()
+            .unwrap_or_else(|| tile_id.zoom.to_string());
+
+        self.template
+            .replace("{TileMatrix}", &matrix)
+            .replace("{TileRow}", &tile_id.y.to_string())
+            .replace("{TileCol}", &tile_id.x.to_string())
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.attribution.clone()
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        WmtsSource::zoom_range(self).unwrap_or(super::DEFAULT_ZOOM_RANGE)
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(layer_urls: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <Capabilities xmlns="http://www.opengis.net/wmts/1.0"
+                          xmlns:ows="http://www.opengis.net/ows/1.1"
+                          xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">
+              <ows:OperationsMetadata>
+                <ows:Operation name="GetTile">
+                  <ows:DCP><ows:HTTP>
+                    <ows:Get xlink:href="https://example.com/wmts?"/>
+                  </ows:HTTP></ows:DCP>
+                </ows:Operation>
+              </ows:OperationsMetadata>
+              <Contents>
+                <Layer>
+                  <ows:Identifier>orthophoto</ows:Identifier>
+                  <Style isDefault="true"><ows:Identifier>normal</ows:Identifier></Style>
+                  <Format>image/jpeg</Format>
+                  <TileMatrixSetLink><TileMatrixSet>google</TileMatrixSet></TileMatrixSetLink>
+                  <TileMatrixSetLink><TileMatrixSet>wgs84</TileMatrixSet></TileMatrixSetLink>
+                  <TileMatrixSetLink><TileMatrixSet>poland</TileMatrixSet></TileMatrixSetLink>
+                  {layer_urls}
+                </Layer>
+                <TileMatrixSet>
+                  <ows:Identifier>google</ows:Identifier>
+                  <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+                  <TileMatrix>
+                    <ows:Identifier>g:5</ows:Identifier>
+                    <TopLeftCorner>-20037508.3428 20037508.3428</TopLeftCorner>
+                    <TileWidth>512</TileWidth><TileHeight>512</TileHeight>
+                    <MatrixWidth>32</MatrixWidth><MatrixHeight>32</MatrixHeight>
+                  </TileMatrix>
+                  <TileMatrix>
+                    <ows:Identifier>g:6</ows:Identifier>
+                    <TileWidth>512</TileWidth><TileHeight>512</TileHeight>
+                    <MatrixWidth>64</MatrixWidth><MatrixHeight>64</MatrixHeight>
+                  </TileMatrix>
+                </TileMatrixSet>
+                <TileMatrixSet>
+                  <ows:Identifier>wgs84</ows:Identifier>
+                  <ows:SupportedCRS>urn:ogc:def:crs:EPSG::4326</ows:SupportedCRS>
+                  <TileMatrix>
+                    <ows:Identifier>0</ows:Identifier>
+                    <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+                    <MatrixWidth>2</MatrixWidth><MatrixHeight>2</MatrixHeight>
+                  </TileMatrix>
+                </TileMatrixSet>
+                <TileMatrixSet>
+                  <ows:Identifier>poland</ows:Identifier>
+                  <ows:SupportedCRS>EPSG:3857</ows:SupportedCRS>
+                  <TileMatrix>
+                    <ows:Identifier>0</ows:Identifier>
+                    <TopLeftCorner>1500000 7400000</TopLeftCorner>
+                    <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+                    <MatrixWidth>2</MatrixWidth><MatrixHeight>2</MatrixHeight>
+                  </TileMatrix>
+                </TileMatrixSet>
+              </Contents>
+            </Capabilities>"#
+        )
+    }
+
+    #[test]
+    fn restful_source() {
+        let capabilities = WmtsCapabilities::parse(capabilities(
+            r#"<ResourceURL format="image/jpeg" resourceType="tile"
+                template="https://example.com/{Style}/{TileMatrix}/{TileRow}/{TileCol}.jpg"/>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(vec!["orthophoto".to_owned()], capabilities.layers());
+        assert_eq!(
+            vec!["google", "wgs84", "poland"],
+            capabilities.tile_matrix_sets("orthophoto").unwrap()
+        );
+
+        let source = capabilities.source("orthophoto", "google").unwrap();
+        assert_eq!(Some(5..=6), source.zoom_range());
+        assert_eq!(512, source.tile_size());
+        assert_eq!(
+            "https://example.com/normal/g:6/2/1.jpg",
+            source.tile_url(TileId {
+                x: 1,
+                y: 2,
+                zoom: 6
+            })
+        );
+    }
+
+    #[test]
+    fn kvp_source() {
+        let capabilities = WmtsCapabilities::parse(capabilities("")).unwrap();
+        let source = capabilities.source("orthophoto", "google").unwrap();
+        assert_eq!(
+            "https://example.com/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER=orthophoto\
+             &STYLE=normal&FORMAT=image/jpeg&TILEMATRIXSET=google&TILEMATRIX=g:5&TILEROW=2\
+             &TILECOL=1",
+            source.tile_url(TileId {
+                x: 1,
+                y: 2,
+                zoom: 5
+            })
+        );
+    }
+
+    #[test]
+    fn capabilities_are_fetched_with_the_http_options() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/wmts")
+            .match_header("x-api-key", "secret")
+            .with_body(capabilities(""))
+            .create();
+
+        let options = HttpOptions {
+            headers: vec![("X-Api-Key".to_owned(), "secret".to_owned())],
+            ..Default::default()
+        };
+        let capabilities = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(WmtsCapabilities::fetch_with_options(
+                &format!("{}/wmts", server.url()),
+                &options,
+            ))
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(vec!["orthophoto".to_owned()], capabilities.layers());
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let capabilities = WmtsCapabilities::parse(capabilities("")).unwrap();
+        assert!(matches!(
+            capabilities.source("roads", "google"),
+            Err(WmtsError::NoSuchLayer(_))
+        ));
+        assert!(matches!(
+            capabilities.source("orthophoto", "utm"),
+            Err(WmtsError::NoSuchTileMatrixSet(_))
+        ));
+        assert!(matches!(
+            capabilities.source("orthophoto", "wgs84"),
+            Err(WmtsError::NotWebMercator)
+        ));
+        assert!(matches!(
+            capabilities.source("orthophoto", "poland"),
+            Err(WmtsError::NotWebMercator)
+        ));
+        assert!(WmtsCapabilities::parse("<Capabilities>").is_err());
+    }
+}
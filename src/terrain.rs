@@ -0,0 +1,591 @@
+//! Elevation of the terrain, taken from the tiles which encode heights in their colors, like the
+//! [Terrarium](https://github.com/tilezen/joerd/blob/master/docs/formats.md#terrarium) ones made
+//! by Mapzen. [`Hillshade`] turns them into a shaded relief, drawn as a layer on top of the map,
+//! and [`Terrain`] tells the elevation of any position, e.g. the one under the cursor.
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::{Color32, ColorImage, Context};
+use tokio::sync::mpsc::error::TryRecvError;
+use web_time::Instant;
+
+use crate::io::{self, Runtime};
+use crate::mercator::{
+    mercator_normalized, meters_per_point, position_from_normalized, TileId, TILE_SIZE,
+};
+use crate::sources::{Attribution, FetchFuture, TileSource};
+use crate::tiles::Request;
+use crate::{Position, PositionExt};
+
+/// Elevation tiles in the Terrarium format, hosted by the
+/// [AWS Open Data](https://registry.opendata.aws/terrain-tiles/).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Terrarium;
+
+impl TileSource for Terrarium {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://s3.amazonaws.com/elevation-tiles-prod/terrarium/{}/{}/{}.png",
+            tile_id.zoom, tile_id.x, tile_id.y
+        )
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        Some(Attribution::new(
+            "Terrain Tiles by Mapzen",
+            "https://github.com/tilezen/joerd/blob/master/docs/attribution.md",
+        ))
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        0..=15
+    }
+}
+
+/// Elevation (in meters) encoded in the color of a Terrarium tile's pixel.
+pub fn elevation(color: Color32) -> f64 {
+    let [r, g, b, _] = color.to_array().map(f64::from);
+    r * 256. + g + b / 256. - 32768.
+}
+
+/// Elevations of a tile's pixels, in the row-major order.
+struct Heights {
+    size: [usize; 2],
+    values: Vec<f32>,
+}
+
+impl Heights {
+    fn new(image: &ColorImage) -> Self {
+        Self {
+            size: image.size,
+            values: image
+                .pixels
+                .iter()
+                .map(|pixel| elevation(*pixel) as f32)
+                .collect(),
+        }
+    }
+
+    /// Elevation of the pixel, or of the nearest one at the tile's edge.
+    fn at(&self, x: isize, y: isize) -> f32 {
+        let [width, height] = self.size;
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        self.values[y * width + x]
+    }
+
+    /// Elevation of the pixel, where the ones beyond the tile's edges continue the slope at the
+    /// edge. With the nearest ones instead, the slope would flatten along every edge, and the
+    /// shaded tiles would be outlined by light and dark lines.
+    fn extrapolated(&self, x: isize, y: isize) -> f32 {
+        let [width, height] = self.size.map(|size| size as isize);
+        let continued = |value: isize, size: isize, at: &dyn Fn(isize) -> f32| {
+            let edge = value.clamp(0, size - 1);
+            let beyond = value - edge;
+            if beyond == 0 {
+                return at(value);
+            }
+            let inner = (edge - beyond.signum()).clamp(0, size - 1);
+            at(edge) + beyond.abs() as f32 * (at(edge) - at(inner))
+        };
+        continued(x, width, &|x| continued(y, height, &|y| self.at(x, y)))
+    }
+}
+
+/// [`TileSource`] which shades the elevation tiles of the `source`, as if the terrain was lit
+/// by the sun. Slopes facing away from the light are darkened, the ones facing it are lightened,
+/// and the flat areas stay transparent, so the tiles are meant to be added with
+/// [`crate::Map::with_layer`] on top of a regular map.
+#[derive(Clone, Debug)]
+pub struct Hillshade<S = Terrarium> {
+    pub source: S,
+
+    /// Direction the light comes from, in degrees clockwise from the north. Default is 315,
+    /// i.e. north-west, which is what the cartographers use.
+    pub azimuth: f64,
+
+    /// Angle (in degrees) of the light above the horizon. Default is 45.
+    pub altitude: f64,
+
+    /// Factor the heights are multiplied by, to make the relief of the lowlands visible.
+    /// Default is 1.
+    pub exaggeration: f64,
+}
+
+impl<S> Hillshade<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            azimuth: 315.,
+            altitude: 45.,
+            exaggeration: 1.,
+        }
+    }
+}
+
+impl<S: TileSource> TileSource for Hillshade<S> {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self.source.tile_url(tile_id)
+    }
+
+    fn fetch(&self, tile_id: TileId) -> Option<FetchFuture> {
+        self.source.fetch(tile_id)
+    }
+
+    fn attribution(&self) -> Option<Attribution> {
+        self.source.attribution()
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.source.zoom_range()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.source.tile_size()
+    }
+
+    fn decode(&self, data: &[u8], content_type: Option<&str>) -> Result<ColorImage, String> {
+        self.source.decode(data, content_type)
+    }
+
+    fn decode_tile(
+        &self,
+        tile_id: TileId,
+        data: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<ColorImage, String> {
+        let image = self.source.decode_tile(tile_id, data, content_type)?;
+        Ok(self.shade(tile_id, &Heights::new(&image)))
+    }
+}
+
+impl<S> Hillshade<S> {
+    fn shade(&self, tile_id: TileId, heights: &Heights) -> ColorImage {
+        let [width, height] = heights.size;
+
+        // Ground distance between the neighbouring pixels, at the tile's middle.
+        let tiles = (1u64 << tile_id.zoom) as f64;
+        let latitude = position_from_normalized((
+            (tile_id.x as f64 + 0.5) / tiles,
+            (tile_id.y as f64 + 0.5) / tiles,
+        ))
+        .y();
+        let spacing = meters_per_point(latitude, tile_id.zoom as f64) * TILE_SIZE as f64
+            / width.max(1) as f64;
+
+        let (azimuth, altitude) = (self.azimuth.to_radians(), self.altitude.to_radians());
+        let light = [
+            azimuth.sin() * altitude.cos(),
+            azimuth.cos() * altitude.cos(),
+            altitude.sin(),
+        ];
+
+        let pixels = (0..height as isize)
+            .flat_map(|y| (0..width as isize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let z = |dx: isize, dy: isize| heights.extrapolated(x + dx, y + dy) as f64;
+
+                // Horn's method, which weights the closest neighbours twice.
+                let east =
+                    (z(1, -1) + 2. * z(1, 0) + z(1, 1)) - (z(-1, -1) + 2. * z(-1, 0) + z(-1, 1));
+                let south =
+                    (z(-1, 1) + 2. * z(0, 1) + z(1, 1)) - (z(-1, -1) + 2. * z(0, -1) + z(1, -1));
+                let scale = self.exaggeration / (8. * spacing);
+
+                // Rows grow southwards, so the northward slope is the opposite.
+                let normal = [-east * scale, south * scale, 1.];
+                let length = normal.iter().map(|c| c * c).sum::<f64>().sqrt();
+                let lit = normal.iter().zip(light).map(|(n, l)| n * l).sum::<f64>() / length;
+                shade(lit.max(0.), light[2])
+            })
+            .collect();
+
+        ColorImage {
+            size: heights.size,
+            pixels,
+        }
+    }
+}
+
+/// Black or white, as much opaque as the slope is darker or lighter than the flat ground.
+fn shade(lit: f64, flat: f64) -> Color32 {
+    if lit < flat {
+        let alpha = (flat - lit) / flat;
+        Color32::from_black_alpha((alpha * 255.).round() as u8)
+    } else {
+        let alpha = (lit - flat) / (1. - flat).max(f64::EPSILON);
+        Color32::from_white_alpha((alpha.min(1.) * 255.).round() as u8)
+    }
+}
+
+/// Entry of the cache.
+struct CachedHeights {
+    /// `None` means that the tile was requested, but not yet downloaded, or that it failed.
+    heights: Option<Arc<Heights>>,
+
+    /// When the tile failed to download or decode.
+    failed_at: Option<Instant>,
+
+    /// Value of `Terrain::use_counter` when the tile was last used.
+    last_used: u64,
+}
+
+/// Default number of the tiles kept in the cache.
+const DEFAULT_CACHE_SIZE: usize = 64;
+
+/// How many levels below the `Terrain`'s zoom the coarse tiles are. Each of them covers 256 of
+/// the precise ones.
+const COARSE_LEVELS: u8 = 4;
+
+/// Elevation of the positions, taken from the tiles of a source like [`Terrarium`], which are
+/// downloaded as they are asked for. It keeps a cache of the tiles, so it must persist between
+/// frames.
+pub struct Terrain {
+    cache: HashMap<TileId, CachedHeights>,
+    use_counter: u64,
+    cache_size: usize,
+    zoom: u8,
+    zoom_range: RangeInclusive<u8>,
+    failed_tile_cooldown: Duration,
+
+    /// Tiles to be downloaded by the IO thread.
+    request_tx: tokio::sync::mpsc::UnboundedSender<Request>,
+
+    /// Tiles that got downloaded (or failed to) and should be put in the cache.
+    tile_rx: tokio::sync::mpsc::Receiver<(TileId, Result<Heights, String>)>,
+
+    #[allow(dead_code)] // Significant Drop
+    runtime: Option<Runtime>,
+}
+
+impl Terrain {
+    pub fn new<S>(source: S, egui_ctx: Context) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        // Same as for the raster tiles.
+        let channel_size = 20;
+
+        let zoom_range = source.zoom_range();
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
+        let http = crate::http::default_client();
+        let failed_tile_cooldown = http.failed_tile_cooldown;
+        let runtime = io::spawn(
+            None,
+            crate::tiles::download(
+                source,
+                |source: &S, tile_id: TileId, data: &[u8], content_type: Option<&str>| {
+                    source
+                        .decode_tile(tile_id, data, content_type)
+                        .map(|image| Heights::new(&image))
+                        .map_err(|err| log::warn!("Could not decode an elevation tile: {}.", err))
+                        .ok()
+                },
+                http,
+                None,
+                request_rx,
+                tile_tx,
+                egui_ctx,
+            ),
+        );
+
+        Self {
+            cache: Default::default(),
+            use_counter: 0,
+            cache_size: DEFAULT_CACHE_SIZE,
+            zoom: *zoom_range.end(),
+            zoom_range,
+            failed_tile_cooldown,
+            request_tx,
+            tile_rx,
+            runtime,
+        }
+    }
+
+    /// Zoom level of the tiles the elevations are taken from. Higher ones are more precise, but
+    /// more of them need to be downloaded. Default is the highest one of the source, and it
+    /// is kept within the source's zoom range.
+    pub fn with_zoom(mut self, zoom: u8) -> Self {
+        self.zoom = zoom.clamp(*self.zoom_range.start(), *self.zoom_range.end());
+        self
+    }
+
+    /// How long to wait before requesting a failed tile again. Default is 60 seconds, as for the
+    /// raster tiles.
+    pub fn failed_tile_cooldown(mut self, cooldown: Duration) -> Self {
+        self.failed_tile_cooldown = cooldown;
+        self
+    }
+
+    /// Maximum number of the tiles kept in the memory. Default is 64.
+    pub fn cache_size(mut self, tiles: usize) -> Self {
+        self.cache_size = tiles.max(1);
+        self
+    }
+
+    /// Elevation (in meters) of the position. If its tile is not downloaded yet, it is requested,
+    /// along with a coarse one, a few levels lower, which covers much more of the area around.
+    /// Until then, or if the tile fails, the elevation is taken from a less precise tile of
+    /// a lower zoom, if there is one in the cache. Otherwise, it is `None`.
+    pub fn elevation_at(&mut self, position: Position) -> Option<f64> {
+        self.receive();
+        self.use_counter += 1;
+
+        let tile_id = position.tile_id(self.zoom);
+        self.request(tile_id);
+        let coarse = self
+            .zoom
+            .saturating_sub(COARSE_LEVELS)
+            .max(*self.zoom_range.start());
+        if coarse < self.zoom {
+            self.request(position.tile_id(coarse));
+        }
+
+        let (x, y) = mercator_normalized(position.into());
+        std::iter::successors(Some(tile_id), TileId::parent).find_map(|tile_id| {
+            let cached = self.cache.get_mut(&tile_id)?;
+            cached.last_used = self.use_counter;
+            let heights = cached.heights.as_ref()?;
+
+            // Nearest pixel of the tile.
+            let tiles = (1u64 << tile_id.zoom) as f64;
+            let pixel = |normalized: f64, first: u32, size: usize| {
+                ((normalized * tiles - first as f64) * size as f64).floor() as isize
+            };
+            let [width, height] = heights.size;
+            Some(heights.at(pixel(x, tile_id.x, width), pixel(y, tile_id.y, height)) as f64)
+        })
+    }
+
+    /// Schedule a download, unless the tile is in the cache already, or it failed recently.
+    fn request(&mut self, tile_id: TileId) {
+        if let Some(cached) = self.cache.get(&tile_id) {
+            let cooled_down = cached
+                .failed_at
+                .is_some_and(|failed_at| failed_at.elapsed() >= self.failed_tile_cooldown);
+            if !cooled_down {
+                return;
+            }
+            log::debug!("Requesting elevation tile {:?} again.", tile_id);
+        }
+
+        if let Ok(()) = self.request_tx.send(Request::Download(tile_id)) {
+            log::debug!("Requested elevation tile: {:?}", tile_id);
+            self.insert(tile_id, None, None);
+        }
+    }
+
+    /// Put the downloaded tiles into the cache.
+    fn receive(&mut self) {
+        loop {
+            match self.tile_rx.try_recv() {
+                Ok((tile_id, Ok(heights))) => self.insert(tile_id, Some(Arc::new(heights)), None),
+                // Failed tiles stay empty, until the cooldown passes.
+                Ok((tile_id, Err(_))) => self.insert(tile_id, None, Some(Instant::now())),
+                Err(TryRecvError::Empty) => break,
+                // Requests will not get through either, so there is nothing more to do.
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        tile_id: TileId,
+        heights: Option<Arc<Heights>>,
+        failed_at: Option<Instant>,
+    ) {
+        self.cache.insert(
+            tile_id,
+            CachedHeights {
+                heights,
+                failed_at,
+                last_used: self.use_counter,
+            },
+        );
+
+        // Tiles which are still being downloaded stay, otherwise they would be requested again.
+        while self.cache.len() > self.cache_size {
+            let least_recently_used = self
+                .cache
+                .iter()
+                .filter(|(_, cached)| cached.heights.is_some() || cached.failed_at.is_some())
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(tile_id, _)| *tile_id);
+
+            let Some(tile_id) = least_recently_used else {
+                break;
+            };
+            self.cache.remove(&tile_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Color of the Terrarium pixel at given elevation.
+    fn encoded(elevation: f64) -> Color32 {
+        let value = elevation + 32768.;
+        Color32::from_rgb(
+            (value / 256.) as u8,
+            (value % 256.) as u8,
+            ((value.fract()) * 256.) as u8,
+        )
+    }
+
+    /// Tiles of a plane rising eastwards by 1 meter per pixel, 100 meters high at the west.
+    struct Slope;
+
+    impl TileSource for Slope {
+        fn tile_url(&self, tile_id: TileId) -> String {
+            format!("slope://{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y)
+        }
+
+        fn fetch(&self, _tile_id: TileId) -> Option<FetchFuture> {
+            Some(Box::pin(async { Ok(Vec::new()) }))
+        }
+
+        fn zoom_range(&self) -> RangeInclusive<u8> {
+            0..=3
+        }
+
+        fn decode(&self, _data: &[u8], _content_type: Option<&str>) -> Result<ColorImage, String> {
+            let pixels = (0..256 * 256)
+                .map(|index| encoded(100. + (index % 256) as f64))
+                .collect();
+            Ok(ColorImage {
+                size: [256, 256],
+                pixels,
+            })
+        }
+    }
+
+    #[test]
+    fn elevation_is_decoded_from_the_color() {
+        assert_eq!(0., elevation(Color32::from_rgb(128, 0, 0)));
+        assert_eq!(-32768., elevation(Color32::BLACK));
+        approx::assert_relative_eq!(8848.5, elevation(encoded(8848.5)));
+        approx::assert_relative_eq!(-10.25, elevation(encoded(-10.25)));
+    }
+
+    #[test]
+    fn slopes_facing_the_light_are_lighter() {
+        let tile_id = TileId {
+            x: 4,
+            y: 3,
+            zoom: 3,
+        };
+        let image = |hillshade: &Hillshade<Slope>| hillshade.decode_tile(tile_id, &[], None);
+
+        // Slope rising eastwards faces the west, so the light from the east darkens it.
+        let mut hillshade = Hillshade::new(Slope);
+        hillshade.azimuth = 90.;
+        hillshade.exaggeration = 1e4;
+        let shaded = image(&hillshade).unwrap();
+        assert_eq!([256, 256], shaded.size);
+        let middle = shaded.pixels[100 * 256 + 100];
+        assert_eq!(Color32::from_black_alpha(middle.a()), middle);
+        assert!(middle.a() > 100);
+
+        // From the west, it is lightened instead.
+        hillshade.azimuth = 270.;
+        let middle = image(&hillshade).unwrap().pixels[100 * 256 + 100];
+        assert_eq!(Color32::from_white_alpha(middle.a()), middle);
+        assert!(middle.a() > 100);
+
+        // Same at the edges, where the neighbouring pixels are in the other tiles.
+        let shaded = image(&hillshade).unwrap();
+        for (x, y) in [
+            (0, 100),
+            (255, 100),
+            (100, 0),
+            (100, 255),
+            (0, 0),
+            (255, 255),
+        ] {
+            assert_eq!(middle, shaded.pixels[y * 256 + x], "{x} {y}");
+        }
+
+        // Flat ground is not shaded at all.
+        let flat = Heights {
+            size: [2, 2],
+            values: vec![300.; 4],
+        };
+        assert!(hillshade
+            .shade(tile_id, &flat)
+            .pixels
+            .iter()
+            .all(|pixel| pixel.a() == 0));
+    }
+
+    #[test]
+    fn elevation_of_a_position() {
+        let _ = env_logger::try_init();
+
+        let mut terrain = Terrain::new(Slope, Context::default());
+        let position = Position::new(0.1, 0.);
+        while terrain.elevation_at(position).is_none() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        // Position is just east of the tile's west edge at zoom 3.
+        approx::assert_relative_eq!(100., terrain.elevation_at(position).unwrap());
+
+        // Tiles of lower zooms are used until the right one is downloaded.
+        let mut terrain = Terrain::new(Slope, Context::default()).with_zoom(1);
+        let world = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        terrain.insert(world.children()[1], None, None);
+        terrain.insert(
+            world,
+            Some(Arc::new(Heights {
+                size: [1, 1],
+                values: vec![42.],
+            })),
+            None,
+        );
+        assert_eq!(Some(42.), terrain.elevation_at(position));
+
+        // Zoom is kept within the source's range.
+        let terrain = Terrain::new(Slope, Context::default()).with_zoom(10);
+        assert_eq!(3, terrain.zoom);
+    }
+
+    #[test]
+    fn coarse_and_failed_tiles_are_requested() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = crate::tiles::tests::mockito_server();
+        let precise = server
+            .mock("GET", "/8/142/84.png")
+            .with_status(404)
+            .expect_at_least(2)
+            .create();
+        let coarse = server
+            .mock("GET", "/4/8/5.png")
+            .with_status(404)
+            .expect_at_least(1)
+            .create();
+
+        let mut terrain = Terrain::new(source, Context::default())
+            .with_zoom(8)
+            .failed_tile_cooldown(Duration::from_millis(100));
+        let position = Position::new(21.00027, 52.26470);
+        for _ in 0..500 {
+            if precise.matched() && coarse.matched() {
+                return;
+            }
+            assert!(terrain.elevation_at(position).is_none());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("tiles were not requested");
+    }
+}
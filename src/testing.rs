@@ -0,0 +1,476 @@
+//! Rendering the map widget into an image, without the GPU, network nor Tokio, so the same frames
+//! give the same pixels every time. Meant for the snapshot ("golden image") tests of the
+//! projections, layers, plugins and gestures, both in the applications and in walkers itself.
+//! Available with the `test-utils` feature.
+use std::collections::HashMap;
+
+use egui::epaint::{ImageData, Primitive, Vertex};
+use egui::{
+    CentralPanel, Color32, ColorImage, Context, Event, Frame, Modifiers, PointerButton, Pos2,
+    RawInput, Rect, TextureId, Ui, Vec2,
+};
+use image::RgbaImage;
+
+use crate::mercator::{TileId, TILE_SIZE};
+use crate::sources::{FetchFuture, TileSource};
+use crate::Tiles;
+
+/// Tiles of the source, loaded right when the map asks for them, on the same thread, and shown
+/// without fading in. Only the sources which [`TileSource::fetch`] their tiles without waiting
+/// work, like [`MemorySource`] or [`crate::sources::Plain`].
+pub fn tiles(source: impl TileSource + Send + 'static) -> Tiles {
+    Tiles::immediate(source)
+}
+
+/// Source of the tiles given by the test, without any files or network. Use it with [`tiles`].
+#[derive(Clone, Default)]
+pub struct MemorySource {
+    tiles: HashMap<TileId, ColorImage>,
+
+    /// Color of the tiles which were not given. `None` makes them fail to load.
+    pub fallback: Option<Color32>,
+}
+
+impl MemorySource {
+    pub fn new(fallback: Option<Color32>) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            fallback,
+        }
+    }
+
+    /// Image of the tile, instead of the fallback.
+    pub fn with_tile(mut self, tile_id: TileId, image: ColorImage) -> Self {
+        self.tiles.insert(tile_id, image);
+        self
+    }
+}
+
+impl TileSource for MemorySource {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!("memory://{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y)
+    }
+
+    /// Tiles are already decoded, so there is nothing to fetch.
+    fn fetch(&self, _tile_id: TileId) -> Option<FetchFuture> {
+        Some(Box::pin(async { Ok(Vec::new()) }))
+    }
+
+    fn decode_tile(
+        &self,
+        tile_id: TileId,
+        _data: &[u8],
+        _content_type: Option<&str>,
+    ) -> Result<ColorImage, String> {
+        match (self.tiles.get(&tile_id), self.fallback) {
+            (Some(image), _) => Ok(image.clone()),
+            (None, Some(color)) => Ok(ColorImage::new([TILE_SIZE as usize; 2], color)),
+            (None, None) => Err(format!("no tile {:?} in memory", tile_id)),
+        }
+    }
+}
+
+/// egui's time between the frames, in seconds.
+const FRAME: f64 = 1. / 60.;
+
+/// Pixels are sampled slightly off their centers, so the ones on the edge shared by two triangles
+/// are drawn by only one of them.
+const SAMPLE_OFFSET: Vec2 = Vec2::new(0.5 + 1. / 1024., 0.5 + 1. / 2048.);
+
+/// egui context which runs the frames with made up input and time, and rasterizes what they
+/// paint, one pixel per point.
+pub struct Harness {
+    ctx: Context,
+    size: Vec2,
+    frame: u64,
+
+    /// Given to egui in the next frame.
+    events: Vec<Event>,
+    textures: HashMap<TextureId, ColorImage>,
+}
+
+impl Harness {
+    /// Harness making images of `size` pixels.
+    pub fn new(size: [u32; 2]) -> Self {
+        Self {
+            ctx: Context::default(),
+            size: Vec2::new(size[0] as f32, size[1] as f32),
+            frame: 0,
+            events: Vec::new(),
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn ctx(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Event, e.g. a key press or a scroll, which egui gets in the next frame.
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Run a frame, whose contents fill the whole image, and draw it.
+    pub fn run(&mut self, add_contents: impl FnOnce(&mut Ui)) -> RgbaImage {
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, self.size)),
+            pixels_per_point: Some(1.),
+            time: Some(self.frame as f64 * FRAME),
+            predicted_dt: FRAME as f32,
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        self.frame += 1;
+
+        let output = self.ctx.run(input, |ctx| {
+            CentralPanel::default()
+                .frame(Frame::none())
+                .show(ctx, add_contents);
+        });
+
+        for (texture_id, delta) in output.textures_delta.set {
+            let image = match delta.image {
+                ImageData::Color(image) => image,
+                ImageData::Font(font) => ColorImage {
+                    size: font.size,
+                    pixels: font.srgba_pixels(None).collect(),
+                },
+            };
+            match (delta.pos, self.textures.get_mut(&texture_id)) {
+                (Some([x, y]), Some(texture)) => {
+                    for row in 0..image.size[1] {
+                        let start = (y + row) * texture.size[0] + x;
+                        texture.pixels[start..start + image.size[0]].copy_from_slice(
+                            &image.pixels[row * image.size[0]..(row + 1) * image.size[0]],
+                        );
+                    }
+                }
+                _ => {
+                    self.textures.insert(texture_id, image);
+                }
+            }
+        }
+
+        let mut canvas = Canvas::new(self.size);
+        for primitive in self.ctx.tessellate(output.shapes) {
+            // Callbacks draw with the GPU directly, so they are left out.
+            if let Primitive::Mesh(mesh) = primitive.primitive {
+                let texture = self.textures.get(&mesh.texture_id);
+                for triangle in mesh.indices.chunks_exact(3) {
+                    let vertex = |index: u32| mesh.vertices[index as usize];
+                    canvas.triangle(
+                        [
+                            vertex(triangle[0]),
+                            vertex(triangle[1]),
+                            vertex(triangle[2]),
+                        ],
+                        texture,
+                        primitive.clip_rect,
+                    );
+                }
+            }
+        }
+
+        for texture_id in output.textures_delta.free {
+            self.textures.remove(&texture_id);
+        }
+
+        canvas.image()
+    }
+
+    /// Drag with the primary button from one point to another, over a few frames, each drawn
+    /// by `ui`. Returns the image of the last one, when the button is released.
+    pub fn drag(&mut self, from: Pos2, to: Pos2, mut ui: impl FnMut(&mut Ui)) -> RgbaImage {
+        let button = |pos, pressed| Event::PointerButton {
+            pos,
+            button: PointerButton::Primary,
+            pressed,
+            modifiers: Modifiers::NONE,
+        };
+
+        self.push_event(Event::PointerMoved(from));
+        self.push_event(button(from, true));
+        self.run(&mut ui);
+
+        let steps = 4;
+        for step in 1..=steps {
+            self.push_event(Event::PointerMoved(
+                from.lerp(to, step as f32 / steps as f32),
+            ));
+            self.run(&mut ui);
+        }
+
+        self.push_event(button(to, false));
+        self.run(&mut ui)
+    }
+}
+
+/// Pixels, with the colors premultiplied by their alpha, like egui does.
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    fn new(size: Vec2) -> Self {
+        let (width, height) = (size.x as usize, size.y as usize);
+        Self {
+            width,
+            height,
+            pixels: vec![[0.; 4]; width * height],
+        }
+    }
+
+    /// Blend the triangle over the canvas, interpolating the vertices' colors and texture
+    /// coordinates. Texture is sampled at the nearest pixel.
+    fn triangle(&mut self, vertices: [Vertex; 3], texture: Option<&ColorImage>, clip_rect: Rect) {
+        let [a, b, c] = vertices.map(|vertex| vertex.pos);
+        let edge = |from: Pos2, to: Pos2, point: Pos2| {
+            (to.x - from.x) * (point.y - from.y) - (to.y - from.y) * (point.x - from.x)
+        };
+        let area = edge(a, b, c);
+        if area == 0. {
+            return;
+        }
+
+        let bounds = Rect::from_points(&[a, b, c])
+            .intersect(clip_rect)
+            .intersect(Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(self.width as f32, self.height as f32),
+            ));
+        if !bounds.is_positive() {
+            return;
+        }
+
+        for y in bounds.min.y.floor() as usize..(bounds.max.y.ceil() as usize).min(self.height) {
+            for x in bounds.min.x.floor() as usize..(bounds.max.x.ceil() as usize).min(self.width) {
+                let point = Pos2::new(x as f32, y as f32) + SAMPLE_OFFSET;
+                let weights = [edge(b, c, point), edge(c, a, point), edge(a, b, point)]
+                    .map(|weight| weight / area);
+                if weights.iter().any(|weight| *weight < 0.) || !clip_rect.contains(point) {
+                    continue;
+                }
+
+                let color = interpolated(&vertices, weights, texture);
+                let pixel = &mut self.pixels[y * self.width + x];
+                let transparency = 1. - color[3];
+                for (channel, value) in pixel.iter_mut().zip(color) {
+                    *channel = value + *channel * transparency;
+                }
+            }
+        }
+    }
+
+    fn image(&self) -> RgbaImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| {
+                let [r, g, b, a] =
+                    pixel.map(|channel| (channel.clamp(0., 1.) * 255.).round() as u8);
+                Color32::from_rgba_premultiplied(r, g, b, a).to_srgba_unmultiplied()
+            })
+            .collect();
+        RgbaImage::from_raw(self.width as u32, self.height as u32, pixels).unwrap_or_default()
+    }
+}
+
+/// Premultiplied color (in 0..1 components) of the point given by its barycentric coordinates.
+fn interpolated(
+    vertices: &[Vertex; 3],
+    weights: [f32; 3],
+    texture: Option<&ColorImage>,
+) -> [f32; 4] {
+    let mix = |component: &dyn Fn(&Vertex) -> f32| {
+        vertices
+            .iter()
+            .zip(weights)
+            .map(|(vertex, weight)| component(vertex) * weight)
+            .sum::<f32>()
+    };
+
+    let texel = texture.map_or(Color32::WHITE, |texture| {
+        let [width, height] = texture.size;
+        let sample =
+            |uv: f32, size: usize| ((uv * size as f32).floor().max(0.) as usize).min(size - 1);
+        let (x, y) = (
+            sample(mix(&|v| v.uv.x), width),
+            sample(mix(&|v| v.uv.y), height),
+        );
+        texture.pixels[y * width + x]
+    });
+
+    let mut color = [0.; 4];
+    for (channel, value) in color.iter_mut().enumerate() {
+        let tint = mix(&|vertex| vertex.color.to_array()[channel] as f32 / 255.);
+        *value = tint * texel.to_array()[channel] as f32 / 255.;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::Plain;
+    use crate::{Map, MapMemory, Plugin, Position, Projector};
+
+    fn filled(color: Color32) -> Tiles {
+        tiles(MemorySource::new(Some(color)))
+    }
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> Color32 {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    /// Draws a red square of 10 points at the given position.
+    struct Square(Position);
+
+    impl Plugin for Square {
+        fn draw(&mut self, painter: &egui::Painter, projector: &Projector) {
+            let rect = Rect::from_center_size(projector.to_screen(self.0), Vec2::splat(10.));
+            painter.rect_filled(rect, 0., Color32::RED);
+        }
+    }
+
+    #[test]
+    fn same_frames_give_the_same_images() {
+        let render = || {
+            let mut harness = Harness::new([200, 100]);
+            let mut tiles = tiles(Plain::default());
+            let mut memory = MapMemory::default();
+            let my_position = Position::new(21.00027, 52.26470);
+            (0..3)
+                .map(|_| {
+                    harness.run(|ui| {
+                        ui.add(Map::new(Some(&mut tiles), &mut memory, my_position));
+                    })
+                })
+                .last()
+                .unwrap()
+        };
+
+        let image = render();
+        assert_eq!((200, 100), image.dimensions());
+        assert_eq!(image, render());
+
+        // Background of the tiles, and the "my position" marker in the middle.
+        assert_eq!(Plain::default().background, pixel(&image, 5, 5));
+        assert_ne!(pixel(&image, 5, 5), pixel(&image, 100, 50));
+    }
+
+    #[test]
+    fn layers_and_plugins_are_drawn_in_order() {
+        let mut harness = Harness::new([100, 100]);
+        let mut base = filled(Color32::WHITE);
+        let mut overlay = filled(Color32::BLUE);
+        let mut memory = MapMemory::default();
+        let center = Position::new(21., 52.);
+
+        let image = harness.run(|ui| {
+            ui.add(
+                Map::new(Some(&mut base), &mut memory, center)
+                    .with_layer(&mut overlay, 0.5)
+                    .with_plugin(Square(Position::new(21.0001, 52.)))
+                    .my_position_marker(None),
+            );
+        });
+
+        // Half-transparent blue over white.
+        let blended = pixel(&image, 5, 5);
+        assert_eq!(255, blended.b());
+        assert!((120..136).contains(&blended.r()), "{blended:?}");
+
+        // Plugin is drawn on top of the layers, slightly east of the center.
+        assert_eq!(Color32::RED, pixel(&image, 51, 50));
+        assert_eq!(blended, pixel(&image, 40, 50));
+    }
+
+    #[test]
+    fn given_tiles_are_drawn_instead_of_the_fallback() {
+        let mut harness = Harness::new([100, 100]);
+        let north_west = TileId {
+            x: 0,
+            y: 0,
+            zoom: 1,
+        };
+        let mut tiles = tiles(
+            MemorySource::new(Some(Color32::WHITE))
+                .with_tile(north_west, ColorImage::new([256, 256], Color32::BLUE)),
+        );
+        let mut memory = MapMemory::default();
+        memory.zoom = 1.0.try_into().unwrap();
+
+        let image = harness.run(|ui| {
+            ui.add(
+                Map::new(Some(&mut tiles), &mut memory, Position::new(0., 0.))
+                    .my_position_marker(None),
+            );
+        });
+
+        assert_eq!(Color32::BLUE, pixel(&image, 25, 25));
+        assert_eq!(Color32::WHITE, pixel(&image, 75, 25));
+        assert_eq!(Color32::WHITE, pixel(&image, 75, 75));
+    }
+
+    #[test]
+    fn missing_tiles_fail_without_a_fallback() {
+        let source = MemorySource::new(None);
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        assert!(source.decode_tile(tile_id, &[], None).is_err());
+        let source = source.with_tile(tile_id, ColorImage::new([1, 1], Color32::RED));
+        let image = source.decode_tile(tile_id, &[], None).unwrap();
+        assert_eq!(vec![Color32::RED], image.pixels);
+    }
+
+    #[test]
+    fn dragging_moves_the_map() {
+        let mut harness = Harness::new([100, 100]);
+        let mut tiles = filled(Color32::WHITE);
+        let mut memory = MapMemory::default();
+        memory.inertia = None;
+        let center = Position::new(21., 52.);
+        let mut ui = |ui: &mut Ui| {
+            ui.add(
+                Map::new(Some(&mut tiles), &mut memory, center)
+                    .with_plugin(Square(center))
+                    .my_position_marker(None),
+            );
+        };
+
+        assert_eq!(Color32::RED, pixel(&harness.run(&mut ui), 50, 50));
+        let image = harness.drag(Pos2::new(50., 50.), Pos2::new(20., 30.), &mut ui);
+        assert_eq!(Color32::WHITE, pixel(&image, 50, 50));
+        assert_eq!(Color32::RED, pixel(&image, 20, 30));
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_are_blended_once() {
+        let mut canvas = Canvas::new(Vec2::new(4., 4.));
+        let vertex = |x, y| Vertex {
+            pos: Pos2::new(x, y),
+            uv: Pos2::ZERO,
+            color: Color32::from_black_alpha(128),
+        };
+        let clip_rect = Rect::EVERYTHING;
+        canvas.triangle(
+            [vertex(0., 0.), vertex(4., 0.), vertex(4., 4.)],
+            None,
+            clip_rect,
+        );
+        canvas.triangle(
+            [vertex(0., 0.), vertex(4., 4.), vertex(0., 4.)],
+            None,
+            clip_rect,
+        );
+
+        let image = canvas.image();
+        assert!(image.pixels().all(|pixel| pixel.0 == [0, 0, 0, 128]));
+    }
+}
@@ -1,214 +1,2044 @@
-use std::collections::hash_map::Entry;
-use std::{collections::HashMap, sync::Arc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use egui::{pos2, Color32, Context, Mesh, Rect, Vec2};
-use egui_extras::RetainedImage;
-use reqwest::header::USER_AGENT;
+use egui::{mutex::Mutex, pos2, Color32, ColorImage, Context, Mesh, Rect, Vec2};
+use futures_util::future::abortable;
+use futures_util::stream::{FuturesUnordered, Stream, StreamExt};
 use tokio::sync::mpsc::error::TryRecvError;
+use web_time::Instant;
 
-use crate::mercator::TileId;
-use crate::tokio::TokioRuntimeThread;
+use crate::atlas::Texture;
+use crate::disk_cache::{CacheMetadata, DiskCache};
+use crate::filter::TileFilter;
+use crate::http::{
+    default_client, Connectivity, HttpClient, HttpOptions, HttpOptionsError, RateLimiter,
+};
+use crate::io::{self, Runtime, Spawner};
+use crate::mercator::{Position, TileId, TILE_SIZE};
+use crate::prefetch::{Job, Progress};
+use crate::sources::{decode_image, Attribution, TileSource, DEFAULT_ZOOM_RANGE};
+use crate::zoom::Zoom;
 
 #[derive(Clone)]
 pub struct Tile {
-    image: Arc<RetainedImage>,
+    image: Arc<ColorImage>,
+
+    /// Tiles are decoded on the IO thread, but uploaded to the GPU when drawn for the first time.
+    /// Pixels are kept, e.g. for [`crate::Map::snapshot`].
+    texture: Arc<Mutex<Option<Texture>>>,
+}
+
+impl Tile {
+    fn from_color_image(image: ColorImage) -> Self {
+        Self {
+            image: Arc::new(image),
+            texture: Arc::default(),
+        }
+    }
+
+    /// Whether the texture is already on the GPU.
+    fn is_uploaded(&self) -> bool {
+        self.texture.lock().is_some()
+    }
+
+    /// How much memory the decoded image (and its texture) takes.
+    fn size_in_bytes(&self) -> usize {
+        self.image.size[0] * self.image.size[1] * 4
+    }
+
+    pub(crate) fn image(&self) -> &ColorImage {
+        &self.image
+    }
+
+    /// Screen area taken by the tile when drawn `size` points wide.
+    pub fn rect(&self, screen_position: Vec2, size: f32) -> Rect {
+        Rect::from_min_size(screen_position.to_pos2(), Vec2::splat(size))
+    }
+
+    /// Textured mesh of the tile. `tint` multiplies the colors of the image, so it can be used
+    /// to make the tile transparent.
+    pub fn mesh(&self, screen_position: Vec2, size: f32, tint: Color32, ctx: &Context) -> Mesh {
+        self.mesh_with_uv(
+            self.rect(screen_position, size),
+            Rect::from_min_max(pos2(0., 0.0), pos2(1.0, 1.0)),
+            tint,
+            ctx,
+        )
+    }
+
+    /// Textured mesh of the `uv` part of the tile (in 0..1 texture coordinates), drawn in `rect`.
+    pub(crate) fn mesh_with_uv(&self, rect: Rect, uv: Rect, tint: Color32, ctx: &Context) -> Mesh {
+        self.texture
+            .lock()
+            .get_or_insert_with(|| Texture::upload(ctx, &self.image))
+            .mesh(rect, uv, tint)
+    }
+}
+
+/// What is drawn in the place of a tile which could not be loaded, e.g. because the server
+/// does not have it, if it cannot be filled in with its cached parent or children either. See
+/// [`Tiles::error_placeholder`].
+#[derive(Clone)]
+pub enum Placeholder {
+    /// Light checkerboard, like the transparent areas of the image editors.
+    Checkerboard,
+
+    /// Gray tile, crossed out.
+    NoData,
+
+    /// Application's own image, drawn like a tile.
+    Image(ColorImage),
+}
+
+impl Placeholder {
+    fn image(self) -> ColorImage {
+        let size = TILE_SIZE as usize;
+        let pixels = (0..size * size).map(|index| (index % size, index / size));
+        let pixels = match self {
+            Placeholder::Checkerboard => pixels
+                .map(|(x, y)| {
+                    if (x / 16 + y / 16) % 2 == 0 {
+                        Color32::from_gray(235)
+                    } else {
+                        Color32::from_gray(205)
+                    }
+                })
+                .collect(),
+            Placeholder::NoData => pixels
+                .map(|(x, y)| {
+                    let edge = x.min(y).min(size - 1 - x).min(size - 1 - y) == 0;
+                    let diagonal = x.abs_diff(y) <= 1 || (x + y).abs_diff(size - 1) <= 1;
+                    if edge || diagonal {
+                        Color32::from_gray(180)
+                    } else {
+                        Color32::from_gray(225)
+                    }
+                })
+                .collect(),
+            Placeholder::Image(image) => return image,
+        };
+        ColorImage {
+            size: [size, size],
+            pixels,
+        }
+    }
+}
+
+/// Entry of the in-memory cache.
+struct CachedTile {
+    /// `None` means that the tile was requested, but not yet downloaded.
+    tile: Option<Tile>,
+
+    /// Value of `Tiles::use_counter` when the tile was last used.
+    last_used: u64,
+
+    /// When and why the tile could not be downloaded (or read). Downloads are requested again
+    /// after a cooldown.
+    failure: Option<(Instant, String)>,
+
+    /// When the entry was created, i.e. when the tile was loaded. Used to fade it in.
+    created_at: Instant,
+}
+
+impl CachedTile {
+    fn new(tile: Option<Tile>, last_used: u64) -> Self {
+        Self {
+            tile,
+            last_used,
+            failure: None,
+            created_at: Instant::now(),
+        }
+    }
+
+    fn failed(last_used: u64, reason: String) -> Self {
+        Self {
+            tile: None,
+            last_used,
+            failure: Some((Instant::now(), reason)),
+            created_at: Instant::now(),
+        }
+    }
+}
+
+/// Statistics of the [`Tiles`] cache, e.g. to tell the user that the tile server is not
+/// available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TilesStats {
+    /// Tiles kept in the memory.
+    pub loaded: usize,
+
+    /// Tiles requested, but not yet downloaded.
+    pub pending: usize,
+
+    /// Tiles which could not be downloaded (or read).
+    pub failed: usize,
+
+    /// Memory taken by the loaded tiles, in bytes.
+    pub memory_used: usize,
+
+    /// Requests dropped so far, because too many tiles were waiting for the download, see
+    /// [`HttpOptions::max_queued_tiles`].
+    pub dropped: usize,
+}
+
+/// What is known about a tile, used by the debug overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TileStatus {
+    /// In the cache.
+    Loaded,
+
+    /// Requested, but not downloaded yet.
+    Pending,
+
+    /// Could not be downloaded (or read).
+    Failed,
+
+    /// Not requested (or already dropped from the cache).
+    Missing,
+}
+
+/// Default limit of the memory taken by the in-memory cache, which is about 500 tiles of 256x256.
+const DEFAULT_MEMORY_BUDGET: usize = 128 * 1024 * 1024;
+
+/// How many textures of the tiles are uploaded to the GPU during a single frame, by default.
+const DEFAULT_MAX_UPLOADS: usize = 16;
+
+/// How long it takes for a newly loaded tile to become fully opaque.
+const DEFAULT_FADE_IN: Duration = Duration::from_millis(150);
+
+/// Downloads and keeps cache of the tiles. It must persist between frames.
+pub struct Tiles {
+    cache: HashMap<TileId, CachedTile>,
+
+    /// Incremented on each cache access, used to find least recently used tiles.
+    use_counter: u64,
+
+    /// Memory taken by the tiles in the cache.
+    memory_used: usize,
+
+    /// When exceeded, least recently used tiles are dropped.
+    memory_budget: usize,
+
+    fade_in: Duration,
+
+    attribution: Option<Attribution>,
+
+    zoom_range: RangeInclusive<u8>,
+
+    tile_size: u32,
+
+    /// Drawn in the place of the tiles which failed.
+    placeholder: Option<Tile>,
+
+    /// Applied to the tiles when they are decoded. Shared with the IO thread.
+    filter: Arc<Mutex<Option<TileFilter>>>,
+
+    backend: Backend,
+}
+
+/// Where the tiles come from.
+enum Backend {
+    /// Downloaded by the IO thread.
+    Http(Box<HttpBackend>),
+
+    /// Read and decoded on demand, e.g. from a local file.
+    Local(Box<dyn FnMut(TileId) -> Result<ColorImage, String> + Send>),
 }
 
-impl Tile {
-    fn from_image_bytes(image: &[u8]) -> Result<Self, String> {
-        RetainedImage::from_image_bytes("debug_name", image).map(|image| Self {
-            image: Arc::new(image),
-        })
+/// Message sent to the IO thread.
+pub(crate) enum Request {
+    Download(TileId),
+
+    /// Tile is no longer needed, so its download should be abandoned.
+    Cancel(TileId),
+
+    /// Tiles to put in the disk cache, without showing them.
+    Prefetch(Job),
+}
+
+struct HttpBackend {
+    /// Requests to the IO thread.
+    request_tx: tokio::sync::mpsc::UnboundedSender<Request>,
+
+    /// Tiles that got downloaded (or failed to) and should be put in the cache.
+    tile_rx: tokio::sync::mpsc::Receiver<(TileId, Result<Tile, String>)>,
+
+    failed_tile_cooldown: Duration,
+    max_parallel_downloads: usize,
+    max_queued_tiles: usize,
+
+    /// Requests dropped from the `queue`, because it was full.
+    dropped: usize,
+
+    /// Tiles waiting for the download, in the order they were asked for. They are sent to the
+    /// IO thread only when it has a free download slot, so the most recently needed tiles are
+    /// not stuck behind the ones which are no longer visible.
+    queue: VecDeque<TileId>,
+
+    /// Tiles being downloaded by the IO thread.
+    in_flight: HashSet<TileId>,
+
+    /// Tiles asked for during the current frame.
+    wanted: HashSet<TileId>,
+
+    /// Tiles given during the current frame before their textures were uploaded, up to
+    /// `max_uploads`. Uploading is spread over several frames, so they do not stutter when
+    /// a lot of tiles arrive at once.
+    uploads: HashSet<TileId>,
+    max_uploads: usize,
+
+    /// Set by the IO thread when the tile server cannot be reached.
+    offline: Arc<AtomicBool>,
+
+    /// Value of `offline` during the previous frame.
+    was_offline: bool,
+
+    egui_ctx: Context,
+    frame_nr: u64,
+
+    /// `None` if the downloads run on the application's executor.
+    #[allow(dead_code)] // Significant Drop
+    runtime: Option<Runtime>,
+}
+
+impl HttpBackend {
+    /// When a new frame starts, forget the requests for the tiles which were not asked for
+    /// during the previous one, as they are no longer visible. Returns these tiles.
+    fn start_frame(&mut self) -> Vec<TileId> {
+        let frame_nr = self.egui_ctx.frame_nr();
+        if frame_nr == self.frame_nr {
+            return Vec::new();
+        }
+        self.frame_nr = frame_nr;
+        self.uploads.clear();
+
+        let wanted = std::mem::take(&mut self.wanted);
+        let (queue, stale): (VecDeque<_>, VecDeque<_>) = self
+            .queue
+            .drain(..)
+            .partition(|tile_id| wanted.contains(tile_id));
+        self.queue = queue;
+
+        let mut stale = Vec::from(stale);
+        let dropped = stale.len();
+        for tile_id in self
+            .in_flight
+            .iter()
+            .filter(|tile_id| !wanted.contains(tile_id))
+        {
+            // IO thread might be dead, then there is nothing to cancel anyway.
+            let _ = self.request_tx.send(Request::Cancel(*tile_id));
+            stale.push(*tile_id);
+        }
+        self.in_flight.retain(|tile_id| wanted.contains(tile_id));
+
+        if !stale.is_empty() {
+            log::debug!(
+                "Dropped {} and cancelled {} requests for tiles which are no longer visible.",
+                dropped,
+                stale.len() - dropped
+            );
+        }
+        stale
+    }
+}
+
+impl Tiles {
+    pub fn new<S>(source: S, egui_ctx: Context) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        Self::spawn(source, egui_ctx, default_client(), None, None)
+    }
+
+    /// Like [`Tiles::new`], but the HTTP requests are sent with given options, e.g. a custom
+    /// `User-Agent` or a proxy.
+    pub fn with_options<S>(
+        source: S,
+        egui_ctx: Context,
+        options: HttpOptions,
+    ) -> Result<Self, HttpOptionsError>
+    where
+        S: TileSource + Send + 'static,
+    {
+        Ok(Self::spawn(source, egui_ctx, options.client()?, None, None))
+    }
+
+    /// Like [`Tiles::new`], but the downloads run on the application's executor, e.g. an existing
+    /// Tokio runtime, instead of a thread started by walkers.
+    pub fn with_spawner<S>(source: S, egui_ctx: Context, spawner: impl Spawner) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        Self::spawn(source, egui_ctx, default_client(), None, Some(&spawner))
+    }
+
+    /// Like [`Tiles::new`], but downloaded tiles are also stored in the `path` directory, so they
+    /// are available after the application restarts, even without network access. When the
+    /// size of the directory exceeds `max_size` bytes, the oldest tiles are removed.
+    ///
+    /// Server's `Cache-Control` header is respected: tiles older than `max-age` are validated
+    /// using their `ETag`, and `no-store` ones are not stored at all. Expired tiles are still
+    /// shown if the server cannot be reached.
+    pub fn with_disk_cache<S>(
+        source: S,
+        egui_ctx: Context,
+        path: impl Into<PathBuf>,
+        max_size: u64,
+    ) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        Self::spawn(
+            source,
+            egui_ctx,
+            default_client(),
+            Some((path.into(), max_size)),
+            None,
+        )
+    }
+
+    fn spawn<S>(
+        source: S,
+        egui_ctx: Context,
+        http: HttpClient,
+        disk_cache: Option<(PathBuf, u64)>,
+        spawner: Option<&dyn Spawner>,
+    ) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        // Minimum value which didn't cause any stalls while testing.
+        let channel_size = 20;
+
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
+        let failed_tile_cooldown = http.failed_tile_cooldown;
+        let max_parallel_downloads = http.max_parallel_downloads;
+        let max_queued_tiles = http.max_queued_tiles;
+        let frame_nr = egui_ctx.frame_nr();
+        let attribution = source.attribution();
+        let zoom_range = source.zoom_range();
+        let tile_size = source.tile_size();
+        let offline = http.offline.clone();
+        let filter: Arc<Mutex<Option<TileFilter>>> = Arc::default();
+        let io_filter = filter.clone();
+        let runtime = io::spawn(
+            spawner,
+            download(
+                source,
+                move |source: &S, tile_id: TileId, image: &[u8], content_type: Option<&str>| {
+                    let mut image = source
+                        .decode_tile(tile_id, image, content_type)
+                        .map_err(|err| log::warn!("Could not decode a tile: {}.", err))
+                        .ok()?;
+                    if let Some(filter) = &*io_filter.lock() {
+                        filter.apply(&mut image);
+                    }
+                    Some(Tile::from_color_image(image))
+                },
+                http,
+                disk_cache,
+                request_rx,
+                tile_tx,
+                egui_ctx.clone(),
+            ),
+        );
+        let mut tiles = Self::with_backend(Backend::Http(Box::new(HttpBackend {
+            request_tx,
+            tile_rx,
+            failed_tile_cooldown,
+            max_parallel_downloads,
+            max_queued_tiles,
+            dropped: 0,
+            queue: VecDeque::new(),
+            in_flight: HashSet::new(),
+            wanted: HashSet::new(),
+            uploads: HashSet::new(),
+            max_uploads: DEFAULT_MAX_UPLOADS,
+            offline,
+            was_offline: false,
+            egui_ctx,
+            frame_nr,
+            runtime,
+        })));
+        tiles.attribution = attribution;
+        tiles.zoom_range = zoom_range;
+        tiles.tile_size = tile_size;
+        tiles.filter = filter;
+        tiles
+    }
+
+    /// Tiles read from a local directory, without any network access. `template` is the path of
+    /// a tile relative to the `path`, where `{z}`, `{x}` and `{y}` are replaced with the tile's
+    /// coordinates, e.g. `{z}/{x}/{y}.png`. Use `{-y}` for the directories in the TMS scheme,
+    /// where Y grows northward.
+    pub fn from_directory(path: impl Into<PathBuf>, template: impl Into<String>) -> Self {
+        let path = path.into();
+        let template = template.into();
+
+        Self::with_backend(Backend::Local(Box::new(move |tile_id| {
+            let tile_path = path.join(
+                template
+                    .replace("{z}", &tile_id.zoom.to_string())
+                    .replace("{x}", &tile_id.x.to_string())
+                    .replace("{y}", &tile_id.y.to_string())
+                    .replace("{-y}", &tile_id.tms_y().to_string()),
+            );
+
+            let image = std::fs::read(&tile_path).map_err(|err| {
+                log::debug!("Could not read {}: {}.", tile_path.display(), err);
+                "tile not found".to_string()
+            })?;
+            decode_image(&image, None)
+        })))
+    }
+
+    /// Tiles read from a MBTiles file, without any network access.
+    #[cfg(feature = "mbtiles")]
+    pub fn from_mbtiles(mbtiles: crate::sources::MbTiles) -> Self {
+        Self::with_backend(Backend::Local(Box::new(move |tile_id| {
+            let image = mbtiles.tile(tile_id).unwrap_or_else(|err| {
+                log::warn!("Could not read {:?} from MBTiles: {}.", tile_id, err);
+                None
+            });
+            decode_image(&image.ok_or("tile not found")?, None)
+        })))
+    }
+
+    /// Tiles of the source, loaded right when they are asked for, on the caller's thread, so
+    /// the map looks the same every time it is drawn. Only the sources which
+    /// [`TileSource::fetch`] the tiles without waiting, like [`crate::sources::Plain`], work.
+    /// Tiles appear without fading in.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn immediate<S: TileSource + Send + 'static>(source: S) -> Self {
+        use futures_util::FutureExt;
+
+        let attribution = source.attribution();
+        let zoom_range = source.zoom_range();
+        let tile_size = source.tile_size();
+        let mut tiles = Self::with_backend(Backend::Local(Box::new(move |tile_id| {
+            let data = source
+                .fetch(tile_id)
+                .ok_or("source does not fetch its tiles")?
+                .now_or_never()
+                .ok_or("tile is not ready right away")??;
+            source.decode_tile(tile_id, &data, None)
+        })));
+        tiles.attribution = attribution;
+        tiles.zoom_range = zoom_range;
+        tiles.tile_size = tile_size;
+        tiles.fade_in = Duration::ZERO;
+        tiles
+    }
+
+    fn with_backend(backend: Backend) -> Self {
+        Self {
+            cache: Default::default(),
+            use_counter: 0,
+            memory_used: 0,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            fade_in: DEFAULT_FADE_IN,
+            attribution: None,
+            zoom_range: DEFAULT_ZOOM_RANGE,
+            tile_size: TILE_SIZE,
+            placeholder: None,
+            filter: Arc::default(),
+            backend,
+        }
+    }
+
+    /// Limit the memory (in bytes) taken by the decoded tiles. When exceeded, least recently used
+    /// tiles are dropped, along with their textures. Default is 128 MiB.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Attribution shown on the map, instead of the one given by the tile source. Useful for the
+    /// sources which do not provide one, like local files.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attribution = Some(attribution);
+        self
+    }
+
+    /// Attribution required by the tile provider, which the [`crate::Map`] shows in its corner.
+    pub fn attribution(&self) -> Option<&Attribution> {
+        self.attribution.as_ref()
+    }
+
+    /// Zoom levels for which the tiles exist, instead of the ones given by the tile source.
+    /// Useful for the local tiles, which often cover only some levels.
+    pub fn with_zoom_range(mut self, zoom_range: RangeInclusive<u8>) -> Self {
+        self.zoom_range = zoom_range;
+        self
+    }
+
+    /// Zoom levels for which the tiles exist.
+    pub fn zoom_range(&self) -> &RangeInclusive<u8> {
+        &self.zoom_range
+    }
+
+    /// Size (in screen points) at which the tiles are meant to be shown, instead of the one given
+    /// by the tile source. See [`TileSource::tile_size`].
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Level of the tiles used to draw the map at given zoom. Above the zoom range, tiles of
+    /// the highest level are scaled up. Below it, there is nothing to draw.
+    pub(crate) fn tile_level(&self, zoom: Zoom) -> Option<u8> {
+        // Tiles twice as big cover the same area as the ones from one level higher.
+        let offset = (self.tile_size / TILE_SIZE).max(1).ilog2() as u8;
+        let level = zoom
+            .tile_level()
+            .saturating_sub(offset)
+            .min(*self.zoom_range.end());
+        self.zoom_range.contains(&level).then_some(level)
+    }
+
+    /// Change the colors of the tiles, e.g. to make them darker, when the application has a dark
+    /// theme. The filter is applied once per tile, when it is decoded, which happens on the IO
+    /// thread for the downloaded tiles.
+    pub fn with_filter(self, filter: TileFilter) -> Self {
+        *self.filter.lock() = Some(filter);
+        self
+    }
+
+    /// Limit how many textures of the newly loaded tiles are uploaded to the GPU during a single
+    /// frame, default is 16. The rest wait for the next frames, with their parents or children
+    /// drawn in the meantime, so the frame times stay stable when a lot of tiles arrive at once.
+    /// Tiles read from the local files are not limited.
+    pub fn max_uploads_per_frame(mut self, count: usize) -> Self {
+        if let Backend::Http(http) = &mut self.backend {
+            http.max_uploads = count.max(1);
+        }
+        self
+    }
+
+    /// Whether the tile can be given this frame, see [`Tiles::max_uploads_per_frame`].
+    fn may_upload(&mut self, tile_id: TileId, tile: &Tile) -> bool {
+        let Backend::Http(http) = &mut self.backend else {
+            return true;
+        };
+        if tile.is_uploaded() || http.uploads.contains(&tile_id) {
+            return true;
+        }
+        if http.uploads.len() < http.max_uploads {
+            http.uploads.insert(tile_id);
+            true
+        } else {
+            http.egui_ctx.request_repaint();
+            false
+        }
+    }
+
+    /// Draw the placeholder in the place of the tiles which could not be loaded, instead of
+    /// leaving a hole. Not used by default, which suits the layers drawn over others, whose
+    /// servers often do not have the tiles of empty areas. [`Tiles::error`] tells why a tile
+    /// failed.
+    pub fn error_placeholder(mut self, placeholder: Placeholder) -> Self {
+        self.placeholder = Some(Tile::from_color_image(placeholder.image()));
+        self
+    }
+
+    pub(crate) fn placeholder(&self) -> Option<Tile> {
+        self.placeholder.clone()
+    }
+
+    /// How long newly loaded tiles take to fade in, instead of popping in abruptly. Default is
+    /// 150 ms, `Duration::ZERO` disables the animation.
+    pub fn fade_in(mut self, duration: Duration) -> Self {
+        self.fade_in = duration;
+        self
+    }
+
+    /// Opacity (between 0 and 1) of the tile which is fading in.
+    pub(crate) fn opacity(&self, tile_id: TileId) -> f32 {
+        match self.cache.get(&tile_id) {
+            Some(cached) if !self.fade_in.is_zero() => {
+                (cached.created_at.elapsed().as_secs_f32() / self.fade_in.as_secs_f32()).min(1.)
+            }
+            _ => 1.,
+        }
+    }
+
+    /// Tile which is already in cache, without scheduling a download. Used to fill in the place
+    /// of the tiles which are still loading.
+    pub(crate) fn cached(&mut self, tile_id: TileId) -> Option<Tile> {
+        let cached = self.cache.get_mut(&tile_id)?;
+        let tile = cached.tile.clone()?;
+        self.use_counter += 1;
+        cached.last_used = self.use_counter;
+        self.may_upload(tile_id, &tile).then_some(tile)
+    }
+
+    pub(crate) fn status(&self, tile_id: TileId) -> TileStatus {
+        match self.cache.get(&tile_id) {
+            Some(cached) if cached.tile.is_some() => TileStatus::Loaded,
+            Some(cached) if cached.failure.is_some() => TileStatus::Failed,
+            Some(_) => TileStatus::Pending,
+            None => TileStatus::Missing,
+        }
+    }
+
+    /// Return a tile if already in cache, schedule a download otherwise. Downloads of the tiles
+    /// which were not asked for during the previous frame are abandoned.
+    pub fn at(&mut self, tile_id: TileId) -> Option<Tile> {
+        if let Backend::Http(http) = &mut self.backend {
+            let stale = http.start_frame();
+            http.wanted.insert(tile_id);
+            let cooldown = http.failed_tile_cooldown;
+
+            let offline = http.offline.load(Ordering::Relaxed);
+            let reconnected = http.was_offline && !offline;
+            http.was_offline = offline;
+
+            // Just take one at the time.
+            let received = match http.tile_rx.try_recv() {
+                Ok(received) => {
+                    http.in_flight.remove(&received.0);
+                    Some(received)
+                }
+                Err(TryRecvError::Empty) => {
+                    // Just ignore. It means that no new tile was downloaded.
+                    None
+                }
+                Err(TryRecvError::Disconnected) => {
+                    // Requests will fail as well, so the failures are recorded there.
+                    None
+                }
+            };
+
+            for tile_id in stale {
+                self.cache.remove(&tile_id);
+            }
+
+            if reconnected {
+                log::debug!("Back online, requesting the failed tiles again.");
+                self.cache.retain(|_, cached| cached.failure.is_none());
+            }
+
+            match received {
+                Some((tile_id, Ok(tile))) => {
+                    self.insert(tile_id, tile);
+                }
+                Some((tile_id, Err(reason))) => {
+                    let last_used = self
+                        .cache
+                        .get(&tile_id)
+                        .map_or(0, |cached| cached.last_used);
+                    self.cache
+                        .insert(tile_id, CachedTile::failed(last_used, reason));
+                }
+                None => {}
+            }
+
+            let cooled_down = self.cache.get(&tile_id).is_some_and(|cached| {
+                cached
+                    .failure
+                    .as_ref()
+                    .is_some_and(|(failed_at, _)| failed_at.elapsed() >= cooldown)
+            });
+            if cooled_down {
+                log::debug!("Requesting {:?} again.", tile_id);
+                self.cache.remove(&tile_id);
+            }
+
+            self.dispatch();
+        }
+
+        self.use_counter += 1;
+
+        if let Some(cached) = self.cache.get_mut(&tile_id) {
+            cached.last_used = self.use_counter;
+            let tile = cached.tile.clone()?;
+            return self.may_upload(tile_id, &tile).then_some(tile);
+        }
+
+        match &mut self.backend {
+            Backend::Http(http) => {
+                http.queue.push_back(tile_id);
+                self.cache
+                    .insert(tile_id, CachedTile::new(None, self.use_counter));
+                self.drop_stalest();
+                self.dispatch();
+                None
+            }
+            Backend::Local(load) => {
+                match load(tile_id) {
+                    Ok(mut image) => {
+                        if let Some(filter) = &*self.filter.lock() {
+                            filter.apply(&mut image);
+                        }
+                        let tile = Tile::from_color_image(image);
+                        self.insert(tile_id, tile.clone());
+                        Some(tile)
+                    }
+                    Err(reason) => {
+                        // Do not try again.
+                        self.cache
+                            .insert(tile_id, CachedTile::failed(self.use_counter, reason));
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forget the queued requests for the tiles which were asked for least recently, as long as
+    /// there are more than [`HttpOptions::max_queued_tiles`].
+    fn drop_stalest(&mut self) {
+        let Backend::Http(http) = &mut self.backend else {
+            return;
+        };
+
+        while http.queue.len() > http.max_queued_tiles {
+            let stalest = http
+                .queue
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, tile_id)| self.cache.get(tile_id).map_or(0, |c| c.last_used))
+                .map(|(index, _)| index);
+            let Some(tile_id) = stalest.and_then(|index| http.queue.remove(index)) else {
+                break;
+            };
+
+            log::debug!("Too many tiles are waiting, dropping {:?}.", tile_id);
+            self.cache.remove(&tile_id);
+            http.dropped += 1;
+        }
+    }
+
+    /// Send queued requests to the IO thread, as long as it has free download slots.
+    fn dispatch(&mut self) {
+        let Backend::Http(http) = &mut self.backend else {
+            return;
+        };
+
+        while http.in_flight.len() < http.max_parallel_downloads {
+            let Some(tile_id) = http.queue.pop_front() else {
+                break;
+            };
+
+            if http.request_tx.send(Request::Download(tile_id)).is_ok() {
+                log::debug!("Requested tile: {:?}", tile_id);
+                http.in_flight.insert(tile_id);
+            } else {
+                self.cache.insert(
+                    tile_id,
+                    CachedTile::failed(self.use_counter, "IO thread is not running".to_string()),
+                );
+            }
+        }
+    }
+
+    fn insert(&mut self, tile_id: TileId, tile: Tile) {
+        self.memory_used += tile.size_in_bytes();
+
+        let previous = self
+            .cache
+            .insert(tile_id, CachedTile::new(Some(tile), self.use_counter));
+
+        if let Some(previous) = previous.and_then(|previous| previous.tile) {
+            self.memory_used -= previous.size_in_bytes();
+        }
+
+        self.evict();
+    }
+
+    /// Download all tiles covering the box spanned by two positions, at each level of the
+    /// `zoom_range` (limited to the source's one), into the disk cache, so the region can be
+    /// shown without network access later. Tiles which are fresh in the cache are not
+    /// downloaded again.
+    ///
+    /// Tiles are downloaded only when there are no visible ones waiting, so the map stays
+    /// responsive. Returned stream reports the progress after each tile and ends when the region
+    /// is done. Drop it to cancel the download. It ends right away if the tiles have no disk
+    /// cache, see [`Tiles::with_disk_cache`].
+    ///
+    /// Number of the tiles grows fourfold with each zoom level, so check [`Progress::total`]
+    /// and mind the usage policy of the tile server. E.g. OpenStreetMap forbids bulk downloads.
+    pub fn prefetch_region(
+        &self,
+        min_position: Position,
+        max_position: Position,
+        zoom_range: RangeInclusive<u8>,
+    ) -> impl Stream<Item = Progress> {
+        let zoom_range = *zoom_range.start().max(self.zoom_range.start())
+            ..=*zoom_range.end().min(self.zoom_range.end());
+        let (job, progress) = Job::new(min_position, max_position, zoom_range);
+
+        if let Backend::Http(http) = &self.backend {
+            // If the IO thread is dead, the job is dropped, which ends the stream.
+            let _ = http.request_tx.send(Request::Prefetch(job));
+        }
+        progress
+    }
+
+    /// Why the tile could not be downloaded (or read), if that is the case.
+    pub fn error(&self, tile_id: TileId) -> Option<&str> {
+        self.cache
+            .get(&tile_id)
+            .and_then(|cached| cached.failure.as_ref())
+            .map(|(_, reason)| reason.as_str())
+    }
+
+    /// Number of the tiles requested, but not downloaded (or read) yet. Use it to tell the user
+    /// that the map is still loading, see also [`crate::Map::loading_indicator`].
+    pub fn pending_count(&self) -> usize {
+        self.cache
+            .values()
+            .filter(|cached| cached.tile.is_none() && cached.failure.is_none())
+            .count()
+    }
+
+    /// Whether the tile server cannot be reached, e.g. because there is no network. Only the
+    /// tiles in the memory and the disk caches are shown then, while the server is checked every
+    /// now and then. When it is back, the tiles which failed are requested again.
+    pub fn is_offline(&self) -> bool {
+        match &self.backend {
+            Backend::Http(http) => http.offline.load(Ordering::Relaxed),
+            Backend::Local(_) => false,
+        }
+    }
+
+    pub fn stats(&self) -> TilesStats {
+        self.cache.values().fold(
+            TilesStats {
+                memory_used: self.memory_used,
+                dropped: match &self.backend {
+                    Backend::Http(http) => http.dropped,
+                    Backend::Local(_) => 0,
+                },
+                ..Default::default()
+            },
+            |mut stats, cached| {
+                if cached.tile.is_some() {
+                    stats.loaded += 1;
+                } else if cached.failure.is_some() {
+                    stats.failed += 1;
+                } else {
+                    stats.pending += 1;
+                }
+                stats
+            },
+        )
+    }
+
+    /// Drop least recently used tiles until the memory budget is met.
+    fn evict(&mut self) {
+        while self.memory_used > self.memory_budget {
+            let least_recently_used = self
+                .cache
+                .iter()
+                .filter(|(_, cached)| cached.tile.is_some())
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(tile_id, _)| *tile_id);
+
+            let Some(tile_id) = least_recently_used else {
+                break;
+            };
+
+            log::debug!("Evicting {:?} from the memory cache.", tile_id);
+            if let Some(tile) = self.cache.remove(&tile_id).and_then(|cached| cached.tile) {
+                self.memory_used -= tile.size_in_bytes();
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error("tile could not be decoded")]
+    Decode,
+
+    #[error("tile server cannot be reached")]
+    Offline,
+
+    #[error("{0}")]
+    Fetch(String),
+}
+
+impl Error {
+    /// Whether it makes sense to try again, i.e. the server might be temporarily down or
+    /// overloaded.
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Http(err) => match err.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => !err.is_builder(),
+            },
+            Error::Decode | Error::Offline | Error::Fetch(_) => false,
+        }
+    }
+
+    /// Whether the server could not be reached at all.
+    fn is_connection(&self) -> bool {
+        match self {
+            Error::Http(err) => err.is_request() || err.is_timeout(),
+            Error::Decode | Error::Offline | Error::Fetch(_) => false,
+        }
+    }
+}
+
+/// Response of the tile server.
+struct Response {
+    /// `None` if the tile did not change since it was stored in the disk cache.
+    image: Option<Vec<u8>>,
+
+    /// Value of the `Content-Type` header.
+    content_type: Option<String>,
+
+    /// `None` if the server does not allow storing the tile.
+    metadata: Option<CacheMetadata>,
+}
+
+async fn download_single(
+    client: &reqwest::Client,
+    url: &str,
+    etag: Option<&str>,
+) -> Result<Response, Error> {
+    let mut request = client.get(url);
+    if let Some(etag) = etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await?;
+
+    log::debug!("Downloaded {:?}.", response.status());
+
+    let response = response.error_for_status()?;
+    let metadata = CacheMetadata::from_headers(response.headers());
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Response {
+            image: None,
+            content_type,
+            metadata,
+        });
+    }
+
+    let image = response.bytes().await?;
+    Ok(Response {
+        image: Some(image.to_vec()),
+        content_type,
+        metadata,
+    })
+}
+
+/// Download the tile, retrying if the error is transient.
+async fn download_with_retries(
+    http: &HttpClient,
+    url: &str,
+    etag: Option<&str>,
+) -> Result<Response, Error> {
+    let mut attempt = 1;
+    loop {
+        let result = download_single(&http.client, url, etag).await;
+
+        match result {
+            Err(err) if err.is_transient() && attempt < http.max_attempts => {
+                let delay = http.retry_delay(attempt);
+                log::debug!(
+                    "Could not download '{}': {}, retrying in {:?}.",
+                    url,
+                    err,
+                    delay
+                );
+                io::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Get the tile from the source, or download it, and decode it, without any caching.
+pub(crate) async fn load<S: TileSource>(
+    source: &S,
+    http: &HttpClient,
+    tile_id: TileId,
+) -> Result<ColorImage, String> {
+    let response = match source.fetch(tile_id) {
+        Some(fetch) => fetch.await.map_err(Error::Fetch).map(|image| Response {
+            image: Some(image),
+            content_type: None,
+            metadata: None,
+        }),
+        None => download_with_retries(http, &source.tile_url(tile_id), None).await,
+    }
+    .map_err(|err| err.to_string())?;
+
+    let image = response.image.ok_or_else(|| Error::Decode.to_string())?;
+    source.decode_tile(tile_id, &image, response.content_type.as_deref())
+}
+
+/// Open the disk cache if it was requested.
+fn open_disk_cache(disk_cache: Option<(PathBuf, u64)>) -> Option<DiskCache> {
+    let (path, max_size) = disk_cache?;
+    match DiskCache::open(path.clone(), max_size) {
+        Ok(disk_cache) => Some(disk_cache),
+        Err(err) => {
+            log::warn!(
+                "Could not open disk cache in {}, tiles will not be stored: {}.",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+fn find_job(jobs: &mut [(u64, Job)], job_id: u64) -> Option<&mut Job> {
+    jobs.iter_mut()
+        .find(|(id, _)| *id == job_id)
+        .map(|(_, job)| job)
+}
+
+/// Put the downloaded tile of a region into the disk cache. Returns whether it is there now.
+fn store_prefetched<S, D, T>(
+    source: &S,
+    decode: &D,
+    disk_cache: &mut Option<DiskCache>,
+    tile_id: TileId,
+    url: &str,
+    result: Result<Response, Error>,
+) -> bool
+where
+    D: Fn(&S, TileId, &[u8], Option<&str>) -> Option<T>,
+{
+    let Some(disk_cache) = disk_cache else {
+        return false;
+    };
+
+    match result {
+        Ok(Response {
+            image: Some(image),
+            content_type,
+            metadata,
+        }) => {
+            if decode(source, tile_id, &image, content_type.as_deref()).is_none() {
+                return false;
+            }
+            let Some(metadata) = metadata else {
+                log::debug!("'{}' must not be stored in the disk cache.", url);
+                return false;
+            };
+            disk_cache
+                .store(tile_id, url, &image, &metadata)
+                .map_err(|err| log::warn!("Could not store '{}' in the disk cache: {}.", url, err))
+                .is_ok()
+        }
+        Ok(Response {
+            image: None,
+            metadata,
+            ..
+        }) => {
+            if let Some(metadata) = metadata {
+                if let Err(err) = disk_cache.refresh(tile_id, url, &metadata) {
+                    log::warn!("Could not refresh '{}' in the disk cache: {}.", url, err);
+                }
+            }
+            true
+        }
+        Err(err) => {
+            log::warn!("Could not prefetch '{}': {}.", url, err);
+            false
+        }
+    }
+}
+
+/// Serve the requests coming from `request_rx`, turning the downloaded (or cached) data into
+/// tiles using `decode`, which also gets the `Content-Type` of the response, if known. Up to
+/// `HttpOptions::max_parallel_downloads` tiles are downloaded at the same time.
+pub(crate) async fn download<S, D, T>(
+    source: S,
+    decode: D,
+    http: HttpClient,
+    disk_cache: Option<(PathBuf, u64)>,
+    mut request_rx: tokio::sync::mpsc::UnboundedReceiver<Request>,
+    tile_tx: tokio::sync::mpsc::Sender<(TileId, Result<T, String>)>,
+    egui_ctx: Context,
+) -> Result<(), ()>
+where
+    S: TileSource + Send + 'static,
+    D: Fn(&S, TileId, &[u8], Option<&str>) -> Option<T> + Send + Sync + 'static,
+{
+    let mut disk_cache = open_disk_cache(disk_cache);
+    let (http, decode) = (&http, &decode);
+    let mut downloads = FuturesUnordered::new();
+    let mut abort_handles = HashMap::new();
+    let mut connectivity = Connectivity::new(http.offline.clone());
+    let mut rate_limiter = http
+        .rate_limit
+        .map(|limit| RateLimiter::new(limit, Instant::now()));
+
+    // Requests waiting for a free download slot.
+    let mut backlog = VecDeque::new();
+
+    // Regions to put in the disk cache, when the backlog is empty, with their ids.
+    let mut jobs: Vec<(u64, Job)> = Vec::new();
+    let mut next_job_id = 0;
+
+    loop {
+        // How long to wait before the rate limit allows the next download.
+        let mut throttled = None;
+
+        jobs.retain(|(_, job)| !job.is_over());
+
+        while downloads.len() < http.max_parallel_downloads {
+            let (request, job_id) = match backlog.pop_front() {
+                Some(request) => (request, None),
+                None => {
+                    let Some((request, job_id)) = jobs
+                        .iter_mut()
+                        .find_map(|(job_id, job)| Some((job.next_tile()?, *job_id)))
+                    else {
+                        break;
+                    };
+                    (request, Some(job_id))
+                }
+            };
+            let url = source.tile_url(request);
+
+            let cached = disk_cache
+                .as_mut()
+                .and_then(|disk_cache| disk_cache.load(request, &url));
+
+            // Expired tile is kept in case the server says it did not change, or is unreachable.
+            let (stale, etag) = match job_id {
+                Some(job_id) => {
+                    // Prefetched tiles are not shown, so there is no need to decode them.
+                    if cached
+                        .as_ref()
+                        .is_some_and(|cached| cached.metadata.is_fresh())
+                    {
+                        if let Some(job) = find_job(&mut jobs, job_id) {
+                            job.record(true);
+                        }
+                        continue;
+                    }
+                    (None, cached.and_then(|cached| cached.metadata.etag))
+                }
+                None => {
+                    let cached = cached.and_then(|cached| {
+                        Some((
+                            decode(
+                                &source,
+                                request,
+                                &cached.image,
+                                cached.metadata.content_type.as_deref(),
+                            )?,
+                            cached.metadata,
+                        ))
+                    });
+                    match cached {
+                        Some((tile, metadata)) if metadata.is_fresh() => {
+                            log::debug!("Got {:?} from the disk cache.", request);
+                            tile_tx.send((request, Ok(tile))).await.map_err(|_| ())?;
+                            egui_ctx.request_repaint();
+                            continue;
+                        }
+                        Some((tile, metadata)) => (Some(tile), metadata.etag),
+                        None => (None, None),
+                    }
+                }
+            };
+
+            if !connectivity.can_download(Instant::now()) {
+                match job_id {
+                    Some(job_id) => {
+                        if let Some(job) = find_job(&mut jobs, job_id) {
+                            job.record(false);
+                        }
+                    }
+                    None => {
+                        let result = stale.ok_or_else(|| Error::Offline.to_string());
+                        tile_tx.send((request, result)).await.map_err(|_| ())?;
+                        egui_ctx.request_repaint();
+                    }
+                }
+                continue;
+            }
+
+            if let Some(Err(wait)) = rate_limiter
+                .as_mut()
+                .map(|rate_limiter| rate_limiter.acquire(Instant::now()))
+            {
+                // Disk cache will be asked again, but it is much cheaper than the download.
+                log::trace!("Rate limit reached, waiting {:?}.", wait);
+                match job_id.and_then(|job_id| find_job(&mut jobs, job_id)) {
+                    Some(job) => job.put_back(request),
+                    None => backlog.push_front(request),
+                }
+                throttled = Some(wait);
+                break;
+            }
+
+            connectivity.downloading(Instant::now());
+            log::debug!("Getting {:?} from {}.", request, url);
+            let fetch = source.fetch(request);
+            let (download, abort_handle) = abortable(async move {
+                let result = match fetch {
+                    Some(fetch) => fetch.await.map_err(Error::Fetch).map(|image| Response {
+                        image: Some(image),
+                        content_type: None,
+                        metadata: None,
+                    }),
+                    None => download_with_retries(http, &url, etag.as_deref()).await,
+                };
+                (request, job_id, url, stale, result)
+            });
+
+            // Prefetched tiles are not cancelled one by one, and might be shown at the same time.
+            if job_id.is_none() {
+                abort_handles.insert(request, abort_handle);
+            }
+            downloads.push(download);
+        }
+
+        tokio::select! {
+            request = request_rx.recv() => match request.ok_or(())? {
+                Request::Download(tile_id) => backlog.push_back(tile_id),
+                Request::Cancel(tile_id) => {
+                    backlog.retain(|request| *request != tile_id);
+                    if let Some(abort_handle) = abort_handles.remove(&tile_id) {
+                        log::debug!("Cancelling the download of {:?}.", tile_id);
+                        abort_handle.abort();
+                    }
+                }
+                Request::Prefetch(job) => {
+                    if disk_cache.is_some() {
+                        jobs.push((next_job_id, job));
+                        next_job_id += 1;
+                    } else {
+                        log::warn!(
+                            "Tiles have no disk cache, so there is nowhere to prefetch them."
+                        );
+                    }
+                }
+            },
+            _ = io::sleep(throttled.unwrap_or_default()), if throttled.is_some() => {}
+            Some(download) = downloads.next(), if !downloads.is_empty() => {
+                let Ok((request, job_id, url, stale, result)) = download else {
+                    // Cancelled.
+                    continue;
+                };
+                connectivity.record(!result.as_ref().is_err_and(Error::is_connection));
+
+                if let Some(job_id) = job_id {
+                    let stored = store_prefetched(
+                        &source,
+                        decode,
+                        &mut disk_cache,
+                        request,
+                        &url,
+                        result,
+                    );
+                    if let Some(job) = find_job(&mut jobs, job_id) {
+                        job.record(stored);
+                    }
+                    continue;
+                }
+                abort_handles.remove(&request);
+
+                let result = match result {
+                    Ok(Response { image: Some(image), content_type, metadata }) => {
+                        let tile = decode(&source, request, &image, content_type.as_deref())
+                            .ok_or(Error::Decode);
+                        if let (Ok(_), Some(disk_cache), Some(metadata)) =
+                            (&tile, &mut disk_cache, metadata)
+                        {
+                            if let Err(err) = disk_cache.store(request, &url, &image, &metadata) {
+                                log::warn!(
+                                    "Could not store '{}' in the disk cache: {}.",
+                                    &url,
+                                    err
+                                );
+                            }
+                        }
+                        tile
+                    }
+                    Ok(Response { image: None, metadata, .. }) => {
+                        log::debug!("{:?} did not change.", request);
+                        if let (Some(disk_cache), Some(metadata)) = (&mut disk_cache, metadata) {
+                            if let Err(err) = disk_cache.refresh(request, &url, &metadata) {
+                                log::warn!(
+                                    "Could not refresh '{}' in the disk cache: {}.",
+                                    &url,
+                                    err
+                                );
+                            }
+                        }
+                        stale.ok_or(Error::Decode)
+                    }
+                    Err(err) => match stale {
+                        Some(tile) => {
+                            log::warn!(
+                                "Could not refresh '{}': {}, using the cached tile.",
+                                &url,
+                                err
+                            );
+                            Ok(tile)
+                        }
+                        None => Err(err),
+                    },
+                };
+
+                match result {
+                    Ok(tile) => {
+                        tile_tx.send((request, Ok(tile))).await.map_err(|_| ())?;
+                    }
+                    Err(err) => {
+                        log::warn!("Could not download '{}': {}.", &url, err);
+                        tile_tx
+                            .send((request, Err(err.to_string())))
+                            .await
+                            .map_err(|_| ())?;
+                    }
+                }
+                egui_ctx.request_repaint();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Temporary directory with the valid image at the `path`, e.g. `3/1/2.png`.
+    pub(crate) fn directory_with_tile(path: &str) -> tempfile::TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, include_bytes!("valid.png")).unwrap();
+        directory
+    }
+
+    pub(crate) static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 3,
+    };
+
+    pub(crate) type Source = Box<dyn Fn(TileId) -> String + Send>;
+
+    /// Creates `mockito::Server` and function mapping `TileId` to this
+    /// server's URL.
+    pub(crate) fn mockito_server() -> (mockito::ServerGuard, Source) {
+        let server = mockito::Server::new();
+        let url = server.url();
+
+        let source = move |tile_id: TileId| {
+            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        };
+
+        (server, Box::new(source))
+    }
+
+    #[test]
+    fn download_single_tile() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+
+        // First query start the download, but it will always return None.
+        assert!(tiles.at(TILE_ID).is_none());
+
+        // Eventually it gets downloaded and become available in cache.
+        assert_eq!(1, tiles.stats().pending);
+        assert_eq!(1, tiles.pending_count());
+        while tiles.at(TILE_ID).is_none() {}
+
+        tile_mock.assert();
+        assert_eq!(1, tiles.stats().loaded);
+        assert_eq!(0, tiles.stats().pending);
+        assert_eq!(0, tiles.pending_count());
+    }
+
+    #[test]
+    fn pending_count_drops_once_tiles_arrive_or_fail() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let missing = server.mock("GET", "/3/2/2.png").with_status(404).create();
+        let other = TileId { x: 2, ..TILE_ID };
+
+        let mut tiles = Tiles::new(source, Context::default());
+        assert_eq!(0, tiles.pending_count());
+        assert!(tiles.at(TILE_ID).is_none());
+        assert!(tiles.at(other).is_none());
+        assert_eq!(2, tiles.pending_count());
+
+        while tiles.pending_count() > 0 {
+            tiles.at(TILE_ID);
+            tiles.at(other);
+        }
+
+        tile_mock.assert();
+        missing.assert();
+        assert_eq!(1, tiles.stats().loaded);
+        assert_eq!(1, tiles.stats().failed);
     }
 
-    pub fn rect(&self, screen_position: Vec2) -> Rect {
-        let tile_size = pos2(self.image.width() as f32, self.image.height() as f32);
-        Rect::from_two_pos(
-            screen_position.to_pos2(),
-            (screen_position + tile_size.to_vec2()).to_pos2(),
-        )
+    /// Source which makes up the tiles, without any server.
+    struct Generated;
+
+    impl TileSource for Generated {
+        fn tile_url(&self, tile_id: TileId) -> String {
+            format!("generated://{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y)
+        }
+
+        fn fetch(&self, tile_id: TileId) -> Option<crate::sources::FetchFuture> {
+            Some(Box::pin(async move {
+                if tile_id.x == 0 {
+                    Ok(include_bytes!("valid.png").to_vec())
+                } else {
+                    Err("no such tile".to_string())
+                }
+            }))
+        }
     }
 
-    pub fn mesh(&self, screen_position: Vec2, ctx: &Context) -> Mesh {
-        let mut mesh = Mesh::with_texture(self.image.texture_id(ctx));
-        mesh.add_rect_with_uv(
-            self.rect(screen_position),
-            Rect::from_min_max(pos2(0., 0.0), pos2(1.0, 1.0)),
-            Color32::WHITE,
-        );
-        mesh
+    #[test]
+    fn tiles_are_fetched_by_the_source() {
+        let _ = env_logger::try_init();
+
+        let mut tiles = Tiles::new(Generated, Context::default());
+        let tile_id = TileId { x: 0, ..TILE_ID };
+        while tiles.at(tile_id).is_none() {}
+
+        while tiles.error(TILE_ID).is_none() {
+            tiles.at(TILE_ID);
+        }
+        assert_eq!(Some("no such tile"), tiles.error(TILE_ID));
     }
-}
 
-/// Downloads and keeps cache of the tiles. It must persist between frames.
-pub struct Tiles {
-    cache: HashMap<TileId, Option<Tile>>,
+    #[test]
+    fn uploads_are_spread_over_frames() {
+        let _ = env_logger::try_init();
+
+        let ctx = Context::default();
+        let mut tiles = Tiles::new(Generated, ctx.clone()).max_uploads_per_frame(2);
+        let tile_ids: Vec<_> = (0..3).map(|y| TileId { x: 0, y, zoom: 3 }).collect();
+        while tiles.stats().loaded < 3 {
+            for tile_id in &tile_ids {
+                tiles.at(*tile_id);
+            }
+        }
 
-    /// Tiles to be downloaded by the IO thread.
-    request_tx: tokio::sync::mpsc::Sender<TileId>,
+        let given = |tiles: &mut Tiles| {
+            tile_ids
+                .iter()
+                .filter(|tile_id| tiles.at(**tile_id).is_some())
+                .count()
+        };
+        assert_eq!(2, given(&mut tiles));
+        assert_eq!(2, given(&mut tiles));
 
-    /// Tiles that got downloaded and should be put in the cache.
-    tile_rx: tokio::sync::mpsc::Receiver<(TileId, Tile)>,
+        // Textures were not really uploaded, so the next frame gives another two.
+        let _ = ctx.run(Default::default(), |_| {});
+        assert_eq!(2, given(&mut tiles));
+    }
 
-    #[allow(dead_code)] // Significant Drop
-    tokio_runtime_thread: TokioRuntimeThread,
-}
+    #[test]
+    fn placeholders() {
+        let checkerboard = Placeholder::Checkerboard.image();
+        assert_eq!([256, 256], checkerboard.size);
+        assert_ne!(checkerboard.pixels[0], checkerboard.pixels[16]);
+        assert_eq!(checkerboard.pixels[0], checkerboard.pixels[32]);
 
-pub fn openstreetmap(tile_id: TileId) -> String {
-    format!(
-        "https://tile.openstreetmap.org/{}/{}/{}.png",
-        tile_id.zoom, tile_id.x, tile_id.y
-    )
-}
+        // Crossed out from corner to corner.
+        let no_data = Placeholder::NoData.image();
+        let at = |x: usize, y: usize| no_data.pixels[y * 256 + x];
+        assert_eq!(at(100, 100), at(100, 155));
+        assert_ne!(at(100, 100), at(100, 120));
 
-impl Tiles {
-    pub fn new<S>(source: S, egui_ctx: Context) -> Self
-    where
-        S: Fn(TileId) -> String + Send + 'static,
-    {
-        let tokio_runtime_thread = TokioRuntimeThread::new();
+        let image = ColorImage::new([512, 512], Color32::RED);
+        assert_eq!([512, 512], Placeholder::Image(image).image().size);
 
-        // Minimum value which didn't cause any stalls while testing.
-        let channel_size = 20;
+        let tiles = Tiles::from_directory("nonexistent", "{z}/{x}/{y}.png");
+        assert!(tiles.placeholder().is_none());
+        assert!(tiles
+            .error_placeholder(Placeholder::NoData)
+            .placeholder()
+            .is_some());
+    }
 
-        let (request_tx, request_rx) = tokio::sync::mpsc::channel(channel_size);
-        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
-        tokio_runtime_thread
-            .runtime
-            .spawn(download(source, request_rx, tile_tx, egui_ctx));
-        Self {
-            cache: Default::default(),
-            request_tx,
-            tile_rx,
-            tokio_runtime_thread,
+    #[test]
+    fn filter_is_applied_to_loaded_tiles() {
+        let directory = directory_with_tile("3/1/2.png");
+
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png")
+            .with_filter(TileFilter::Tint(Color32::BLACK));
+        let tile = tiles.at(TILE_ID).unwrap();
+        assert!(tile
+            .image()
+            .pixels
+            .iter()
+            .all(|pixel| [pixel.r(), pixel.g(), pixel.b()] == [0, 0, 0]));
+    }
+
+    #[test]
+    fn download_with_custom_headers() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("user-agent", "MyApp/1.0")
+            .match_header("x-api-key", "secret")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let options = HttpOptions {
+            user_agent: "MyApp/1.0".to_string(),
+            headers: vec![("X-Api-Key".to_string(), "secret".to_string())],
+            ..Default::default()
+        };
+        let mut tiles = Tiles::with_options(source, Context::default(), options).unwrap();
+        while tiles.at(TILE_ID).is_none() {}
+
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn tiles_are_downloaded_in_parallel() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", mockito::Matcher::Regex(r"^/3/\d/2.png$".to_string()))
+            .with_chunked_body(|writer| {
+                std::thread::sleep(Duration::from_millis(500));
+                writer.write_all(include_bytes!("valid.png"))
+            })
+            .expect(4)
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+        let tile_ids: Vec<_> = (0..4).map(|x| TileId { x, y: 2, zoom: 3 }).collect();
+
+        // Query all of them each time, so they are all requested.
+        let start = Instant::now();
+        while tile_ids
+            .iter()
+            .filter(|tile_id| tiles.at(**tile_id).is_none())
+            .count()
+            > 0
+        {}
+
+        // One by one, it would take at least 2 seconds.
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn requests_for_tiles_no_longer_visible_are_dropped() {
+        let _ = env_logger::try_init();
+
+        let slow_tile = |writer: &mut dyn std::io::Write| {
+            std::thread::sleep(Duration::from_millis(500));
+            writer.write_all(include_bytes!("valid.png"))
+        };
+
+        let (mut server, source) = mockito_server();
+        let in_flight = server
+            .mock("GET", "/3/1/2.png")
+            .with_chunked_body(slow_tile)
+            .expect(1)
+            .create();
+        let queued = server.mock("GET", "/3/2/2.png").expect(0).create();
+        let visible = server
+            .mock("GET", "/3/3/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let options = HttpOptions {
+            max_parallel_downloads: 1,
+            ..Default::default()
+        };
+        let egui_ctx = Context::default();
+        let mut tiles = Tiles::with_options(source, egui_ctx.clone(), options).unwrap();
+
+        let tile_id = |x| TileId { x, y: 2, zoom: 3 };
+        assert!(tiles.at(tile_id(1)).is_none());
+        assert!(tiles.at(tile_id(2)).is_none());
+        assert_eq!(2, tiles.stats().pending);
+
+        // Wait until the first one is being downloaded.
+        while !in_flight.matched() {
+            std::thread::sleep(Duration::from_millis(10));
         }
+
+        // Next frames show only the third tile. Requests are dropped once a whole frame passed
+        // without asking for them.
+        let _ = egui_ctx.run(Default::default(), |_| {});
+        assert!(tiles.at(tile_id(3)).is_none());
+        let _ = egui_ctx.run(Default::default(), |_| {});
+        while tiles.at(tile_id(3)).is_none() {}
+
+        assert_eq!(1, tiles.stats().loaded);
+        assert_eq!(0, tiles.stats().pending);
+        in_flight.assert();
+        queued.assert();
+        visible.assert();
     }
 
-    /// Return a tile if already in cache, schedule a download otherwise.
-    pub fn at(&mut self, tile_id: TileId) -> Option<Tile> {
-        // Just take one at the time.
-        match self.tile_rx.try_recv() {
-            Ok((tile_id, tile)) => {
-                self.cache.insert(tile_id, Some(tile));
-            }
-            Err(TryRecvError::Empty) => {
-                // Just ignore. It means that no new tile was downloaded.
-            }
-            Err(TryRecvError::Disconnected) => panic!("IO thread is dead"),
+    /// Source whose tiles never arrive.
+    struct Stuck;
+
+    impl TileSource for Stuck {
+        fn tile_url(&self, tile_id: TileId) -> String {
+            format!("stuck://{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y)
         }
 
-        match self.cache.entry(tile_id) {
-            Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => {
-                if let Ok(()) = self.request_tx.try_send(tile_id) {
-                    log::debug!("Requested tile: {:?}", tile_id);
-                    entry.insert(None);
-                } else {
-                    log::debug!("Request queue is full.");
-                }
-                None
-            }
+        fn fetch(&self, _: TileId) -> Option<crate::sources::FetchFuture> {
+            Some(Box::pin(std::future::pending()))
         }
     }
-}
 
-#[derive(Debug, thiserror::Error)]
-#[error("tile could not be downloaded")]
-struct Error;
-
-async fn download_single(client: &reqwest::Client, url: &str) -> Result<Tile, Error> {
-    let image = client
-        .get(url)
-        .header(USER_AGENT, "Walkers")
-        .send()
-        .await
-        .map_err(|_| Error)?;
-
-    log::debug!("Downloaded {:?}.", image.status());
-
-    let image = image
-        .error_for_status()
-        .map_err(|_| Error)?
-        .bytes()
-        .await
-        .map_err(|_| Error)?;
-
-    Tile::from_image_bytes(&image).map_err(|_| Error)
-}
+    #[test]
+    fn least_recently_asked_for_requests_are_dropped_when_queue_is_full() {
+        let options = HttpOptions {
+            max_parallel_downloads: 1,
+            max_queued_tiles: 2,
+            ..Default::default()
+        };
+        let mut tiles = Tiles::with_options(Stuck, Context::default(), options).unwrap();
+        let tile_id = |x| TileId { x, y: 2, zoom: 3 };
 
-async fn download<S>(
-    source: S,
-    mut request_rx: tokio::sync::mpsc::Receiver<TileId>,
-    tile_tx: tokio::sync::mpsc::Sender<(TileId, Tile)>,
-    egui_ctx: Context,
-) -> Result<(), ()>
-where
-    S: Fn(TileId) -> String + Send + 'static,
-{
-    // Keep outside the loop to reuse it as much as possible.
-    let client = reqwest::Client::new();
+        // First one is being downloaded, the rest waits.
+        for x in 0..3 {
+            assert!(tiles.at(tile_id(x)).is_none());
+        }
+        assert_eq!(0, tiles.stats().dropped);
 
-    loop {
-        let request = request_rx.recv().await.ok_or(())?;
-        let url = source(request);
+        // Asking again makes it the freshest one, and does not queue it twice.
+        assert!(tiles.at(tile_id(1)).is_none());
+        assert!(tiles.at(tile_id(3)).is_none());
 
-        log::debug!("Getting {:?} from {}.", request, url);
+        assert_eq!(1, tiles.stats().dropped);
+        assert_eq!(TileStatus::Missing, tiles.status(tile_id(2)));
+        assert_eq!(TileStatus::Pending, tiles.status(tile_id(1)));
+        assert_eq!(3, tiles.stats().pending);
+    }
 
-        if let Ok(image) = download_single(&client, &url).await {
-            tile_tx.send((request, image)).await.map_err(|_| ())?;
-            egui_ctx.request_repaint();
-        } else {
-            log::warn!("Could not download '{}'.", &url);
+    #[test]
+    fn transient_errors_are_retried() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let unavailable = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let options = HttpOptions {
+            retry_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut tiles = Tiles::with_options(source, Context::default(), options).unwrap();
+        while tiles.at(TILE_ID).is_none() {}
+
+        unavailable.assert();
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn failed_tile_is_requested_again_after_cooldown() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(404)
+            .expect_at_least(2)
+            .create();
+
+        let options = HttpOptions {
+            failed_tile_cooldown: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut tiles = Tiles::with_options(source, Context::default(), options).unwrap();
+        while !tile_mock.matched() {
+            assert!(tiles.at(TILE_ID).is_none());
+            std::thread::sleep(Duration::from_millis(10));
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    #[test]
+    fn download_on_application_runtime() {
+        let _ = env_logger::try_init();
 
-    use super::*;
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
 
-    static TILE_ID: TileId = TileId {
-        x: 1,
-        y: 2,
-        zoom: 3,
-    };
+        let application_runtime = crate::tokio::TokioRuntimeThread::new().unwrap();
+        let mut tiles = Tiles::with_spawner(
+            source,
+            Context::default(),
+            application_runtime.runtime.handle().clone(),
+        );
+        while tiles.at(TILE_ID).is_none() {}
 
-    type Source = Box<dyn Fn(TileId) -> String + Send>;
+        tile_mock.assert();
+    }
 
-    /// Creates `mockito::Server` and function mapping `TileId` to this
-    /// server's URL.
-    fn mockito_server() -> (mockito::ServerGuard, Source) {
-        let server = mockito::Server::new();
-        let url = server.url();
+    #[test]
+    fn tile_is_loaded_from_disk_cache_after_restart() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let mut tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
+        while tiles.at(TILE_ID).is_none() {}
+        drop(tiles);
 
+        // Server is not asked again, tile comes from the disk.
+        let url = server.url();
         let source = move |tile_id: TileId| {
             format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
         };
+        let mut tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
+        while tiles.at(TILE_ID).is_none() {}
 
-        (server, Box::new(source))
+        tile_mock.assert();
     }
 
     #[test]
-    fn download_single_tile() {
+    fn region_is_prefetched_into_disk_cache() {
         let _ = env_logger::try_init();
 
+        let directory = tempfile::tempdir().unwrap();
         let (mut server, source) = mockito_server();
         let tile_mock = server
             .mock("GET", "/3/1/2.png")
             .with_body(include_bytes!("valid.png"))
+            .expect(1)
             .create();
 
-        let mut tiles = Tiles::new(source, Context::default());
+        let tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
+        let position = Position::new(-100., 50.);
+        let progress = tiles.prefetch_region(position, position, 3..=3);
+        let reported: Vec<_> = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(progress.collect());
+        assert_eq!(
+            vec![Progress {
+                done: 1,
+                failed: 0,
+                total: 1
+            }],
+            reported
+        );
+        drop(tiles);
 
-        // First query start the download, but it will always return None.
-        assert!(tiles.at(TILE_ID).is_none());
+        // Server is not asked again, once the tile is in the disk cache.
+        let url = server.url();
+        let source = move |tile_id: TileId| {
+            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        };
+        let mut tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
 
-        // Eventually it gets downloaded and become available in cache.
+        // Without the disk cache, there is nothing to do.
+        let progress =
+            Tiles::new(Generated, Context::default()).prefetch_region(position, position, 3..=3);
+        let reported: Vec<_> = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(progress.collect());
+        assert!(reported.is_empty());
+    }
+
+    #[test]
+    fn expired_tile_is_validated_with_etag() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_header("cache-control", "max-age=0")
+            .with_header("etag", "\"abc\"")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+        let not_modified = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("if-none-match", "\"abc\"")
+            .with_status(304)
+            .expect(1)
+            .create();
+
+        let mut tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
+        while tiles.at(TILE_ID).is_none() {}
+        drop(tiles);
+
+        // Tile expired immediately, so the server is asked whether it changed.
+        let url = server.url();
+        let source = move |tile_id: TileId| {
+            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        };
+        let mut tiles = Tiles::with_disk_cache(source, Context::default(), directory.path(), 1024);
         while tiles.at(TILE_ID).is_none() {}
 
         tile_mock.assert();
+        not_modified.assert();
+    }
+
+    #[test]
+    fn tiles_are_scaled_up_above_the_zoom_range() {
+        let tiles = Tiles::from_directory("tiles", "{z}/{x}/{y}.png").with_zoom_range(5..=17);
+        let zoom = |zoom: f64| Zoom::try_from(zoom).unwrap();
+
+        assert_eq!(None, tiles.tile_level(zoom(4.5)));
+        assert_eq!(Some(5), tiles.tile_level(zoom(5.)));
+        assert_eq!(Some(16), tiles.tile_level(zoom(16.5)));
+        assert_eq!(Some(17), tiles.tile_level(zoom(21.)));
+    }
+
+    #[test]
+    fn large_tiles_are_taken_from_the_level_below() {
+        let tiles = Tiles::from_directory("tiles", "{z}/{x}/{y}.png").with_tile_size(512);
+        let zoom = |zoom: f64| Zoom::try_from(zoom).unwrap();
+
+        assert_eq!(Some(15), tiles.tile_level(zoom(16.5)));
+        assert_eq!(Some(0), tiles.tile_level(zoom(0.5)));
+    }
+
+    #[test]
+    fn loaded_tile_fades_in() {
+        let directory = directory_with_tile("3/1/2.png");
+
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png")
+            .fade_in(Duration::from_millis(100));
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.opacity(TILE_ID) < 1.);
+
+        std::thread::sleep(Duration::from_millis(110));
+        assert_eq!(1., tiles.opacity(TILE_ID));
+
+        let mut tiles =
+            Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png").fade_in(Duration::ZERO);
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(1., tiles.opacity(TILE_ID));
+    }
+
+    #[test]
+    fn least_recently_used_tiles_are_evicted() {
+        let source = |_| "totally invalid url".to_string();
+        let tile = Tile::from_color_image(decode_image(include_bytes!("valid.png"), None).unwrap());
+        let mut tiles =
+            Tiles::new(source, Context::default()).memory_budget(2 * tile.size_in_bytes());
+
+        let tile_id = |x| TileId { x, y: 0, zoom: 1 };
+        tiles.insert(tile_id(0), tile.clone());
+        tiles.insert(tile_id(1), tile.clone());
+
+        // Make the first one more recently used.
+        assert!(tiles.at(tile_id(0)).is_some());
+
+        tiles.insert(tile_id(2), tile.clone());
+        assert!(tiles.cache.contains_key(&tile_id(0)));
+        assert!(!tiles.cache.contains_key(&tile_id(1)));
+        assert!(tiles.cache.contains_key(&tile_id(2)));
+        assert_eq!(2 * tile.size_in_bytes(), tiles.memory_used);
+    }
+
+    #[test]
+    fn tiles_are_read_from_directory() {
+        let directory = directory_with_tile("3/1/2.png");
+
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{y}.png");
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.at(TILE_ID.east()).is_none());
+
+        assert_eq!(None, tiles.error(TILE_ID));
+        assert_eq!(Some("tile not found"), tiles.error(TILE_ID.east()));
+        assert_eq!(1, tiles.stats().loaded);
+        assert_eq!(1, tiles.stats().failed);
+
+        // Same file is the tile 3/1/5 in the TMS scheme.
+        let mut tiles = Tiles::from_directory(directory.path(), "{z}/{x}/{-y}.png");
+        assert!(tiles.at(TileId { y: 5, ..TILE_ID }).is_some());
+        assert!(tiles.at(TILE_ID).is_none());
+    }
+
+    #[test]
+    fn tiles_are_not_downloaded_while_offline() {
+        let _ = env_logger::try_init();
+
+        // Nothing listens there.
+        let source = |tile_id: TileId| format!("http://127.0.0.1:1/{}.png", tile_id.x);
+        let options = HttpOptions {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let mut tiles = Tiles::with_options(source, Context::default(), options).unwrap();
+        let tile_id = |x| TileId { x, y: 0, zoom: 5 };
+
+        for x in 0..3 {
+            assert!(!tiles.is_offline());
+            while tiles.error(tile_id(x)).is_none() {
+                tiles.at(tile_id(x));
+            }
+        }
+        assert!(tiles.is_offline());
+
+        while tiles.error(tile_id(3)).is_none() {
+            tiles.at(tile_id(3));
+        }
+        assert_eq!(
+            Some("tile server cannot be reached"),
+            tiles.error(tile_id(3))
+        );
     }
 
     fn assert_tile_is_empty_forever(tiles: &mut Tiles) {
@@ -228,6 +2058,15 @@ mod tests {
 
         assert_tile_is_empty_forever(&mut tiles);
         tile_mock.assert();
+
+        let stats = tiles.stats();
+        assert_eq!(1, stats.failed);
+        assert_eq!(0, stats.pending);
+        assert!(tiles
+            .error(TILE_ID)
+            .is_some_and(|error| error.contains("404")));
+        assert_eq!(TileStatus::Failed, tiles.status(TILE_ID));
+        assert_eq!(TileStatus::Missing, tiles.status(TILE_ID.east()));
     }
 
     #[test]
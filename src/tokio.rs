@@ -8,30 +8,29 @@ pub struct TokioRuntimeThread {
 }
 
 impl TokioRuntimeThread {
-    pub fn new() -> Self {
+    pub fn new() -> std::io::Result<Self> {
         let (quit_tx, mut quit_rx) = tokio::sync::mpsc::unbounded_channel();
-        let (rt_tx, mut rt_rx) = tokio::sync::mpsc::unbounded_channel();
 
-        let join_handle = std::thread::spawn(move || {
-            let runtime = tokio::runtime::Builder::new_current_thread()
+        let runtime = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
                 .enable_all()
-                .build()
-                .expect("could not create the Tokio runtime, downloads will not work");
-
-            let rt = Arc::new(runtime);
-            rt_tx
-                .send(rt.clone())
-                .expect("could not return the Tokio runtime to the main thread");
-            rt.block_on(quit_rx.recv());
-        });
-
-        Self {
+                .build()?,
+        );
+
+        let join_handle = std::thread::Builder::new()
+            .name("walkers".to_string())
+            .spawn({
+                let runtime = runtime.clone();
+                move || {
+                    runtime.block_on(quit_rx.recv());
+                }
+            })?;
+
+        Ok(Self {
             join_handle: Some(join_handle),
             quit_tx,
-            runtime: rt_rx
-                .blocking_recv()
-                .expect("Tokio thread died before returning the Tokio runtime"),
-        }
+            runtime,
+        })
     }
 }
 
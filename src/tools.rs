@@ -0,0 +1,267 @@
+//! Interactive tools, which let the user draw on the map, see [`crate::Map::with_tool`].
+use egui::{Align2, Color32, FontId, Key, Painter, Pos2, Rect, Response, Shape, Stroke, Ui, Vec2};
+use geo_types::{Geometry, LineString, Polygon};
+
+use crate::{
+    extras::shapes::filled_polygon,
+    geodesy::{area, length},
+    Position, Projector,
+};
+
+/// What clicking on the map does, when a tool is active. Each click adds a vertex, double-click
+/// (or Enter) finishes, Backspace removes the last vertex and Escape starts over. Polygons are
+/// also finished by clicking their first vertex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    DrawLine,
+    DrawPolygon,
+
+    /// Like [`Tool::DrawLine`], but shows the length of the line.
+    MeasureDistance,
+
+    /// Like [`Tool::DrawPolygon`], but shows the area of the polygon.
+    MeasureArea,
+}
+
+impl Tool {
+    fn is_polygon(self) -> bool {
+        matches!(self, Tool::DrawPolygon | Tool::MeasureArea)
+    }
+
+    fn measures(self) -> bool {
+        matches!(self, Tool::MeasureDistance | Tool::MeasureArea)
+    }
+
+    /// Geometry made of the vertices, if there are enough of them.
+    fn geometry(self, vertices: &[Position]) -> Option<Geometry> {
+        let line: LineString = vertices.iter().copied().collect();
+        if self.is_polygon() {
+            (vertices.len() >= 3).then(|| Polygon::new(line, Vec::new()).into())
+        } else {
+            (vertices.len() >= 2).then(|| line.into())
+        }
+    }
+}
+
+/// Vertices placed so far, kept in the [`crate::MapMemory`].
+#[derive(Clone, Default)]
+pub(crate) struct ToolState {
+    tool: Option<Tool>,
+    vertices: Vec<Position>,
+}
+
+/// How close (in screen points) to the first vertex a click closes the polygon.
+const CLOSING_DISTANCE: f32 = 10.;
+
+const STROKE: Stroke = Stroke {
+    width: 2.,
+    color: Color32::from_rgb(230, 120, 30),
+};
+
+/// Handle the input and draw what the user has drawn so far. Returns the geometry, once it is
+/// finished.
+pub(crate) fn show(
+    tool: Tool,
+    state: &mut ToolState,
+    ui: &Ui,
+    response: &Response,
+    painter: &Painter,
+    projector: &Projector,
+) -> Option<Geometry> {
+    if state.tool != Some(tool) {
+        *state = ToolState {
+            tool: Some(tool),
+            vertices: Vec::new(),
+        };
+    }
+
+    let (enter, escape, backspace) = if response.hovered() {
+        ui.input(|input| {
+            (
+                input.key_pressed(Key::Enter),
+                input.key_pressed(Key::Escape),
+                input.key_pressed(Key::Backspace),
+            )
+        })
+    } else {
+        Default::default()
+    };
+
+    let finished = if let Some(pointer) = response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+    {
+        click(tool, state, pointer, response.double_clicked(), projector)
+    } else {
+        enter
+    };
+
+    if escape {
+        state.vertices.clear();
+    }
+    if backspace {
+        state.vertices.pop();
+    }
+
+    if finished {
+        let geometry = tool.geometry(&state.vertices);
+        state.vertices.clear();
+        if geometry.is_some() {
+            return geometry;
+        }
+    }
+
+    draw(
+        tool,
+        &state.vertices,
+        response.hover_pos(),
+        painter,
+        projector,
+    );
+    None
+}
+
+/// Add a vertex, unless the click finishes the geometry. Returns whether it does.
+fn click(
+    tool: Tool,
+    state: &mut ToolState,
+    pointer: Pos2,
+    double_click: bool,
+    projector: &Projector,
+) -> bool {
+    // First click of the double-click has already added the vertex.
+    if double_click {
+        return true;
+    }
+
+    let closes_polygon = tool.is_polygon()
+        && state.vertices.len() >= 3
+        && projector.to_screen(state.vertices[0]).distance(pointer) <= CLOSING_DISTANCE;
+    if closes_polygon {
+        return true;
+    }
+
+    state.vertices.push(projector.to_position(pointer));
+    false
+}
+
+fn draw(
+    tool: Tool,
+    vertices: &[Position],
+    hover: Option<Pos2>,
+    painter: &Painter,
+    projector: &Projector,
+) {
+    if vertices.is_empty() {
+        return;
+    }
+
+    // Vertices, plus where the next one would be.
+    let mut positions = vertices.to_vec();
+    positions.extend(hover.map(|pointer| projector.to_position(pointer)));
+    let points: Vec<_> = positions
+        .iter()
+        .map(|position| projector.to_screen(*position))
+        .collect();
+
+    if tool.is_polygon() && points.len() >= 3 {
+        painter.add(filled_polygon(
+            &points,
+            &[],
+            STROKE.color.gamma_multiply(0.2),
+        ));
+        painter.add(Shape::closed_line(points.clone(), STROKE));
+    } else {
+        painter.add(Shape::line(points.clone(), STROKE));
+    }
+
+    for point in &points[..vertices.len()] {
+        painter.circle(*point, 4., Color32::WHITE, STROKE);
+    }
+
+    if let Some(anchor) = points.last().copied().filter(|_| tool.measures()) {
+        let label = if tool.is_polygon() {
+            format_area(area(&positions))
+        } else {
+            format_distance(length(&positions))
+        };
+        let galley = painter.layout_no_wrap(label, FontId::proportional(13.), Color32::BLACK);
+        let rect = Align2::LEFT_BOTTOM
+            .anchor_rect(Rect::from_min_size(
+                anchor + Vec2::new(10., -10.),
+                galley.size(),
+            ))
+            .expand(3.);
+        painter.rect_filled(rect, 3., Color32::from_white_alpha(220));
+        painter.galley(rect.min + Vec2::splat(3.), galley);
+    }
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters < 1000. {
+        format!("{:.0} m", meters)
+    } else {
+        format!("{:.2} km", meters / 1000.)
+    }
+}
+
+fn format_area(square_meters: f64) -> String {
+    if square_meters < 1_000_000. {
+        format!("{:.0} m²", square_meters)
+    } else {
+        format!("{:.2} km²", square_meters / 1_000_000.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicking_adds_vertices_until_polygon_is_closed() {
+        let rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.));
+        let projector = Projector::new(rect, Position::new(21.00027, 52.26470), 16.);
+        let mut state = ToolState::default();
+        let tool = Tool::DrawPolygon;
+
+        for point in [(100., 100.), (300., 100.), (300., 300.)] {
+            assert!(!click(tool, &mut state, point.into(), false, &projector));
+        }
+        assert_eq!(3, state.vertices.len());
+
+        // Clicking near the first vertex closes the polygon.
+        assert!(click(
+            tool,
+            &mut state,
+            Pos2::new(104., 98.),
+            false,
+            &projector
+        ));
+        assert_eq!(3, state.vertices.len());
+
+        let Some(Geometry::Polygon(polygon)) = tool.geometry(&state.vertices) else {
+            panic!("expected a polygon");
+        };
+        assert_eq!(4, polygon.exterior().0.len());
+    }
+
+    #[test]
+    fn geometry_needs_enough_vertices() {
+        let a = Position::new(21., 52.);
+        let b = Position::new(21.1, 52.);
+        assert_eq!(None, Tool::MeasureDistance.geometry(&[a]));
+        assert!(matches!(
+            Tool::MeasureDistance.geometry(&[a, b]),
+            Some(Geometry::LineString(_))
+        ));
+        assert_eq!(None, Tool::MeasureArea.geometry(&[a, b]));
+    }
+
+    #[test]
+    fn formatting_measurements() {
+        assert_eq!("250 m", format_distance(250.4));
+        assert_eq!("1.25 km", format_distance(1250.));
+        assert_eq!("5000 m²", format_area(5000.));
+        assert_eq!("2.50 km²", format_area(2_500_000.));
+    }
+}
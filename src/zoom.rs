@@ -4,31 +4,45 @@ use std::ops::Deref;
 #[error("invalid zoom level")]
 pub struct InvalidZoom;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct Zoom(u8);
+/// Zoom level of the map. It does not have to be an integer, fractional levels are drawn by
+/// scaling the tiles of the level below.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "f64"))]
+pub struct Zoom(f64);
 
-impl TryFrom<u8> for Zoom {
+/// Tiles above [`crate::sources::TileSource::zoom_range`] are scaled up, so the map can be zoomed
+/// in further than any tile server goes.
+const MAX_ZOOM: f64 = 26.;
+
+impl TryFrom<f64> for Zoom {
     type Error = InvalidZoom;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        // OSM wiki has level 20 listed https://wiki.openstreetmap.org/wiki/Zoom_levels,
-        // but when requested, server responds with 400: Bad Request.
-        if value > 19 {
-            Err(InvalidZoom)
-        } else {
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if (0. ..=MAX_ZOOM).contains(&value) {
             Ok(Self(value))
+        } else {
+            Err(InvalidZoom)
         }
     }
 }
 
+impl TryFrom<u8> for Zoom {
+    type Error = InvalidZoom;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::try_from(value as f64)
+    }
+}
+
 impl Default for Zoom {
     fn default() -> Self {
-        Self(16)
+        Self(16.)
     }
 }
 
 impl Deref for Zoom {
-    type Target = u8;
+    type Target = f64;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -36,15 +50,41 @@ impl Deref for Zoom {
 }
 
 impl Zoom {
+    /// Lowest zoom level, at which the whole world fits in a single tile.
+    pub const MIN: Zoom = Zoom(0.);
+
+    /// Highest zoom level the map can be zoomed to.
+    pub const MAX: Zoom = Zoom(MAX_ZOOM);
+
+    /// Default [`crate::MapMemory::max_zoom`]. OSM wiki has level 20 listed
+    /// https://wiki.openstreetmap.org/wiki/Zoom_levels, but when requested, server responds
+    /// with 400: Bad Request.
+    pub(crate) const DEFAULT_MAX: Zoom = Zoom(19.);
+
     pub fn zoom_in(&mut self) -> Result<(), InvalidZoom> {
-        *self = Self::try_from(self.0 + 1)?;
+        *self = Self::try_from(self.0 + 1.)?;
         Ok(())
     }
 
     pub fn zoom_out(&mut self) -> Result<(), InvalidZoom> {
-        *self = Self::try_from(self.0.checked_sub(1).ok_or(InvalidZoom)?)?;
+        *self = Self::try_from(self.0 - 1.)?;
         Ok(())
     }
+
+    /// Change the zoom by a (possibly fractional) number of levels, stopping at the limits.
+    pub fn zoom_by(&mut self, delta: f64) {
+        self.0 = (self.0 + delta).clamp(0., MAX_ZOOM);
+    }
+
+    /// Keep the zoom between `min` and `max`. If they are swapped, `max` wins.
+    pub(crate) fn clamp(self, min: Zoom, max: Zoom) -> Zoom {
+        Self(self.0.max(min.0).min(max.0))
+    }
+
+    /// Zoom level of the tiles used to draw the map at this zoom.
+    pub(crate) fn tile_level(&self) -> u8 {
+        self.0.floor() as u8
+    }
 }
 
 #[cfg(test)]
@@ -53,16 +93,19 @@ mod tests {
 
     #[test]
     fn test_constructing_zoom() {
-        assert_eq!(16, *Zoom::default());
-        assert_eq!(19, *Zoom::try_from(19).unwrap());
-        assert_eq!(Err(InvalidZoom), Zoom::try_from(20));
+        assert_eq!(16., *Zoom::default());
+        assert_eq!(26., *Zoom::try_from(26).unwrap());
+        assert_eq!(18.5, *Zoom::try_from(18.5).unwrap());
+        assert_eq!(Err(InvalidZoom), Zoom::try_from(27));
+        assert_eq!(Err(InvalidZoom), Zoom::try_from(-0.5));
+        assert_eq!(Err(InvalidZoom), Zoom::try_from(f64::NAN));
     }
 
     #[test]
     fn test_zooming_in() {
-        let mut zoom = Zoom::try_from(18).unwrap();
+        let mut zoom = Zoom::try_from(25).unwrap();
         assert!(zoom.zoom_in().is_ok());
-        assert_eq!(19, *zoom);
+        assert_eq!(26., *zoom);
         assert_eq!(Err(InvalidZoom), zoom.zoom_in());
     }
 
@@ -70,7 +113,30 @@ mod tests {
     fn test_zooming_out() {
         let mut zoom = Zoom::try_from(1).unwrap();
         assert!(zoom.zoom_out().is_ok());
-        assert_eq!(0, *zoom);
+        assert_eq!(0., *zoom);
         assert_eq!(Err(InvalidZoom), zoom.zoom_out());
     }
+
+    #[test]
+    fn test_zooming_by_fraction() {
+        let mut zoom = Zoom::try_from(18).unwrap();
+        zoom.zoom_by(0.75);
+        assert_eq!(18.75, *zoom);
+        assert_eq!(18, zoom.tile_level());
+
+        zoom.zoom_by(100.);
+        assert_eq!(26., *zoom);
+
+        zoom.zoom_by(-100.);
+        assert_eq!(0., *zoom);
+    }
+
+    #[test]
+    fn test_clamping_zoom() {
+        let min = Zoom::try_from(5).unwrap();
+        let max = Zoom::try_from(12).unwrap();
+        assert_eq!(5., *Zoom::try_from(2).unwrap().clamp(min, max));
+        assert_eq!(12., *Zoom::try_from(14.5).unwrap().clamp(min, max));
+        assert_eq!(7.5, *Zoom::try_from(7.5).unwrap().clamp(min, max));
+    }
 }